@@ -0,0 +1,3 @@
+-- GNU AGPL v3 License 
+
+DROP TABLE Uploads
@@ -0,0 +1,14 @@
+-- GNU AGPL v3 License 
+
+CREATE TABLE Uploads (
+  id SERIAL PRIMARY KEY,
+  path VARCHAR NOT NULL UNIQUE,
+  content_type VARCHAR NOT NULL,
+  size BIGINT NOT NULL,
+  uploader_id INT NOT NULL,
+  created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+
+  CONSTRAINT fk_uploader
+    FOREIGN KEY(uploader_id)
+      REFERENCES Users(id)
+)
@@ -0,0 +1,8 @@
+-- GNU AGPL v3 License 
+
+DROP TRIGGER bump_version ON Blogposts;
+DROP TRIGGER bump_version ON Users;
+DROP FUNCTION bump_version();
+
+ALTER TABLE Blogposts DROP COLUMN version;
+ALTER TABLE Users DROP COLUMN version;
@@ -0,0 +1,23 @@
+-- GNU AGPL v3 License 
+
+ALTER TABLE Users ADD COLUMN version INT NOT NULL DEFAULT 1;
+ALTER TABLE Blogposts ADD COLUMN version INT NOT NULL DEFAULT 1;
+
+-- Bump the version of a row whenever it is modified, unless the update
+-- sets the version itself.
+CREATE OR REPLACE FUNCTION bump_version() RETURNS trigger AS $$
+BEGIN
+    IF (
+        NEW IS DISTINCT FROM OLD AND
+        NEW.version IS NOT DISTINCT FROM OLD.version
+    ) THEN
+        NEW.version := OLD.version + 1;
+    END IF;
+    RETURN NEW;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER bump_version BEFORE UPDATE ON Users
+    FOR EACH ROW EXECUTE PROCEDURE bump_version();
+CREATE TRIGGER bump_version BEFORE UPDATE ON Blogposts
+    FOR EACH ROW EXECUTE PROCEDURE bump_version();
@@ -0,0 +1,7 @@
+-- GNU AGPL v3 License 
+
+DROP TRIGGER set_updated_at ON Blogposts;
+DROP TRIGGER set_updated_at ON Users;
+
+ALTER TABLE Blogposts DROP COLUMN updated_at;
+ALTER TABLE Users DROP COLUMN updated_at;
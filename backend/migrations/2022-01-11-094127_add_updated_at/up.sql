@@ -0,0 +1,13 @@
+-- GNU AGPL v3 License 
+
+ALTER TABLE Users ADD COLUMN updated_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP;
+ALTER TABLE Blogposts ADD COLUMN updated_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP;
+
+-- existing posts were last updated whenever they were created, as far as
+-- we know; don't count this as a new version
+ALTER TABLE Blogposts DISABLE TRIGGER bump_version;
+UPDATE Blogposts SET updated_at = created_at;
+ALTER TABLE Blogposts ENABLE TRIGGER bump_version;
+
+SELECT diesel_manage_updated_at('Users');
+SELECT diesel_manage_updated_at('Blogposts');
@@ -0,0 +1,3 @@
+-- GNU AGPL v3 License 
+
+ALTER TABLE Users ADD COLUMN deleted_at TIMESTAMP;
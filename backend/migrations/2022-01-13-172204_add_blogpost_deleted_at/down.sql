@@ -0,0 +1,3 @@
+-- GNU AGPL v3 License 
+
+ALTER TABLE Blogposts DROP COLUMN deleted_at;
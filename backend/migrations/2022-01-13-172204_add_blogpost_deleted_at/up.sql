@@ -0,0 +1,3 @@
+-- GNU AGPL v3 License 
+
+ALTER TABLE Blogposts ADD COLUMN deleted_at TIMESTAMP;
@@ -0,0 +1,17 @@
+-- GNU AGPL v3 License 
+
+CREATE TABLE UrlRedirects (
+  id SERIAL PRIMARY KEY,
+  path VARCHAR NOT NULL UNIQUE,
+  blogpost_id INT,
+  target VARCHAR,
+  created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+
+  CONSTRAINT fk_blogpost
+    FOREIGN KEY(blogpost_id)
+      REFERENCES Blogposts(id)
+      ON DELETE CASCADE,
+
+  CONSTRAINT one_destination
+    CHECK ((blogpost_id IS NULL) <> (target IS NULL))
+)
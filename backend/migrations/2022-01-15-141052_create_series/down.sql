@@ -0,0 +1,8 @@
+-- GNU AGPL v3 License 
+
+ALTER TABLE Blogposts
+  DROP CONSTRAINT fk_series,
+  DROP COLUMN series_id,
+  DROP COLUMN series_position;
+
+DROP TABLE Series;
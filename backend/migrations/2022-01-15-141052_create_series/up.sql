@@ -0,0 +1,16 @@
+-- GNU AGPL v3 License 
+
+CREATE TABLE Series (
+  id SERIAL PRIMARY KEY,
+  title VARCHAR NOT NULL,
+  url VARCHAR NOT NULL UNIQUE,
+  description TEXT NOT NULL DEFAULT '',
+  created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+ALTER TABLE Blogposts
+  ADD COLUMN series_id INT,
+  ADD COLUMN series_position INT,
+  ADD CONSTRAINT fk_series
+    FOREIGN KEY(series_id)
+      REFERENCES Series(id);
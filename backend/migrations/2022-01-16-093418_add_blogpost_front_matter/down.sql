@@ -0,0 +1,5 @@
+-- GNU AGPL v3 License 
+
+ALTER TABLE Blogposts DROP COLUMN canonical_url;
+ALTER TABLE Blogposts DROP COLUMN cover_image;
+ALTER TABLE Blogposts DROP COLUMN summary;
@@ -0,0 +1,5 @@
+-- GNU AGPL v3 License 
+
+ALTER TABLE Blogposts ADD COLUMN summary TEXT;
+ALTER TABLE Blogposts ADD COLUMN cover_image VARCHAR;
+ALTER TABLE Blogposts ADD COLUMN canonical_url VARCHAR;
@@ -0,0 +1,15 @@
+-- GNU AGPL v3 License 
+
+CREATE TABLE PostLinks (
+  source_id INT NOT NULL,
+  target_url VARCHAR NOT NULL,
+
+  PRIMARY KEY (source_id, target_url),
+
+  CONSTRAINT fk_source
+    FOREIGN KEY(source_id)
+      REFERENCES Blogposts(id)
+      ON DELETE CASCADE
+);
+
+CREATE INDEX PostLinks_target_url ON PostLinks (target_url);
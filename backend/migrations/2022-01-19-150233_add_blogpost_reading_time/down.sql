@@ -0,0 +1,4 @@
+-- GNU AGPL v3 License 
+
+ALTER TABLE Blogposts DROP COLUMN reading_minutes;
+ALTER TABLE Blogposts DROP COLUMN word_count;
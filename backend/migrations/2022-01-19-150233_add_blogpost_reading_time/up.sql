@@ -0,0 +1,7 @@
+-- GNU AGPL v3 License 
+
+ALTER TABLE Blogposts ADD COLUMN word_count INT NOT NULL DEFAULT 0;
+ALTER TABLE Blogposts ADD COLUMN reading_minutes INT NOT NULL DEFAULT 1;
+
+-- the words are counted in the text that the body renders into, which only
+-- the server can do, so existing posts are counted when it starts
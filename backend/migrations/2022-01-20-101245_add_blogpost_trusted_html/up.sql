@@ -0,0 +1,13 @@
+-- GNU AGPL v3 License 
+
+ALTER TABLE Blogposts ADD COLUMN trusted_html BOOLEAN NOT NULL DEFAULT FALSE;
+
+-- until now, posts were trusted if the user they were credited to was an
+-- administrator, so keep rendering those the same way
+ALTER TABLE Blogposts DISABLE TRIGGER bump_version;
+ALTER TABLE Blogposts DISABLE TRIGGER set_updated_at;
+UPDATE Blogposts SET trusted_html = TRUE
+    FROM Users
+    WHERE Users.id = Blogposts.author_id AND Users.roles & 2 <> 0;
+ALTER TABLE Blogposts ENABLE TRIGGER set_updated_at;
+ALTER TABLE Blogposts ENABLE TRIGGER bump_version;
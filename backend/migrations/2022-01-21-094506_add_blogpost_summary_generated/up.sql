@@ -0,0 +1,11 @@
+-- GNU AGPL v3 License 
+
+ALTER TABLE Blogposts ADD COLUMN summary_generated BOOLEAN NOT NULL DEFAULT FALSE;
+
+-- posts without a summary get an excerpt of their body stored as one when
+-- the server starts
+ALTER TABLE Blogposts DISABLE TRIGGER bump_version;
+ALTER TABLE Blogposts DISABLE TRIGGER set_updated_at;
+UPDATE Blogposts SET summary_generated = TRUE WHERE summary IS NULL;
+ALTER TABLE Blogposts ENABLE TRIGGER set_updated_at;
+ALTER TABLE Blogposts ENABLE TRIGGER bump_version;
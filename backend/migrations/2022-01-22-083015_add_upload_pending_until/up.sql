@@ -0,0 +1,5 @@
+-- GNU AGPL v3 License 
+
+-- presigned uploads are recorded as soon as they're handed out, so that the
+-- path is reserved, and can be completed until this time
+ALTER TABLE Uploads ADD COLUMN pending_until TIMESTAMP;
@@ -9,12 +9,20 @@ use dashmap::mapref::one::Ref;
 use futures_util::future::ready;
 use warp::{reject::custom as reject, reply::html, Filter, Rejection, Reply};
 
+mod redirects;
+mod trash;
 mod user;
 
 #[inline]
 pub fn admin(
 ) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone + Send + Sync + 'static {
-    warp::path("admin").and(admin_console().or(user::user_info()).or(user::user_list()))
+    warp::path("admin").and(
+        admin_console()
+            .or(user::user_info())
+            .or(user::user_list())
+            .or(trash::trash())
+            .or(redirects::redirects()),
+    )
 }
 
 #[inline]
@@ -0,0 +1,23 @@
+// GNU AGPL v3 License
+
+use crate::{
+    pagerender::{page_render_loader, PageRenderState},
+    templates, PageRenderError, Title,
+};
+use futures_util::future::ready;
+use warp::{reject::custom as reject, reply::html, Filter, Rejection, Reply};
+
+#[inline]
+pub fn trash(
+) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone + Send + Sync + 'static {
+    warp::path!("trash")
+        .and(warp::get())
+        .and(page_render_loader::<true>(0b10))
+        .and_then(|mut pr: PageRenderState| {
+            ready({
+                templates::template("trash", Title { title: "Trash" }, pr.template_options())
+                    .map_err(|e| reject(PageRenderError::from(e)))
+            })
+        })
+        .map(|res: String| html(res))
+}
@@ -1,38 +1,48 @@
 // GNU AGPL v3 License
 
+use super::{recover_error, with_role, ApiError, Caller};
 use crate::{
-    auth::{with_session, Session},
+    auth::Permissions,
     csrf_integration::{self, verify_csrf_pair, Base64CsrfPair, CsrfError},
+    models::NewUpload,
+    query::{with_database, Database, DatabaseError},
     Config,
 };
 use aws_sdk_s3::{error::PutObjectError, Client, Region, SdkError};
 use aws_smithy_http::endpoint::Endpoint;
 use bytes::Buf;
-use dashmap::mapref::one::Ref;
-use futures_util::{
-    future::{err, ok, TryFutureExt},
-    stream, StreamExt, TryStreamExt,
-};
+use futures_util::{future::TryFutureExt, stream, StreamExt, TryStreamExt};
 use once_cell::sync::OnceCell;
-use std::convert::{TryFrom, TryInto};
+use std::{
+    convert::{TryFrom, TryInto},
+    sync::Arc,
+};
 use warp::{
     http::{StatusCode, Uri},
     hyper::Body,
     multipart::{FormData, Part},
     reject::custom as reject,
-    reply::{json, with_status},
+    reply::json,
     Filter, Rejection, Reply,
 };
 
 const MAX_LEN: u64 = 5 * 1024 * 1024;
 
-struct S3Data {
-    client: Client,
-    bucket_name: String,
+pub(super) struct S3Data {
+    pub(super) client: Client,
+    pub(super) bucket_name: String,
 }
 
 static S3_DATA: OnceCell<S3Data> = OnceCell::new();
 
+/// Get the S3 client and bucket shared by the upload routes.
+#[inline]
+pub(super) fn s3_data() -> &'static S3Data {
+    S3_DATA
+        .get()
+        .expect("`initialize_s3` was not called before s3 functions")
+}
+
 #[inline]
 pub async fn initialize_s3(cfg: &Config) {
     let aws_cfg = aws_config::load_from_env().await;
@@ -62,37 +72,14 @@ pub fn image(
 ) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone + Send + Sync + 'static {
     warp::path!("image")
         .and(warp::post())
-        .and(with_session())
-        .and_then(|s: Option<Ref<'static, String, Session>>| {
-            let do_pass = match s {
-                Some(s) => s.roles.0 & 0b01 != 0,
-                None => false,
-            };
-
-            if do_pass {
-                ok(())
-            } else {
-                err(reject(UploadImageError::PermissionDenied))
-            }
-        })
-        .untuple_one()
+        .and(with_role::<UploadImageError>(Permissions::AUTHOR))
         .and(with_upload_data())
-        .and_then(|u| image_to_s3(u).map_err(reject))
-        .map(|url: String| json(&UrlSer { url: &url }))
-        .recover(|rej: Rejection| match rej.find::<UploadImageError>() {
-            Some(uie) => {
-                tracing::error!("Image upload error: {}", &uie);
-                let (code, msg) = uie.as_err();
-                ok(with_status(
-                    json(&ErrSer {
-                        error: true,
-                        description: msg,
-                    }),
-                    code,
-                ))
-            }
-            None => err(rej),
+        .and(with_database())
+        .and_then(|uploader: Caller, u, db: Arc<_>| async move {
+            image_to_s3(&*db, uploader.id, u).await.map_err(reject)
         })
+        .map(|url: String| json(&UrlSer { url: &url }))
+        .recover(recover_error::<UploadImageError>)
 }
 
 #[inline]
@@ -108,8 +95,14 @@ struct UrlSer<'a> {
     url: &'a str,
 }
 
+/// Upload an image to S3, and record it alongside the files that were
+/// uploaded directly.
 #[inline]
-async fn image_to_s3(ud: UploadData) -> Result<String, UploadImageError> {
+async fn image_to_s3(
+    db: &impl Database,
+    uploader_id: i32,
+    ud: UploadData,
+) -> Result<String, UploadImageError> {
     let UploadData {
         category,
         subcategory,
@@ -118,10 +111,10 @@ async fn image_to_s3(ud: UploadData) -> Result<String, UploadImageError> {
         content_type,
     } = ud;
 
-    let s3data = S3_DATA
-        .get()
-        .expect("`initialize_s3` was not called before s3 functions");
+    let s3data = s3_data();
     let path = format!("files/{}/{}/{}", category, subcategory, filename);
+    let data = warp::hyper::body::to_bytes(data).await?;
+    let size = i64::try_from(data.len()).unwrap_or(i64::MAX);
     let body: aws_smithy_http::body::SdkBody = data.into();
 
     s3data
@@ -130,10 +123,19 @@ async fn image_to_s3(ud: UploadData) -> Result<String, UploadImageError> {
         .bucket(&s3data.bucket_name)
         .key(path.clone())
         .body(body.into())
-        .content_type(content_type)
+        .content_type(content_type.clone())
         .send()
         .await?;
 
+    db.insert_upload(NewUpload {
+        path: path.clone(),
+        content_type,
+        size,
+        uploader_id,
+        pending_until: None,
+    })
+    .await?;
+
     Ok(path)
 }
 
@@ -248,6 +250,8 @@ enum UploadImageError {
     IncompleteData(&'static str),
     #[error("Could not parse multipart data: {0}")]
     Multipart(#[from] warp::Error),
+    #[error("Could not read the file: {0}")]
+    Body(#[from] warp::hyper::Error),
     #[error("Could not parse bytes at UTF-8: {0}")]
     Utf8(#[from] std::string::FromUtf8Error),
     #[error("Part has no data")]
@@ -258,6 +262,8 @@ enum UploadImageError {
     PermissionDenied,
     #[error("CSRF: {0}")]
     Csrf(#[from] CsrfError),
+    #[error("{0}")]
+    Database(#[from] DatabaseError),
 }
 
 impl From<SdkError<PutObjectError>> for UploadImageError {
@@ -267,7 +273,14 @@ impl From<SdkError<PutObjectError>> for UploadImageError {
     }
 }
 
-impl UploadImageError {
+impl ApiError for UploadImageError {
+    const KIND: &'static str = "Image upload error";
+
+    #[inline]
+    fn permission_denied() -> Self {
+        Self::PermissionDenied
+    }
+
     #[inline]
     fn as_err(&self) -> (StatusCode, &'static str) {
         match self {
@@ -278,24 +291,23 @@ impl UploadImageError {
             ),
             Self::Utf8(..) => (StatusCode::BAD_REQUEST, "String was not UTF-8"),
             Self::NoPartData => (StatusCode::BAD_REQUEST, "Part has no data?"),
+            Self::Body(..) => (StatusCode::BAD_REQUEST, "Unable to read the file"),
             Self::S3(..) => (
                 StatusCode::INTERNAL_SERVER_ERROR,
                 "An error occurred while uploading to S3",
             ),
             Self::PermissionDenied => (StatusCode::UNAUTHORIZED, "Permission denied"),
             Self::Csrf(..) => (StatusCode::BAD_REQUEST, "CSRF failure"),
+            Self::Database(..) => (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "An SQL error occurred while recording the upload",
+            ),
         }
     }
 }
 
 impl warp::reject::Reject for UploadImageError {}
 
-#[derive(serde::Serialize)]
-struct ErrSer {
-    error: bool,
-    description: &'static str,
-}
-
 #[cfg(test)]
 mod tests {
     use super::*;
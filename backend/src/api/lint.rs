@@ -0,0 +1,141 @@
+// GNU AGPL v3 License
+
+use super::{recover_error, with_role, ApiError, Caller};
+use crate::{
+    csrf_integration::{self, CsrfError},
+    lint::{self, LintWarning},
+    models::{Blogpost, Model},
+    query::{with_database, Database, DatabaseError},
+};
+use bytes::Bytes;
+use std::sync::Arc;
+use warp::{http::StatusCode, reject::custom as reject, reply::json, Filter, Rejection, Reply};
+
+/// Check a blogpost for broken links and other mistakes. Links to other
+/// sites are only followed if `external=true` is in the query.
+#[inline]
+pub fn lint(
+) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone + Send + Sync + 'static {
+    warp::path!("blogpost" / i32 / "lint")
+        .and(warp::get())
+        // only the people who can edit posts can lint them
+        .and(with_role::<LintError>(Blogpost::UPDATE_PERMS))
+        .and(csrf_integration::check_csrf::<LintError>())
+        .and(with_database())
+        .and_then(|id: i32, _: Caller, query: Bytes, db: Arc<_>| async move {
+            let LintOptions { external } = serde_urlencoded::from_bytes::<LintOptions>(&query)
+                .map_err(|e| reject(LintError::from(e)))?;
+            lint_blogpost(&*db, id, external).await.map_err(reject)
+        })
+        .map(|warnings| json(&LintReport { warnings }))
+        .recover(recover_error::<LintError>)
+}
+
+#[inline]
+async fn lint_blogpost(
+    db: &impl Database,
+    id: i32,
+    external: bool,
+) -> Result<Vec<LintWarning>, LintError> {
+    let blogpost = db.get_blogpost_by_id(id).await?;
+    Ok(lint::check(db, &blogpost.body, external).await?)
+}
+
+#[derive(serde::Deserialize)]
+struct LintOptions {
+    #[serde(default)]
+    external: bool,
+}
+
+#[derive(serde::Serialize)]
+struct LintReport {
+    warnings: Vec<LintWarning>,
+}
+
+#[derive(Debug, thiserror::Error)]
+enum LintError {
+    #[error("CSRF: {0}")]
+    Csrf(#[from] CsrfError),
+    #[error("{0}")]
+    Query(#[from] serde_urlencoded::de::Error),
+    #[error("{0}")]
+    Database(#[from] DatabaseError),
+    #[error("Permission denied")]
+    PermissionDenied,
+}
+
+impl ApiError for LintError {
+    const KIND: &'static str = "Lint error";
+
+    #[inline]
+    fn permission_denied() -> Self {
+        Self::PermissionDenied
+    }
+
+    #[inline]
+    fn as_err(&self) -> (StatusCode, &'static str) {
+        match self {
+            Self::Csrf(..) => (StatusCode::BAD_REQUEST, "CSRF failure"),
+            Self::Query(..) => (StatusCode::BAD_REQUEST, "Unable to parse query"),
+            Self::Database(DatabaseError::NotFound) => {
+                (StatusCode::NOT_FOUND, "Unable to find the blogpost")
+            }
+            Self::Database(..) => (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "An SQL error occurred while linting the blogpost",
+            ),
+            Self::PermissionDenied => (StatusCode::UNAUTHORIZED, "Permission denied"),
+        }
+    }
+}
+
+impl warp::reject::Reject for LintError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        auth::{fake_access_token, fake_access_token_fewer_perms, initialize_auth_test},
+        csrf_integration::EncryptedCsrfPair,
+    };
+    use percent_encoding::{utf8_percent_encode, NON_ALPHANUMERIC};
+    use warp::http::Response;
+
+    #[inline]
+    async fn request(token: &str, id: i32, external: bool) -> Response<Bytes> {
+        csrf_integration::initialize_csrf_test();
+        initialize_auth_test();
+        let EncryptedCsrfPair {
+            token: csrf_token,
+            cookie,
+        } = csrf_integration::generate_csrf_pair().unwrap();
+        warp::test::request()
+            .path(&format!(
+                "/blogpost/{}/lint?external={}&csrf_token={}&csrf_cookie={}",
+                id,
+                external,
+                utf8_percent_encode(&csrf_token, NON_ALPHANUMERIC),
+                utf8_percent_encode(&cookie, NON_ALPHANUMERIC),
+            ))
+            .header("Cookie", format!("access_token={}", token))
+            .reply(&lint())
+            .await
+    }
+
+    #[tokio::test]
+    async fn lints_blogposts() {
+        let value = request(fake_access_token(), 1, true).await;
+        assert_eq!(value.status(), StatusCode::OK);
+        let value: serde_json::Value = serde_json::from_slice(value.body()).unwrap();
+        assert_eq!(value, serde_json::json!({ "warnings": [] }));
+
+        let value = request(fake_access_token(), 100, false).await;
+        assert_eq!(value.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn requires_author() {
+        let value = request(fake_access_token_fewer_perms(), 1, false).await;
+        assert_eq!(value.status(), StatusCode::UNAUTHORIZED);
+    }
+}
@@ -1,20 +1,61 @@
 // GNU AGPL v3 License
 
 mod image;
+mod lint;
 mod model;
+mod openapi;
+mod preview;
+mod rate_limit;
+mod redirects;
 mod set_username;
+mod trash;
+mod upload;
+
+pub use trash::purge_trash_task;
 
 use crate::{
-    models::{Blogpost, User},
+    auth::{with_session, Permissions, Session},
+    models::{Blogpost, Series, User},
     Config,
 };
-use warp::{http::StatusCode, Filter, Reply};
+use dashmap::mapref::one::Ref;
+use futures_util::future::ready;
+use std::fmt;
+use warp::{
+    http::StatusCode,
+    reject::{custom as reject, Reject},
+    reply::{json, with_status, Json, WithStatus},
+    Filter, Rejection, Reply,
+};
 
 #[inline]
 pub async fn initialize_api(cfg: &Config) {
     image::initialize_s3(cfg).await;
 }
 
+/// The names that the models are served under, as in `/api/blogpost/{id}`.
+const USER: &str = "user";
+const BLOGPOST: &str = "blogpost";
+const SERIES: &str = "series";
+
+/// The paths of every other route in `api()`, relative to `/api` and with
+/// `{id}` standing in for IDs. The OpenAPI document describes exactly these
+/// and the models, so a new route needs to be added here too.
+const ROUTES: [&str; 12] = [
+    "/username",
+    "/image",
+    "/upload/presign",
+    "/upload/complete",
+    "/trash",
+    "/trash/{id}",
+    "/trash/{id}/restore",
+    "/redirects",
+    "/redirects/{id}",
+    "/preview",
+    "/blogpost/{id}/lint",
+    "/openapi.json",
+];
+
 #[inline]
 fn no_cache(_: i32) {}
 
@@ -22,13 +63,14 @@ fn no_cache(_: i32) {}
 pub fn api(
 ) -> impl Filter<Extract = (impl Reply,), Error = warp::Rejection> + Clone + Send + Sync + 'static {
     // create model routes
-    let user = model::model::<User, _>("user", no_cache);
-    let blogpost = model::model::<Blogpost, _>("blogpost", crate::blog::invalidate_cache);
+    let user = model::model::<User, _>(USER, no_cache);
+    let blogpost = model::model::<Blogpost, _>(BLOGPOST, |_| crate::blog::invalidate_cache());
+    let series = model::model::<Series, _>(SERIES, |_| crate::blog::invalidate_cache());
 
     // handle 404's by sending back an error message
     let not_found = warp::any().map(|| {
-        warp::reply::with_status(
-            warp::reply::json(&NotFoundError {
+        with_status(
+            json(&ErrSer {
                 error: true,
                 description: "No route found",
             }),
@@ -38,15 +80,79 @@ pub fn api(
 
     let api = user
         .or(blogpost)
+        .or(series)
         .or(set_username::set_username())
         .or(image::image())
+        .or(upload::upload())
+        .or(trash::trash())
+        .or(redirects::redirects())
+        .or(preview::preview())
+        .or(lint::lint())
+        .or(openapi::openapi())
         .or(not_found);
 
     warp::path("api").and(api).boxed()
 }
 
+/// An error that an API route sends back to the client.
+trait ApiError: Reject + fmt::Display + Sized {
+    /// What the error is called in the logs, e.g. `"Upload error"`.
+    const KIND: &'static str;
+
+    /// The error for a user who doesn't have the roles a route needs.
+    fn permission_denied() -> Self;
+
+    /// The status code and description to send back.
+    fn as_err(&self) -> (StatusCode, &'static str);
+}
+
+/// The user behind a request, once they've been checked for the roles that
+/// a route needs.
+struct Caller {
+    id: i32,
+    name: Option<String>,
+    roles: Permissions,
+}
+
+/// Only let through users who have all of the given roles.
+#[inline]
+fn with_role<E: ApiError>(
+    roles: Permissions,
+) -> impl Filter<Extract = (Caller,), Error = Rejection> + Clone + Send + Sync + 'static {
+    with_session().and_then(move |s: Option<Ref<'static, String, Session>>| {
+        ready(match s {
+            Some(s) if roles.applies_to(s.roles) => Ok(Caller {
+                id: s.id,
+                name: s.name.clone(),
+                roles: s.roles,
+            }),
+            _ => Err(reject(E::permission_denied())),
+        })
+    })
+}
+
+/// Turn a rejection carrying an `E` into a JSON error, for use with
+/// `Filter::recover`.
+#[inline]
+async fn recover_error<E: ApiError>(rej: Rejection) -> Result<WithStatus<Json>, Rejection> {
+    match rej.find::<E>() {
+        Some(e) => {
+            tracing::error!("{}: {}", E::KIND, e);
+            let (code, msg) = e.as_err();
+            Ok(with_status(
+                json(&ErrSer {
+                    error: true,
+                    description: msg,
+                }),
+                code,
+            ))
+        }
+        None => Err(rej),
+    }
+}
+
 #[derive(serde::Serialize)]
-struct NotFoundError {
+struct ErrSer {
     error: bool,
     description: &'static str,
 }
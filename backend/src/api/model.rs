@@ -3,16 +3,29 @@
 use crate::{
     auth::{self, with_session, Permissions, Session},
     csrf_integration::{self, CsrfError},
-    models::Model,
+    http_date,
+    lint::LintWarning,
+    markdown::FrontMatterError,
+    models::{default_count, Model, SaveError, UnknownSortField, ValidationError},
     query::{with_database, Database, DatabaseError},
+    slug::SlugError,
 };
 use bytes::Bytes;
 use dashmap::mapref::one::Ref;
 use futures_util::future::{self, TryFutureExt};
-use serde::{de::DeserializeOwned, Serialize};
-use std::sync::Arc;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::{iter, sync::Arc};
 use tracing::Level;
-use warp::{http::StatusCode, reject::custom as reject, reply::json, Filter, Reply};
+use warp::{
+    http::{
+        header::{ETAG, LAST_MODIFIED, LINK},
+        HeaderValue, StatusCode,
+    },
+    path::FullPath,
+    reject::custom as reject,
+    reply::{json, Response},
+    Filter, Reply,
+};
 
 #[inline]
 pub fn model<M: Model + 'static, I>(
@@ -21,9 +34,10 @@ pub fn model<M: Model + 'static, I>(
 ) -> impl Filter<Extract = (impl Reply,), Error = warp::Rejection> + Clone + Send + Sync + 'static
 where
     M: Serialize,
-    M::ListFilter: DeserializeOwned + Send + 'static,
+    M::ListFilter: DeserializeOwned + Clone + Send + 'static,
     M::NewInstance: DeserializeOwned + Send + 'static,
     M::UpdateInstance: DeserializeOwned + Send + 'static,
+    M::DeleteOptions: DeserializeOwned + Default + Send + 'static,
     I: Fn(i32) + Clone + Copy + Send + Sync + 'static,
 {
     // base that gets the body to deserialize from, as well as the current database
@@ -35,6 +49,7 @@ where
     let create = create_filter::<M, _, _>(&loader);
     let update = update_filter::<M, _, _, I>(&loader, invalidator);
     let delete = delete_filter::<M, _, _, I>(&loader, invalidator);
+    let batch = batch_filter::<M, _, _, I>(&loader, invalidator);
 
     // combine into final filter
     warp::path(name)
@@ -43,6 +58,7 @@ where
                 .or(create)
                 .or(update)
                 .or(delete)
+                .or(batch)
                 .recover(|rej: warp::Rejection| {
                     future::ready({
                         match rej.find::<ModelError>() {
@@ -54,6 +70,8 @@ where
                                     warp::reply::json(&SerModelError {
                                         error: true,
                                         description,
+                                        suggestion: me.suggestion(),
+                                        field: me.field(),
                                     }),
                                     status_code,
                                 ))
@@ -104,33 +122,128 @@ fn list_filter<M: Model, D: Database + Send + Sync + 'static, F>(
 ) -> impl Filter<Extract = (impl Reply,), Error = warp::Rejection> + Clone + Send + Sync + 'static
 where
     M: Serialize,
-    M::ListFilter: DeserializeOwned + Send + 'static,
+    M::ListFilter: DeserializeOwned + Clone + Send + 'static,
     F: Filter<Extract = LoaderData<D>, Error = warp::Rejection> + Clone + Send + Sync + 'static,
 {
     warp::path::end()
         .and(warp::get())
+        .and(warp::path::full())
         .and(loader.clone())
         .and(warp::any().map(|| M::LIST_PERMS))
-        .and_then(|body: Bytes, db, uperms, rperms| {
-            future::ready(check_permsissions((body, db), uperms, rperms))
+        .and_then(|path: FullPath, body: Bytes, db, uperms, rperms| {
+            future::ready(check_permsissions((path, body, db), uperms, rperms))
         })
         .untuple_one()
-        .and_then(|body: Bytes, db| {
-            future::ready({
-                let filters = serde_urlencoded::from_bytes::<M::ListFilter>(&body);
-                match filters {
-                    Ok(filters) => Ok((filters, db)),
-                    Err(e) => Err(reject(ModelError::from(e))),
+        .and_then(|path: FullPath, body: Bytes, db| {
+            future::ready(match list_params::<M>(&body) {
+                Ok((filters, pagination, query)) => {
+                    Ok((filters, pagination, PageLinks { path, query }, db))
                 }
+                Err(e) => Err(reject(e)),
             })
         })
         .untuple_one()
-        .and_then(|filters, db: Arc<_>| async move {
-            M::list(&*db, filters)
-                .await
-                .map_err(|e| reject(ModelError::from(e)))
-        })
-        .map(|instances| json(&instances))
+        .and_then(
+            |filters: M::ListFilter, pagination, links, db: Arc<_>| async move {
+                let total = M::count(&*db, filters.clone())
+                    .await
+                    .map_err(|e| reject(ModelError::from(e)))?;
+                let items = M::list(&*db, filters)
+                    .await
+                    .map_err(|e| reject(ModelError::from(e)))?;
+                Ok::<_, warp::Rejection>((items, total, pagination, links))
+            },
+        )
+        .untuple_one()
+        .map(|items, total, pagination, links| list_response(items, total, pagination, &links))
+}
+
+/// Read the filter and pagination out of the query string of a list, along
+/// with the parameters to keep in the links to other pages.
+#[inline]
+fn list_params<M: Model>(
+    body: &[u8],
+) -> Result<(M::ListFilter, Pagination, Vec<(String, String)>), ModelError>
+where
+    M::ListFilter: DeserializeOwned,
+{
+    // only carry the filter over to the other pages, so that tokens in the
+    // query don't end up in links
+    let mut query = serde_urlencoded::from_bytes::<Vec<(String, String)>>(body)?;
+    query.retain(|(key, _)| {
+        M::FILTER_KEYS.contains(&key.as_str()) || PAGE_KEYS.contains(&key.as_str())
+    });
+
+    // check the sort on its own, so that the error can name the field
+    if let Some((_, sort)) = query.iter().find(|(key, _)| key == "sort") {
+        M::check_sort(sort)?;
+        if !sort.is_empty() && query.iter().any(|(key, _)| key == "cursor") {
+            return Err(ModelError::CursorWithSort);
+        }
+    }
+
+    let filters = serde_urlencoded::from_bytes::<M::ListFilter>(body)?;
+    let pagination = serde_urlencoded::from_bytes::<Pagination>(body)?;
+    Ok((filters, pagination, query))
+}
+
+/// Wrap a page of instances in a `ListEnvelope`, and link to the adjacent
+/// pages in the `Link` header.
+#[inline]
+fn list_response<M: Model + Serialize>(
+    items: Vec<M>,
+    total: u64,
+    pagination: Pagination,
+    links: &PageLinks,
+) -> Response {
+    let Pagination { skip, count } = pagination;
+
+    // a full page means there may be more items after the last one, but
+    // cursors can't point into a custom order
+    let next_cursor = if items.len() as u64 == count && !links.is_sorted() {
+        items.last().and_then(M::cursor)
+    } else {
+        None
+    };
+
+    // keyset pagination can only move forwards
+    let (next, prev) = if links.uses_cursor() {
+        (
+            next_cursor.as_deref().map(|cursor| links.link("cursor", cursor)),
+            None,
+        )
+    } else {
+        (
+            (skip.saturating_add(count) < total)
+                .then(|| links.link("skip", &(skip + count).to_string())),
+            (skip > 0).then(|| links.link("skip", &skip.saturating_sub(count).to_string())),
+        )
+    };
+
+    let link_header = [(next.as_ref(), "next"), (prev.as_ref(), "prev")]
+        .into_iter()
+        .filter_map(|(link, rel)| link.map(|link| format!("<{}>; rel=\"{}\"", link, rel)))
+        .collect::<Vec<_>>()
+        .join(", ");
+
+    let mut response = json(&ListEnvelope {
+        items,
+        total,
+        skip,
+        count,
+        next,
+        prev,
+        next_cursor,
+    })
+    .into_response();
+
+    if !link_header.is_empty() {
+        if let Ok(value) = HeaderValue::from_str(&link_header) {
+            response.headers_mut().insert(LINK, value);
+        }
+    }
+
+    response
 }
 
 /// Get a single model based on its id.
@@ -150,12 +263,83 @@ where
             future::ready(check_permsissions((id, db), uperms, rperms))
         })
         .untuple_one()
-        .and_then(|id, db: Arc<_>| async move {
-            M::get(&*db, id)
+        .and(warp::header::optional::<String>("if-none-match"))
+        .and_then(|id, db: Arc<_>, if_none_match: Option<String>| async move {
+            let instance = M::get(&*db, id)
                 .await
-                .map_err(|e| reject(ModelError::from(e)))
+                .map_err(|e| reject(ModelError::from(e)))?;
+            Ok::<_, warp::Rejection>((instance, if_none_match))
+        })
+        .untuple_one()
+        .map(|instance, if_none_match: Option<String>| {
+            get_response(instance, if_none_match.as_deref())
         })
-        .map(|instance| json(&instance))
+}
+
+/// Send back an instance along with its `ETag`, or `304 Not Modified` if
+/// the client already has the current version.
+#[inline]
+fn get_response<M: Model + Serialize>(instance: M, if_none_match: Option<&str>) -> Response {
+    let etag = instance.version().map(etag);
+
+    let mut response = match (&etag, if_none_match) {
+        (Some(etag), Some(header)) if etag_matches(header, etag, false) => {
+            StatusCode::NOT_MODIFIED.into_response()
+        }
+        _ => json(&instance).into_response(),
+    };
+
+    if let Some(value) = etag.and_then(|etag| HeaderValue::from_str(&etag).ok()) {
+        response.headers_mut().insert(ETAG, value);
+    }
+    if let Some(value) = instance
+        .last_modified()
+        .and_then(|time| HeaderValue::from_str(&http_date(time)).ok())
+    {
+        response.headers_mut().insert(LAST_MODIFIED, value);
+    }
+
+    response
+}
+
+/// The strong entity tag for a version of an instance.
+#[inline]
+fn etag(version: i32) -> String {
+    format!("\"{}\"", version)
+}
+
+/// Tell whether the list of entity tags in an `If-Match` or `If-None-Match`
+/// header matches the current entity tag.
+///
+/// Weak comparison, used for `If-None-Match`, ignores the `W/` prefix.
+#[inline]
+fn etag_matches(header: &str, etag: &str, strong: bool) -> bool {
+    header.split(',').map(str::trim).any(|tag| {
+        tag == "*" || tag == etag || (!strong && tag.strip_prefix("W/") == Some(etag))
+    })
+}
+
+/// If the client sent an `If-Match` header, make sure that the instance has
+/// not changed since the client last fetched it.
+///
+/// This should be called inside of the transaction that changes the
+/// instance, which keeps it from changing again until that's done.
+#[inline]
+async fn check_if_match<M: Model>(
+    db: &(impl Database + Send + Sync),
+    id: i32,
+    if_match: Option<String>,
+) -> Result<(), ModelError> {
+    let if_match = match if_match {
+        Some(if_match) => if_match,
+        None => return Ok(()),
+    };
+
+    match M::lock(db, id).await?.map(etag) {
+        Some(etag) if etag_matches(&if_match, &etag, true) => Ok(()),
+        None if if_match.trim() == "*" => Ok(()),
+        _ => Err(ModelError::PreconditionFailed),
+    }
 }
 
 /// Create a new instance of the model.
@@ -173,29 +357,30 @@ where
         .and(loader.clone())
         .and(warp::any().map(|| M::CREATE_PERMS))
         .and_then(|body: Bytes, db, uperms, rperms| {
-            future::ready(check_permsissions((body, db), uperms, rperms))
+            future::ready(check_permsissions((body, db, uperms), uperms, rperms))
         })
         .untuple_one()
-        .and_then(|body: Bytes, db| {
+        .and_then(|body: Bytes, db, uperms| {
             future::ready({
                 let new = serde_json::from_slice::<M::NewInstance>(&body);
                 match new {
-                    Ok(new) => Ok((new, db)),
+                    Ok(mut new) => {
+                        M::created_by(&mut new, uperms);
+                        Ok((new, db))
+                    }
                     Err(e) => Err(reject(ModelError::from(e))),
                 }
             })
         })
         .untuple_one()
         .and_then(move |new, db: Arc<_>| async move {
-            let res = M::create(&*db, new)
+            let id = M::create(&*db, new)
                 .await
-                .map_err(|e| reject(ModelError::from(e)));
-            res
-        })
-        .map(|id| {
-            let wrapper = IdWrapper { id };
-            warp::reply::with_status(json(&wrapper), StatusCode::CREATED)
+                .map_err(|e| reject(ModelError::from(e)))?;
+            let warnings = saved_warnings::<M, _>(&*db, id).await;
+            Ok::<_, warp::Rejection>(IdWrapper { id, warnings })
         })
+        .map(|wrapper: IdWrapper| warp::reply::with_status(json(&wrapper), StatusCode::CREATED))
 }
 
 /// Update the model based on a few facts.
@@ -214,27 +399,53 @@ where
         .and(loader.clone())
         .and(warp::any().map(|| M::UPDATE_PERMS))
         .and_then(|id, body: Bytes, db, uperms, rperms| {
-            future::ready(check_permsissions((id, body, db), uperms, rperms))
+            future::ready(check_permsissions((id, body, db, uperms), uperms, rperms))
         })
         .untuple_one()
-        .and_then(|id, body: Bytes, db| {
+        .and_then(|id, body: Bytes, db, uperms| {
             future::ready({
                 let changes = serde_json::from_slice::<M::UpdateInstance>(&body);
                 match changes {
-                    Ok(changes) => Ok((id, changes, db)),
+                    Ok(mut changes) => {
+                        M::updated_by(&mut changes, uperms);
+                        Ok((id, changes, db))
+                    }
                     Err(e) => Err(reject(ModelError::from(e))),
                 }
             })
         })
         .untuple_one()
-        .and_then(move |id, changes, db: Arc<_>| async move {
-            let res = M::update(&*db, id, changes)
-                .await
-                .map_err(|e| reject(ModelError::from(e)));
+        .and(warp::header::optional::<String>("if-match"))
+        .and_then(move |id, changes, db: Arc<D>, if_match| async move {
+            db.transaction(move |tx| {
+                Box::pin(async move {
+                    check_if_match::<M>(tx, id, if_match).await?;
+                    M::update(tx, id, changes).await?;
+                    Ok::<_, ModelError>(())
+                })
+            })
+            .await
+            .map_err(reject)?;
             invalidator(id);
-            res
+            Ok::<_, warp::Rejection>(saved_warnings::<M, _>(&*db, id).await)
         })
-        .map(|()| StatusCode::NO_CONTENT)
+        .map(|warnings: Vec<LintWarning>| {
+            if warnings.is_empty() {
+                StatusCode::NO_CONTENT.into_response()
+            } else {
+                json(&WarningsWrapper { warnings }).into_response()
+            }
+        })
+}
+
+/// Look for problems with an instance that was just saved. It's already
+/// been saved, so if they can't be looked for, that's only logged.
+#[inline]
+async fn saved_warnings<M: Model, D: Database + Send + Sync>(db: &D, id: i32) -> Vec<LintWarning> {
+    M::warnings(db, id).await.unwrap_or_else(|e| {
+        tracing::warn!("Unable to look for problems with instance {}: {}", id, e);
+        vec![]
+    })
 }
 
 #[inline]
@@ -243,6 +454,7 @@ fn delete_filter<M: Model, D: Database + Send + Sync + 'static, F, I>(
     invalidator: I,
 ) -> impl Filter<Extract = (impl Reply,), Error = warp::Rejection> + Clone + Send + Sync + 'static
 where
+    M::DeleteOptions: DeserializeOwned + Send + 'static,
     F: Filter<Extract = LoaderData<D>, Error = warp::Rejection> + Clone + Send + Sync + 'static,
     I: Fn(i32) + Clone + Copy + Send + Sync + 'static,
 {
@@ -250,30 +462,351 @@ where
         .and(warp::delete())
         .and(loader.clone())
         .and(warp::any().map(|| M::DELETE_PERMS))
-        .and_then(|id: i32, _, db, uperms, rperms| {
-            future::ready(check_permsissions((id, db), uperms, rperms))
+        .and_then(|id: i32, body: Bytes, db, uperms, rperms| {
+            future::ready(check_permsissions((id, body, db), uperms, rperms))
         })
         .untuple_one()
-        .and_then(move |id, db: Arc<_>| async move {
-            let res = M::delete(&*db, id)
-                .await
-                .map_err(|e| reject(ModelError::from(e)));
+        .and_then(|id, body: Bytes, db| {
+            future::ready({
+                match delete_options::<M::DeleteOptions>(&body) {
+                    Ok(options) => Ok((id, options, db)),
+                    Err(e) => Err(reject(e)),
+                }
+            })
+        })
+        .untuple_one()
+        .and(warp::header::optional::<String>("if-match"))
+        .and_then(move |id, options, db: Arc<D>, if_match| async move {
+            db.transaction(move |tx| {
+                Box::pin(async move {
+                    check_if_match::<M>(tx, id, if_match).await?;
+                    M::delete(tx, id, options).await?;
+                    Ok::<_, ModelError>(())
+                })
+            })
+            .await
+            .map_err(reject)?;
             invalidator(id);
-            res
+            Ok::<_, warp::Rejection>(())
         })
         .map(|()| StatusCode::NO_CONTENT)
 }
 
+/// Parse the options for a deletion out of the request body, which may be
+/// either JSON or URL-encoded, like the CSRF tokens that come with it.
+#[inline]
+fn delete_options<O: DeserializeOwned>(body: &[u8]) -> Result<O, ModelError> {
+    let is_json = body.iter().find(|b| !b.is_ascii_whitespace()) == Some(&b'{');
+    if is_json {
+        serde_json::from_slice(body).map_err(ModelError::from)
+    } else {
+        serde_urlencoded::from_bytes(body).map_err(ModelError::from)
+    }
+}
+
+/// Run several operations on the model at once, inside of a single
+/// transaction.
+#[inline]
+fn batch_filter<M: Model, D: Database + Send + Sync + 'static, F, I>(
+    loader: &F,
+    invalidator: I,
+) -> impl Filter<Extract = (impl Reply,), Error = warp::Rejection> + Clone + Send + Sync + 'static
+where
+    M::NewInstance: DeserializeOwned + Send + 'static,
+    M::UpdateInstance: DeserializeOwned + Send + 'static,
+    M::DeleteOptions: DeserializeOwned + Default + Send + 'static,
+    F: Filter<Extract = LoaderData<D>, Error = warp::Rejection> + Clone + Send + Sync + 'static,
+    I: Fn(i32) + Clone + Copy + Send + Sync + 'static,
+{
+    warp::path!("batch")
+        .and(warp::post())
+        .and(loader.clone())
+        .and_then(|body: Bytes, db, perms| {
+            future::ready({
+                let request = serde_json::from_slice::<
+                    BatchRequest<M::NewInstance, M::UpdateInstance, M::DeleteOptions>,
+                >(&body);
+                match request {
+                    Ok(BatchRequest { operations }) if operations.len() > MAX_BATCH_LEN => {
+                        Err(reject(ModelError::BatchTooLarge(operations.len())))
+                    }
+                    Ok(BatchRequest { operations }) => Ok((operations, db, perms)),
+                    Err(e) => Err(reject(ModelError::from(e))),
+                }
+            })
+        })
+        .untuple_one()
+        .and_then(move |operations, db: Arc<D>, perms| async move {
+            let outcome = run_batch::<M, _>(&*db, operations, perms).await;
+            if outcome.committed {
+                outcome
+                    .results
+                    .iter()
+                    .filter_map(|result| result.id)
+                    .for_each(invalidator);
+            }
+
+            Ok::<_, warp::Rejection>(outcome)
+        })
+        .map(|outcome: BatchResponse| {
+            // report the status of the operation that failed, if any
+            let status = if outcome.committed {
+                StatusCode::OK
+            } else {
+                outcome
+                    .results
+                    .last()
+                    .and_then(|result| StatusCode::from_u16(result.status).ok())
+                    .unwrap_or(StatusCode::INTERNAL_SERVER_ERROR)
+            };
+
+            warp::reply::with_status(json(&outcome), status)
+        })
+}
+
+/// Apply each of the operations in order, stopping and rolling back at the
+/// first one that fails.
+#[inline]
+async fn run_batch<M: Model, D: Database + Send + Sync>(
+    db: &D,
+    operations: Vec<BatchOperation<M::NewInstance, M::UpdateInstance, M::DeleteOptions>>,
+    perms: Permissions,
+) -> BatchResponse
+where
+    M::NewInstance: Send,
+    M::UpdateInstance: Send,
+    M::DeleteOptions: Send,
+{
+    let result = db
+        .transaction(move |tx| {
+            Box::pin(async move {
+                let mut results = Vec::with_capacity(operations.len());
+                for operation in operations {
+                    match run_operation::<M, _>(tx, operation, perms).await {
+                        Ok(result) => results.push(result),
+                        Err(error) => return Err(BatchFailure { results, error }),
+                    }
+                }
+
+                Ok(results)
+            })
+        })
+        .await;
+
+    match result {
+        Ok(results) => BatchResponse {
+            committed: true,
+            results,
+        },
+        Err(BatchFailure { mut results, error }) => {
+            tracing::event!(Level::ERROR, "Batch operation failed: {}", error);
+
+            let (status, description) = error.as_status_and_description();
+            results.push(BatchResult {
+                status: status.as_u16(),
+                id: None,
+                description: Some(description),
+                suggestion: error.suggestion().map(String::from),
+            });
+            BatchResponse {
+                committed: false,
+                results,
+            }
+        }
+    }
+}
+
+#[inline]
+async fn run_operation<M: Model, D: Database + Send + Sync>(
+    db: &D,
+    operation: BatchOperation<M::NewInstance, M::UpdateInstance, M::DeleteOptions>,
+    perms: Permissions,
+) -> Result<BatchResult, ModelError> {
+    let required = match &operation {
+        BatchOperation::Create { .. } => M::CREATE_PERMS,
+        BatchOperation::Update { .. } => M::UPDATE_PERMS,
+        BatchOperation::Delete { .. } => M::DELETE_PERMS,
+    };
+    if !required.applies_to(perms) {
+        return Err(ModelError::PermissionDenied);
+    }
+
+    let (status, id) = match operation {
+        BatchOperation::Create { mut data } => {
+            M::created_by(&mut data, perms);
+            (StatusCode::CREATED, M::create(db, data).await?)
+        }
+        BatchOperation::Update { id, mut data } => {
+            M::updated_by(&mut data, perms);
+            M::update(db, id, data).await?;
+            (StatusCode::NO_CONTENT, id)
+        }
+        BatchOperation::Delete { id, options } => {
+            M::delete(db, id, options).await?;
+            (StatusCode::NO_CONTENT, id)
+        }
+    };
+
+    Ok(BatchResult {
+        status: status.as_u16(),
+        id: Some(id),
+        description: None,
+        suggestion: None,
+    })
+}
+
+/// The most operations that can be sent in one batch.
+pub(super) const MAX_BATCH_LEN: usize = 100;
+
+#[derive(Deserialize)]
+struct BatchRequest<N, U, D> {
+    operations: Vec<BatchOperation<N, U, D>>,
+}
+
+/// A single create, update or delete in a batch.
+#[derive(Deserialize)]
+#[serde(tag = "op", rename_all = "lowercase")]
+enum BatchOperation<N, U, D> {
+    Create {
+        data: N,
+    },
+    Update {
+        id: i32,
+        data: U,
+    },
+    Delete {
+        id: i32,
+        #[serde(default)]
+        options: D,
+    },
+}
+
+/// The outcome of a batch. If it was not committed, the last result is the
+/// operation that failed, and none of the other results took effect.
+#[derive(Serialize)]
+struct BatchResponse {
+    committed: bool,
+    results: Vec<BatchResult>,
+}
+
+#[derive(Serialize)]
+struct BatchResult {
+    status: u16,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    id: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    description: Option<&'static str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    suggestion: Option<String>,
+}
+
+/// The operations that succeeded before one of them failed.
+struct BatchFailure {
+    results: Vec<BatchResult>,
+    error: ModelError,
+}
+
+impl From<DatabaseError> for BatchFailure {
+    #[inline]
+    fn from(e: DatabaseError) -> Self {
+        BatchFailure {
+            results: vec![],
+            error: ModelError::from(e),
+        }
+    }
+}
+
 #[derive(Serialize)]
 #[cfg_attr(test, derive(serde::Deserialize))]
 struct IdWrapper {
     id: i32,
+    #[serde(skip_serializing_if = "Vec::is_empty", skip_deserializing)]
+    warnings: Vec<LintWarning>,
+}
+
+/// Problems with an instance that was updated anyway.
+#[derive(Serialize)]
+struct WarningsWrapper {
+    warnings: Vec<LintWarning>,
+}
+
+/// The pagination parameters that were used to list a model.
+#[derive(Deserialize)]
+struct Pagination {
+    #[serde(default)]
+    skip: u64,
+    #[serde(default = "default_count")]
+    count: u64,
+}
+
+/// A page of instances, along with enough information to find the others.
+#[derive(Serialize)]
+#[cfg_attr(test, derive(serde::Deserialize))]
+struct ListEnvelope<T> {
+    items: Vec<T>,
+    total: u64,
+    skip: u64,
+    count: u64,
+    next: Option<String>,
+    prev: Option<String>,
+    next_cursor: Option<String>,
+}
+
+/// The query parameters that pick which page of a list is shown.
+const PAGE_KEYS: &[&str] = &["skip", "count", "cursor"];
+
+/// The request a page was listed with, used to build links to other pages.
+/// Only the filter and pagination parameters are kept from the query.
+struct PageLinks {
+    path: FullPath,
+    query: Vec<(String, String)>,
+}
+
+impl PageLinks {
+    /// Whether the page was listed using a cursor rather than an offset.
+    #[inline]
+    fn uses_cursor(&self) -> bool {
+        self.query.iter().any(|(key, _)| key == "cursor")
+    }
+
+    /// Whether the page was listed in something other than the default
+    /// order.
+    #[inline]
+    fn is_sorted(&self) -> bool {
+        self.query
+            .iter()
+            .any(|(key, value)| key == "sort" && !value.is_empty())
+    }
+
+    /// Link to the page where `key` is set to `value`.
+    #[inline]
+    fn link(&self, key: &str, value: &str) -> String {
+        let query: Vec<(&str, &str)> = self
+            .query
+            .iter()
+            .filter(|(k, _)| k != key)
+            .map(|(k, v)| (k.as_str(), v.as_str()))
+            .chain(iter::once((key, value)))
+            .collect();
+
+        format!(
+            "{}?{}",
+            self.path.as_str(),
+            serde_urlencoded::to_string(&query).unwrap_or_default()
+        )
+    }
 }
 
 #[derive(Serialize)]
-struct SerModelError {
+struct SerModelError<'a> {
     error: bool,
     description: &'static str,
+    /// Something else to try instead, e.g. a URL that isn't taken.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    suggestion: Option<&'a str>,
+    /// The field that the request got wrong, if it was only one.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    field: Option<&'a str>,
 }
 
 #[derive(Debug, thiserror::Error)]
@@ -285,9 +818,29 @@ enum ModelError {
     #[error("{0}")]
     Database(#[from] DatabaseError),
     #[error("{0}")]
+    Invalid(#[from] ValidationError),
+    #[error("{0}")]
     Csrf(#[from] csrf_integration::CsrfError),
     #[error("User is unable to access resource")]
     PermissionDenied,
+    #[error("Instance does not match the `If-Match` header")]
+    PreconditionFailed,
+    #[error("Batch of {0} operations is too large")]
+    BatchTooLarge(usize),
+    #[error("{0}")]
+    UnknownSortField(#[from] UnknownSortField),
+    #[error("Cursors can't be used with a custom sort order")]
+    CursorWithSort,
+}
+
+impl From<SaveError> for ModelError {
+    #[inline]
+    fn from(e: SaveError) -> Self {
+        match e {
+            SaveError::Database(e) => Self::Database(e),
+            SaveError::Invalid(e) => Self::Invalid(e),
+        }
+    }
 }
 
 impl ModelError {
@@ -305,30 +858,135 @@ impl ModelError {
             ModelError::Database(DatabaseError::NotFound) => {
                 (StatusCode::NOT_FOUND, "Unable to find the specified model")
             }
+            ModelError::Invalid(ValidationError::InvalidReassignment(..)) => (
+                StatusCode::BAD_REQUEST,
+                "Posts can only be reassigned to another user who hasn't been deleted",
+            ),
+            ModelError::Invalid(ValidationError::InvalidSlug(..)) => (
+                StatusCode::BAD_REQUEST,
+                "URLs can only contain lowercase letters, digits and hyphens, \
+                and can't be the same as another page",
+            ),
+            ModelError::Invalid(ValidationError::SlugTaken { .. }) => (
+                StatusCode::CONFLICT,
+                "Another blogpost is already using that URL",
+            ),
+            ModelError::Invalid(ValidationError::NoFreeSlug(..)) => (
+                StatusCode::CONFLICT,
+                "Too many blogposts are using a URL like that one, pick another",
+            ),
+            ModelError::Invalid(ValidationError::InvalidSeries(..)) => (
+                StatusCode::BAD_REQUEST,
+                "Blogposts can only be added to a series that exists",
+            ),
+            ModelError::Invalid(ValidationError::InvalidFrontMatter(..)) => (
+                StatusCode::BAD_REQUEST,
+                "Unable to read the front matter at the top of the body",
+            ),
+            ModelError::Invalid(ValidationError::MissingTitle) => (
+                StatusCode::BAD_REQUEST,
+                "Blogposts need a title, either in the request or in the front matter",
+            ),
+            ModelError::Database(DatabaseError::AlreadyExists) => (
+                StatusCode::CONFLICT,
+                "Another instance is already using one of those values",
+            ),
             ModelError::Database(..) => (
                 StatusCode::INTERNAL_SERVER_ERROR,
                 "An SQL error occurred during processing",
             ),
             ModelError::Csrf(..) => (StatusCode::BAD_REQUEST, "CSRF verification failed"),
             ModelError::PermissionDenied => (StatusCode::UNAUTHORIZED, "Permission denied"),
+            ModelError::PreconditionFailed => (
+                StatusCode::PRECONDITION_FAILED,
+                "The model has changed since it was last fetched",
+            ),
+            ModelError::BatchTooLarge(..) => (
+                StatusCode::PAYLOAD_TOO_LARGE,
+                "Too many operations in one batch",
+            ),
+            ModelError::UnknownSortField(..) => (
+                StatusCode::BAD_REQUEST,
+                "Unable to sort by a field that doesn't exist",
+            ),
+            ModelError::CursorWithSort => (
+                StatusCode::BAD_REQUEST,
+                "Cursors can only be used with the default sort order",
+            ),
+        }
+    }
+
+    /// Something else the client could try, if there is anything.
+    #[inline]
+    fn suggestion(&self) -> Option<&str> {
+        match self {
+            ModelError::Invalid(ValidationError::SlugTaken { suggestion }) => Some(suggestion),
+            _ => None,
+        }
+    }
+
+    /// The field that the request got wrong, if the error is about one.
+    #[inline]
+    fn field(&self) -> Option<&str> {
+        match self {
+            ModelError::UnknownSortField(UnknownSortField(field)) => Some(field),
+            _ => None,
         }
     }
 }
 
 impl warp::reject::Reject for ModelError {}
 
+/// Every status code and description that a model route may respond with
+/// when it fails, used to document the routes.
+#[inline]
+pub(super) fn error_responses() -> Vec<(StatusCode, &'static str)> {
+    use serde::de::Error;
+
+    [
+        ModelError::UrlEncoding(serde_urlencoded::de::Error::custom("")),
+        ModelError::Json(serde_json::Error::custom("")),
+        ModelError::Database(DatabaseError::NotFound),
+        ModelError::Invalid(ValidationError::InvalidReassignment(0)),
+        ModelError::Invalid(ValidationError::InvalidSlug(SlugError::Empty)),
+        ModelError::Invalid(ValidationError::SlugTaken {
+            suggestion: String::new(),
+        }),
+        ModelError::Invalid(ValidationError::NoFreeSlug(String::new())),
+        ModelError::Invalid(ValidationError::InvalidSeries(0)),
+        ModelError::Invalid(ValidationError::InvalidFrontMatter(
+            FrontMatterError::InvalidDate(String::new()),
+        )),
+        ModelError::Invalid(ValidationError::MissingTitle),
+        ModelError::Database(DatabaseError::AlreadyExists),
+        ModelError::Database(DatabaseError::Diesel(
+            diesel::result::Error::RollbackTransaction,
+        )),
+        ModelError::Csrf(CsrfError::VerificationFailed),
+        ModelError::PermissionDenied,
+        ModelError::PreconditionFailed,
+        ModelError::BatchTooLarge(MAX_BATCH_LEN + 1),
+        ModelError::UnknownSortField(UnknownSortField(String::new())),
+        ModelError::CursorWithSort,
+    ]
+    .iter()
+    .map(ModelError::as_status_and_description)
+    .collect()
+}
+
 #[cfg(test)]
 mod tests {
     use super::{
         create_filter, delete_filter, get_filter, list_filter, loader_filter, update_filter,
-        IdWrapper,
+        IdWrapper, ListEnvelope,
     };
     use crate::{
         auth::{
-            fake_access_token, fake_access_token_fewer_perms, initialize_auth_test, Permissions,
+            fake_access_token, fake_access_token_author, fake_access_token_fewer_perms,
+            initialize_auth_test, Permissions,
         },
         csrf_integration::{self, EncryptedCsrfPair},
-        models::{Blogpost, Model},
+        models::{Blogpost, Model, NoOptions, SaveError, User},
         query::{with_database, Database, DatabaseError},
     };
     use bytes::Bytes;
@@ -352,7 +1010,7 @@ mod tests {
         data: String,
     }
 
-    #[derive(Deserialize)]
+    #[derive(Clone, Deserialize)]
     struct DummyFilter {
         data: Option<String>,
     }
@@ -381,6 +1039,7 @@ mod tests {
         type ListFilter = DummyFilter;
         type NewInstance = NewDummy;
         type UpdateInstance = DummyChanges;
+        type DeleteOptions = NoOptions;
 
         /// Get a single instance by its ID.
         async fn get(_db: &(impl Database + Send + Sync), id: i32) -> Result<Self, DatabaseError> {
@@ -425,11 +1084,18 @@ mod tests {
                 panic!()
             }
         }
+        /// Count instances using a filter.
+        async fn count(
+            _db: &(impl Database + Send + Sync),
+            _filter: Self::ListFilter,
+        ) -> Result<u64, DatabaseError> {
+            Ok(3)
+        }
         /// Create a new instance.
         async fn create(
             _db: &(impl Database + Send + Sync),
             new: Self::NewInstance,
-        ) -> Result<i32, DatabaseError> {
+        ) -> Result<i32, SaveError> {
             assert_eq!(new.data, "create()");
             Ok(2)
         }
@@ -438,13 +1104,17 @@ mod tests {
             _db: &(impl Database + Send + Sync),
             id: i32,
             patch: Self::UpdateInstance,
-        ) -> Result<(), DatabaseError> {
+        ) -> Result<(), SaveError> {
             assert_eq!(id, 1);
             assert!(patch.data.as_deref() == Some("update()") || patch.data == None);
             Ok(())
         }
         /// Delete this instance by its ID.
-        async fn delete(_db: &(impl Database + Send + Sync), id: i32) -> Result<(), DatabaseError> {
+        async fn delete(
+            _db: &(impl Database + Send + Sync),
+            id: i32,
+            _options: Self::DeleteOptions,
+        ) -> Result<(), SaveError> {
             assert_eq!(id, 1);
             Ok(())
         }
@@ -473,10 +1143,11 @@ mod tests {
         assert_eq!(value.status(), StatusCode::OK);
 
         let value = to_bytes(value.into_body()).await.unwrap();
-        let values: Vec<Dummy> = serde_json::from_slice(&value).unwrap();
+        let values: ListEnvelope<Dummy> = serde_json::from_slice(&value).unwrap();
+        assert_eq!(values.total, 3);
 
         assert_eq!(
-            values,
+            values.items,
             vec![
                 Dummy {
                     data: "list() 1".into()
@@ -515,10 +1186,11 @@ mod tests {
         assert_eq!(value.status(), StatusCode::OK);
 
         let value = to_bytes(value.into_body()).await.unwrap();
-        let values: Vec<Dummy> = serde_json::from_slice(&value).unwrap();
+        let values: ListEnvelope<Dummy> = serde_json::from_slice(&value).unwrap();
+        assert_eq!(values.total, 3);
 
         assert_eq!(
-            values,
+            values.items,
             vec![
                 Dummy {
                     data: "list() foobar 1".into(),
@@ -556,10 +1228,11 @@ mod tests {
         assert_eq!(value.status(), StatusCode::OK);
 
         let value = to_bytes(value.into_body()).await.unwrap();
-        let values: Vec<Dummy> = serde_json::from_slice(&value).unwrap();
+        let values: ListEnvelope<Dummy> = serde_json::from_slice(&value).unwrap();
+        assert_eq!(values.total, 3);
 
         assert_eq!(
-            values,
+            values.items,
             vec![
                 Dummy {
                     data: "list() 1".into()
@@ -764,44 +1437,19 @@ mod tests {
         assert_eq!(value.status(), StatusCode::OK);
 
         let value = to_bytes(value.into_body()).await.unwrap();
-        let value: Vec<Blogpost> = serde_json::from_slice(&value).unwrap();
-        assert!(value.iter().any(|bp| bp.title == "Chasing Suns"));
-        assert!(value.iter().any(|bp| bp.title == "How to make a website"));
-    }
-
-    #[tokio::test]
-    async fn blogpost_get() {
-        csrf_integration::initialize_csrf_test();
-        initialize_auth_test();
-        let tok = fake_access_token();
-        let EncryptedCsrfPair { token, cookie } = csrf_integration::generate_csrf_pair().unwrap();
-        let model_filter = super::model::<Blogpost, _>("tbp", no_cache);
-        for (id, title) in [(1, "Chasing Suns"), (2, "How to make a website")] {
-            let value = warp::test::request()
-                .path(&format!(
-                    "/tbp/{}?csrf_token={}&csrf_cookie={}&access_token={}",
-                    id,
-                    url_encode(token.clone()),
-                    url_encode(cookie.clone()),
-                    url_encode(tok),
-                ))
-                .method("GET")
-                .header("Cookie", format!("access_token={}", tok))
-                .filter(&model_filter)
-                .await
-                .unwrap()
-                .into_response();
-
-            assert_eq!(value.status(), StatusCode::OK);
-
-            let value = to_bytes(value.into_body()).await.unwrap();
-            let value: Blogpost = serde_json::from_slice(&value).unwrap();
-            assert_eq!(value.title, title);
-        }
+        let value: ListEnvelope<Blogpost> = serde_json::from_slice(&value).unwrap();
+        assert!(value.items.iter().any(|bp| bp.title == "Chasing Suns"));
+        assert!(value
+            .items
+            .iter()
+            .any(|bp| bp.title == "How to make a website"));
+        assert_eq!(value.total, 2);
+        assert!(value.next.is_none());
+        assert!(value.prev.is_none());
     }
 
     #[tokio::test]
-    async fn blogpost_get_not_found() {
+    async fn blogpost_list_paginated() {
         csrf_integration::initialize_csrf_test();
         initialize_auth_test();
         let tok = fake_access_token();
@@ -809,19 +1457,318 @@ mod tests {
         let model_filter = super::model::<Blogpost, _>("tbp", no_cache);
         let value = warp::test::request()
             .path(&format!(
-                "/tbp/3?csrf_token={}&csrf_cookie={}&access_token={}",
+                "/tbp?count=1&csrf_token={}&csrf_cookie={}&access_token={}",
                 url_encode(token),
                 url_encode(cookie),
                 url_encode(tok)
             ))
             .method("GET")
-            .header("Cookie", format!("access_token={}", tok))
             .filter(&model_filter)
             .await
             .unwrap()
             .into_response();
 
-        assert_eq!(value.status(), StatusCode::NOT_FOUND);
+        assert_eq!(value.status(), StatusCode::OK);
+        assert_eq!(
+            value.headers()["Link"],
+            "</tbp?count=1&skip=1>; rel=\"next\""
+        );
+
+        let value = to_bytes(value.into_body()).await.unwrap();
+        let value: ListEnvelope<Blogpost> = serde_json::from_slice(&value).unwrap();
+        assert_eq!(value.items.len(), 1);
+        assert_eq!(value.total, 2);
+        assert_eq!(value.skip, 0);
+        assert_eq!(value.count, 1);
+        assert_eq!(value.next.as_deref(), Some("/tbp?count=1&skip=1"));
+        assert!(!value.next.unwrap().contains("access_token"));
+        assert!(value.prev.is_none());
+    }
+
+    #[tokio::test]
+    async fn blogpost_list_cursor() {
+        csrf_integration::initialize_csrf_test();
+        initialize_auth_test();
+        let tok = fake_access_token();
+        let EncryptedCsrfPair { token, cookie } = csrf_integration::generate_csrf_pair().unwrap();
+        let model_filter = super::model::<Blogpost, _>("tbp", no_cache);
+        let list_page = |cursor: Option<String>| {
+            let mut path = format!(
+                "/tbp?count=1&csrf_token={}&csrf_cookie={}",
+                url_encode(token.clone()),
+                url_encode(cookie.clone()),
+            );
+            if let Some(cursor) = cursor {
+                path.push_str(&format!("&cursor={}", url_encode(cursor)));
+            }
+
+            warp::test::request()
+                .path(&path)
+                .method("GET")
+                .filter(&model_filter)
+        };
+
+        let value = list_page(None).await.unwrap().into_response();
+        assert_eq!(value.status(), StatusCode::OK);
+        let value = to_bytes(value.into_body()).await.unwrap();
+        let first: ListEnvelope<Blogpost> = serde_json::from_slice(&value).unwrap();
+
+        let value = list_page(first.next_cursor.clone())
+            .await
+            .unwrap()
+            .into_response();
+        assert_eq!(value.status(), StatusCode::OK);
+        let value = to_bytes(value.into_body()).await.unwrap();
+        let second: ListEnvelope<Blogpost> = serde_json::from_slice(&value).unwrap();
+
+        assert_eq!(second.items.len(), 1);
+        assert_ne!(first.items[0].id, second.items[0].id);
+        assert!(second.prev.is_none());
+
+        // garbage cursors are rejected
+        let value = list_page(Some("garbage".into()))
+            .await
+            .unwrap()
+            .into_response();
+        assert_eq!(value.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn blogpost_list_sorted() {
+        csrf_integration::initialize_csrf_test();
+        initialize_auth_test();
+        let EncryptedCsrfPair { token, cookie } = csrf_integration::generate_csrf_pair().unwrap();
+        let model_filter = super::model::<Blogpost, _>("tbp", no_cache);
+        let list_sorted = |sort: &str| {
+            warp::test::request()
+                .path(&format!(
+                    "/tbp?sort={}&csrf_token={}&csrf_cookie={}",
+                    sort,
+                    url_encode(token.clone()),
+                    url_encode(cookie.clone()),
+                ))
+                .method("GET")
+                .filter(&model_filter)
+        };
+
+        let value = list_sorted("-id").await.unwrap().into_response();
+        assert_eq!(value.status(), StatusCode::OK);
+        let value = to_bytes(value.into_body()).await.unwrap();
+        let list: ListEnvelope<Blogpost> = serde_json::from_slice(&value).unwrap();
+        let ids: Vec<i32> = list.items.iter().map(|bp| bp.id).collect();
+        assert_eq!(ids, [2, 1]);
+
+        // sorted pages can't be followed with a cursor
+        let value = list_sorted("-id&count=1").await.unwrap().into_response();
+        assert_eq!(value.status(), StatusCode::OK);
+        let value = to_bytes(value.into_body()).await.unwrap();
+        let list: ListEnvelope<Blogpost> = serde_json::from_slice(&value).unwrap();
+        assert!(list.next.is_some());
+        assert!(list.next_cursor.is_none());
+
+        let value = list_sorted("-id&cursor=MTY0MTYwMDAwMC4wOjE")
+            .await
+            .unwrap()
+            .into_response();
+        assert_eq!(value.status(), StatusCode::BAD_REQUEST);
+
+        // unknown sort fields are rejected by name
+        let value = list_sorted("title,body").await.unwrap().into_response();
+        assert_eq!(value.status(), StatusCode::BAD_REQUEST);
+        let value = to_bytes(value.into_body()).await.unwrap();
+        let value: serde_json::Value = serde_json::from_slice(&value).unwrap();
+        assert_eq!(value["field"], "body");
+    }
+
+    #[tokio::test]
+    async fn blogpost_get() {
+        csrf_integration::initialize_csrf_test();
+        initialize_auth_test();
+        let tok = fake_access_token();
+        let EncryptedCsrfPair { token, cookie } = csrf_integration::generate_csrf_pair().unwrap();
+        let model_filter = super::model::<Blogpost, _>("tbp", no_cache);
+        for (id, title) in [(1, "Chasing Suns"), (2, "How to make a website")] {
+            let value = warp::test::request()
+                .path(&format!(
+                    "/tbp/{}?csrf_token={}&csrf_cookie={}&access_token={}",
+                    id,
+                    url_encode(token.clone()),
+                    url_encode(cookie.clone()),
+                    url_encode(tok),
+                ))
+                .method("GET")
+                .header("Cookie", format!("access_token={}", tok))
+                .filter(&model_filter)
+                .await
+                .unwrap()
+                .into_response();
+
+            assert_eq!(value.status(), StatusCode::OK);
+
+            let value = to_bytes(value.into_body()).await.unwrap();
+            let value: Blogpost = serde_json::from_slice(&value).unwrap();
+            assert_eq!(value.title, title);
+        }
+    }
+
+    #[tokio::test]
+    async fn blogpost_conditional_requests() {
+        csrf_integration::initialize_csrf_test();
+        initialize_auth_test();
+        let tok = fake_access_token();
+        let EncryptedCsrfPair { token, cookie } = csrf_integration::generate_csrf_pair().unwrap();
+        let model_filter = super::model::<Blogpost, _>("tbp", no_cache);
+        let get_path = format!(
+            "/tbp/1?csrf_token={}&csrf_cookie={}",
+            url_encode(token.clone()),
+            url_encode(cookie.clone()),
+        );
+        let patch = |if_match: &'static str| {
+            warp::test::request()
+                .path("/tbp/1")
+                .method("PATCH")
+                .body(format!(
+                    r#"{{"title":"Breaking Bones","csrf_token":"{}","csrf_cookie":"{}"}}"#,
+                    &token, &cookie
+                ))
+                .header("Cookie", format!("access_token={}", tok))
+                .header("If-Match", if_match)
+                .filter(&model_filter)
+        };
+
+        let value = warp::test::request()
+            .path(&get_path)
+            .method("GET")
+            .filter(&model_filter)
+            .await
+            .unwrap()
+            .into_response();
+        assert_eq!(value.status(), StatusCode::OK);
+        assert_eq!(value.headers()["ETag"], "\"1\"");
+
+        // the client already has this version
+        let value = warp::test::request()
+            .path(&get_path)
+            .method("GET")
+            .header("If-None-Match", "W/\"1\"")
+            .filter(&model_filter)
+            .await
+            .unwrap()
+            .into_response();
+        assert_eq!(value.status(), StatusCode::NOT_MODIFIED);
+
+        // stale versions are rejected
+        let value = patch("\"0\"").await.unwrap().into_response();
+        assert_eq!(value.status(), StatusCode::PRECONDITION_FAILED);
+
+        let value = patch("\"1\"").await.unwrap().into_response();
+        assert_eq!(value.status(), StatusCode::NO_CONTENT);
+
+        // now the version has moved on
+        let value = patch("\"1\"").await.unwrap().into_response();
+        assert_eq!(value.status(), StatusCode::PRECONDITION_FAILED);
+    }
+
+    #[tokio::test]
+    async fn blogpost_html_trust() {
+        csrf_integration::initialize_csrf_test();
+        initialize_auth_test();
+        let EncryptedCsrfPair { token, cookie } = csrf_integration::generate_csrf_pair().unwrap();
+        let model_filter = super::model::<Blogpost, _>("tbp", no_cache);
+        let iframe = r#"<iframe src="https://www.youtube.com/embed/dQw4w9WgXcQ"></iframe>"#;
+        let save = |method: &str, path: &str, tok: &str, mut body: serde_json::Value| {
+            body["csrf_token"] = token.clone().into();
+            body["csrf_cookie"] = cookie.clone().into();
+            warp::test::request()
+                .path(path)
+                .method(method)
+                .body(body.to_string())
+                .header("Cookie", format!("access_token={}", tok))
+                .filter(&model_filter)
+        };
+        let trusted = |id: i32| {
+            let path = format!(
+                "/tbp/{}?csrf_token={}&csrf_cookie={}",
+                id,
+                url_encode(token.clone()),
+                url_encode(cookie.clone()),
+            );
+            async move {
+                let value = warp::test::request()
+                    .path(&path)
+                    .method("GET")
+                    .filter(&model_filter)
+                    .await
+                    .unwrap()
+                    .into_response();
+                let value = to_bytes(value.into_body()).await.unwrap();
+                let value: serde_json::Value = serde_json::from_slice(&value).unwrap();
+                value["trusted_html"].as_bool().unwrap()
+            }
+        };
+
+        // crediting the post to an administrator doesn't make it trusted
+        let new = serde_json::json!({
+            "title": "Breaking Bones",
+            "body": iframe,
+            "author_id": 1,
+            "trusted_html": true,
+        });
+        let value = save("POST", "/tbp", fake_access_token_author(), new.clone())
+            .await
+            .unwrap()
+            .into_response();
+        assert_eq!(value.status(), StatusCode::CREATED);
+        let value = to_bytes(value.into_body()).await.unwrap();
+        let IdWrapper { id, .. } = serde_json::from_slice(&value).unwrap();
+        assert!(!trusted(id).await);
+
+        // but an administrator saving the body does
+        let value = save("POST", "/tbp", fake_access_token(), new)
+            .await
+            .unwrap()
+            .into_response();
+        let value = to_bytes(value.into_body()).await.unwrap();
+        let IdWrapper { id, .. } = serde_json::from_slice(&value).unwrap();
+        assert!(trusted(id).await);
+
+        // and a guest author changing the body of an administrator's post
+        // takes the trust away
+        let path = format!("/tbp/{}", id);
+        let change = serde_json::json!({ "title": "Mending Bones" });
+        save("PATCH", &path, fake_access_token_author(), change)
+            .await
+            .unwrap();
+        assert!(trusted(id).await);
+        let change = serde_json::json!({ "body": iframe });
+        save("PATCH", &path, fake_access_token_author(), change)
+            .await
+            .unwrap();
+        assert!(!trusted(id).await);
+    }
+
+    #[tokio::test]
+    async fn blogpost_get_not_found() {
+        csrf_integration::initialize_csrf_test();
+        initialize_auth_test();
+        let tok = fake_access_token();
+        let EncryptedCsrfPair { token, cookie } = csrf_integration::generate_csrf_pair().unwrap();
+        let model_filter = super::model::<Blogpost, _>("tbp", no_cache);
+        let value = warp::test::request()
+            .path(&format!(
+                "/tbp/3?csrf_token={}&csrf_cookie={}&access_token={}",
+                url_encode(token),
+                url_encode(cookie),
+                url_encode(tok)
+            ))
+            .method("GET")
+            .header("Cookie", format!("access_token={}", tok))
+            .filter(&model_filter)
+            .await
+            .unwrap()
+            .into_response();
+
+        assert_eq!(value.status(), StatusCode::NOT_FOUND);
 
         let value = to_bytes(value.into_body()).await.unwrap();
         let value: serde_json::Value = serde_json::from_slice(&value).unwrap();
@@ -868,7 +1815,7 @@ mod tests {
         if value.contains("error") {
             panic!("{}", value);
         }
-        let IdWrapper { id } = serde_json::from_str(&value).unwrap();
+        let IdWrapper { id, .. } = serde_json::from_str(&value).unwrap();
 
         assert_eq!(id, 3);
 
@@ -895,6 +1842,69 @@ mod tests {
         assert_eq!(value.title, "Test1");
     }
 
+    #[tokio::test]
+    async fn blogpost_create_taken_url() {
+        csrf_integration::initialize_csrf_test();
+        initialize_auth_test();
+        let tok = fake_access_token();
+        let EncryptedCsrfPair { token, cookie } = csrf_integration::generate_csrf_pair().unwrap();
+        let model_filter = super::model::<Blogpost, _>("tbp", no_cache);
+        let body = serde_json::json!({
+            "title": "Chasing Suns Again",
+            "tags": "story",
+            "url": "Chasing Suns",
+            "body": "...",
+            "author_id": 1,
+            "csrf_token": token,
+            "csrf_cookie": cookie,
+        });
+        let value = warp::test::request()
+            .path("/tbp/")
+            .method("POST")
+            .body(body.to_string())
+            .header("Cookie", format!("access_token={}", tok))
+            .filter(&model_filter)
+            .await
+            .unwrap()
+            .into_response();
+
+        assert_eq!(value.status(), StatusCode::CONFLICT);
+
+        let value = to_bytes(value.into_body()).await.unwrap();
+        let value: serde_json::Value = serde_json::from_slice(&value).unwrap();
+        assert_eq!(value["suggestion"], "chasing-suns-2");
+    }
+
+    #[tokio::test]
+    async fn blogpost_create_unknown_series() {
+        csrf_integration::initialize_csrf_test();
+        initialize_auth_test();
+        let tok = fake_access_token();
+        let EncryptedCsrfPair { token, cookie } = csrf_integration::generate_csrf_pair().unwrap();
+        let model_filter = super::model::<Blogpost, _>("tbp", no_cache);
+        let body = serde_json::json!({
+            "title": "Chasing Suns, Part 2",
+            "tags": "story",
+            "body": "...",
+            "author_id": 1,
+            "series_id": 42,
+            "series_position": 2,
+            "csrf_token": token,
+            "csrf_cookie": cookie,
+        });
+        let value = warp::test::request()
+            .path("/tbp/")
+            .method("POST")
+            .body(body.to_string())
+            .header("Cookie", format!("access_token={}", tok))
+            .filter(&model_filter)
+            .await
+            .unwrap()
+            .into_response();
+
+        assert_eq!(value.status(), StatusCode::BAD_REQUEST);
+    }
+
     #[tokio::test]
     async fn blogpost_update() {
         csrf_integration::initialize_csrf_test();
@@ -944,6 +1954,37 @@ mod tests {
         assert_eq!(value.title, "Breaking Bones")
     }
 
+    #[tokio::test]
+    async fn blogpost_update_warnings() {
+        csrf_integration::initialize_csrf_test();
+        initialize_auth_test();
+        let tok = fake_access_token();
+        let EncryptedCsrfPair { token, cookie } = csrf_integration::generate_csrf_pair().unwrap();
+        let model_filter = super::model::<Blogpost, _>("tbp", no_cache);
+        let body = serde_json::json!({
+            "body": "I broke some bones today\n\n![](/bones.png)",
+            "csrf_token": token,
+            "csrf_cookie": cookie,
+        })
+        .to_string();
+        let value = warp::test::request()
+            .path("/tbp/1")
+            .method("PATCH")
+            .body(body)
+            .header("Cookie", format!("access_token={}", tok))
+            .filter(&model_filter)
+            .await
+            .unwrap()
+            .into_response();
+
+        // the post is still saved, but with a warning
+        assert_eq!(value.status(), StatusCode::OK);
+        let value = to_bytes(value.into_body()).await.unwrap();
+        let value: serde_json::Value = serde_json::from_slice(&value).unwrap();
+        assert_eq!(value["warnings"][0]["kind"], "missing_alt_text");
+        assert_eq!(value["warnings"][0]["line"], 3);
+    }
+
     #[tokio::test]
     async fn blogpost_delete() {
         csrf_integration::initialize_csrf_test();
@@ -982,4 +2023,250 @@ mod tests {
 
         assert_eq!(value.status(), StatusCode::NOT_FOUND);
     }
+
+    #[tokio::test]
+    async fn blogpost_update_trashed() {
+        csrf_integration::initialize_csrf_test();
+        initialize_auth_test();
+        let tok = fake_access_token();
+        let EncryptedCsrfPair { token, cookie } = csrf_integration::generate_csrf_pair().unwrap();
+        let model_filter = super::model::<Blogpost, _>("tbp", no_cache);
+        let request = |method: &str, body: String| {
+            warp::test::request()
+                .path("/tbp/1")
+                .method(method)
+                .body(body)
+                .header("Cookie", format!("access_token={}", tok))
+                .filter(&model_filter)
+        };
+
+        let body = format!(
+            r#"{{"csrf_token":"{}","csrf_cookie":"{}"}}"#,
+            &token, &cookie
+        );
+        let value = request("DELETE", body).await.unwrap().into_response();
+        assert_eq!(value.status(), StatusCode::NO_CONTENT);
+
+        // posts in the trash can't be changed until they are restored
+        let body = format!(
+            r#"{{"title":"Breaking Bones","csrf_token":"{}","csrf_cookie":"{}"}}"#,
+            &token, &cookie
+        );
+        let value = request("PATCH", body).await.unwrap().into_response();
+        assert_eq!(value.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn blogpost_invalidates_only_saved_changes() {
+        use std::sync::atomic::{AtomicI32, Ordering};
+
+        static INVALIDATED: AtomicI32 = AtomicI32::new(0);
+
+        csrf_integration::initialize_csrf_test();
+        initialize_auth_test();
+        let tok = fake_access_token();
+        let EncryptedCsrfPair { token, cookie } = csrf_integration::generate_csrf_pair().unwrap();
+        let model_filter = super::model::<Blogpost, _>("tbp", |id| {
+            INVALIDATED.store(id, Ordering::SeqCst);
+        });
+        let request = |method: &str, path: &str| {
+            warp::test::request()
+                .path(path)
+                .method(method)
+                .body(format!(
+                    r#"{{"title":"Breaking Bones","csrf_token":"{}","csrf_cookie":"{}"}}"#,
+                    &token, &cookie
+                ))
+                .header("Cookie", format!("access_token={}", tok))
+                .filter(&model_filter)
+        };
+
+        for method in ["PATCH", "DELETE"] {
+            let value = request(method, "/tbp/404").await.unwrap().into_response();
+            assert_eq!(value.status(), StatusCode::NOT_FOUND);
+            assert_eq!(INVALIDATED.load(Ordering::SeqCst), 0);
+        }
+
+        let value = request("PATCH", "/tbp/2").await.unwrap().into_response();
+        assert_eq!(value.status(), StatusCode::NO_CONTENT);
+        assert_eq!(INVALIDATED.swap(0, Ordering::SeqCst), 2);
+
+        let value = request("DELETE", "/tbp/1").await.unwrap().into_response();
+        assert_eq!(value.status(), StatusCode::NO_CONTENT);
+        assert_eq!(INVALIDATED.swap(0, Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn user_delete_options() {
+        csrf_integration::initialize_csrf_test();
+        initialize_auth_test();
+        let tok = fake_access_token();
+        let model_filter = super::model::<User, _>("tu", no_cache);
+        let delete = |id: i32, options: &str| {
+            let EncryptedCsrfPair { token, cookie } =
+                csrf_integration::generate_csrf_pair().unwrap();
+            warp::test::request()
+                .path(&format!("/tu/{}", id))
+                .method("DELETE")
+                .header("Cookie", format!("access_token={}", tok))
+                .body(format!(
+                    r#"{{{}"csrf_token":"{}","csrf_cookie":"{}"}}"#,
+                    options, token, cookie
+                ))
+        };
+        let get = |id: i32| {
+            let EncryptedCsrfPair { token, cookie } =
+                csrf_integration::generate_csrf_pair().unwrap();
+            warp::test::request()
+                .path(&format!(
+                    "/tu/{}?csrf_token={}&csrf_cookie={}",
+                    id,
+                    url_encode(token),
+                    url_encode(cookie)
+                ))
+                .method("GET")
+                .header("Cookie", format!("access_token={}", tok))
+        };
+
+        // posts can't be given to the user being deleted, and reassigning
+        // needs someone to reassign them to
+        for options in [
+            r#""mode":"reassign","reassign_to":1,"#,
+            r#""mode":"reassign","#,
+        ] {
+            let value = delete(1, options)
+                .filter(&model_filter)
+                .await
+                .unwrap()
+                .into_response();
+            assert_eq!(value.status(), StatusCode::BAD_REQUEST);
+        }
+
+        // soft deletion is the default, and keeps the user around
+        let value = delete(2, "")
+            .filter(&model_filter)
+            .await
+            .unwrap()
+            .into_response();
+        assert_eq!(value.status(), StatusCode::NO_CONTENT);
+        let value = get(2).filter(&model_filter).await.unwrap().into_response();
+        assert_eq!(value.status(), StatusCode::OK);
+        let body = to_bytes(value.into_body()).await.unwrap();
+        let user: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert!(user["deleted_at"].is_string());
+
+        // deleted users can't take over anyone's posts
+        let value = delete(1, r#""mode":"reassign","reassign_to":2,"#)
+            .filter(&model_filter)
+            .await
+            .unwrap()
+            .into_response();
+        assert_eq!(value.status(), StatusCode::BAD_REQUEST);
+
+        // reassigning removes the user for good
+        let value = delete(2, r#""mode":"reassign","reassign_to":1,"#)
+            .filter(&model_filter)
+            .await
+            .unwrap()
+            .into_response();
+        assert_eq!(value.status(), StatusCode::NO_CONTENT);
+        let value = get(2).filter(&model_filter).await.unwrap().into_response();
+        assert_eq!(value.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn blogpost_batch() {
+        csrf_integration::initialize_csrf_test();
+        initialize_auth_test();
+        let tok = fake_access_token();
+        let EncryptedCsrfPair { token, cookie } = csrf_integration::generate_csrf_pair().unwrap();
+        let model_filter = super::model::<Blogpost, _>("tbp", no_cache);
+        let batch = |operations: &str| {
+            warp::test::request()
+                .path("/tbp/batch")
+                .method("POST")
+                .header("Cookie", format!("access_token={}", tok))
+                .body(format!(
+                    r#"{{"operations":{},"csrf_token":"{}","csrf_cookie":"{}"}}"#,
+                    operations, &token, &cookie
+                ))
+                .filter(&model_filter)
+        };
+
+        let value = batch(
+            r#"[
+                {"op":"create","data":{"title":"Breaking Bones","tags":"story","url":"breaking-bones","body":"Ouch.","author_id":1}},
+                {"op":"update","id":2,"data":{"tags":"tutorial"}},
+                {"op":"delete","id":1}
+            ]"#,
+        )
+        .await
+        .unwrap()
+        .into_response();
+        assert_eq!(value.status(), StatusCode::OK);
+
+        let value = to_bytes(value.into_body()).await.unwrap();
+        let value: serde_json::Value = serde_json::from_slice(&value).unwrap();
+        assert_eq!(value["committed"], true);
+        let statuses: Vec<_> = value["results"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|result| result["status"].as_u64().unwrap())
+            .collect();
+        assert_eq!(statuses, [201, 204, 204]);
+
+        // the second operation fails, so the first one is rolled back
+        let value = batch(
+            r#"[
+                {"op":"delete","id":2},
+                {"op":"update","id":1,"data":{"tags":"gone"}}
+            ]"#,
+        )
+        .await
+        .unwrap()
+        .into_response();
+        assert_eq!(value.status(), StatusCode::NOT_FOUND);
+
+        let value = to_bytes(value.into_body()).await.unwrap();
+        let value: serde_json::Value = serde_json::from_slice(&value).unwrap();
+        assert_eq!(value["committed"], false);
+        assert_eq!(value["results"][1]["status"], 404);
+
+        let value = warp::test::request()
+            .path(&format!(
+                "/tbp/2?csrf_token={}&csrf_cookie={}",
+                url_encode(token.clone()),
+                url_encode(cookie.clone()),
+            ))
+            .method("GET")
+            .filter(&model_filter)
+            .await
+            .unwrap()
+            .into_response();
+        assert_eq!(value.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn blogpost_batch_checks_permissions() {
+        csrf_integration::initialize_csrf_test();
+        initialize_auth_test();
+        let tok = fake_access_token_fewer_perms();
+        let EncryptedCsrfPair { token, cookie } = csrf_integration::generate_csrf_pair().unwrap();
+        let model_filter = super::model::<Blogpost, _>("tbp", no_cache);
+        let value = warp::test::request()
+            .path("/tbp/batch")
+            .method("POST")
+            .header("Cookie", format!("access_token={}", tok))
+            .body(format!(
+                r#"{{"operations":[{{"op":"delete","id":1}}],"csrf_token":"{}","csrf_cookie":"{}"}}"#,
+                &token, &cookie
+            ))
+            .filter(&model_filter)
+            .await
+            .unwrap()
+            .into_response();
+
+        assert_eq!(value.status(), StatusCode::UNAUTHORIZED);
+    }
 }
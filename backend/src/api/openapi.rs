@@ -0,0 +1,1238 @@
+// GNU AGPL v3 License
+
+use super::{
+    model::{error_responses, MAX_BATCH_LEN},
+    BLOGPOST, ROUTES, SERIES, USER,
+};
+use crate::{
+    auth::Permissions,
+    models::{Blogpost, Model, NewUrlRedirect, Series, UrlRedirect, User},
+};
+use once_cell::sync::Lazy;
+use schemars::{gen::SchemaSettings, JsonSchema};
+use serde_json::{json, Map, Value};
+use std::collections::BTreeMap;
+use warp::{reply::json as reply_json, Filter, Rejection, Reply};
+
+/// The OpenAPI document describing every route under `/api`.
+static SPEC: Lazy<Value> = Lazy::new(spec);
+
+/// Serve the OpenAPI document at `/api/openapi.json`.
+#[inline]
+pub fn openapi(
+) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone + Send + Sync + 'static {
+    warp::path!("openapi.json")
+        .and(warp::get())
+        .map(|| reply_json(&*SPEC))
+}
+
+/// Build the OpenAPI document from the models and `ROUTES` that `api()`
+/// serves.
+#[inline]
+fn spec() -> Value {
+    let mut spec = ApiSpec::new()
+        .model::<User>(USER)
+        .model::<Blogpost>(BLOGPOST)
+        .model::<Series>(SERIES)
+        .output_schema::<UrlRedirect>()
+        .schema::<NewUrlRedirect>();
+
+    for path in ROUTES {
+        if let Some(item) = path_item(path) {
+            spec = spec.path(path, item);
+        }
+    }
+
+    spec.build()
+}
+
+/// Describe one of the routes in `ROUTES`.
+#[inline]
+fn path_item(path: &str) -> Option<Value> {
+    Some(match path {
+        "/username" => username_path(),
+        "/image" => image_path(),
+        "/upload/presign" => presign_path(),
+        "/upload/complete" => complete_path(),
+        "/trash" => trash_path(),
+        "/trash/{id}" => purge_path(),
+        "/trash/{id}/restore" => restore_path(),
+        "/redirects" => redirects_path(),
+        "/redirects/{id}" => redirect_path(),
+        "/preview" => preview_path(),
+        "/blogpost/{id}/lint" => lint_path(),
+        "/openapi.json" => openapi_path(),
+        _ => return None,
+    })
+}
+
+/// The JSON schema for a type, derived from how serde reads and writes it.
+#[inline]
+fn schema_for<S: JsonSchema>() -> Value {
+    let settings = SchemaSettings::openapi3().with(|s| s.inline_subschemas = true);
+    let schema = settings.into_generator().subschema_for::<S>();
+    let mut schema = serde_json::to_value(schema).expect("schemas are valid JSON");
+    single_example(&mut schema);
+    schema
+}
+
+/// The JSON schema for a type that's sent back in responses, which always
+/// has every property, even the ones that are `null`.
+#[inline]
+fn output_schema_for<S: JsonSchema>() -> Value {
+    let mut schema = schema_for::<S>();
+    if let Some(properties) = schema["properties"].as_object() {
+        let required: Vec<String> = properties.keys().cloned().collect();
+        schema["required"] = json!(required);
+    }
+    schema
+}
+
+/// OpenAPI 3.0 gives each schema a single `example`, where JSON Schema has
+/// a list of `examples`.
+#[inline]
+fn single_example(schema: &mut Value) {
+    match schema {
+        Value::Object(schema) => {
+            if let Some(Value::Array(mut examples)) = schema.remove("examples") {
+                if !examples.is_empty() {
+                    schema.insert("example".into(), examples.swap_remove(0));
+                }
+            }
+            schema.values_mut().for_each(single_example);
+        }
+        Value::Array(schemas) => schemas.iter_mut().for_each(single_example),
+        _ => {}
+    }
+}
+
+/// An OpenAPI document that is being built up route by route.
+struct ApiSpec {
+    paths: Map<String, Value>,
+    schemas: Map<String, Value>,
+}
+
+impl ApiSpec {
+    #[inline]
+    fn new() -> Self {
+        let mut schemas = Map::new();
+        schemas.insert("Error".into(), error_schema());
+        schemas.insert("Id".into(), id_schema());
+        schemas.insert("CsrfTokens".into(), csrf_schema());
+        schemas.insert("LintReport".into(), lint_report_schema());
+
+        Self {
+            paths: Map::new(),
+            schemas,
+        }
+    }
+
+    /// Describe the routes that `model::model::<M, _>(name, ..)` creates.
+    #[inline]
+    fn model<M: Model + JsonSchema>(mut self, name: &str) -> Self
+    where
+        M::ListFilter: JsonSchema,
+        M::NewInstance: JsonSchema,
+        M::UpdateInstance: JsonSchema,
+        M::DeleteOptions: JsonSchema,
+    {
+        let model = M::schema_name();
+        let new = M::NewInstance::schema_name();
+        let update = M::UpdateInstance::schema_name();
+        let options = M::DeleteOptions::schema_name();
+
+        self.schemas.extend([
+            (model.clone(), output_schema_for::<M>()),
+            (new.clone(), schema_for::<M::NewInstance>()),
+            (update.clone(), schema_for::<M::UpdateInstance>()),
+            (options.clone(), schema_for::<M::DeleteOptions>()),
+        ]);
+
+        let list = operation(
+            format!("List {} instances", name),
+            M::LIST_PERMS,
+            json!({
+                "parameters": query_parameters(&schema_for::<M::ListFilter>()),
+                "responses": with_errors(json!({
+                    "200": {
+                        "description": "A page of instances",
+                        "headers": {
+                            "Link": {
+                                "description": "Links to the next and previous pages",
+                                "schema": { "type": "string" },
+                            },
+                        },
+                        "content": json_content(list_envelope_schema(&model)),
+                    },
+                })),
+            }),
+        );
+        let create = operation(
+            format!("Create a new {}", name),
+            M::CREATE_PERMS,
+            json!({
+                "requestBody": with_csrf_body(&new),
+                "responses": with_errors(json!({
+                    "201": {
+                        "description": "The ID of the new instance, and any problems with it",
+                        "content": json_content(json!({
+                            "allOf": [schema_ref("Id"), schema_ref("LintReport")],
+                        })),
+                    },
+                })),
+            }),
+        );
+        let get = operation(
+            format!("Get a {} by its ID", name),
+            M::GET_PERMS,
+            json!({
+                "parameters": with_header(csrf_parameters(), "If-None-Match"),
+                "responses": with_errors(json!({
+                    "200": {
+                        "description": "The instance",
+                        "headers": {
+                            "ETag": etag_header(),
+                            "Last-Modified": {
+                                "description": "When the instance was last updated",
+                                "schema": { "type": "string" },
+                            },
+                        },
+                        "content": json_content(schema_ref(&model)),
+                    },
+                    "304": {
+                        "description": "The instance matches the `If-None-Match` header",
+                        "headers": { "ETag": etag_header() },
+                    },
+                })),
+            }),
+        );
+        let update = operation(
+            format!("Update a {}", name),
+            M::UPDATE_PERMS,
+            json!({
+                "parameters": with_header(json!([]), "If-Match"),
+                "requestBody": with_csrf_body(&update),
+                "responses": with_errors(json!({
+                    "200": {
+                        "description": "The instance was updated, but there are problems with it",
+                        "content": json_content(schema_ref("LintReport")),
+                    },
+                    "204": { "description": "The instance was updated" },
+                })),
+            }),
+        );
+        let delete = operation(
+            format!("Delete a {}", name),
+            M::DELETE_PERMS,
+            json!({
+                "parameters": with_header(json!([]), "If-Match"),
+                "requestBody": with_csrf_body(&options),
+                "responses": with_errors(json!({
+                    "204": { "description": "The instance was deleted" },
+                })),
+            }),
+        );
+
+        let batch = json!({
+            "summary": format!("Create, update or delete several {} instances at once", name),
+            "description": "The operations run in a single transaction, and each one \
+                requires the same permissions as the matching single-instance route. \
+                If any operation fails, none of them take effect, and the response has \
+                the status of the failed operation.",
+            "security": [{ "accessToken": [] }],
+            "requestBody": {
+                "required": true,
+                "content": json_content(json!({
+                    "allOf": [
+                        batch_request_schema(&new, &update, &options),
+                        schema_ref("CsrfTokens"),
+                    ],
+                })),
+            },
+            "responses": with_errors(json!({
+                "200": {
+                    "description": "Every operation succeeded",
+                    "content": json_content(batch_response_schema()),
+                },
+            })),
+        });
+
+        self.path(&format!("/{}", name), json!({ "get": list, "post": create }))
+            .path(&format!("/{}/batch", name), json!({ "post": batch }))
+            .path(
+                &format!("/{}/{{id}}", name),
+                json!({
+                    "parameters": [id_parameter()],
+                    "get": get,
+                    "patch": update,
+                    "delete": delete,
+                }),
+            )
+    }
+
+    #[inline]
+    fn path(mut self, path: &str, item: Value) -> Self {
+        self.paths.insert(path.into(), item);
+        self
+    }
+
+    /// Describe a type that isn't part of a model, for other routes to
+    /// refer to.
+    #[inline]
+    fn schema<S: JsonSchema>(mut self) -> Self {
+        let schema = schema_for::<S>();
+        self.schemas.insert(S::schema_name(), schema);
+        self
+    }
+
+    /// Like `schema`, but for a type that's only sent back in responses.
+    #[inline]
+    fn output_schema<S: JsonSchema>(mut self) -> Self {
+        let schema = output_schema_for::<S>();
+        self.schemas.insert(S::schema_name(), schema);
+        self
+    }
+
+    #[inline]
+    fn build(self) -> Value {
+        json!({
+            "openapi": "3.0.3",
+            "info": {
+                "title": "Blog API",
+                "version": env!("CARGO_PKG_VERSION"),
+            },
+            "servers": [{ "url": "/api" }],
+            "paths": self.paths,
+            "components": {
+                "schemas": self.schemas,
+                "securitySchemes": {
+                    "accessToken": {
+                        "type": "apiKey",
+                        "in": "cookie",
+                        "name": "access_token",
+                    },
+                },
+            },
+        })
+    }
+}
+
+/// Build an operation that requires the given permissions.
+#[inline]
+fn operation(summary: String, perms: Permissions, mut op: Value) -> Value {
+    op["summary"] = json!(summary);
+    op["x-required-permissions"] = json!(perms);
+    op["security"] = if perms.0 == 0 {
+        json!([])
+    } else {
+        json!([{ "accessToken": [] }])
+    };
+    op
+}
+
+/// Add the error responses shared by every model route, grouping the
+/// descriptions by status code.
+#[inline]
+fn with_errors(mut responses: Value) -> Value {
+    let mut descriptions: BTreeMap<u16, Vec<&'static str>> = BTreeMap::new();
+    for (status, description) in error_responses() {
+        descriptions
+            .entry(status.as_u16())
+            .or_default()
+            .push(description);
+    }
+
+    for (status, descriptions) in descriptions {
+        responses[status.to_string()] = error_response(&descriptions.join("; "));
+    }
+
+    responses
+}
+
+#[inline]
+fn error_response(description: &str) -> Value {
+    json!({
+        "description": description,
+        "content": json_content(schema_ref("Error")),
+    })
+}
+
+#[inline]
+fn json_content(schema: Value) -> Value {
+    json!({ "application/json": { "schema": schema } })
+}
+
+#[inline]
+fn schema_ref(name: &str) -> Value {
+    json!({ "$ref": format!("#/components/schemas/{}", name) })
+}
+
+/// A JSON request body that also carries the CSRF tokens.
+#[inline]
+fn with_csrf_body(name: &str) -> Value {
+    let schema = if name == "CsrfTokens" {
+        schema_ref(name)
+    } else {
+        json!({ "allOf": [schema_ref(name), schema_ref("CsrfTokens")] })
+    };
+
+    json!({ "required": true, "content": json_content(schema) })
+}
+
+/// Turn each property of an object schema into an optional query parameter.
+#[inline]
+fn query_parameters(schema: &Value) -> Value {
+    let mut parameters = csrf_parameters();
+    if let (Some(parameters), Some(properties)) = (
+        parameters.as_array_mut(),
+        schema["properties"].as_object(),
+    ) {
+        parameters.extend(properties.iter().map(|(name, schema)| {
+            json!({
+                "name": name,
+                "in": "query",
+                "required": false,
+                "schema": schema,
+            })
+        }));
+    }
+    parameters
+}
+
+/// GET requests carry their CSRF tokens in the query string.
+#[inline]
+fn csrf_parameters() -> Value {
+    json!([
+        { "name": "csrf_token", "in": "query", "required": true, "schema": { "type": "string" } },
+        { "name": "csrf_cookie", "in": "query", "required": true, "schema": { "type": "string" } },
+    ])
+}
+
+/// Add an optional header containing entity tags to a list of parameters.
+#[inline]
+fn with_header(mut parameters: Value, name: &str) -> Value {
+    if let Some(parameters) = parameters.as_array_mut() {
+        parameters.push(json!({
+            "name": name,
+            "in": "header",
+            "required": false,
+            "description": "Comma-separated list of entity tags",
+            "schema": { "type": "string" },
+        }));
+    }
+    parameters
+}
+
+#[inline]
+fn etag_header() -> Value {
+    json!({
+        "description": "Changes every time the instance is updated",
+        "schema": { "type": "string" },
+    })
+}
+
+#[inline]
+fn list_envelope_schema(name: &str) -> Value {
+    object(
+        [
+            ("items", json!({ "type": "array", "items": schema_ref(name) })),
+            ("total", integer("int64")),
+            ("skip", integer("int64")),
+            ("count", integer("int64")),
+            ("next", nullable(string())),
+            ("prev", nullable(string())),
+            ("next_cursor", nullable(string())),
+        ],
+        &["items", "total", "skip", "count"],
+    )
+}
+
+#[inline]
+fn batch_request_schema(
+    new_instance: &str,
+    update_instance: &str,
+    delete_options: &str,
+) -> Value {
+    let op = |name: &str| json!({ "type": "string", "enum": [name] });
+    let id = || integer("int32");
+
+    object(
+        [(
+            "operations",
+            json!({
+                "type": "array",
+                "maxItems": MAX_BATCH_LEN,
+                "items": {
+                    "oneOf": [
+                        object(
+                            [("op", op("create")), ("data", schema_ref(new_instance))],
+                            &["op", "data"],
+                        ),
+                        object(
+                            [
+                                ("op", op("update")),
+                                ("id", id()),
+                                ("data", schema_ref(update_instance)),
+                            ],
+                            &["op", "id", "data"],
+                        ),
+                        object(
+                            [
+                                ("op", op("delete")),
+                                ("id", id()),
+                                ("options", schema_ref(delete_options)),
+                            ],
+                            &["op", "id"],
+                        ),
+                    ],
+                },
+            }),
+        )],
+        &["operations"],
+    )
+}
+
+#[inline]
+fn batch_response_schema() -> Value {
+    let result = object(
+        [
+            ("status", integer("int32")),
+            ("id", integer("int32")),
+            ("description", string()),
+            ("suggestion", string()),
+        ],
+        &["status"],
+    );
+
+    object(
+        [
+            ("committed", json!({ "type": "boolean" })),
+            ("results", json!({ "type": "array", "items": result })),
+        ],
+        &["committed", "results"],
+    )
+}
+
+#[inline]
+fn error_schema() -> Value {
+    object(
+        [
+            ("error", json!({ "type": "boolean" })),
+            ("description", string()),
+            (
+                "suggestion",
+                json!({
+                    "type": "string",
+                    "description": "Something else to try instead, e.g. a URL that isn't taken",
+                }),
+            ),
+            (
+                "field",
+                json!({
+                    "type": "string",
+                    "description": "The field that the request got wrong, e.g. an unknown sort field",
+                }),
+            ),
+        ],
+        &["error", "description"],
+    )
+}
+
+#[inline]
+fn id_schema() -> Value {
+    object([("id", integer("int32"))], &["id"])
+}
+
+/// Problems with a blogpost that don't stop it from being saved. Other
+/// models never have any.
+#[inline]
+fn lint_report_schema() -> Value {
+    let warning = object(
+        [
+            (
+                "kind",
+                json!({
+                    "type": "string",
+                    "enum": [
+                        "broken_link",
+                        "missing_media",
+                        "missing_alt_text",
+                        "skipped_heading_level",
+                        "unclosed_fence",
+                        "broken_external_link",
+                    ],
+                }),
+            ),
+            ("line", integer("int32")),
+            ("message", string()),
+        ],
+        &["kind", "line", "message"],
+    );
+    object(
+        [("warnings", json!({ "type": "array", "items": warning }))],
+        &[],
+    )
+}
+
+#[inline]
+fn csrf_schema() -> Value {
+    object(
+        [("csrf_token", string()), ("csrf_cookie", string())],
+        &["csrf_token", "csrf_cookie"],
+    )
+}
+
+#[inline]
+fn username_path() -> Value {
+    json!({
+        "post": {
+            "summary": "Set the name of the current user",
+            "security": [{ "accessToken": [] }],
+            "requestBody": {
+                "required": true,
+                "content": json_content(json!({
+                    "allOf": [
+                        object([("username", string())], &["username"]),
+                        schema_ref("CsrfTokens"),
+                    ],
+                })),
+            },
+            "responses": {
+                "200": {
+                    "description": "The name was set",
+                    "content": json_content(schema_ref("Id")),
+                },
+                "400": error_response("CSRF verification failed; JSON deserialization failed"),
+                "404": error_response("Unable to find user"),
+                "500": error_response("A database error occurred"),
+            },
+        },
+    })
+}
+
+#[inline]
+fn image_path() -> Value {
+    json!({
+        "post": {
+            "summary": "Upload an image through the server",
+            "x-required-permissions": Permissions::AUTHOR,
+            "security": [{ "accessToken": [] }],
+            "requestBody": {
+                "required": true,
+                "content": {
+                    "multipart/form-data": {
+                        "schema": object(
+                            [
+                                ("category", string()),
+                                ("subcategory", string()),
+                                ("filename", string()),
+                                ("content_type", string()),
+                                ("data", json!({ "type": "string", "format": "binary" })),
+                                ("csrf_token", string()),
+                                ("csrf_cookie", string()),
+                            ],
+                            &[
+                                "category",
+                                "subcategory",
+                                "filename",
+                                "content_type",
+                                "data",
+                                "csrf_token",
+                                "csrf_cookie",
+                            ],
+                        ),
+                    },
+                },
+            },
+            "responses": {
+                "200": {
+                    "description": "The URL of the uploaded image",
+                    "content": json_content(object([("url", string())], &["url"])),
+                },
+                "400": error_response("The form data was incomplete or invalid"),
+                "401": error_response("Permission denied"),
+                "500": error_response("An error occurred while uploading to storage"),
+            },
+        },
+    })
+}
+
+#[inline]
+fn presign_path() -> Value {
+    json!({
+        "post": {
+            "summary": "Get a presigned URL to upload a file directly to storage",
+            "x-required-permissions": Permissions::AUTHOR,
+            "security": [{ "accessToken": [] }],
+            "requestBody": {
+                "required": true,
+                "content": json_content(json!({
+                    "allOf": [
+                        object(
+                            [
+                                ("category", string()),
+                                ("subcategory", string()),
+                                ("filename", string()),
+                                ("content_type", string()),
+                                ("size", integer("int64")),
+                            ],
+                            &["category", "subcategory", "filename", "content_type", "size"],
+                        ),
+                        schema_ref("CsrfTokens"),
+                    ],
+                })),
+            },
+            "responses": {
+                "200": {
+                    "description": "The URL to PUT the file to",
+                    "content": json_content(object(
+                        [
+                            ("url", string()),
+                            ("path", string()),
+                            ("expires_in", integer("int64")),
+                            ("max_size", integer("int64")),
+                        ],
+                        &["url", "path", "expires_in", "max_size"],
+                    )),
+                },
+                "400": error_response(
+                    "CSRF failure; Unable to parse JSON-encoded request body; \
+                    Upload categories and filenames cannot be empty or contain slashes, \
+                    \"..\" or control characters",
+                ),
+                "401": error_response("Permission denied"),
+                "409": error_response("A file has already been uploaded to that path"),
+                "413": error_response("File is empty or exceeds the maximum upload size"),
+                "500": error_response(
+                    "An error occurred while presigning the upload; \
+                    An SQL error occurred while recording the upload",
+                ),
+            },
+        },
+    })
+}
+
+#[inline]
+fn complete_path() -> Value {
+    json!({
+        "post": {
+            "summary": "Record a finished presigned upload",
+            "x-required-permissions": Permissions::AUTHOR,
+            "security": [{ "accessToken": [] }],
+            "requestBody": {
+                "required": true,
+                "content": json_content(json!({
+                    "allOf": [
+                        object([("path", string())], &["path"]),
+                        schema_ref("CsrfTokens"),
+                    ],
+                })),
+            },
+            "responses": {
+                "201": {
+                    "description": "The ID of the recorded upload",
+                    "content": json_content(schema_ref("Id")),
+                },
+                "400": error_response(
+                    "CSRF failure; Unable to find the uploaded file in storage; \
+                    The uploaded file does not have the presigned size or content type",
+                ),
+                "401": error_response("Permission denied"),
+                "404": error_response("No pending upload was found for the given path"),
+                "409": error_response("A file has already been uploaded to that path"),
+                "500": error_response("An SQL error occurred while recording the upload"),
+            },
+        },
+    })
+}
+
+#[inline]
+fn trash_path() -> Value {
+    json!({
+        "get": {
+            "summary": "List the blogposts in the trash",
+            "x-required-permissions": Permissions::ADMIN,
+            "security": [{ "accessToken": [] }],
+            "parameters": query_parameters(&object(pagination(), &[])),
+            "responses": {
+                "200": {
+                    "description": "A page of trashed blogposts",
+                    "content": json_content(object(
+                        [
+                            (
+                                "items",
+                                json!({ "type": "array", "items": schema_ref("Blogpost") }),
+                            ),
+                            ("total", integer("int64")),
+                        ],
+                        &["items", "total"],
+                    )),
+                },
+                "400": error_response("CSRF failure; Unable to parse URL-encoded query parameters"),
+                "401": error_response("Permission denied"),
+                "500": error_response("An SQL error occurred while managing the trash"),
+            },
+        },
+    })
+}
+
+#[inline]
+fn purge_path() -> Value {
+    json!({
+        "parameters": [id_parameter()],
+        "delete": trash_operation(
+            "Permanently delete a blogpost in the trash",
+            "The blogpost was deleted",
+        ),
+    })
+}
+
+#[inline]
+fn restore_path() -> Value {
+    json!({
+        "parameters": [id_parameter()],
+        "post": trash_operation(
+            "Take a blogpost back out of the trash",
+            "The blogpost was restored",
+        ),
+    })
+}
+
+#[inline]
+fn trash_operation(summary: &str, success: &str) -> Value {
+    json!({
+        "summary": summary,
+        "x-required-permissions": Permissions::ADMIN,
+        "security": [{ "accessToken": [] }],
+        "requestBody": with_csrf_body("CsrfTokens"),
+        "responses": {
+            "204": { "description": success },
+            "400": error_response("CSRF failure"),
+            "401": error_response("Permission denied"),
+            "404": error_response("Unable to find the blogpost in the trash"),
+            "500": error_response("An SQL error occurred while managing the trash"),
+        },
+    })
+}
+
+#[inline]
+fn redirects_path() -> Value {
+    json!({
+        "get": {
+            "summary": "List every redirect",
+            "x-required-permissions": Permissions::ADMIN,
+            "security": [{ "accessToken": [] }],
+            "parameters": csrf_parameters(),
+            "responses": {
+                "200": {
+                    "description": "The redirects, newest first",
+                    "content": json_content(json!({
+                        "type": "array",
+                        "items": schema_ref("UrlRedirect"),
+                    })),
+                },
+                "400": error_response("CSRF failure"),
+                "401": error_response("Permission denied"),
+                "500": error_response("An SQL error occurred while managing redirects"),
+            },
+        },
+        "post": {
+            "summary": "Redirect a path somewhere else",
+            "x-required-permissions": Permissions::ADMIN,
+            "security": [{ "accessToken": [] }],
+            "requestBody": with_csrf_body("NewUrlRedirect"),
+            "responses": {
+                "201": {
+                    "description": "The ID of the new redirect",
+                    "content": json_content(schema_ref("Id")),
+                },
+                "400": error_response(
+                    "CSRF failure; Unable to parse JSON body; \
+                    Redirects must start from a path on this site; \
+                    Redirects must lead to a path or URL; \
+                    Redirects can't lead back to themselves",
+                ),
+                "401": error_response("Permission denied"),
+                "409": error_response("There is already a redirect from that path"),
+                "500": error_response("An SQL error occurred while managing redirects"),
+            },
+        },
+    })
+}
+
+#[inline]
+fn redirect_path() -> Value {
+    json!({
+        "parameters": [id_parameter()],
+        "delete": {
+            "summary": "Delete a redirect",
+            "x-required-permissions": Permissions::ADMIN,
+            "security": [{ "accessToken": [] }],
+            "requestBody": with_csrf_body("CsrfTokens"),
+            "responses": {
+                "204": { "description": "The redirect was deleted" },
+                "400": error_response("CSRF failure"),
+                "401": error_response("Permission denied"),
+                "404": error_response("Unable to find the redirect"),
+                "500": error_response("An SQL error occurred while managing redirects"),
+            },
+        },
+    })
+}
+
+#[inline]
+fn preview_path() -> Value {
+    json!({
+        "post": {
+            "summary": "Render markdown into the page that it would be as a blogpost",
+            "description": "The title and tags fall back to the front matter. \
+                Each author can ask for 60 previews a minute.",
+            "x-required-permissions": Permissions::AUTHOR,
+            "security": [{ "accessToken": [] }],
+            "requestBody": {
+                "required": true,
+                "content": json_content(json!({
+                    "allOf": [
+                        object(
+                            [("title", string()), ("tags", string()), ("body", string())],
+                            &["body"],
+                        ),
+                        schema_ref("CsrfTokens"),
+                    ],
+                })),
+            },
+            "responses": {
+                "200": {
+                    "description": "The rendered page",
+                    "content": { "text/html": { "schema": string() } },
+                },
+                "400": error_response(
+                    "CSRF failure; Unable to parse JSON body; Unable to read the front matter",
+                ),
+                "401": error_response("Permission denied"),
+                "429": error_response("Too many previews, try again in a minute"),
+                "500": error_response(
+                    "An SQL error occurred while looking up linked posts; \
+                    Unable to render the preview",
+                ),
+            },
+        },
+    })
+}
+
+#[inline]
+fn lint_path() -> Value {
+    let mut parameters = csrf_parameters();
+    if let Some(parameters) = parameters.as_array_mut() {
+        parameters.push(json!({
+            "name": "external",
+            "in": "query",
+            "required": false,
+            "description": "Also follow links to other sites",
+            "schema": { "type": "boolean" },
+        }));
+    }
+
+    json!({
+        "parameters": [id_parameter()],
+        "get": {
+            "summary": "Check a blogpost for broken links and other mistakes",
+            "x-required-permissions": Permissions::AUTHOR,
+            "security": [{ "accessToken": [] }],
+            "parameters": parameters,
+            "responses": {
+                "200": {
+                    "description": "The problems with the blogpost, in the order they appear",
+                    "content": json_content(schema_ref("LintReport")),
+                },
+                "400": error_response("CSRF failure; Unable to parse query"),
+                "401": error_response("Permission denied"),
+                "404": error_response("Unable to find the blogpost"),
+                "500": error_response("An SQL error occurred while linting the blogpost"),
+            },
+        },
+    })
+}
+
+#[inline]
+fn id_parameter() -> Value {
+    json!({
+        "name": "id",
+        "in": "path",
+        "required": true,
+        "schema": { "type": "integer", "format": "int32" },
+    })
+}
+
+#[inline]
+fn openapi_path() -> Value {
+    json!({
+        "get": {
+            "summary": "This document",
+            "security": [],
+            "responses": {
+                "200": {
+                    "description": "The OpenAPI document for the API",
+                    "content": json_content(json!({ "type": "object" })),
+                },
+            },
+        },
+    })
+}
+
+#[inline]
+fn object<const N: usize>(properties: [(&str, Value); N], required: &[&str]) -> Value {
+    let properties: Map<String, Value> = properties
+        .into_iter()
+        .map(|(name, schema)| (name.to_string(), schema))
+        .collect();
+
+    let mut schema = json!({ "type": "object", "properties": properties });
+    if !required.is_empty() {
+        schema["required"] = json!(required);
+    }
+    schema
+}
+
+#[inline]
+fn string() -> Value {
+    json!({ "type": "string" })
+}
+
+#[inline]
+fn integer(format: &str) -> Value {
+    json!({ "type": "integer", "format": format })
+}
+
+#[inline]
+fn nullable(mut schema: Value) -> Value {
+    schema["nullable"] = json!(true);
+    schema
+}
+
+#[inline]
+fn pagination() -> [(&'static str, Value); 2] {
+    [
+        ("skip", json!({ "type": "integer", "format": "int64", "default": 0 })),
+        ("count", json!({ "type": "integer", "format": "int64", "default": 25 })),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{schema_for, spec, BLOGPOST, ROUTES, SERIES, USER};
+    use crate::{
+        auth::initialize_auth_test,
+        csrf_integration,
+        models::{
+            Blogpost, BlogpostChange, BlogpostFilter, NewBlogpost, NewSeries, NewUrlRedirect,
+            NewUser, NoOptions, Series, SeriesChange, SeriesFilter, UrlRedirect, User, UserChange,
+            UserDeletion, UserFilter,
+        },
+        mock_database::MockDatabase,
+        query::Database,
+    };
+    use schemars::JsonSchema;
+    use serde::{de::DeserializeOwned, Serialize};
+    use serde_json::{Map, Value};
+    use std::collections::BTreeSet;
+    use warp::{hyper::body::to_bytes, Reply};
+
+    const METHODS: [&str; 5] = ["GET", "POST", "PUT", "PATCH", "DELETE"];
+
+    /// The paths of the routes that `api()` serves.
+    #[inline]
+    fn routes() -> BTreeSet<String> {
+        let mut routes: BTreeSet<String> = ROUTES.iter().map(|path| path.to_string()).collect();
+        for name in [USER, BLOGPOST, SERIES] {
+            routes.insert(format!("/{}", name));
+            routes.insert(format!("/{}/batch", name));
+            routes.insert(format!("/{}/{{id}}", name));
+        }
+        routes
+    }
+
+    /// The document describes every route and nothing else, and on each
+    /// path, exactly the methods that are routed.
+    #[tokio::test]
+    async fn spec_matches_routes() {
+        csrf_integration::initialize_csrf_test();
+        initialize_auth_test();
+        let spec = spec();
+        let api = super::super::api();
+
+        let documented: BTreeSet<String> =
+            spec["paths"].as_object().unwrap().keys().cloned().collect();
+        assert_eq!(documented, routes());
+
+        for route in routes() {
+            let item = &spec["paths"][&route];
+            let path = format!("/api{}", route.replace("{id}", "1"));
+            for method in METHODS {
+                let documented = item.get(method.to_lowercase()).is_some();
+                let response = warp::test::request()
+                    .path(&path)
+                    .method(method)
+                    .filter(&api)
+                    .await
+                    .unwrap()
+                    .into_response();
+                let body = to_bytes(response.into_body()).await.unwrap();
+                let routed = !String::from_utf8_lossy(&body).contains("No route found");
+
+                assert_eq!(
+                    documented, routed,
+                    "{} {} is {}documented, but is {}routed",
+                    method,
+                    path,
+                    if documented { "" } else { "not " },
+                    if routed { "" } else { "not " },
+                );
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn output_schemas_match_models() {
+        let db = MockDatabase::with_test_data();
+        let user = db.get_user_by_id(1).await.unwrap();
+        let blogpost = db.get_blogpost_by_id(1).await.unwrap();
+
+        db.update_blogpost(
+            1,
+            BlogpostChange {
+                url: Some("suns".into()),
+                ..Default::default()
+            },
+        )
+        .await
+        .unwrap();
+        let redirect = db.list_redirects().await.unwrap().remove(0);
+
+        let id = db
+            .insert_series(NewSeries {
+                title: "Chasing".into(),
+                url: "chasing".into(),
+                description: String::new(),
+            })
+            .await
+            .unwrap();
+        let series = db.get_series_by_id(id).await.unwrap();
+
+        assert_properties_match::<User>(&user);
+        assert_properties_match::<Blogpost>(&blogpost);
+        assert_properties_match::<UrlRedirect>(&redirect);
+        assert_properties_match::<Series>(&series);
+    }
+
+    #[test]
+    fn input_schemas_match_models() {
+        assert_example_deserializes::<NewUser>();
+        assert_example_deserializes::<UserChange>();
+        assert_example_deserializes::<UserDeletion>();
+        assert_example_deserializes::<NoOptions>();
+        assert_example_deserializes::<NewBlogpost>();
+        assert_example_deserializes::<BlogpostChange>();
+        assert_example_deserializes::<NewUrlRedirect>();
+        assert_example_deserializes::<NewSeries>();
+        assert_example_deserializes::<SeriesChange>();
+    }
+
+    #[test]
+    fn filter_schemas_match_models() {
+        assert_query_deserializes::<UserFilter>();
+        assert_query_deserializes::<BlogpostFilter>();
+        assert_query_deserializes::<SeriesFilter>();
+    }
+
+    #[inline]
+    fn properties<S: JsonSchema>() -> Map<String, Value> {
+        schema_for::<S>()["properties"]
+            .as_object()
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    /// An example value for a property, as a string.
+    #[inline]
+    fn example(schema: &Value) -> String {
+        if let Some(example) = schema["example"].as_str() {
+            return example.into();
+        }
+        let variant = &schema["oneOf"][0];
+        if let Some(variant) = schema["enum"][0].as_str().or(variant["enum"][0].as_str()) {
+            return variant.into();
+        }
+        match schema["type"].as_str().unwrap() {
+            "integer" => "1".into(),
+            "boolean" => "true".into(),
+            _ => "example".into(),
+        }
+    }
+
+    #[inline]
+    fn example_object<S: JsonSchema>() -> Map<String, Value> {
+        properties::<S>()
+            .iter()
+            .map(|(name, schema)| {
+                let value = example(schema);
+                let value = match schema["type"].as_str() {
+                    Some("integer") => Value::from(value.parse::<i64>().unwrap()),
+                    _ => Value::from(value),
+                };
+                (name.clone(), value)
+            })
+            .collect()
+    }
+
+    #[inline]
+    fn assert_properties_match<S: JsonSchema + Serialize>(instance: &S) {
+        let serialized = serde_json::to_value(instance).unwrap();
+        let keys: BTreeSet<&String> = serialized.as_object().unwrap().keys().collect();
+        let documented = properties::<S>();
+        let documented: BTreeSet<&String> = documented.keys().collect();
+        assert_eq!(
+            keys,
+            documented,
+            "schema for {} is out of date",
+            S::schema_name()
+        );
+    }
+
+    /// The example built from the schema deserializes, but not if any of
+    /// the required properties are left out.
+    #[inline]
+    fn assert_example_deserializes<S: JsonSchema + DeserializeOwned>() {
+        let example = example_object::<S>();
+        assert!(
+            serde_json::from_value::<S>(Value::Object(example.clone())).is_ok(),
+            "schema for {} is out of date",
+            S::schema_name()
+        );
+
+        let schema = schema_for::<S>();
+        for required in schema["required"].as_array().into_iter().flatten() {
+            let mut example = example.clone();
+            example.remove(required.as_str().unwrap());
+            assert!(
+                serde_json::from_value::<S>(Value::Object(example)).is_err(),
+                "{} should not require `{}`",
+                S::schema_name(),
+                required
+            );
+        }
+    }
+
+    #[inline]
+    fn assert_query_deserializes<S: JsonSchema + DeserializeOwned>() {
+        let query: Vec<(String, String)> = properties::<S>()
+            .iter()
+            .map(|(name, schema)| (name.clone(), example(schema)))
+            .collect();
+        let query = serde_urlencoded::to_string(&query).unwrap();
+
+        if let Err(e) = serde_urlencoded::from_str::<S>(&query) {
+            panic!("schema for {} is out of date: {}", S::schema_name(), e);
+        }
+    }
+}
@@ -0,0 +1,310 @@
+// GNU AGPL v3 License
+
+use super::{rate_limit::RateLimiter, recover_error, with_role, ApiError, Caller};
+use crate::{
+    blog::PostContext,
+    csrf_integration::{self, CsrfError},
+    markdown::{self, FrontMatterError},
+    models::{Blogpost, Model},
+    pagerender::PageRenderState,
+    query::{with_database, Database, DatabaseError},
+    sanitize::Policy,
+    wikilink, PageRenderError,
+};
+use bytes::Bytes;
+use chrono::Local;
+use futures_util::future::ready;
+use std::{sync::Arc, time::Duration};
+use warp::{http::StatusCode, reject::custom as reject, reply::html, Filter, Rejection, Reply};
+
+/// How many previews each author can render in a minute. The editor asks
+/// for one as the author types, so this only stops runaway clients.
+const PREVIEWS_PER_MINUTE: u32 = 60;
+
+/// Render markdown into the page that it would be if it were posted, without
+/// saving anything.
+#[inline]
+pub fn preview(
+) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone + Send + Sync + 'static {
+    let limiter = Arc::new(RateLimiter::new(
+        PREVIEWS_PER_MINUTE,
+        Duration::from_secs(60),
+    ));
+
+    warp::path!("preview")
+        .and(warp::post())
+        .and(with_author(limiter))
+        .and(with_database())
+        .and_then(|author: Caller, body: Bytes, db: Arc<_>| async move {
+            let preview = serde_json::from_slice::<Preview>(&body)
+                .map_err(|e| reject(PreviewError::from(e)))?;
+            render_preview(&*db, author, preview).await.map_err(reject)
+        })
+        .map(html)
+        .recover(recover_error::<PreviewError>)
+}
+
+/// Only authors can preview posts, and only so often. Requests are only
+/// counted once their CSRF token checks out, along with the body that it
+/// came in.
+#[inline]
+fn with_author(
+    limiter: Arc<RateLimiter>,
+) -> impl Filter<Extract = (Caller, Bytes), Error = Rejection> + Clone + Send + Sync + 'static {
+    with_role::<PreviewError>(Blogpost::CREATE_PERMS)
+        .and(csrf_integration::check_csrf::<PreviewError>())
+        .and_then(move |author: Caller, body: Bytes| {
+            ready(if limiter.check(author.id) {
+                Ok((author, body))
+            } else {
+                Err(reject(PreviewError::RateLimited))
+            })
+        })
+        .untuple_one()
+}
+
+/// Render the preview the same way that the post would be rendered once it
+/// was saved, with the title and tags falling back to the front matter.
+#[inline]
+async fn render_preview(
+    database: &impl Database,
+    author: Caller,
+    preview: Preview,
+) -> Result<String, PreviewError> {
+    let Preview { title, tags, body } = preview;
+    let front_matter = markdown::front_matter(&body)?.unwrap_or_default();
+    let context = PostContext {
+        links: wikilink::resolve(database, &body).await?,
+        ..PostContext::default()
+    };
+
+    let html = tokio::task::spawn_blocking(move || {
+        let now = Local::now().naive_local();
+        let summary = markdown::summarize(&body);
+        let blogpost = Blogpost {
+            id: 0,
+            title: Some(title)
+                .filter(|title| !title.is_empty())
+                .or(front_matter.title)
+                .unwrap_or_default(),
+            tags: Some(tags)
+                .filter(|tags| !tags.is_empty())
+                .or(front_matter.tags)
+                .unwrap_or_default(),
+            url: String::new(),
+            body,
+            author_id: author.id,
+            created_at: front_matter.date.unwrap_or(now),
+            version: 0,
+            updated_at: now,
+            deleted_at: None,
+            series_id: None,
+            series_position: None,
+            summary: None,
+            cover_image: front_matter.cover_image,
+            canonical_url: front_matter.canonical_url,
+            word_count: summary.word_count,
+            reading_minutes: summary.reading_minutes,
+            trusted_html: Policy::for_author(author.roles) == Policy::Trusted,
+            summary_generated: true,
+        };
+        let policy = blogpost.policy();
+        blogpost.render_to_html(
+            author.name.as_deref().unwrap_or("Anonymous"),
+            policy,
+            &context,
+            &mut PageRenderState::default(),
+        )
+    })
+    .await
+    .expect("Blocking markdown task panicked")?;
+
+    Ok(html)
+}
+
+#[derive(serde::Deserialize)]
+struct Preview {
+    #[serde(default)]
+    title: String,
+    #[serde(default)]
+    tags: String,
+    body: String,
+}
+
+#[derive(Debug, thiserror::Error)]
+enum PreviewError {
+    #[error("CSRF: {0}")]
+    Csrf(#[from] CsrfError),
+    #[error("{0}")]
+    Json(#[from] serde_json::Error),
+    #[error("{0}")]
+    FrontMatter(#[from] FrontMatterError),
+    #[error("{0}")]
+    Database(#[from] DatabaseError),
+    #[error("{0}")]
+    Render(#[from] PageRenderError),
+    #[error("Permission denied")]
+    PermissionDenied,
+    #[error("Too many previews")]
+    RateLimited,
+}
+
+impl ApiError for PreviewError {
+    const KIND: &'static str = "Preview error";
+
+    #[inline]
+    fn permission_denied() -> Self {
+        Self::PermissionDenied
+    }
+
+    #[inline]
+    fn as_err(&self) -> (StatusCode, &'static str) {
+        match self {
+            Self::Csrf(..) => (StatusCode::BAD_REQUEST, "CSRF failure"),
+            Self::Json(..) => (StatusCode::BAD_REQUEST, "Unable to parse JSON body"),
+            Self::FrontMatter(..) => (StatusCode::BAD_REQUEST, "Unable to read the front matter"),
+            Self::Database(..) => (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "An SQL error occurred while looking up linked posts",
+            ),
+            Self::Render(..) => (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Unable to render the preview",
+            ),
+            Self::PermissionDenied => (StatusCode::UNAUTHORIZED, "Permission denied"),
+            Self::RateLimited => (
+                StatusCode::TOO_MANY_REQUESTS,
+                "Too many previews, try again in a minute",
+            ),
+        }
+    }
+}
+
+impl warp::reject::Reject for PreviewError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        auth::{fake_access_token, fake_access_token_fewer_perms, initialize_auth_test},
+        csrf_integration::EncryptedCsrfPair,
+        templates,
+    };
+    use warp::http::Response;
+
+    #[inline]
+    fn csrf_json(title: &str, body: &str) -> String {
+        let EncryptedCsrfPair { token, cookie } = csrf_integration::generate_csrf_pair().unwrap();
+        serde_json::json!({
+            "title": title,
+            "body": body,
+            "csrf_token": token,
+            "csrf_cookie": cookie,
+        })
+        .to_string()
+    }
+
+    #[inline]
+    async fn request(
+        filter: &(impl Filter<Extract = (impl Reply,), Error = Rejection> + 'static),
+        token: &str,
+        json: String,
+    ) -> Response<Bytes> {
+        csrf_integration::initialize_csrf_test();
+        initialize_auth_test();
+        templates::initialize_test_templates().unwrap();
+        markdown::initialize_markdown();
+        warp::test::request()
+            .path("/preview")
+            .method("POST")
+            .header("Cookie", format!("access_token={}", token))
+            .header("Content-Type", "application/json")
+            .body(json)
+            .reply(filter)
+            .await
+    }
+
+    #[tokio::test]
+    async fn renders_previews() {
+        let filter = preview();
+        let value = request(
+            &filter,
+            fake_access_token(),
+            csrf_json("Chasing Suns", "we forgot what **we** were after"),
+        )
+        .await;
+        assert_eq!(value.status(), StatusCode::OK);
+        let html = String::from_utf8(value.body().to_vec()).unwrap();
+        for string in [
+            "<h1>Chasing Suns</h1>",
+            "<strong>we</strong>",
+            "By John Notgull",
+            "1 min read",
+        ] {
+            assert!(html.contains(string), "Could not find `{}`", string);
+        }
+
+        // the title can come from the front matter instead
+        let value = request(
+            &filter,
+            fake_access_token(),
+            csrf_json("", "---\ntitle: Catching Suns\n---\nHello"),
+        )
+        .await;
+        let html = String::from_utf8(value.body().to_vec()).unwrap();
+        assert!(html.contains("<h1>Catching Suns</h1>"));
+    }
+
+    #[tokio::test]
+    async fn rejects_bad_previews() {
+        let filter = preview();
+        let value = request(
+            &filter,
+            fake_access_token_fewer_perms(),
+            csrf_json("Chasing Suns", "Hello"),
+        )
+        .await;
+        assert_eq!(value.status(), StatusCode::UNAUTHORIZED);
+
+        let value = request(
+            &filter,
+            fake_access_token(),
+            serde_json::json!({ "body": "Hello" }).to_string(),
+        )
+        .await;
+        assert_eq!(value.status(), StatusCode::BAD_REQUEST);
+
+        let value = request(
+            &filter,
+            fake_access_token(),
+            csrf_json("", "---\ntitle: [unclosed\n---\nHello"),
+        )
+        .await;
+        assert_eq!(value.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn limits_previews() {
+        let filter = preview();
+        for _ in 0..PREVIEWS_PER_MINUTE {
+            let value = request(&filter, fake_access_token(), csrf_json("", "Hello")).await;
+            assert_eq!(value.status(), StatusCode::OK);
+        }
+
+        let value = request(&filter, fake_access_token(), csrf_json("", "Hello")).await;
+        assert_eq!(value.status(), StatusCode::TOO_MANY_REQUESTS);
+    }
+
+    #[tokio::test]
+    async fn only_limits_checked_previews() {
+        let filter = preview();
+        let no_csrf = serde_json::json!({ "body": "Hello" }).to_string();
+        for _ in 0..PREVIEWS_PER_MINUTE {
+            let value = request(&filter, fake_access_token(), no_csrf.clone()).await;
+            assert_eq!(value.status(), StatusCode::BAD_REQUEST);
+        }
+
+        let value = request(&filter, fake_access_token(), csrf_json("", "Hello")).await;
+        assert_eq!(value.status(), StatusCode::OK);
+    }
+}
@@ -0,0 +1,64 @@
+// GNU AGPL v3 License
+
+use dashmap::DashMap;
+use std::time::{Duration, Instant};
+
+/// Counts how many times each user has hit a route, and turns them away
+/// once they've hit it too many times in one window.
+pub(super) struct RateLimiter {
+    limit: u32,
+    window: Duration,
+    /// When each user's current window started, and how many hits they've
+    /// had in it.
+    hits: DashMap<i32, (Instant, u32)>,
+}
+
+impl RateLimiter {
+    #[inline]
+    pub(super) fn new(limit: u32, window: Duration) -> RateLimiter {
+        RateLimiter {
+            limit,
+            window,
+            hits: DashMap::new(),
+        }
+    }
+
+    /// Count a hit from a user, returning whether they're still under the
+    /// limit.
+    #[inline]
+    pub(super) fn check(&self, user_id: i32) -> bool {
+        let now = Instant::now();
+        let mut entry = self.hits.entry(user_id).or_insert((now, 0));
+        let (start, count) = &mut *entry;
+        if now.duration_since(*start) >= self.window {
+            *start = now;
+            *count = 0;
+        }
+
+        if *count < self.limit {
+            *count += 1;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::RateLimiter;
+    use std::time::Duration;
+
+    #[test]
+    fn limits_each_user() {
+        let limiter = RateLimiter::new(2, Duration::from_secs(60));
+        assert!(limiter.check(1));
+        assert!(limiter.check(1));
+        assert!(!limiter.check(1));
+        assert!(limiter.check(2));
+
+        let limiter = RateLimiter::new(1, Duration::ZERO);
+        assert!(limiter.check(1));
+        assert!(limiter.check(1));
+    }
+}
@@ -0,0 +1,241 @@
+// GNU AGPL v3 License
+
+use super::{recover_error, with_role, ApiError, Caller};
+use crate::{
+    auth::Permissions,
+    csrf_integration::{self, CsrfError},
+    models::NewUrlRedirect,
+    query::{with_database, Database, DatabaseError},
+};
+use bytes::Bytes;
+use std::sync::Arc;
+use warp::{
+    http::{StatusCode, Uri},
+    reject::custom as reject,
+    reply::{json, with_status},
+    Filter, Rejection, Reply,
+};
+
+#[inline]
+pub fn redirects(
+) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone + Send + Sync + 'static {
+    list_redirects()
+        .or(create_redirect())
+        .or(delete_redirect())
+        .recover(recover_error::<RedirectError>)
+}
+
+/// List every redirect, both the ones recorded for posts and the ones
+/// added by hand.
+#[inline]
+fn list_redirects(
+) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone + Send + Sync + 'static {
+    warp::path!("redirects")
+        .and(warp::get())
+        .and(with_role::<RedirectError>(Permissions::ADMIN))
+        .and(csrf_integration::check_csrf::<RedirectError>())
+        .and(with_database())
+        .and_then(|_: Caller, _, db: Arc<_>| async move {
+            db.list_redirects()
+                .await
+                .map_err(|e| reject(RedirectError::from(e)))
+        })
+        .map(|redirects| json(&redirects))
+}
+
+/// Add a redirect from an arbitrary path.
+#[inline]
+fn create_redirect(
+) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone + Send + Sync + 'static {
+    warp::path!("redirects")
+        .and(warp::post())
+        .and(with_role::<RedirectError>(Permissions::ADMIN))
+        .and(csrf_integration::check_csrf::<RedirectError>())
+        .and(with_database())
+        .and_then(|_: Caller, body: Bytes, db: Arc<_>| async move {
+            let redirect = serde_json::from_slice::<NewUrlRedirect>(&body)
+                .map_err(|e| reject(RedirectError::from(e)))?;
+            validate(&redirect).map_err(reject)?;
+            let id = db
+                .insert_redirect(redirect)
+                .await
+                .map_err(|e| reject(RedirectError::from(e)))?;
+            Ok::<_, Rejection>(with_status(json(&Created { id }), StatusCode::CREATED))
+        })
+}
+
+/// Delete a redirect.
+#[inline]
+fn delete_redirect(
+) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone + Send + Sync + 'static {
+    warp::path!("redirects" / i32)
+        .and(warp::delete())
+        .and(with_role::<RedirectError>(Permissions::ADMIN))
+        .and(csrf_integration::check_csrf::<RedirectError>())
+        .and(with_database())
+        .and_then(|id: i32, _: Caller, _, db: Arc<_>| async move {
+            db.delete_redirect(id)
+                .await
+                .map_err(|e| reject(RedirectError::from(e)))?;
+            Ok::<_, Rejection>(StatusCode::NO_CONTENT)
+        })
+}
+
+/// Make sure a redirect goes from a local path to somewhere we can send
+/// the browser.
+#[inline]
+fn validate(redirect: &NewUrlRedirect) -> Result<(), RedirectError> {
+    if !redirect.path.starts_with('/') || redirect.path.parse::<Uri>().is_err() {
+        Err(RedirectError::InvalidPath)
+    } else if redirect.target.is_empty() || redirect.target.parse::<Uri>().is_err() {
+        Err(RedirectError::InvalidTarget)
+    } else if redirect.path == redirect.target {
+        Err(RedirectError::RedirectsToItself)
+    } else {
+        Ok(())
+    }
+}
+
+#[derive(serde::Serialize)]
+struct Created {
+    id: i32,
+}
+
+#[derive(Debug, thiserror::Error)]
+enum RedirectError {
+    #[error("CSRF: {0}")]
+    Csrf(#[from] CsrfError),
+    #[error("{0}")]
+    Json(#[from] serde_json::Error),
+    #[error("{0}")]
+    Database(#[from] DatabaseError),
+    #[error("Permission denied")]
+    PermissionDenied,
+    #[error("Redirects must start from a path on this site")]
+    InvalidPath,
+    #[error("Redirects must lead to a path or URL")]
+    InvalidTarget,
+    #[error("Redirects can't lead back to themselves")]
+    RedirectsToItself,
+}
+
+impl ApiError for RedirectError {
+    const KIND: &'static str = "Redirect error";
+
+    #[inline]
+    fn permission_denied() -> Self {
+        Self::PermissionDenied
+    }
+
+    #[inline]
+    fn as_err(&self) -> (StatusCode, &'static str) {
+        match self {
+            Self::Csrf(..) => (StatusCode::BAD_REQUEST, "CSRF failure"),
+            Self::Json(..) => (StatusCode::BAD_REQUEST, "Unable to parse JSON body"),
+            Self::Database(DatabaseError::NotFound) => {
+                (StatusCode::NOT_FOUND, "Unable to find the redirect")
+            }
+            Self::Database(DatabaseError::AlreadyExists) => (
+                StatusCode::CONFLICT,
+                "There is already a redirect from that path",
+            ),
+            Self::Database(..) => (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "An SQL error occurred while managing redirects",
+            ),
+            Self::PermissionDenied => (StatusCode::UNAUTHORIZED, "Permission denied"),
+            Self::InvalidPath => (
+                StatusCode::BAD_REQUEST,
+                "Redirects must start from a path on this site",
+            ),
+            Self::InvalidTarget => (
+                StatusCode::BAD_REQUEST,
+                "Redirects must lead to a path or URL",
+            ),
+            Self::RedirectsToItself => (
+                StatusCode::BAD_REQUEST,
+                "Redirects can't lead back to themselves",
+            ),
+        }
+    }
+}
+
+impl warp::reject::Reject for RedirectError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        auth::{fake_access_token, fake_access_token_fewer_perms, initialize_auth_test},
+        csrf_integration::EncryptedCsrfPair,
+    };
+
+    #[inline]
+    fn csrf_json(path: &str, target: &str) -> String {
+        let EncryptedCsrfPair { token, cookie } = csrf_integration::generate_csrf_pair().unwrap();
+        serde_json::json!({
+            "path": path,
+            "target": target,
+            "csrf_token": token,
+            "csrf_cookie": cookie,
+        })
+        .to_string()
+    }
+
+    #[inline]
+    async fn create(token: String, path: &str, target: &str) -> StatusCode {
+        csrf_integration::initialize_csrf_test();
+        initialize_auth_test();
+        warp::test::request()
+            .path("/redirects")
+            .method("POST")
+            .header("Cookie", format!("access_token={}", token))
+            .header("Content-Type", "application/json")
+            .body(csrf_json(path, target))
+            .filter(&redirects())
+            .await
+            .unwrap()
+            .into_response()
+            .status()
+    }
+
+    #[tokio::test]
+    async fn create_redirect() {
+        assert_eq!(
+            create(fake_access_token(), "/feed.xml", "/blog").await,
+            StatusCode::CREATED
+        );
+        assert_eq!(
+            create(
+                fake_access_token(),
+                "/atom.xml",
+                "https://example.com/atom.xml"
+            )
+            .await,
+            StatusCode::CREATED
+        );
+    }
+
+    #[tokio::test]
+    async fn rejects_invalid_redirects() {
+        for (path, target) in [
+            ("feed.xml", "/blog"),
+            ("/feed.xml", ""),
+            ("/feed.xml", "not a url"),
+            ("/blog", "/blog"),
+        ] {
+            assert_eq!(
+                create(fake_access_token(), path, target).await,
+                StatusCode::BAD_REQUEST
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn requires_admin() {
+        assert_eq!(
+            create(fake_access_token_fewer_perms(), "/feed.xml", "/blog").await,
+            StatusCode::UNAUTHORIZED
+        );
+    }
+}
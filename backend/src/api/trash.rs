@@ -0,0 +1,269 @@
+// GNU AGPL v3 License
+
+use super::{recover_error, with_role, ApiError, Caller};
+use crate::{
+    auth::Permissions,
+    blog::invalidate_cache,
+    csrf_integration::{self, CsrfError},
+    database::SqlDatabase,
+    models::{default_count, Blogpost, BlogpostFilter},
+    query::{with_database, Database, DatabaseError},
+};
+use bytes::Bytes;
+use chrono::{Duration as ChronoDuration, Local};
+use std::{sync::Arc, time::Duration};
+use tokio::time::interval;
+use warp::{http::StatusCode, reject::custom as reject, reply::json, Filter, Rejection, Reply};
+
+/// How often the trash is checked for posts that have been there too long.
+const PURGE_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+#[inline]
+pub fn trash(
+) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone + Send + Sync + 'static {
+    list_trash()
+        .or(restore())
+        .or(purge())
+        .recover(recover_error::<TrashError>)
+}
+
+/// List the posts that are in the trash.
+#[inline]
+fn list_trash(
+) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone + Send + Sync + 'static {
+    warp::path!("trash")
+        .and(warp::get())
+        .and(with_role::<TrashError>(Permissions::ADMIN))
+        .and(csrf_integration::check_csrf::<TrashError>())
+        .and(with_database())
+        .and_then(|_: Caller, body: Bytes, db: Arc<_>| async move {
+            let TrashQuery { skip, count } = serde_urlencoded::from_bytes::<TrashQuery>(&body)
+                .map_err(|e| reject(TrashError::from(e)))?;
+            list_trashed(&*db, skip, count).await.map_err(reject)
+        })
+        .map(|page: TrashPage| json(&page))
+}
+
+/// Take a post back out of the trash.
+#[inline]
+fn restore(
+) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone + Send + Sync + 'static {
+    warp::path!("trash" / i32 / "restore")
+        .and(warp::post())
+        .and(with_role::<TrashError>(Permissions::ADMIN))
+        .and(csrf_integration::check_csrf::<TrashError>())
+        .and(with_database())
+        .and_then(|id: i32, _: Caller, _, db: Arc<_>| async move {
+            db.restore_blogpost(id)
+                .await
+                .map_err(|e| reject(TrashError::from(e)))?;
+            invalidate_cache();
+            Ok::<_, Rejection>(StatusCode::NO_CONTENT)
+        })
+}
+
+/// Delete a post in the trash for good.
+#[inline]
+fn purge() -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone + Send + Sync + 'static
+{
+    warp::path!("trash" / i32)
+        .and(warp::delete())
+        .and(with_role::<TrashError>(Permissions::ADMIN))
+        .and(csrf_integration::check_csrf::<TrashError>())
+        .and(with_database())
+        .and_then(|id: i32, _: Caller, _, db: Arc<_>| async move {
+            db.purge_blogpost(id)
+                .await
+                .map_err(|e| reject(TrashError::from(e)))?;
+            invalidate_cache();
+            Ok::<_, Rejection>(StatusCode::NO_CONTENT)
+        })
+}
+
+#[inline]
+async fn list_trashed(db: &impl Database, skip: u64, count: u64) -> Result<TrashPage, TrashError> {
+    let filter = BlogpostFilter {
+        trashed: true,
+        skip,
+        count,
+        ..Default::default()
+    };
+
+    let total = db.count_blogposts(filter.clone()).await?;
+    let items = db.list_blogposts(filter).await?;
+    Ok(TrashPage { items, total })
+}
+
+/// Periodically purge the posts that have been in the trash for longer
+/// than the retention period.
+#[inline]
+pub async fn purge_trash_task(retention_days: u32) {
+    let db = SqlDatabase::default();
+    let retention = ChronoDuration::days(retention_days.into());
+    let mut i = interval(PURGE_INTERVAL);
+    loop {
+        i.tick().await;
+        purge_expired(&db, retention).await;
+    }
+}
+
+#[inline]
+async fn purge_expired(db: &impl Database, retention: ChronoDuration) {
+    let cutoff = Local::now().naive_local() - retention;
+    match db.purge_trash(cutoff).await {
+        Ok(0) => {}
+        Ok(purged) => tracing::info!("Purged {} blogposts from the trash", purged),
+        Err(e) => tracing::error!("Unable to purge the trash: {}", e),
+    }
+}
+
+#[derive(serde::Deserialize)]
+struct TrashQuery {
+    #[serde(default)]
+    skip: u64,
+    #[serde(default = "default_count")]
+    count: u64,
+}
+
+#[derive(serde::Serialize)]
+struct TrashPage {
+    items: Vec<Blogpost>,
+    total: u64,
+}
+
+#[derive(Debug, thiserror::Error)]
+enum TrashError {
+    #[error("CSRF: {0}")]
+    Csrf(#[from] CsrfError),
+    #[error("{0}")]
+    UrlEncoding(#[from] serde_urlencoded::de::Error),
+    #[error("{0}")]
+    Database(#[from] DatabaseError),
+    #[error("Permission denied")]
+    PermissionDenied,
+}
+
+impl ApiError for TrashError {
+    const KIND: &'static str = "Trash error";
+
+    #[inline]
+    fn permission_denied() -> Self {
+        Self::PermissionDenied
+    }
+
+    #[inline]
+    fn as_err(&self) -> (StatusCode, &'static str) {
+        match self {
+            Self::Csrf(..) => (StatusCode::BAD_REQUEST, "CSRF failure"),
+            Self::UrlEncoding(..) => (
+                StatusCode::BAD_REQUEST,
+                "Unable to parse URL-encoded query parameters",
+            ),
+            Self::Database(DatabaseError::NotFound) => (
+                StatusCode::NOT_FOUND,
+                "Unable to find the blogpost in the trash",
+            ),
+            Self::Database(..) => (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "An SQL error occurred while managing the trash",
+            ),
+            Self::PermissionDenied => (StatusCode::UNAUTHORIZED, "Permission denied"),
+        }
+    }
+}
+
+impl warp::reject::Reject for TrashError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        auth::{fake_access_token, fake_access_token_fewer_perms, initialize_auth_test},
+        csrf_integration::EncryptedCsrfPair,
+        mock_database::MockDatabase,
+    };
+
+    #[inline]
+    fn csrf_query() -> String {
+        let EncryptedCsrfPair { token, cookie } = csrf_integration::generate_csrf_pair().unwrap();
+        serde_urlencoded::to_string(&[("csrf_token", token), ("csrf_cookie", cookie)]).unwrap()
+    }
+
+    #[tokio::test]
+    async fn only_trashed_posts() {
+        csrf_integration::initialize_csrf_test();
+        initialize_auth_test();
+        let trash = trash();
+        let cookie = format!("access_token={}", fake_access_token());
+
+        let response = warp::test::request()
+            .path(&format!("/trash?{}", csrf_query()))
+            .method("GET")
+            .header("Cookie", &cookie)
+            .filter(&trash)
+            .await
+            .unwrap()
+            .into_response();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = warp::hyper::body::to_bytes(response.into_body())
+            .await
+            .unwrap();
+        let page: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(page["total"], 0);
+
+        // published posts can't be restored or purged
+        for (path, method) in [("/trash/1/restore", "POST"), ("/trash/1", "DELETE")] {
+            let response = warp::test::request()
+                .path(path)
+                .method(method)
+                .header("Cookie", &cookie)
+                .body(csrf_query())
+                .filter(&trash)
+                .await
+                .unwrap()
+                .into_response();
+            assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        }
+    }
+
+    #[tokio::test]
+    async fn list_trashed_posts() {
+        let db = MockDatabase::with_test_data();
+        db.delete_blogpost(2).await.unwrap();
+
+        let page = list_trashed(&db, 0, 25).await.unwrap();
+        assert_eq!(page.total, 1);
+        assert_eq!(page.items[0].id, 2);
+    }
+
+    #[tokio::test]
+    async fn requires_admin() {
+        csrf_integration::initialize_csrf_test();
+        initialize_auth_test();
+        let response = warp::test::request()
+            .path(&format!("/trash?{}", csrf_query()))
+            .method("GET")
+            .header(
+                "Cookie",
+                format!("access_token={}", fake_access_token_fewer_perms()),
+            )
+            .filter(&trash())
+            .await
+            .unwrap()
+            .into_response();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    }
+
+    #[tokio::test]
+    async fn purges_expired_posts() {
+        let db = MockDatabase::with_test_data();
+        db.delete_blogpost(1).await.unwrap();
+
+        purge_expired(&db, ChronoDuration::days(30)).await;
+        assert!(db.restore_blogpost(1).await.is_ok());
+
+        db.delete_blogpost(1).await.unwrap();
+        purge_expired(&db, ChronoDuration::days(-1)).await;
+        assert!(db.restore_blogpost(1).await.is_err());
+    }
+}
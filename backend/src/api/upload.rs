@@ -0,0 +1,460 @@
+// GNU AGPL v3 License
+
+use super::{image::s3_data, recover_error, with_role, ApiError, Caller};
+use crate::{
+    auth::Permissions,
+    csrf_integration::{self, CsrfError},
+    models::{NewUpload, Upload},
+    query::{with_database, Database, DatabaseError},
+};
+use aws_sdk_s3::{
+    error::{HeadObjectError, PutObjectError},
+    presigning::config::{Error as PresigningConfigError, PresigningConfig},
+    SdkError,
+};
+use bytes::Bytes;
+use chrono::{Duration as ChronoDuration, Local, NaiveDateTime};
+use std::{sync::Arc, time::Duration};
+use warp::{
+    http::StatusCode,
+    reject::custom as reject,
+    reply::{json, with_status},
+    Filter, Rejection, Reply,
+};
+
+/// The largest file that can be uploaded through a presigned URL.
+const MAX_PRESIGNED_LEN: i64 = 2 * 1024 * 1024 * 1024;
+/// How long a presigned URL can be used to start an upload.
+const PRESIGN_EXPIRY: Duration = Duration::from_secs(15 * 60);
+/// How many seconds after presigning that an upload can still be completed.
+const COMPLETE_WINDOW_SECS: i64 = 6 * 60 * 60;
+
+#[inline]
+pub fn upload(
+) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone + Send + Sync + 'static {
+    warp::post().and(
+        presign()
+            .or(complete())
+            .recover(recover_error::<UploadError>),
+    )
+}
+
+/// Hand out a presigned URL that the client can `PUT` the file to directly.
+#[inline]
+fn presign(
+) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone + Send + Sync + 'static {
+    warp::path!("upload" / "presign")
+        .and(with_role::<UploadError>(Permissions::AUTHOR))
+        .and(csrf_integration::check_csrf::<UploadError>())
+        .and(with_database())
+        .and_then(|uploader: Caller, body: Bytes, db: Arc<_>| async move {
+            let request = serde_json::from_slice::<PresignRequest>(&body)
+                .map_err(|e| reject(UploadError::from(e)))?;
+            presign_upload(&*db, request, uploader.id)
+                .await
+                .map_err(reject)
+        })
+        .map(|presigned: Presigned| json(&presigned))
+}
+
+/// Register a finished presigned upload in the database.
+#[inline]
+fn complete(
+) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone + Send + Sync + 'static {
+    warp::path!("upload" / "complete")
+        .and(with_role::<UploadError>(Permissions::AUTHOR))
+        .and(csrf_integration::check_csrf::<UploadError>())
+        .and(with_database())
+        .and_then(|uploader: Caller, body: Bytes, db: Arc<_>| async move {
+            let CompleteRequest { path } = serde_json::from_slice::<CompleteRequest>(&body)
+                .map_err(|e| reject(UploadError::from(e)))?;
+            complete_upload(&*db, uploader.id, path)
+                .await
+                .map_err(reject)
+        })
+        .map(|id| with_status(json(&IdSer { id }), StatusCode::CREATED))
+}
+
+#[inline]
+async fn presign_upload(
+    db: &impl Database,
+    request: PresignRequest,
+    uploader_id: i32,
+) -> Result<Presigned, UploadError> {
+    let PresignRequest {
+        category,
+        subcategory,
+        filename,
+        content_type,
+        size,
+    } = request;
+
+    if size <= 0 || size > MAX_PRESIGNED_LEN {
+        return Err(UploadError::InvalidSize(size));
+    }
+
+    let path = upload_path(&category, &subcategory, &filename)?;
+    reserve_upload(db, path.clone(), content_type.clone(), size, uploader_id).await?;
+
+    let s3data = s3_data();
+
+    // the content length is part of the signature, so S3 will refuse any
+    // upload that is larger than what we agreed to here
+    let presigned = s3data
+        .client
+        .put_object()
+        .bucket(&s3data.bucket_name)
+        .key(path.clone())
+        .content_type(content_type.clone())
+        .content_length(size)
+        .presigned(PresigningConfig::expires_in(PRESIGN_EXPIRY)?)
+        .await?;
+
+    Ok(Presigned {
+        url: presigned.uri().to_string(),
+        path,
+        expires_in: PRESIGN_EXPIRY.as_secs(),
+        max_size: size,
+    })
+}
+
+#[inline]
+async fn complete_upload(
+    db: &impl Database,
+    uploader_id: i32,
+    path: String,
+) -> Result<i32, UploadError> {
+    let upload = match db.get_upload_by_path(path.clone()).await {
+        Err(DatabaseError::NotFound) => return Err(UploadError::NotPending),
+        upload => upload?,
+    };
+    check_pending(&upload, uploader_id, Local::now().naive_local())?;
+
+    // make sure the client actually finished the upload, and uploaded what
+    // it said it would
+    let s3data = s3_data();
+    let head = s3data
+        .client
+        .head_object()
+        .bucket(&s3data.bucket_name)
+        .key(path)
+        .send()
+        .await?;
+    if head.content_length() != upload.size
+        || head.content_type() != Some(upload.content_type.as_str())
+    {
+        return Err(UploadError::Mismatch);
+    }
+
+    db.complete_upload(upload.id).await?;
+    Ok(upload.id)
+}
+
+/// Build the storage key for an upload, making sure that none of its parts
+/// can escape their place in it.
+#[inline]
+fn upload_path(category: &str, subcategory: &str, filename: &str) -> Result<String, UploadError> {
+    let valid = |part: &str| {
+        !part.is_empty()
+            && part != "."
+            && !part.contains("..")
+            && !part.contains(|c: char| c == '/' || c == '\\' || c.is_control())
+    };
+    if !(valid(category) && valid(subcategory) && valid(filename)) {
+        return Err(UploadError::InvalidPath);
+    }
+
+    Ok(format!("files/{}/{}/{}", category, subcategory, filename))
+}
+
+/// Record a presigned upload, so that nobody else can upload to its path
+/// while it is pending.
+#[inline]
+async fn reserve_upload(
+    db: &impl Database,
+    path: String,
+    content_type: String,
+    size: i64,
+    uploader_id: i32,
+) -> Result<i32, UploadError> {
+    // clear out any reservations that were never completed
+    let now = Local::now().naive_local();
+    db.purge_pending_uploads(now).await?;
+
+    let id = db
+        .insert_upload(NewUpload {
+            path,
+            content_type,
+            size,
+            uploader_id,
+            pending_until: Some(now + ChronoDuration::seconds(COMPLETE_WINDOW_SECS)),
+        })
+        .await?;
+    Ok(id)
+}
+
+/// Make sure that an upload is still pending, and that the user is allowed
+/// to complete it.
+#[inline]
+fn check_pending(upload: &Upload, uploader_id: i32, now: NaiveDateTime) -> Result<(), UploadError> {
+    if upload.uploader_id != uploader_id {
+        return Err(UploadError::NotPending);
+    }
+    match upload.pending_until {
+        None => Err(DatabaseError::AlreadyExists.into()),
+        Some(until) if until <= now => Err(UploadError::NotPending),
+        Some(_) => Ok(()),
+    }
+}
+
+#[derive(serde::Deserialize)]
+struct PresignRequest {
+    category: String,
+    subcategory: String,
+    filename: String,
+    content_type: String,
+    size: i64,
+}
+
+#[derive(serde::Deserialize)]
+struct CompleteRequest {
+    path: String,
+}
+
+#[derive(serde::Serialize)]
+struct Presigned {
+    url: String,
+    path: String,
+    expires_in: u64,
+    max_size: i64,
+}
+
+#[derive(serde::Serialize)]
+struct IdSer {
+    id: i32,
+}
+
+#[derive(Debug, thiserror::Error)]
+enum UploadError {
+    #[error("CSRF: {0}")]
+    Csrf(#[from] CsrfError),
+    #[error("{0}")]
+    Json(#[from] serde_json::Error),
+    #[error("{0}")]
+    Database(#[from] DatabaseError),
+    #[error("Invalid presigning configuration: {0}")]
+    PresigningConfig(#[from] PresigningConfigError),
+    #[error("S3 Error: {0}")]
+    Presign(#[from] Box<SdkError<PutObjectError>>),
+    #[error("S3 Error: {0}")]
+    Head(#[from] Box<SdkError<HeadObjectError>>),
+    #[error("Cannot upload a file of {0} bytes")]
+    InvalidSize(i64),
+    #[error("Invalid upload path")]
+    InvalidPath,
+    #[error("The uploaded file does not match what was presigned")]
+    Mismatch,
+    #[error("No pending upload was found for this user")]
+    NotPending,
+    #[error("Permission denied")]
+    PermissionDenied,
+}
+
+impl From<SdkError<PutObjectError>> for UploadError {
+    #[inline]
+    fn from(s: SdkError<PutObjectError>) -> Self {
+        Self::Presign(Box::new(s))
+    }
+}
+
+impl From<SdkError<HeadObjectError>> for UploadError {
+    #[inline]
+    fn from(s: SdkError<HeadObjectError>) -> Self {
+        Self::Head(Box::new(s))
+    }
+}
+
+impl ApiError for UploadError {
+    const KIND: &'static str = "Upload error";
+
+    #[inline]
+    fn permission_denied() -> Self {
+        Self::PermissionDenied
+    }
+
+    #[inline]
+    fn as_err(&self) -> (StatusCode, &'static str) {
+        match self {
+            Self::Csrf(..) => (StatusCode::BAD_REQUEST, "CSRF failure"),
+            Self::Json(..) => (
+                StatusCode::BAD_REQUEST,
+                "Unable to parse JSON-encoded request body",
+            ),
+            Self::Database(DatabaseError::AlreadyExists) => (
+                StatusCode::CONFLICT,
+                "A file has already been uploaded to that path",
+            ),
+            Self::Database(..) => (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "An SQL error occurred while recording the upload",
+            ),
+            Self::PresigningConfig(..) | Self::Presign(..) => (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "An error occurred while presigning the upload",
+            ),
+            Self::Head(..) => (
+                StatusCode::BAD_REQUEST,
+                "Unable to find the uploaded file in storage",
+            ),
+            Self::InvalidSize(..) => (
+                StatusCode::PAYLOAD_TOO_LARGE,
+                "File is empty or exceeds the maximum upload size",
+            ),
+            Self::InvalidPath => (
+                StatusCode::BAD_REQUEST,
+                "Upload categories and filenames cannot be empty or contain slashes, \"..\" or control characters",
+            ),
+            Self::Mismatch => (
+                StatusCode::BAD_REQUEST,
+                "The uploaded file does not have the presigned size or content type",
+            ),
+            Self::NotPending => (
+                StatusCode::NOT_FOUND,
+                "No pending upload was found for the given path",
+            ),
+            Self::PermissionDenied => (StatusCode::UNAUTHORIZED, "Permission denied"),
+        }
+    }
+}
+
+impl warp::reject::Reject for UploadError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::mock_database::MockDatabase;
+
+    #[tokio::test]
+    async fn presign_rejects_invalid_size() {
+        let db = MockDatabase::new();
+        for size in [0, -1, MAX_PRESIGNED_LEN + 1] {
+            let request = PresignRequest {
+                category: "video".into(),
+                subcategory: "2022".into(),
+                filename: "intro.mp4".into(),
+                content_type: "video/mp4".into(),
+                size,
+            };
+
+            match presign_upload(&db, request, 1).await {
+                Err(UploadError::InvalidSize(s)) => assert_eq!(s, size),
+                _ => panic!("Expected size {} to be rejected", size),
+            }
+        }
+    }
+
+    #[test]
+    fn upload_path_stays_in_place() {
+        assert_eq!(
+            upload_path("video", "2022", "intro.mp4").unwrap(),
+            "files/video/2022/intro.mp4"
+        );
+        assert_eq!(
+            upload_path("video", "2022", "intro.v2.mp4").unwrap(),
+            "files/video/2022/intro.v2.mp4"
+        );
+
+        for (category, subcategory, filename) in [
+            ("", "2022", "intro.mp4"),
+            ("video", "", "intro.mp4"),
+            ("video", "2022", ""),
+            ("video", "2022/01", "intro.mp4"),
+            ("..", "..", "config.toml"),
+            ("video", ".", "intro.mp4"),
+            ("video", "2022", "..\\intro.mp4"),
+            ("video", "2022", "intro\n.mp4"),
+        ] {
+            assert!(
+                matches!(
+                    upload_path(category, subcategory, filename),
+                    Err(UploadError::InvalidPath)
+                ),
+                "{:?} should have been rejected",
+                (category, subcategory, filename)
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn pending_uploads_are_reserved() {
+        const TPATH: &str = "files/archive/2022/backup.tar.gz";
+
+        let db = MockDatabase::new();
+        let reserve = |uploader_id| {
+            reserve_upload(
+                &db,
+                TPATH.into(),
+                "application/gzip".into(),
+                1024,
+                uploader_id,
+            )
+        };
+
+        let id = reserve(1).await.unwrap();
+        let err = reserve(2).await.unwrap_err();
+        assert_eq!(err.as_err().0, StatusCode::CONFLICT);
+        // pending uploads don't count as uploaded yet
+        assert!(db
+            .get_upload_paths(vec![TPATH.into()])
+            .await
+            .unwrap()
+            .is_empty());
+
+        let upload = db.get_upload_by_path(TPATH.into()).await.unwrap();
+        let now = Local::now().naive_local();
+        assert_eq!(upload.id, id);
+        assert!(matches!(
+            check_pending(&upload, 2, now),
+            Err(UploadError::NotPending)
+        ));
+        assert!(check_pending(&upload, 1, now).is_ok());
+        let expired = now + ChronoDuration::seconds(COMPLETE_WINDOW_SECS + 1);
+        assert!(matches!(
+            check_pending(&upload, 1, expired),
+            Err(UploadError::NotPending)
+        ));
+
+        db.complete_upload(id).await.unwrap();
+        let upload = db.get_upload_by_path(TPATH.into()).await.unwrap();
+        assert_eq!(
+            check_pending(&upload, 1, now).unwrap_err().as_err().0,
+            StatusCode::CONFLICT
+        );
+        assert!(matches!(
+            db.complete_upload(id).await,
+            Err(DatabaseError::AlreadyExists)
+        ));
+        assert_eq!(
+            db.get_upload_paths(vec![TPATH.into()]).await.unwrap(),
+            vec![TPATH.to_string()]
+        );
+
+        // an expired reservation gives the path back up
+        db.insert_upload(NewUpload {
+            path: "files/archive/2022/stale.tar.gz".into(),
+            content_type: "application/gzip".into(),
+            size: 1024,
+            uploader_id: 2,
+            pending_until: Some(now - ChronoDuration::seconds(1)),
+        })
+        .await
+        .unwrap();
+        assert_eq!(db.purge_pending_uploads(now).await.unwrap(), 1);
+        assert!(matches!(
+            db.get_upload_by_path("files/archive/2022/stale.tar.gz".into())
+                .await,
+            Err(DatabaseError::NotFound)
+        ));
+    }
+}
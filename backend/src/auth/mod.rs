@@ -42,6 +42,8 @@ pub fn initialize_auth(cfg: &Config) {
 const FAKE_SESSION_ACCESS_TOKEN: &str = "fakeAccessToken";
 #[cfg(test)]
 const FAKE_SESSION_FEWER_PERMS: &str = "fewerPerms";
+#[cfg(test)]
+const FAKE_SESSION_AUTHOR: &str = "authorOnly";
 
 #[inline]
 #[cfg(test)]
@@ -72,6 +74,18 @@ pub fn initialize_auth_test() {
             expires: Instant::now() + Duration::from_secs(60 * 60 * 24 * 365),
         },
     );
+
+    // insert a fake session, for an author who isn't an administrator
+    LOGIN_TABLE.get().unwrap().insert(
+        FAKE_SESSION_AUTHOR.into(),
+        Session {
+            name: Some("Alan Smithee".into()),
+            roles: Permissions::AUTHOR,
+            id: 2,
+            access_token: FAKE_SESSION_AUTHOR.into(),
+            expires: Instant::now() + Duration::from_secs(60 * 60 * 24 * 365),
+        },
+    );
 }
 
 #[inline]
@@ -86,6 +100,12 @@ pub fn fake_access_token_fewer_perms() -> &'static str {
     FAKE_SESSION_FEWER_PERMS
 }
 
+#[inline]
+#[cfg(test)]
+pub fn fake_access_token_author() -> &'static str {
+    FAKE_SESSION_AUTHOR
+}
+
 #[inline]
 fn clear_expired_auth() {
     oauth::clear_expired_states();
@@ -115,8 +135,15 @@ pub async fn create_login_session(
 ) -> Result<(), CreateLoginSessionError> {
     let IdToken { sub } = jsonwebtoken::dangerous_insecure_decode(&id_token)?.claims;
     let User {
-        roles, name, id, ..
+        roles,
+        name,
+        id,
+        deleted_at,
+        ..
     } = db.get_user_by_uuid(sub).await?;
+    if deleted_at.is_some() {
+        return Err(CreateLoginSessionError::Deleted);
+    }
     let login_table = LOGIN_TABLE.get().expect(NO_SET);
 
     // insert the session
@@ -154,6 +181,14 @@ pub fn set_session_name(access: &str, name: String) {
     }
 }
 
+/// Log a user out everywhere, e.g. once they've been deleted.
+#[inline]
+pub fn end_user_sessions(id: i32) {
+    if let Some(login_table) = LOGIN_TABLE.get() {
+        login_table.retain(|_, session| session.id != id);
+    }
+}
+
 static LOGIN_TABLE: OnceCell<DashMap<String, Session>> = OnceCell::new();
 
 #[derive(Debug)]
@@ -170,6 +205,11 @@ pub struct Session {
 pub struct Permissions(pub i64);
 
 impl Permissions {
+    /// Can write blogposts and series, and upload files.
+    pub const AUTHOR: Permissions = Permissions(0b01);
+    /// Can manage users, the trash and redirects.
+    pub const ADMIN: Permissions = Permissions(0b10);
+
     #[inline]
     pub fn applies_to(self, user_roles: Permissions) -> bool {
         tracing::debug!(
@@ -194,6 +234,8 @@ pub enum CreateLoginSessionError {
     Database(#[from] DatabaseError),
     #[error("{0}")]
     Jwt(#[from] jsonwebtoken::errors::Error),
+    #[error("User has been deleted")]
+    Deleted,
 }
 
 /// The ID token details we care about.
@@ -206,7 +248,7 @@ const NO_SET: &str = "`initialize_auth` not called before auth functions";
 
 #[cfg(test)]
 mod tests {
-    use super::Permissions;
+    use super::*;
 
     #[test]
     fn sanity_perm_matches() {
@@ -214,4 +256,26 @@ mod tests {
         let perm_user = Permissions(0b11101);
         assert!(perm_req.applies_to(perm_user));
     }
+
+    #[test]
+    fn ends_user_sessions() {
+        initialize_auth_test();
+        for token in ["deletedOne", "deletedTwo"] {
+            LOGIN_TABLE.get().unwrap().insert(
+                token.into(),
+                Session {
+                    name: None,
+                    roles: Permissions(0),
+                    id: 99,
+                    access_token: token.into(),
+                    expires: Instant::now() + Duration::from_secs(60),
+                },
+            );
+        }
+
+        end_user_sessions(99);
+        assert!(session("deletedOne").is_none());
+        assert!(session("deletedTwo").is_none());
+        assert!(session(fake_access_token()).is_some());
+    }
 }
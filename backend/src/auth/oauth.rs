@@ -327,6 +327,7 @@ impl From<CreateLoginSessionError> for EndOauthError {
         match clse {
             CreateLoginSessionError::Database(db) => Self::from(db),
             CreateLoginSessionError::Jwt(j) => Self::from(j),
+            e @ CreateLoginSessionError::Deleted => Self::Msg(e.to_string()),
         }
     }
 }
@@ -1,21 +1,42 @@
 // GNU AGPL v3 License
 
 use crate::{
-    markdown,
-    models::Blogpost,
-    pagerender,
+    highlight,
+    markdown::{self, TocEntry},
+    models::{blogpost_path, Blogpost, BlogpostFilter, Series},
+    pagerender, redirects,
+    sanitize::Policy,
     templates::{self, TemplateOptions},
-    Database, PageRenderError, Title,
+    wikilink::{self, ResolvedLinks},
+    http_date, Database, DatabaseError, PageRenderError, Title,
 };
 use bytes::Bytes;
-use chrono::NaiveDateTime;
-use dashmap::{mapref::entry::Entry, DashMap};
+use chrono::{Datelike, NaiveDate, NaiveDateTime};
+use dashmap::DashMap;
 use futures_util::{future, TryFutureExt};
 use once_cell::sync::Lazy;
-use std::sync::Arc;
-use warp::{reply::html, Filter, Reply};
+use std::sync::{
+    atomic::{AtomicU64, Ordering},
+    Arc,
+};
+use warp::{
+    http::{header::LAST_MODIFIED, HeaderValue},
+    reply::{html, with_header, Response},
+    Filter, Reply,
+};
+
+static BLOGPOST_CACHE: Lazy<DashMap<i32, CachedBlogpost>> = Lazy::new(DashMap::new);
 
-static BLOGPOST_CACHE: Lazy<DashMap<i32, Bytes>> = Lazy::new(DashMap::new);
+/// Goes up every time the cache is invalidated, so that pages which were
+/// being rendered from what the database held before then aren't used.
+static CACHE_GENERATION: AtomicU64 = AtomicU64::new(0);
+
+/// A rendered blogpost, along with the generation of the cache that it was
+/// rendered in.
+struct CachedBlogpost {
+    generation: u64,
+    page: Bytes,
+}
 
 #[must_use]
 #[inline]
@@ -24,6 +45,8 @@ pub fn blog(
     warp::path("blog").and(
         view_blogpost()
             .or(list_blogpost())
+            .or(archive_blogpost())
+            .or(view_series())
             .or(create_blogpost())
             .or(delete_blogpost())
             .or(edit_blogpost()),
@@ -36,19 +59,53 @@ pub fn list_blogpost(
 ) -> impl Filter<Extract = (impl Reply,), Error = warp::Rejection> + Clone + Send + Sync + 'static {
     warp::path::end()
         .and(warp::get())
-        .map(|| Title { title: "Blog" })
+        .and(warp::query::<PageQuery>())
+        .and(crate::with_database())
         .and(pagerender::page_render_loader::<true>(0))
-        .and_then(|data, mut state: pagerender::PageRenderState| {
-            future::ready({
-                let options = state.template_options();
-
-                templates::template("bloglist", data, options)
-                    .map_err(|e| warp::reject::custom(PageRenderError::from(e)))
-                    .map(move |t| (t, state))
-            })
+        .and_then(|PageQuery { page }, database, pr| {
+            list_blogpost_inner(
+                "Blog".into(),
+                "/blog".into(),
+                BlogpostFilter::default(),
+                page,
+                database,
+                pr,
+            )
+            .map_err(warp::reject::custom)
         })
-        .untuple_one()
-        .map(|res: String, _| html(res))
+        .with(warp::reply::with::header("Cache-Control", "max-age=300"))
+}
+
+#[must_use]
+#[inline]
+pub fn archive_blogpost(
+) -> impl Filter<Extract = (impl Reply,), Error = warp::Rejection> + Clone + Send + Sync + 'static {
+    warp::path!("archive" / i32 / u32)
+        .and(warp::get())
+        .and(warp::query::<PageQuery>())
+        .and(crate::with_database())
+        .and(pagerender::page_render_loader::<true>(0))
+        .and_then(
+            |year, month, PageQuery { page }, database, pr| async move {
+                let (start, end) = month_bounds(year, month).ok_or_else(warp::reject::not_found)?;
+                let filter = BlogpostFilter {
+                    created_after: Some(start),
+                    created_before: Some(end),
+                    ..Default::default()
+                };
+
+                list_blogpost_inner(
+                    format!("Archive: {}", start.format("%B %Y")),
+                    format!("/blog/archive/{}/{}", year, month),
+                    filter,
+                    page,
+                    database,
+                    pr,
+                )
+                .await
+                .map_err(warp::reject::custom)
+            },
+        )
         .with(warp::reply::with::header("Cache-Control", "max-age=3600"))
 }
 
@@ -147,27 +204,260 @@ async fn view_blogpost_inner(
     url: String,
     database: Arc<impl Database>,
     mut pr: pagerender::PageRenderState,
-) -> Result<impl Reply, PageRenderError> {
-    // load blogpost and then user from database
-    let (blogpost, user) = database.get_blogpost_and_user_by_url(url).await?;
+) -> Result<Response, PageRenderError> {
+    let generation = CACHE_GENERATION.load(Ordering::SeqCst);
+
+    // load blogpost and then user from database, sending the reader on if
+    // the post has moved
+    let (blogpost, user) = match database.get_blogpost_and_user_by_url(url.clone()).await {
+        Err(DatabaseError::NotFound) => {
+            return Ok(redirects::find_redirect(&*database, blogpost_path(&url)).await?);
+        }
+        res => res?,
+    };
+    let last_modified = http_date(blogpost.updated_at);
 
     // if the blogpost is already in the cache, return that
     let cache = &*BLOGPOST_CACHE;
-    match cache.entry(blogpost.id) {
-        Entry::Vacant(v) => {
+    let cached = cache
+        .get(&blogpost.id)
+        .filter(|cached| cached.generation == generation)
+        .map(|cached| cached.page.clone());
+    let post = match cached {
+        Some(page) => page,
+        None => {
+            let id = blogpost.id;
+            let context = post_context(&*database, &blogpost).await?;
+
             // format
             let post = tokio::task::spawn_blocking(move || {
-                blogpost.render_to_html(user.name.as_deref().unwrap_or("Anonymous"), &mut pr)
+                let policy = blogpost.policy();
+                blogpost.render_to_html(
+                    user.name.as_deref().unwrap_or("Anonymous"),
+                    policy,
+                    &context,
+                    &mut pr,
+                )
             })
             .await
             .expect("Blocking markdown task panicked")?;
 
-            let the_ref = v.insert(post.into_bytes().into());
-            let post: &Bytes = &*the_ref;
-            Ok(html(post.clone()))
+            let page: Bytes = post.into_bytes().into();
+            cache.insert(
+                id,
+                CachedBlogpost {
+                    generation,
+                    page: page.clone(),
+                },
+            );
+            page
+        }
+    };
+
+    Ok(with_header(html(post), "Last-Modified", last_modified).into_response())
+}
+
+/// Load everything besides the blogpost itself that goes into rendering it.
+#[inline]
+async fn post_context(
+    database: &impl Database,
+    blogpost: &Blogpost,
+) -> Result<PostContext, DatabaseError> {
+    let series = series_nav(database, blogpost).await?;
+    let links = wikilink::resolve(database, &blogpost.body).await?;
+    let backlinks = database
+        .list_backlinks(blogpost.id)
+        .await?
+        .iter()
+        .map(PostLink::new)
+        .collect();
+
+    Ok(PostContext {
+        series,
+        links,
+        backlinks,
+    })
+}
+
+/// Figure out where a blogpost sits in its series, if it's in one.
+#[inline]
+async fn series_nav(
+    database: &impl Database,
+    blogpost: &Blogpost,
+) -> Result<Option<SeriesNav>, DatabaseError> {
+    let series_id = match blogpost.series_id {
+        Some(series_id) => series_id,
+        None => return Ok(None),
+    };
+    let series = database.get_series_by_id(series_id).await?;
+    let parts = database.list_series_blogposts(series_id).await?;
+
+    let index = match parts.iter().position(|part| part.id == blogpost.id) {
+        Some(index) => index,
+        None => return Ok(None),
+    };
+
+    Ok(Some(SeriesNav {
+        title: series.title,
+        url: series_path(&series.url),
+        part: index + 1,
+        parts: parts.len(),
+        prev: index.checked_sub(1).map(|i| PostLink::new(&parts[i])),
+        next: parts.get(index + 1).map(PostLink::new),
+    }))
+}
+
+/// The path that a series' index page is served from.
+#[inline]
+fn series_path(url: &str) -> String {
+    format!("/blog/series/{}", url)
+}
+
+#[must_use]
+#[inline]
+pub fn view_series(
+) -> impl Filter<Extract = (impl Reply,), Error = warp::Rejection> + Clone + Send + Sync + 'static {
+    warp::path!("series" / String)
+        .and(warp::get())
+        .and(crate::with_database())
+        .and(pagerender::page_render_loader::<true>(0))
+        .and_then(|url, database, pr| {
+            view_series_inner(url, database, pr).map_err(warp::reject::custom)
+        })
+        .with(warp::reply::with::header("Cache-Control", "max-age=300"))
+}
+
+#[inline]
+async fn view_series_inner(
+    url: String,
+    database: Arc<impl Database>,
+    mut pr: pagerender::PageRenderState,
+) -> Result<impl Reply, PageRenderError> {
+    let Series {
+        id,
+        title,
+        description,
+        ..
+    } = database.get_series_by_url(url).await?;
+    let posts = database.list_series_blogposts(id).await?;
+    let last_modified = posts.iter().map(|post| post.updated_at).max();
+
+    // anyone who can write blogposts can write series descriptions
+    let description =
+        tokio::task::spawn_blocking(move || markdown::markdown(&description, Policy::Strict))
+            .await
+            .expect("Blocking markdown task panicked");
+    let page = SeriesPage {
+        title,
+        description,
+        parts: posts
+            .into_iter()
+            .enumerate()
+            .map(|(index, post)| SeriesPart {
+                number: index + 1,
+                title: post.title,
+                url: blogpost_path(&post.url),
+                created_at: post.created_at,
+            })
+            .collect(),
+    };
+
+    let page = templates::template("series", page, pr.template_options())?;
+    let mut response = html(page).into_response();
+    if let Some(last_modified) = last_modified {
+        if let Ok(value) = HeaderValue::from_str(&http_date(last_modified)) {
+            response.headers_mut().insert(LAST_MODIFIED, value);
+        }
+    }
+
+    Ok(response)
+}
+
+#[inline]
+async fn list_blogpost_inner(
+    title: String,
+    base_url: String,
+    mut filter: BlogpostFilter,
+    page: Option<u64>,
+    database: Arc<impl Database>,
+    mut pr: pagerender::PageRenderState,
+) -> Result<impl Reply, PageRenderError> {
+    // load the current page, as well as enough info to build the links;
+    // pages past the end show the last page
+    let total = database.count_blogposts(filter.clone()).await?;
+    let page_count = (total + POSTS_PER_PAGE - 1) / POSTS_PER_PAGE;
+    let page = page.unwrap_or(1).clamp(1, page_count.max(1));
+    filter.skip = (page - 1) * POSTS_PER_PAGE;
+    filter.count = POSTS_PER_PAGE;
+
+    let posts = database.list_blogposts(filter).await?;
+    let dates = database.list_blogpost_dates().await?;
+    let last_modified = posts.iter().map(|post| post.updated_at).max();
+
+    let page_url = |number: u64| format!("{}?page={}", base_url, number);
+
+    let list = BlogList {
+        title,
+        posts: posts.into_iter().map(ListedBlogpost::new).collect(),
+        total,
+        pages: (1..=page_count)
+            .map(|number| PageLink {
+                number,
+                url: page_url(number),
+                current: number == page,
+            })
+            .collect(),
+        prev_url: (page > 1).then(|| page_url(page - 1)),
+        next_url: (page < page_count).then(|| page_url(page + 1)),
+        archive: archive_months(dates),
+    };
+
+    let list = templates::template("bloglist", list, pr.template_options())?;
+    let mut response = html(list).into_response();
+    if let Some(last_modified) = last_modified {
+        if let Ok(value) = HeaderValue::from_str(&http_date(last_modified)) {
+            response.headers_mut().insert(LAST_MODIFIED, value);
+        }
+    }
+
+    Ok(response)
+}
+
+/// Get the start of the given month, as well as the start of the month
+/// after it.
+#[inline]
+fn month_bounds(year: i32, month: u32) -> Option<(NaiveDateTime, NaiveDateTime)> {
+    let start = NaiveDate::from_ymd_opt(year, month, 1)?;
+    let end = if month == 12 {
+        NaiveDate::from_ymd_opt(year + 1, 1, 1)?
+    } else {
+        NaiveDate::from_ymd_opt(year, month + 1, 1)?
+    };
+
+    Some((start.and_hms(0, 0, 0), end.and_hms(0, 0, 0)))
+}
+
+/// Group post creation dates into the months they were created in.
+#[inline]
+fn archive_months(mut dates: Vec<NaiveDateTime>) -> Vec<ArchiveMonth> {
+    dates.sort_unstable_by(|a, b| b.cmp(a));
+
+    let mut months: Vec<ArchiveMonth> = vec![];
+    for date in dates {
+        match months.last_mut() {
+            Some(last) if last.year == date.year() && last.month == date.month() => {
+                last.count += 1;
+            }
+            _ => months.push(ArchiveMonth {
+                year: date.year(),
+                month: date.month(),
+                name: date.format("%B %Y").to_string(),
+                count: 1,
+            }),
         }
-        Entry::Occupied(o) => Ok(html(o.get().clone())),
     }
+
+    months
 }
 
 #[inline]
@@ -193,7 +483,20 @@ async fn delete_blogpost_inner(
 }
 
 impl Blogpost {
-    /// Render this blogpost into HTML.
+    /// How much of the HTML in the body to keep, which depends on who last
+    /// saved it.
+    #[must_use]
+    #[inline]
+    pub fn policy(&self) -> Policy {
+        if self.trusted_html {
+            Policy::Trusted
+        } else {
+            Policy::Strict
+        }
+    }
+
+    /// Render this blogpost into HTML, along with the other posts that are
+    /// linked to from it.
     ///
     /// # Errors
     ///
@@ -202,24 +505,44 @@ impl Blogpost {
     pub fn render_to_html(
         self,
         author_name: &str,
+        policy: Policy,
+        context: &PostContext,
         pr: &mut pagerender::PageRenderState,
     ) -> Result<String, PageRenderError> {
         let Blogpost {
             title,
             tags,
             created_at,
+            updated_at,
             body,
             id,
+            cover_image,
+            canonical_url,
+            reading_minutes,
             ..
         } = self;
-        let body = markdown::markdown(&body);
+        let highlight_stylesheet = markdown::front_matter(&body)
+            .ok()
+            .flatten()
+            .and_then(|fm| fm.highlight_theme)
+            .map(|theme| highlight::stylesheet_path(&theme));
+        let markdown::Rendered { html: body, toc } =
+            markdown::render(&body, policy, Some(&context.links));
         let rendered = RenderedBlogpost {
             title: &title,
             author_name,
             created_at,
+            updated_at: (updated_at > created_at).then(|| updated_at),
             body: &body,
             taglist: tags.split(',').collect(),
             blogpost_id: id,
+            series: context.series.as_ref(),
+            cover_image: cover_image.as_deref(),
+            canonical_url: canonical_url.as_deref(),
+            reading_minutes,
+            toc,
+            backlinks: &context.backlinks,
+            highlight_stylesheet,
         };
         let result = templates::template("blogpost", rendered, pr.template_options())?;
         Ok(result)
@@ -231,21 +554,168 @@ struct RenderedBlogpost<'a, 'b> {
     title: &'a str,
     author_name: &'b str,
     created_at: NaiveDateTime,
+    /// Only set if the blogpost has been edited since it was posted.
+    updated_at: Option<NaiveDateTime>,
     body: &'a str,
     taglist: Vec<&'a str>,
     blogpost_id: i32,
+    series: Option<&'b SeriesNav>,
+    cover_image: Option<&'a str>,
+    canonical_url: Option<&'a str>,
+    reading_minutes: i32,
+    /// Every heading in the post, for the sidebar.
+    toc: Vec<TocEntry>,
+    backlinks: &'b [PostLink],
+    /// Only set if the post picks its own highlighting theme.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    highlight_stylesheet: Option<String>,
+}
+
+/// What a blogpost is rendered with, from the other posts around it.
+#[derive(Clone, Default, PartialEq)]
+pub struct PostContext {
+    pub series: Option<SeriesNav>,
+    /// Where the wiki links in the post lead.
+    pub links: ResolvedLinks,
+    /// Published posts that link to this one, newest first.
+    pub backlinks: Vec<PostLink>,
+}
+
+/// Where a blogpost sits in its series, for linking between the parts.
+#[derive(Clone, PartialEq, serde::Serialize)]
+pub struct SeriesNav {
+    title: String,
+    url: String,
+    /// Which part this is, starting from 1.
+    part: usize,
+    parts: usize,
+    prev: Option<PostLink>,
+    next: Option<PostLink>,
+}
+
+/// A link to another blogpost.
+#[derive(Clone, PartialEq, serde::Serialize)]
+pub struct PostLink {
+    title: String,
+    url: String,
+}
+
+impl PostLink {
+    #[inline]
+    fn new(blogpost: &Blogpost) -> Self {
+        Self {
+            title: blogpost.title.clone(),
+            url: blogpost_path(&blogpost.url),
+        }
+    }
+}
+
+#[derive(serde::Serialize)]
+struct SeriesPage {
+    title: String,
+    description: String,
+    parts: Vec<SeriesPart>,
+}
+
+#[derive(serde::Serialize)]
+struct SeriesPart {
+    number: usize,
+    title: String,
+    url: String,
+    created_at: NaiveDateTime,
+}
+
+/// Number of blogposts shown on each page of the listing.
+const POSTS_PER_PAGE: u64 = 10;
+
+#[derive(serde::Deserialize)]
+struct PageQuery {
+    page: Option<u64>,
+}
+
+#[derive(serde::Serialize)]
+struct BlogList {
+    title: String,
+    posts: Vec<ListedBlogpost>,
+    total: u64,
+    pages: Vec<PageLink>,
+    prev_url: Option<String>,
+    next_url: Option<String>,
+    archive: Vec<ArchiveMonth>,
+}
+
+#[derive(serde::Serialize)]
+struct ListedBlogpost {
+    title: String,
+    url: String,
+    created_at: NaiveDateTime,
+    /// Plain text, which the template escapes.
+    summary: String,
+    reading_minutes: i32,
+}
+
+impl ListedBlogpost {
+    #[inline]
+    fn new(bp: Blogpost) -> Self {
+        let Blogpost {
+            title,
+            url,
+            created_at,
+            summary,
+            reading_minutes,
+            ..
+        } = bp;
+        ListedBlogpost {
+            title,
+            url,
+            created_at,
+            summary: summary.unwrap_or_default(),
+            reading_minutes,
+        }
+    }
+}
+
+#[derive(serde::Serialize)]
+struct PageLink {
+    number: u64,
+    url: String,
+    current: bool,
 }
 
+#[derive(serde::Serialize)]
+struct ArchiveMonth {
+    year: i32,
+    month: u32,
+    name: String,
+    count: usize,
+}
+
+/// Forget every rendered blogpost. Pages show the titles and URLs of the
+/// posts that they link to, that link to them and that are in their
+/// series, so saving one post or series can change the pages of others.
 #[inline]
-pub fn invalidate_cache(id: i32) {
-    let cache = &*BLOGPOST_CACHE;
-    cache.remove(&id);
+pub fn invalidate_cache() {
+    CACHE_GENERATION.fetch_add(1, Ordering::SeqCst);
+    BLOGPOST_CACHE.clear();
 }
 
 #[cfg(test)]
 mod tests {
-    use super::view_blogpost;
-    use crate::{markdown, models::Blogpost, pagerender::PageRenderState, templates};
+    use super::{
+        archive_blogpost, archive_months, invalidate_cache, list_blogpost, month_bounds,
+        post_context, series_nav, view_blogpost, view_blogpost_inner, view_series_inner,
+        PostContext,
+    };
+    use crate::{
+        csrf_integration, markdown,
+        mock_database::MockDatabase,
+        models::{Blogpost, BlogpostChange, Model, NewSeries},
+        pagerender::PageRenderState,
+        sanitize::Policy,
+        templates, Database,
+    };
+    use chrono::{Datelike, NaiveDate};
+    use std::sync::Arc;
     use warp::Reply;
 
     #[test]
@@ -254,18 +724,19 @@ mod tests {
         markdown::initialize_markdown();
 
         let blogpost = Blogpost {
-            id: 1,
-            title: "Chasing Suns".into(),
             tags: "story,humor,nothing".into(),
-            url: "chasing-suns".into(),
             body: "...and we spent so much *time* chasing ~~suns~~, we forgot what **we** were really after.".into(),
-            author_id: 1,
-            created_at: chrono::Local::now().naive_local(),
+            ..Blogpost::test()
         };
         let author_name = "John Notgull";
 
         let html = blogpost
-            .render_to_html(author_name, &mut PageRenderState::default())
+            .render_to_html(
+                author_name,
+                Policy::Trusted,
+                &PostContext::default(),
+                &mut PageRenderState::default(),
+            )
             .unwrap();
 
         // check to see if it contains strings
@@ -277,6 +748,7 @@ mod tests {
             ">nothing</",
             "<strong>we</strong>",
             "and",
+            "1 min read",
         ];
 
         for string in string_contained {
@@ -284,6 +756,61 @@ mod tests {
         }
     }
 
+    #[test]
+    fn render_highlight_theme() {
+        templates::initialize_test_templates().unwrap();
+        markdown::initialize_markdown();
+
+        let mut blogpost = Blogpost {
+            body: "```rust\nfn main() {}\n```".into(),
+            ..Blogpost::test()
+        };
+        let render = |blogpost: Blogpost| {
+            blogpost
+                .render_to_html(
+                    "John Notgull",
+                    Policy::Strict,
+                    &PostContext::default(),
+                    &mut PageRenderState::default(),
+                )
+                .unwrap()
+        };
+
+        let html = render(blogpost.clone());
+        assert!(html.contains("href=\"/highlight/base16-ocean-dark.css\""));
+        assert!(html.contains("<pre class=\"hl-code\">"));
+
+        blogpost
+            .body
+            .insert_str(0, "+++\nhighlight_theme = \"InspiredGitHub\"\n+++\n");
+        let html = render(blogpost);
+        assert!(html.contains("href=\"/highlight/inspiredgithub.css\""));
+        assert!(!html.contains("base16-ocean-dark.css"));
+    }
+
+    #[test]
+    fn render_table_of_contents() {
+        templates::initialize_test_templates().unwrap();
+        markdown::initialize_markdown();
+
+        let blogpost = Blogpost {
+            body: "## Before\n\nText\n\n## After & Beyond\n\nMore text".into(),
+            ..Blogpost::test()
+        };
+        let html = blogpost
+            .render_to_html(
+                "John Notgull",
+                Policy::Strict,
+                &PostContext::default(),
+                &mut PageRenderState::default(),
+            )
+            .unwrap();
+
+        assert!(html.contains("<nav id=\"toc\">"));
+        assert!(html.contains("href=\"#user-content-after--beyond\">After &amp; Beyond</a>"));
+        assert!(html.contains("id=\"user-content-after--beyond\""));
+    }
+
     #[tokio::test]
     async fn test_blogpost_route() {
         templates::initialize_test_templates().unwrap();
@@ -300,10 +827,273 @@ mod tests {
             .into_response();
 
         assert_eq!(value.status(), 200);
+        assert!(value.headers().contains_key("Last-Modified"));
         let response = warp::hyper::body::to_bytes(value.into_body())
             .await
             .unwrap();
         let response = String::from_utf8(response.to_vec()).unwrap();
         assert!(response.contains("we spent so much time chasing suns"));
     }
+
+    #[tokio::test]
+    async fn test_blog_list_route() {
+        templates::initialize_test_templates().unwrap();
+        markdown::initialize_markdown();
+        csrf_integration::initialize_csrf_test();
+
+        let filter = list_blogpost();
+
+        let value = warp::test::request()
+            .method("GET")
+            .path("/?page=1")
+            .filter(&filter)
+            .await
+            .unwrap()
+            .into_response();
+
+        assert_eq!(value.status(), 200);
+        let response = warp::hyper::body::to_bytes(value.into_body())
+            .await
+            .unwrap();
+        let response = String::from_utf8(response.to_vec()).unwrap();
+        assert!(response.contains("href=\"/blog/chasing-suns\""));
+        assert!(response.contains("href=\"/blog/how-to-make-a-website\""));
+        // stored excerpts are plain text
+        assert!(response.contains("Hello, I am John Notgull. What if we made a website?"));
+        assert!(response.contains("1 min read"));
+
+        // pages far past the end don't overflow
+        let value = warp::test::request()
+            .method("GET")
+            .path(&format!("/?page={}", u64::MAX))
+            .filter(&filter)
+            .await
+            .unwrap()
+            .into_response();
+        assert_eq!(value.status(), 200);
+    }
+
+    #[tokio::test]
+    async fn test_blog_archive_route() {
+        templates::initialize_test_templates().unwrap();
+        markdown::initialize_markdown();
+        csrf_integration::initialize_csrf_test();
+
+        let filter = archive_blogpost();
+        let now = chrono::Local::now().naive_local();
+
+        let value = warp::test::request()
+            .method("GET")
+            .path(&format!("/archive/{}/{}", now.year(), now.month()))
+            .filter(&filter)
+            .await
+            .unwrap()
+            .into_response();
+
+        assert_eq!(value.status(), 200);
+        let response = warp::hyper::body::to_bytes(value.into_body())
+            .await
+            .unwrap();
+        let response = String::from_utf8(response.to_vec()).unwrap();
+        assert!(response.contains("Chasing Suns"));
+
+        // there are no posts before the test data was created
+        let value = warp::test::request()
+            .method("GET")
+            .path("/archive/2001/1")
+            .filter(&filter)
+            .await
+            .unwrap()
+            .into_response();
+
+        assert_eq!(value.status(), 200);
+        let response = warp::hyper::body::to_bytes(value.into_body())
+            .await
+            .unwrap();
+        let response = String::from_utf8(response.to_vec()).unwrap();
+        assert!(!response.contains("Chasing Suns"));
+
+        let value = warp::test::request()
+            .method("GET")
+            .path("/archive/2001/13")
+            .filter(&filter)
+            .await;
+        assert!(value.is_err());
+    }
+
+    #[tokio::test]
+    async fn series_navigation() {
+        templates::initialize_test_templates().unwrap();
+        markdown::initialize_markdown();
+
+        let database = MockDatabase::with_test_data();
+        let id = database
+            .insert_series(NewSeries {
+                title: "Suns and Websites".into(),
+                url: "suns-and-websites".into(),
+                description: "Two *unrelated* things".into(),
+            })
+            .await
+            .unwrap();
+        for (post, position) in [(1, 1), (2, 2)] {
+            let change = BlogpostChange {
+                series_id: Some(Some(id)),
+                series_position: Some(Some(position)),
+                ..Default::default()
+            };
+            database.update_blogpost(post, change).await.unwrap();
+        }
+
+        let blogpost = database.get_blogpost_by_id(2).await.unwrap();
+        let nav = series_nav(&database, &blogpost).await.unwrap().unwrap();
+        assert!(nav.next.is_none());
+        let context = PostContext {
+            series: Some(nav),
+            ..Default::default()
+        };
+        let html = blogpost
+            .render_to_html(
+                "John Notgull",
+                Policy::Trusted,
+                &context,
+                &mut PageRenderState::default(),
+            )
+            .unwrap();
+        for string in [
+            "Part 2 of 2",
+            "href=\"/blog/series/suns-and-websites\"",
+            "href=\"/blog/chasing-suns\"",
+        ] {
+            assert!(html.contains(string), "Could not find `{}`", string);
+        }
+
+        let blogpost = database.get_blogpost_by_id(1).await.unwrap();
+        let nav = series_nav(&database, &blogpost).await.unwrap().unwrap();
+        assert_eq!((nav.part, nav.parts), (1, 2));
+        assert!(nav.prev.is_none());
+
+        let value = view_series_inner(
+            "suns-and-websites".into(),
+            Arc::new(database),
+            PageRenderState::default(),
+        )
+        .await
+        .unwrap()
+        .into_response();
+        let response = warp::hyper::body::to_bytes(value.into_body())
+            .await
+            .unwrap();
+        let response = String::from_utf8(response.to_vec()).unwrap();
+        assert!(response.contains("<em>unrelated</em>"));
+        let first = response.find("Chasing Suns").unwrap();
+        let second = response.find("How to make a website").unwrap();
+        assert!(first < second);
+    }
+
+    #[tokio::test]
+    async fn wiki_links_and_backlinks() {
+        templates::initialize_test_templates().unwrap();
+        markdown::initialize_markdown();
+
+        let database = MockDatabase::with_test_data();
+        let change = BlogpostChange {
+            body: Some("See [[how-to-make-a-website|this]] and [[nowhere]].".into()),
+            ..Default::default()
+        };
+        Blogpost::update(&database, 1, change).await.unwrap();
+
+        // links follow the post when its URL changes
+        let change = BlogpostChange {
+            url: Some("making-websites".into()),
+            ..Default::default()
+        };
+        Blogpost::update(&database, 2, change).await.unwrap();
+
+        let blogpost = database.get_blogpost_by_id(1).await.unwrap();
+        let context = post_context(&database, &blogpost).await.unwrap();
+        assert!(context.backlinks.is_empty());
+        let html = blogpost
+            .render_to_html(
+                "John Notgull",
+                Policy::Trusted,
+                &context,
+                &mut PageRenderState::default(),
+            )
+            .unwrap();
+        assert!(html.contains("<a class=\"wiki-link\" href=\"/blog/making-websites\">this</a>"));
+        assert!(html.contains("<span class=\"broken-link\""));
+
+        let blogpost = database.get_blogpost_by_id(2).await.unwrap();
+        let context = post_context(&database, &blogpost).await.unwrap();
+        let html = blogpost
+            .render_to_html(
+                "John Notgull",
+                Policy::Trusted,
+                &context,
+                &mut PageRenderState::default(),
+            )
+            .unwrap();
+        assert!(html.contains("Linked from"));
+        assert!(html.contains("href=\"/blog/chasing-suns\">Chasing Suns</a>"));
+
+        // deleted posts don't link anywhere
+        database.delete_blogpost(1).await.unwrap();
+        let context = post_context(&database, &blogpost).await.unwrap();
+        assert!(context.backlinks.is_empty());
+    }
+
+    #[tokio::test]
+    async fn cached_pages_follow_other_posts() {
+        templates::initialize_test_templates().unwrap();
+        markdown::initialize_markdown();
+
+        let database = Arc::new(MockDatabase::with_test_data());
+        let view = |database: Arc<MockDatabase>| async move {
+            let value = view_blogpost_inner(
+                "how-to-make-a-website".into(),
+                database,
+                PageRenderState::default(),
+            )
+            .await
+            .unwrap();
+            let response = warp::hyper::body::to_bytes(value.into_body())
+                .await
+                .unwrap();
+            String::from_utf8(response.to_vec()).unwrap()
+        };
+        assert!(!view(database.clone()).await.contains("Linked from"));
+
+        // saving another post that links here renders this one again
+        let change = BlogpostChange {
+            body: Some("See [[how-to-make-a-website]].".into()),
+            ..Default::default()
+        };
+        Blogpost::update(&*database, 1, change).await.unwrap();
+        invalidate_cache();
+        assert!(view(database).await.contains("Linked from"));
+    }
+
+    #[test]
+    fn archive_grouping() {
+        let date = |y, m, d| NaiveDate::from_ymd(y, m, d).and_hms(12, 0, 0);
+        let months = archive_months(vec![
+            date(2021, 12, 20),
+            date(2022, 1, 3),
+            date(2021, 12, 1),
+            date(2022, 1, 30),
+            date(2021, 11, 5),
+        ]);
+
+        let summary: Vec<_> = months.iter().map(|m| (m.year, m.month, m.count)).collect();
+        assert_eq!(summary, vec![(2022, 1, 2), (2021, 12, 2), (2021, 11, 1)]);
+        assert_eq!(months[0].name, "January 2022");
+    }
+
+    #[test]
+    fn month_bounds_wrap_year() {
+        let (start, end) = month_bounds(2021, 12).unwrap();
+        assert_eq!(start, NaiveDate::from_ymd(2021, 12, 1).and_hms(0, 0, 0));
+        assert_eq!(end, NaiveDate::from_ymd(2022, 1, 1).and_hms(0, 0, 0));
+        assert!(month_bounds(2021, 0).is_none());
+    }
 }
@@ -25,6 +25,23 @@ pub struct Config {
     pub urls: Urls,
     pub oauth2: Oauth2Details,
     pub s3: S3Details,
+    /// How many days a blogpost stays in the trash before it's purged.
+    #[serde(default = "default_trash_retention_days")]
+    pub trash_retention_days: u32,
+    /// The syntect theme that code is highlighted with, unless a post picks
+    /// another.
+    #[serde(default = "default_highlight_theme")]
+    pub highlight_theme: String,
+}
+
+#[inline]
+const fn default_trash_retention_days() -> u32 {
+    30
+}
+
+#[inline]
+fn default_highlight_theme() -> String {
+    crate::highlight::DEFAULT_THEME.into()
 }
 
 #[derive(serde::Deserialize, Clone)]
@@ -1,20 +1,27 @@
 // GNU AGPL v3 License
 
 use crate::{
+    markdown::Summary,
     models::{
-        Blogpost, BlogpostChange, BlogpostFilter, NewBlogpost, NewUser, User, UserChange,
-        UserFilter,
+        blogpost_path, blogpost_url, Blogpost, BlogpostChange, BlogpostFilter, BlogpostSortField,
+        LinkTarget, MatchMode, NewBlogpost, NewSeries, NewUpload, NewUrlRedirect, NewUser, Series,
+        SeriesChange, SeriesFilter, Sort, SortDirection, Upload, UrlRedirect, User, UserChange,
+        UserDeletion, UserFilter, UserSortField,
     },
     schema, Database, DatabaseError,
 };
+use chrono::NaiveDateTime;
 use diesel::{
-    pg::PgConnection,
+    connection::{Connection, TransactionManager},
+    pg::{Pg, PgConnection},
     r2d2::{ConnectionManager, Pool, PoolError, PooledConnection},
 };
 use dotenv::dotenv;
+use futures_util::future::BoxFuture;
 use once_cell::sync::OnceCell;
-use std::env;
-use tokio::task::spawn_blocking;
+use parking_lot::Mutex;
+use std::{env, sync::Arc};
+use tokio::{runtime::Handle, task::spawn_blocking};
 
 /// Initialize the web server's database connection pool.
 #[inline]
@@ -57,27 +64,285 @@ pub enum InitDatabaseError {
     NoDatabaseUrl,
 }
 
-#[derive(Copy, Clone)]
-pub struct SqlDatabase;
+#[derive(Clone, Default)]
+pub struct SqlDatabase {
+    /// The connection of the transaction this handle is a part of, if any.
+    transaction: Option<Arc<Mutex<PgConn>>>,
+}
+
+impl SqlDatabase {
+    /// Run a blocking operation on the blocking task pool, using the
+    /// transaction's connection if there is one.
+    #[inline]
+    async fn run<T, F>(&self, f: F) -> Result<T, DatabaseError>
+    where
+        T: Send + 'static,
+        F: FnOnce(&PgConnection) -> Result<T, DatabaseError> + Send + 'static,
+    {
+        let transaction = self.transaction.clone();
+        spawn_blocking(move || match transaction {
+            Some(conn) => f(&conn.lock()),
+            None => f(&connect()?),
+        })
+        .await
+        .expect("Blocking task panicked")
+    }
+}
+
+/// Rolls back the transaction (or savepoint) on a connection if it is
+/// dropped before it is finished, e.g. if the future running the
+/// transaction is cancelled.
+struct RollbackGuard(Option<Arc<Mutex<PgConn>>>);
+
+impl RollbackGuard {
+    /// Start a new transaction, or a savepoint if the connection is already
+    /// in a transaction.
+    #[inline]
+    async fn begin(conn: Arc<Mutex<PgConn>>) -> Result<Self, DatabaseError> {
+        let begin = conn.clone();
+        spawn_blocking(move || {
+            let conn = begin.lock();
+            let conn: &PgConnection = &conn;
+            conn.transaction_manager().begin_transaction(conn)
+        })
+        .await
+        .expect("Blocking task panicked")?;
+
+        Ok(RollbackGuard(Some(conn)))
+    }
+
+    /// Finish the transaction by either committing it or rolling it back.
+    #[inline]
+    async fn finish(mut self, commit: bool) -> Result<(), DatabaseError> {
+        let conn = self.0.take().expect("Transaction already finished");
+        spawn_blocking(move || {
+            let conn = conn.lock();
+            let conn: &PgConnection = &conn;
+            let manager = conn.transaction_manager();
+            if commit {
+                manager.commit_transaction(conn)?;
+            } else {
+                manager.rollback_transaction(conn)?;
+            }
+            Ok(())
+        })
+        .await
+        .expect("Blocking task panicked")
+    }
+}
+
+impl Drop for RollbackGuard {
+    #[inline]
+    fn drop(&mut self) {
+        if let Some(conn) = self.0.take() {
+            let rollback = move || {
+                let conn = conn.lock();
+                let conn: &PgConnection = &conn;
+                if let Err(e) = conn.transaction_manager().rollback_transaction(conn) {
+                    tracing::error!("Unable to roll back abandoned transaction: {}", e);
+                }
+            };
+
+            // a blocking task may be holding the connection, so wait for it
+            // somewhere that won't hold up the runtime; the connection isn't
+            // handed back to the pool until this is done
+            match Handle::try_current() {
+                Ok(handle) => drop(handle.spawn_blocking(rollback)),
+                Err(_) => rollback(),
+            }
+        }
+    }
+}
+
+/// Filter a boxed query on a string column, according to the filter's
+/// match mode.
+macro_rules! filter_string {
+    ($query: ident, $mode: expr, $column: expr, $value: expr) => {
+        if let Some(value) = $value {
+            $query = match $mode {
+                MatchMode::Contains => $query.filter($column.ilike(format!("%{}%", value))),
+                MatchMode::Exact => $query.filter($column.eq(value.clone())),
+            };
+        }
+    };
+}
+
+/// Add another ordering to a boxed query, in the given direction.
+macro_rules! order_by_direction {
+    ($query: ident, $column: expr, $direction: expr) => {
+        $query = match $direction {
+            SortDirection::Ascending => $query.then_order_by($column.asc()),
+            SortDirection::Descending => $query.then_order_by($column.desc()),
+        }
+    };
+}
+
+/// Build a query over the `users` table that applies each of the
+/// conditions in a `UserFilter`, not including pagination.
+#[inline]
+fn filtered_users(filter: &UserFilter) -> schema::users::BoxedQuery<'static, Pg> {
+    use diesel::prelude::*;
+    use schema::users::dsl::*;
+
+    let mut query = users.into_boxed();
+    if !filter.include_deleted {
+        query = query.filter(deleted_at.is_null());
+    }
+    if let Some(ids) = &filter.id {
+        query = query.filter(id.eq_any(ids.0.clone()));
+    }
+    filter_string!(query, filter.match_mode, name, &filter.name);
+
+    query
+}
+
+/// Apply the orderings in a `Sort` to a query over the `users` table.
+#[inline]
+fn sorted_users(
+    mut query: schema::users::BoxedQuery<'static, Pg>,
+    sort: &Sort<UserSortField>,
+) -> schema::users::BoxedQuery<'static, Pg> {
+    use diesel::prelude::*;
+    use schema::users::dsl::*;
+
+    for (field, direction) in &sort.0 {
+        match field {
+            UserSortField::Id => order_by_direction!(query, id, direction),
+            UserSortField::Name => order_by_direction!(query, name, direction),
+            UserSortField::Roles => order_by_direction!(query, roles, direction),
+        }
+    }
+
+    query
+}
+
+/// Build a query over the `blogposts` table that applies each of the
+/// conditions in a `BlogpostFilter`, not including pagination.
+#[inline]
+fn filtered_blogposts(filter: &BlogpostFilter) -> schema::blogposts::BoxedQuery<'static, Pg> {
+    use diesel::prelude::*;
+    use schema::blogposts::dsl::*;
+
+    // trashed posts are only ever listed on their own
+    let mut query = if filter.trashed {
+        blogposts.filter(deleted_at.is_not_null()).into_boxed()
+    } else {
+        blogposts.filter(deleted_at.is_null()).into_boxed()
+    };
+
+    // filter on each of the listed fields
+    filter_string!(query, filter.match_mode, title, &filter.title);
+    filter_string!(query, filter.match_mode, tags, &filter.tags);
+    filter_string!(query, filter.match_mode, url, &filter.url);
+    filter_string!(query, filter.match_mode, body, &filter.body);
+    if let Some(ids) = &filter.author_id {
+        query = query.filter(author_id.eq_any(ids.0.clone()));
+    }
+    if let Some(after) = filter.created_after {
+        query = query.filter(created_at.ge(after));
+    }
+    if let Some(before) = filter.created_before {
+        query = query.filter(created_at.lt(before));
+    }
+
+    query
+}
+
+/// Apply the orderings in a `Sort` to a query over the `blogposts` table.
+#[inline]
+fn sorted_blogposts(
+    mut query: schema::blogposts::BoxedQuery<'static, Pg>,
+    sort: &Sort<BlogpostSortField>,
+) -> schema::blogposts::BoxedQuery<'static, Pg> {
+    use diesel::prelude::*;
+    use schema::blogposts::dsl::*;
+
+    for (field, direction) in &sort.0 {
+        match field {
+            BlogpostSortField::Id => order_by_direction!(query, id, direction),
+            BlogpostSortField::Title => order_by_direction!(query, title, direction),
+            BlogpostSortField::Url => order_by_direction!(query, url, direction),
+            BlogpostSortField::AuthorId => order_by_direction!(query, author_id, direction),
+            BlogpostSortField::CreatedAt => order_by_direction!(query, created_at, direction),
+        }
+    }
+
+    query
+}
 
 #[async_trait::async_trait]
 impl Database for SqlDatabase {
+    #[inline]
+    async fn transaction<T, E, F>(&self, f: F) -> Result<T, E>
+    where
+        T: Send,
+        E: From<DatabaseError> + Send,
+        F: for<'a> FnOnce(&'a Self) -> BoxFuture<'a, Result<T, E>> + Send,
+    {
+        // diesel turns transactions started inside of a transaction into
+        // savepoints on the same connection
+        let conn = match &self.transaction {
+            Some(conn) => conn.clone(),
+            None => {
+                let conn = spawn_blocking(connect)
+                    .await
+                    .expect("Blocking task panicked")
+                    .map_err(DatabaseError::from)?;
+                Arc::new(Mutex::new(conn))
+            }
+        };
+
+        let guard = RollbackGuard::begin(conn.clone()).await?;
+        let tx = SqlDatabase {
+            transaction: Some(conn),
+        };
+
+        let result = f(&tx).await;
+        let commit = result.is_ok();
+        if let Err(e) = guard.finish(commit).await {
+            if commit {
+                return Err(e.into());
+            }
+
+            // the original error is more useful to the caller
+            tracing::error!("Unable to roll back transaction: {}", e);
+        }
+
+        result
+    }
+
     #[inline]
     async fn get_blogpost_by_id(&self, sid: i32) -> Result<Blogpost, DatabaseError> {
-        spawn_blocking(move || {
+        self.run(move |conn| {
             use diesel::prelude::*;
             use schema::blogposts::dsl::*;
 
-            let conn = connect()?;
             let blogpost = blogposts
-                .filter(id.eq(sid))
-                .first(&conn)
+                .filter(id.eq(sid).and(deleted_at.is_null()))
+                .first(conn)
                 .optional()?
                 .ok_or(DatabaseError::NotFound)?;
             Ok(blogpost)
         })
         .await
-        .expect("Blocking task panicked")
+    }
+
+    #[inline]
+    async fn lock_blogpost(&self, sid: i32) -> Result<i32, DatabaseError> {
+        self.run(move |conn| {
+            use diesel::prelude::*;
+            use schema::blogposts::dsl::*;
+
+            let current = blogposts
+                .filter(id.eq(sid).and(deleted_at.is_null()))
+                .select(version)
+                .for_update()
+                .first(conn)
+                .optional()?
+                .ok_or(DatabaseError::NotFound)?;
+            Ok(current)
+        })
+        .await
     }
 
     #[inline]
@@ -85,152 +350,474 @@ impl Database for SqlDatabase {
         &self,
         surl: String,
     ) -> Result<(Blogpost, User), DatabaseError> {
-        spawn_blocking(move || {
+        self.run(move |conn| {
             use diesel::prelude::*;
             use schema::{blogposts::dsl::*, users};
 
-            let conn = connect()?;
             let blogpost = blogposts
-                .filter(url.eq(surl))
+                .filter(url.eq(surl).and(deleted_at.is_null()))
                 .inner_join(users::table)
-                .first(&conn)
+                .first(conn)
                 .optional()?
                 .ok_or(DatabaseError::NotFound)?;
             Ok(blogpost)
         })
         .await
-        .expect("Blocking task panicked")
+    }
+
+    #[inline]
+    async fn list_blogpost_urls(
+        &self,
+        prefix: String,
+    ) -> Result<Vec<(i32, String)>, DatabaseError> {
+        self.run(move |conn| {
+            use diesel::prelude::*;
+            use schema::blogposts::dsl::*;
+
+            let prefix = prefix
+                .replace('\\', "\\\\")
+                .replace('%', "\\%")
+                .replace('_', "\\_");
+            let urls = blogposts
+                .filter(url.like(format!("{}%", prefix)))
+                .select((id, url))
+                .load(conn)?;
+            Ok(urls)
+        })
+        .await
     }
 
     #[inline]
     async fn insert_blogpost(&self, bp: NewBlogpost) -> Result<i32, DatabaseError> {
-        spawn_blocking(move || {
+        self.run(move |conn| {
             use diesel::prelude::*;
             use schema::blogposts::dsl::*;
 
-            let conn = connect()?;
             let blogpost: Blogpost = diesel::insert_into(blogposts)
                 .values(bp)
-                .get_result(&conn)?;
+                .get_result(conn)?;
             Ok(blogpost.id)
         })
         .await
-        .expect("Blocking task panicked")
     }
 
     #[inline]
     async fn update_blogpost(&self, sid: i32, bp: BlogpostChange) -> Result<(), DatabaseError> {
-        spawn_blocking(move || {
+        self.run(move |conn| {
             use diesel::prelude::*;
-            use schema::blogposts::dsl::*;
+            use schema::{blogposts::dsl::*, url_redirects};
 
-            let conn = connect()?;
-            diesel::update(blogposts)
-                .filter(id.eq(sid))
-                .set(bp)
-                .execute(&conn)?;
-            Ok(())
+            conn.transaction(|| {
+                // posts in the trash can only be restored, not changed
+                let old_url: String = blogposts
+                    .filter(id.eq(sid).and(deleted_at.is_null()))
+                    .select(url)
+                    .first(conn)
+                    .optional()?
+                    .ok_or(DatabaseError::NotFound)?;
+                let new_url = bp.url.clone();
+
+                let updated = diesel::update(blogposts)
+                    .filter(id.eq(sid).and(deleted_at.is_null()))
+                    .set(bp)
+                    .execute(conn)?;
+                if updated == 0 {
+                    return Err(DatabaseError::NotFound);
+                }
+
+                // keep the old link working by pointing it at the post
+                if let Some(new_url) = new_url {
+                    if old_url != new_url {
+                        let old_path = blogpost_path(&old_url);
+                        diesel::insert_into(url_redirects::table)
+                            .values((
+                                url_redirects::path.eq(&old_path),
+                                url_redirects::blogpost_id.eq(sid),
+                            ))
+                            .on_conflict(url_redirects::path)
+                            .do_update()
+                            .set((
+                                url_redirects::blogpost_id.eq(sid),
+                                url_redirects::target.eq(None::<String>),
+                            ))
+                            .execute(conn)?;
+
+                        // the post is back at a path it used to have
+                        diesel::delete(
+                            url_redirects::table.filter(
+                                url_redirects::path
+                                    .eq(blogpost_path(&new_url))
+                                    .and(url_redirects::blogpost_id.eq(sid)),
+                            ),
+                        )
+                        .execute(conn)?;
+                    }
+                }
+
+                Ok(())
+            })
         })
         .await
-        .expect("Blocking task panicked")
     }
 
     #[inline]
     async fn list_blogposts(&self, filter: BlogpostFilter) -> Result<Vec<Blogpost>, DatabaseError> {
-        let BlogpostFilter {
-            title,
-            tags,
-            url,
-            body,
-            author_id,
-            skip,
-            count,
-        } = filter;
-        let [stitle, stags, surl, sbody] =
-            [title, tags, url, body].map(|t| t.map(|t| format!("%{}%", t)));
-        let sauthor_id = author_id;
+        self.run(move |conn| {
+            use diesel::prelude::*;
+            use schema::blogposts::dsl::*;
 
-        spawn_blocking(move || {
+            let mut query = filtered_blogposts(&filter);
+
+            query = match &filter.sort {
+                Some(sort) => sorted_blogposts(query, sort),
+                None => query.then_order_by(created_at.desc()),
+            };
+            query = query.then_order_by(id.desc());
+
+            // either start after the cursor, or skip a number of posts
+            query = match filter.cursor {
+                Some(cursor) => query.filter(
+                    created_at
+                        .lt(cursor.created_at)
+                        .or(created_at.eq(cursor.created_at).and(id.lt(cursor.id))),
+                ),
+                None => query.offset(filter.skip as i64),
+            };
+
+            let posts: Vec<Blogpost> = query.limit(filter.count as i64).load(conn)?;
+            Ok(posts)
+        })
+        .await
+    }
+
+    #[inline]
+    async fn count_blogposts(&self, filter: BlogpostFilter) -> Result<u64, DatabaseError> {
+        self.run(move |conn| {
+            use diesel::prelude::*;
+
+            let total: i64 = filtered_blogposts(&filter).count().get_result(conn)?;
+            Ok(total as u64)
+        })
+        .await
+    }
+
+    #[inline]
+    async fn list_blogpost_dates(&self) -> Result<Vec<NaiveDateTime>, DatabaseError> {
+        self.run(move |conn| {
             use diesel::prelude::*;
             use schema::blogposts::dsl::*;
 
-            // filter on each of the listed fields
-            let conn = connect()?;
-            let mut query = blogposts.into_boxed();
-            if let Some(stitle) = stitle {
-                query = query.filter(title.ilike(stitle));
-            }
-            if let Some(stags) = stags {
-                query = query.filter(tags.ilike(stags));
+            let dates = blogposts
+                .select(created_at)
+                .filter(deleted_at.is_null())
+                .order_by(created_at.desc())
+                .load(conn)?;
+            Ok(dates)
+        })
+        .await
+    }
+
+    #[inline]
+    async fn delete_blogpost(&self, sid: i32) -> Result<(), DatabaseError> {
+        self.run(move |conn| {
+            use diesel::{dsl::now, prelude::*};
+            use schema::blogposts::dsl::*;
+
+            let trashed = diesel::update(blogposts)
+                .filter(id.eq(sid).and(deleted_at.is_null()))
+                .set(deleted_at.eq(now.nullable()))
+                .execute(conn)?;
+            if trashed == 0 {
+                return Err(DatabaseError::NotFound);
             }
-            if let Some(surl) = surl {
-                query = query.filter(url.ilike(surl));
+            Ok(())
+        })
+        .await
+    }
+
+    #[inline]
+    async fn restore_blogpost(&self, sid: i32) -> Result<(), DatabaseError> {
+        self.run(move |conn| {
+            use diesel::prelude::*;
+            use schema::blogposts::dsl::*;
+
+            let restored = diesel::update(blogposts)
+                .filter(id.eq(sid).and(deleted_at.is_not_null()))
+                .set(deleted_at.eq(None::<NaiveDateTime>))
+                .execute(conn)?;
+            if restored == 0 {
+                return Err(DatabaseError::NotFound);
             }
-            if let Some(sbody) = sbody {
-                query = query.filter(body.ilike(sbody));
+            Ok(())
+        })
+        .await
+    }
+
+    #[inline]
+    async fn purge_blogpost(&self, sid: i32) -> Result<(), DatabaseError> {
+        self.run(move |conn| {
+            use diesel::prelude::*;
+            use schema::blogposts::dsl::*;
+
+            let purged =
+                diesel::delete(blogposts.filter(id.eq(sid).and(deleted_at.is_not_null())))
+                    .execute(conn)?;
+            if purged == 0 {
+                return Err(DatabaseError::NotFound);
             }
-            if let Some(sauthor_id) = sauthor_id {
-                query = query.filter(author_id.eq(sauthor_id));
+            Ok(())
+        })
+        .await
+    }
+
+    #[inline]
+    async fn purge_trash(&self, before: NaiveDateTime) -> Result<u64, DatabaseError> {
+        self.run(move |conn| {
+            use diesel::prelude::*;
+            use schema::blogposts::dsl::*;
+
+            let purged = diesel::delete(blogposts.filter(deleted_at.lt(before))).execute(conn)?;
+            Ok(purged as u64)
+        })
+        .await
+    }
+
+    #[inline]
+    async fn get_link_targets(&self, urls: Vec<String>) -> Result<Vec<LinkTarget>, DatabaseError> {
+        self.run(move |conn| {
+            use diesel::prelude::*;
+            use schema::{blogposts::dsl::*, url_redirects};
+
+            let mut targets: Vec<LinkTarget> = blogposts
+                .filter(url.eq_any(&urls).and(deleted_at.is_null()))
+                .select((url, title))
+                .load::<(String, String)>(conn)?
+                .into_iter()
+                .map(|(post_url, post_title)| LinkTarget {
+                    slug: post_url.clone(),
+                    url: post_url,
+                    title: post_title,
+                })
+                .collect();
+
+            // the rest may be URLs that posts used to have
+            let old_paths: Vec<String> = urls
+                .iter()
+                .filter(|link| !targets.iter().any(|target| &target.slug == *link))
+                .map(|link| blogpost_path(link))
+                .collect();
+            if !old_paths.is_empty() {
+                let moved: Vec<(String, String, String)> = url_redirects::table
+                    .inner_join(blogposts)
+                    .filter(
+                        url_redirects::path
+                            .eq_any(old_paths)
+                            .and(deleted_at.is_null()),
+                    )
+                    .select((url_redirects::path, url, title))
+                    .load(conn)?;
+                targets.extend(
+                    moved
+                        .into_iter()
+                        .filter_map(|(old_path, post_url, post_title)| {
+                            Some(LinkTarget {
+                                slug: blogpost_url(&old_path)?.into(),
+                                url: post_url,
+                                title: post_title,
+                            })
+                        }),
+                );
             }
 
-            let mut posts: Vec<Blogpost> = query
-                .order_by(created_at.desc())
-                .offset(skip as i64)
-                .limit(count as i64)
-                .load(&conn)?;
+            Ok(targets)
+        })
+        .await
+    }
+
+    #[inline]
+    async fn set_post_links(&self, sid: i32, urls: Vec<String>) -> Result<(), DatabaseError> {
+        self.run(move |conn| {
+            use diesel::prelude::*;
+            use schema::postlinks::dsl::*;
+
+            conn.transaction(|| {
+                diesel::delete(postlinks.filter(source_id.eq(sid))).execute(conn)?;
+
+                if !urls.is_empty() {
+                    let rows: Vec<_> = urls
+                        .iter()
+                        .map(|link| (source_id.eq(sid), target_url.eq(link)))
+                        .collect();
+                    diesel::insert_into(postlinks)
+                        .values(&rows)
+                        .on_conflict_do_nothing()
+                        .execute(conn)?;
+                }
+                Ok(())
+            })
+        })
+        .await
+    }
 
-            // limit str len to 100
-            posts.iter_mut().for_each(|post| {
-                let dnewline_index = post.body.find("\n\n").unwrap_or(500);
-                post.body.truncate(dnewline_index)
-            });
+    #[inline]
+    async fn list_unlinked_blogposts(&self) -> Result<Vec<(i32, String)>, DatabaseError> {
+        self.run(move |conn| {
+            use diesel::{
+                dsl::{exists, not},
+                prelude::*,
+            };
+            use schema::{blogposts::dsl::*, postlinks};
 
+            let stored = postlinks::table.filter(postlinks::source_id.eq(id));
+            let posts = blogposts
+                .filter(body.like("%[[%").and(not(exists(stored))))
+                .select((id, body))
+                .load(conn)?;
             Ok(posts)
         })
         .await
-        .expect("Blocking task panicked")
     }
 
     #[inline]
-    async fn delete_blogpost(&self, sid: i32) -> Result<(), DatabaseError> {
-        spawn_blocking(move || {
+    async fn list_unsummarized_blogposts(&self) -> Result<Vec<(i32, String)>, DatabaseError> {
+        self.run(move |conn| {
             use diesel::prelude::*;
             use schema::blogposts::dsl::*;
 
-            let conn = connect()?;
-            diesel::delete(blogposts.filter(id.eq(sid))).execute(&conn)?;
-            Ok(())
+            let posts = blogposts
+                .filter(
+                    word_count
+                        .eq(0)
+                        .or(summary.is_null().and(summary_generated.eq(true))),
+                )
+                .select((id, body))
+                .load(conn)?;
+            Ok(posts)
+        })
+        .await
+    }
+
+    #[inline]
+    async fn backfill_summary(&self, sid: i32, new: Summary) -> Result<(), DatabaseError> {
+        self.run(move |conn| {
+            use diesel::prelude::*;
+            use schema::blogposts::dsl::*;
+
+            let Summary {
+                excerpt,
+                word_count: words,
+                reading_minutes: minutes,
+            } = new;
+
+            // like the migrations that added these columns, this isn't a new
+            // version of the post; the triggers are only off until the
+            // transaction ends, and nobody else can write to the table until
+            // then
+            conn.transaction(|| {
+                diesel::sql_query("ALTER TABLE Blogposts DISABLE TRIGGER bump_version")
+                    .execute(conn)?;
+                diesel::sql_query("ALTER TABLE Blogposts DISABLE TRIGGER set_updated_at")
+                    .execute(conn)?;
+
+                diesel::update(blogposts.filter(id.eq(sid)))
+                    .set((word_count.eq(words), reading_minutes.eq(minutes)))
+                    .execute(conn)?;
+                let unsummarized = blogposts.filter(
+                    id.eq(sid)
+                        .and(summary.is_null())
+                        .and(summary_generated.eq(true)),
+                );
+                diesel::update(unsummarized)
+                    .set(summary.eq((!excerpt.is_empty()).then(|| excerpt)))
+                    .execute(conn)?;
+
+                diesel::sql_query("ALTER TABLE Blogposts ENABLE TRIGGER set_updated_at")
+                    .execute(conn)?;
+                diesel::sql_query("ALTER TABLE Blogposts ENABLE TRIGGER bump_version")
+                    .execute(conn)?;
+                Ok(())
+            })
+        })
+        .await
+    }
+
+    #[inline]
+    async fn list_backlinks(&self, sid: i32) -> Result<Vec<Blogpost>, DatabaseError> {
+        self.run(move |conn| {
+            use diesel::prelude::*;
+            use schema::{blogposts::dsl::*, postlinks, url_redirects};
+
+            let current_url: String = blogposts
+                .filter(id.eq(sid))
+                .select(url)
+                .first(conn)
+                .optional()?
+                .ok_or(DatabaseError::NotFound)?;
+            let old_paths: Vec<String> = url_redirects::table
+                .filter(url_redirects::blogpost_id.eq(sid))
+                .select(url_redirects::path)
+                .load(conn)?;
+
+            let mut urls: Vec<String> = old_paths
+                .iter()
+                .filter_map(|old_path| blogpost_url(old_path))
+                .map(Into::into)
+                .collect();
+            urls.push(current_url);
+
+            let sources = postlinks::table
+                .filter(postlinks::target_url.eq_any(urls))
+                .select(postlinks::source_id);
+            let posts = blogposts
+                .filter(id.eq_any(sources).and(id.ne(sid)).and(deleted_at.is_null()))
+                .order_by((created_at.desc(), id.desc()))
+                .load(conn)?;
+            Ok(posts)
         })
         .await
-        .expect("Blocking task panicked")
     }
 
     #[inline]
     async fn get_user_by_id(&self, sid: i32) -> Result<User, DatabaseError> {
-        spawn_blocking(move || {
+        self.run(move |conn| {
             use diesel::prelude::*;
             use schema::users::dsl::*;
 
-            let conn = connect()?;
             let user = users
                 .filter(id.eq(sid))
-                .first(&conn)
+                .first(conn)
                 .optional()?
                 .ok_or(DatabaseError::NotFound)?;
             Ok(user)
         })
         .await
-        .expect("Blocking task panicked")
+    }
+
+    #[inline]
+    async fn lock_user(&self, sid: i32) -> Result<i32, DatabaseError> {
+        self.run(move |conn| {
+            use diesel::prelude::*;
+            use schema::users::dsl::*;
+
+            let current = users
+                .filter(id.eq(sid))
+                .select(version)
+                .for_update()
+                .first(conn)
+                .optional()?
+                .ok_or(DatabaseError::NotFound)?;
+            Ok(current)
+        })
+        .await
     }
 
     #[inline]
     async fn get_user_by_uuid(&self, suuid: String) -> Result<User, DatabaseError> {
-        spawn_blocking(move || {
+        self.run(move |conn| {
             use diesel::prelude::*;
             use schema::users::dsl::*;
 
-            let conn = connect()?;
-            let user = users.filter(uuid.eq(&suuid)).first(&conn).optional()?;
+            let user = users.filter(uuid.eq(&suuid)).first(conn).optional()?;
             match user {
                 Some(user) => Ok(user),
                 None => {
@@ -242,79 +829,375 @@ impl Database for SqlDatabase {
                     };
                     diesel::insert_into(users)
                         .values(new_user)
-                        .get_result(&conn)
+                        .get_result(conn)
                         .map_err(Into::into)
                 }
             }
         })
         .await
-        .expect("Blocking task panicked")
     }
 
     #[inline]
     async fn insert_user(&self, user: NewUser) -> Result<i32, DatabaseError> {
-        spawn_blocking(move || {
+        self.run(move |conn| {
             use diesel::prelude::*;
             use schema::users::dsl::*;
 
-            let conn = connect()?;
-            let user: User = diesel::insert_into(users).values(user).get_result(&conn)?;
+            let user: User = diesel::insert_into(users).values(user).get_result(conn)?;
             Ok(user.id)
         })
         .await
-        .expect("Blocking task panicked")
     }
 
     #[inline]
     async fn update_user(&self, sid: i32, user: UserChange) -> Result<(), DatabaseError> {
-        spawn_blocking(move || {
+        self.run(move |conn| {
             use diesel::prelude::*;
             use schema::users::dsl::*;
 
-            let conn = connect()?;
             diesel::update(users)
                 .filter(id.eq(sid))
                 .set(user)
-                .execute(&conn)?;
+                .execute(conn)?;
             Ok(())
         })
         .await
-        .expect("Blocking task panicked")
     }
 
     #[inline]
     async fn list_users(&self, filter: UserFilter) -> Result<Vec<User>, DatabaseError> {
-        let UserFilter { name } = filter;
-        let sname = name.map(|name| format!("%{}%", name));
-
-        spawn_blocking(move || {
+        self.run(move |conn| {
             use diesel::prelude::*;
             use schema::users::dsl::*;
 
-            let conn = connect()?;
-            let mut query = users.into_boxed();
-            if let Some(sname) = sname {
-                query = query.filter(name.like(sname));
+            let query = match &filter.sort {
+                Some(sort) => sorted_users(filtered_users(&filter), sort),
+                None => filtered_users(&filter).then_order_by(name),
+            };
+            let userlist: Vec<User> = query
+                .then_order_by(id)
+                .offset(filter.skip as i64)
+                .limit(filter.count as i64)
+                .load(conn)?;
+            Ok(userlist)
+        })
+        .await
+    }
+
+    #[inline]
+    async fn count_users(&self, filter: UserFilter) -> Result<u64, DatabaseError> {
+        self.run(move |conn| {
+            use diesel::prelude::*;
+
+            let total: i64 = filtered_users(&filter).count().get_result(conn)?;
+            Ok(total as u64)
+        })
+        .await
+    }
+
+    #[inline]
+    async fn delete_user(&self, sid: i32, deletion: UserDeletion) -> Result<(), DatabaseError> {
+        self.run(move |conn| {
+            use diesel::{dsl::now, prelude::*};
+            use schema::{blogposts, uploads, users::dsl::*};
+
+            conn.transaction(|| {
+                let user = users.filter(id.eq(sid));
+                let changed = match deletion {
+                    UserDeletion::SoftDelete => diesel::update(user)
+                        .set(deleted_at.eq(now.nullable()))
+                        .execute(conn)?,
+                    UserDeletion::Anonymize => diesel::update(user)
+                        .set((
+                            uuid.eq(format!("deleted:{}", sid)),
+                            name.eq(None::<String>),
+                            roles.eq(0),
+                            deleted_at.eq(now.nullable()),
+                        ))
+                        .execute(conn)?,
+                    UserDeletion::Reassign { to } => {
+                        diesel::update(blogposts::table.filter(blogposts::author_id.eq(sid)))
+                            .set(blogposts::author_id.eq(to))
+                            .execute(conn)?;
+                        diesel::update(uploads::table.filter(uploads::uploader_id.eq(sid)))
+                            .set(uploads::uploader_id.eq(to))
+                            .execute(conn)?;
+                        diesel::delete(user).execute(conn)?
+                    }
+                };
+
+                if changed == 0 {
+                    return Err(DatabaseError::NotFound);
+                }
+                Ok(())
+            })
+        })
+        .await
+    }
+
+    #[inline]
+    async fn insert_upload(&self, upload: NewUpload) -> Result<i32, DatabaseError> {
+        self.run(move |conn| {
+            use diesel::prelude::*;
+            use schema::uploads::dsl::*;
+
+            let upload: Upload = diesel::insert_into(uploads)
+                .values(upload)
+                .get_result(conn)?;
+            Ok(upload.id)
+        })
+        .await
+    }
+
+    #[inline]
+    async fn get_upload_paths(&self, paths: Vec<String>) -> Result<Vec<String>, DatabaseError> {
+        self.run(move |conn| {
+            use diesel::prelude::*;
+            use schema::uploads::dsl::*;
+
+            let found = uploads
+                .filter(path.eq_any(paths).and(pending_until.is_null()))
+                .select(path)
+                .load::<String>(conn)?;
+            Ok(found)
+        })
+        .await
+    }
+
+    #[inline]
+    async fn get_upload_by_path(&self, spath: String) -> Result<Upload, DatabaseError> {
+        self.run(move |conn| {
+            use diesel::prelude::*;
+            use schema::uploads::dsl::*;
+
+            let upload = uploads
+                .filter(path.eq(spath))
+                .first(conn)
+                .optional()?
+                .ok_or(DatabaseError::NotFound)?;
+            Ok(upload)
+        })
+        .await
+    }
+
+    #[inline]
+    async fn complete_upload(&self, sid: i32) -> Result<(), DatabaseError> {
+        self.run(move |conn| {
+            use diesel::prelude::*;
+            use schema::uploads::dsl::*;
+
+            let completed =
+                diesel::update(uploads.filter(id.eq(sid).and(pending_until.is_not_null())))
+                    .set(pending_until.eq(None::<NaiveDateTime>))
+                    .execute(conn)?;
+            if completed == 0 {
+                return Err(DatabaseError::AlreadyExists);
             }
+            Ok(())
+        })
+        .await
+    }
 
-            let userlist: Vec<User> = query.order_by(name).load(&conn)?;
-            Ok(userlist)
+    #[inline]
+    async fn purge_pending_uploads(&self, before: NaiveDateTime) -> Result<u64, DatabaseError> {
+        self.run(move |conn| {
+            use diesel::prelude::*;
+            use schema::uploads::dsl::*;
+
+            let purged = diesel::delete(uploads.filter(pending_until.lt(before))).execute(conn)?;
+            Ok(purged as u64)
         })
         .await
-        .expect("Blocking task panicked")
     }
 
     #[inline]
-    async fn delete_user(&self, sid: i32) -> Result<(), DatabaseError> {
-        spawn_blocking(move || {
+    async fn get_redirect_target(&self, spath: String) -> Result<String, DatabaseError> {
+        self.run(move |conn| {
             use diesel::prelude::*;
-            use schema::users::dsl::*;
+            use schema::{blogposts, url_redirects::dsl::*};
+
+            // redirects for posts lead wherever the post is now, as long as
+            // it hasn't been moved to the trash
+            let (redirect_target, post_url): (Option<String>, Option<String>) = url_redirects
+                .left_join(
+                    blogposts::table.on(blogposts::id
+                        .nullable()
+                        .eq(blogpost_id)
+                        .and(blogposts::deleted_at.is_null())),
+                )
+                .filter(path.eq(spath))
+                .select((target, blogposts::url.nullable()))
+                .first(conn)?;
+
+            redirect_target
+                .or_else(|| post_url.map(|post_url| blogpost_path(&post_url)))
+                .ok_or(DatabaseError::NotFound)
+        })
+        .await
+    }
+
+    #[inline]
+    async fn list_redirects(&self) -> Result<Vec<UrlRedirect>, DatabaseError> {
+        self.run(move |conn| {
+            use diesel::prelude::*;
+            use schema::url_redirects::dsl::*;
+
+            let redirects = url_redirects
+                .order_by((created_at.desc(), id.desc()))
+                .load(conn)?;
+            Ok(redirects)
+        })
+        .await
+    }
+
+    #[inline]
+    async fn insert_redirect(&self, redirect: NewUrlRedirect) -> Result<i32, DatabaseError> {
+        self.run(move |conn| {
+            use diesel::prelude::*;
+            use schema::url_redirects::dsl::*;
 
-            let conn = connect()?;
-            diesel::delete(users.filter(id.eq(sid))).execute(&conn)?;
+            let redirect: UrlRedirect = diesel::insert_into(url_redirects)
+                .values(redirect)
+                .get_result(conn)?;
+            Ok(redirect.id)
+        })
+        .await
+    }
+
+    #[inline]
+    async fn delete_redirect(&self, sid: i32) -> Result<(), DatabaseError> {
+        self.run(move |conn| {
+            use diesel::prelude::*;
+            use schema::url_redirects::dsl::*;
+
+            let deleted = diesel::delete(url_redirects.filter(id.eq(sid))).execute(conn)?;
+            if deleted == 0 {
+                return Err(DatabaseError::NotFound);
+            }
             Ok(())
         })
         .await
-        .expect("Blocking task panicked")
+    }
+
+    #[inline]
+    async fn get_series_by_id(&self, sid: i32) -> Result<Series, DatabaseError> {
+        self.run(move |conn| {
+            use diesel::prelude::*;
+            use schema::series::dsl::*;
+
+            let found = series.filter(id.eq(sid)).first(conn)?;
+            Ok(found)
+        })
+        .await
+    }
+
+    #[inline]
+    async fn get_series_by_url(&self, surl: String) -> Result<Series, DatabaseError> {
+        self.run(move |conn| {
+            use diesel::prelude::*;
+            use schema::series::dsl::*;
+
+            let found = series.filter(url.eq(surl)).first(conn)?;
+            Ok(found)
+        })
+        .await
+    }
+
+    #[inline]
+    async fn insert_series(&self, new: NewSeries) -> Result<i32, DatabaseError> {
+        self.run(move |conn| {
+            use diesel::prelude::*;
+            use schema::series::dsl::*;
+
+            let inserted: Series = diesel::insert_into(series).values(new).get_result(conn)?;
+            Ok(inserted.id)
+        })
+        .await
+    }
+
+    #[inline]
+    async fn update_series(&self, sid: i32, change: SeriesChange) -> Result<(), DatabaseError> {
+        self.run(move |conn| {
+            use diesel::prelude::*;
+            use schema::series::dsl::*;
+
+            diesel::update(series)
+                .filter(id.eq(sid))
+                .set(change)
+                .execute(conn)?;
+            Ok(())
+        })
+        .await
+    }
+
+    #[inline]
+    async fn list_series(&self, filter: SeriesFilter) -> Result<Vec<Series>, DatabaseError> {
+        self.run(move |conn| {
+            use diesel::prelude::*;
+            use schema::series::dsl::*;
+
+            let found = series
+                .order_by((created_at.desc(), id.desc()))
+                .offset(filter.skip as i64)
+                .limit(filter.count as i64)
+                .load(conn)?;
+            Ok(found)
+        })
+        .await
+    }
+
+    #[inline]
+    async fn count_series(&self) -> Result<u64, DatabaseError> {
+        self.run(move |conn| {
+            use diesel::prelude::*;
+            use schema::series::dsl::*;
+
+            let total: i64 = series.count().get_result(conn)?;
+            Ok(total as u64)
+        })
+        .await
+    }
+
+    #[inline]
+    async fn delete_series(&self, sid: i32) -> Result<(), DatabaseError> {
+        self.run(move |conn| {
+            use diesel::prelude::*;
+            use schema::{blogposts, series::dsl::*};
+
+            conn.transaction(|| {
+                diesel::update(blogposts::table.filter(blogposts::series_id.eq(sid)))
+                    .set((
+                        blogposts::series_id.eq(None::<i32>),
+                        blogposts::series_position.eq(None::<i32>),
+                    ))
+                    .execute(conn)?;
+
+                let deleted = diesel::delete(series.filter(id.eq(sid))).execute(conn)?;
+                if deleted == 0 {
+                    return Err(DatabaseError::NotFound);
+                }
+                Ok(())
+            })
+        })
+        .await
+    }
+
+    #[inline]
+    async fn list_series_blogposts(&self, sid: i32) -> Result<Vec<Blogpost>, DatabaseError> {
+        self.run(move |conn| {
+            use diesel::prelude::*;
+            use schema::blogposts::dsl::*;
+
+            let posts = blogposts
+                .filter(series_id.eq(sid).and(deleted_at.is_null()))
+                .order_by((
+                    series_position.asc().nulls_last(),
+                    created_at.asc(),
+                    id.asc(),
+                ))
+                .load(conn)?;
+            Ok(posts)
+        })
+        .await
     }
 }
@@ -1,6 +1,9 @@
 // GNU AGPL v3 License
 
-use crate::{markdown, pagerender, templates, Config, FrontpageEntry, PageRenderError, Title};
+use crate::{
+    markdown, pagerender, sanitize::Policy, templates, Config, FrontpageEntry, PageRenderError,
+    Title,
+};
 use bytes::Bytes;
 use dashmap::DashMap;
 use futures_util::future::{ok, ready, TryFutureExt};
@@ -87,9 +90,11 @@ fn frontpages(
                 file.read_to_string(&mut buf).await?;
 
                 // convert to html via markdown
-                let res = tokio::task::spawn_blocking(move || markdown::markdown(&buf))
-                    .await
-                    .expect("Blocking task failed");
+                // these come from the site's own files
+                let res =
+                    tokio::task::spawn_blocking(move || markdown::markdown(&buf, Policy::Trusted))
+                        .await
+                        .expect("Blocking task failed");
 
                 // convert to Bytes and store in cache
                 let res: Arc<str> = res.into_boxed_str().into();
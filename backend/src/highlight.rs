@@ -0,0 +1,216 @@
+// GNU AGPL v3 License
+
+//! Syntax highlighting for code blocks in markdown.
+//!
+//! Code is highlighted with CSS classes rather than inline colors, so the
+//! colors come from a stylesheet that is generated from one of syntect's
+//! bundled themes and served from `/highlight/{theme}.css`. The config picks
+//! the theme that pages use by default, and a post can pick its own with
+//! `highlight_theme` in its front matter.
+
+use crate::{slug, Config};
+use comrak::adapters::SyntaxHighlighterAdapter;
+use futures_util::future;
+use once_cell::sync::{Lazy, OnceCell};
+use std::collections::HashMap;
+use syntect::{
+    highlighting::{Theme, ThemeSet},
+    html::{css_for_theme_with_class_style, ClassStyle, ClassedHTMLGenerator},
+    parsing::SyntaxSet,
+    util::LinesWithEndings,
+};
+use warp::{reply::with_header, Filter, Reply};
+
+/// The theme that's used if the config doesn't pick one.
+pub const DEFAULT_THEME: &str = "base16-ocean.dark";
+
+/// Classes are prefixed, so that they don't clash with the ones that the
+/// rest of the page uses.
+const CLASS_STYLE: ClassStyle = ClassStyle::SpacedPrefixed { prefix: "hl-" };
+
+/// The class that the stylesheet gives the theme's background and
+/// foreground colors to.
+const CODE_CLASS: &str = "hl-code";
+
+static SYNTAX_SET: Lazy<SyntaxSet> = Lazy::new(SyntaxSet::load_defaults_newlines);
+static THEME_SET: Lazy<ThemeSet> = Lazy::new(ThemeSet::load_defaults);
+static CONFIGURED_THEME: OnceCell<String> = OnceCell::new();
+
+/// Pick the default theme from the config. A theme that doesn't exist is
+/// warned about and left out.
+#[inline]
+pub fn initialize_highlighting(cfg: &Config) {
+    match find_theme(&cfg.highlight_theme) {
+        Some((name, _)) => {
+            let _ = CONFIGURED_THEME.set(name.into());
+        }
+        None => tracing::warn!(
+            "Unknown highlighting theme {:?}, using {:?} instead",
+            &cfg.highlight_theme,
+            DEFAULT_THEME
+        ),
+    }
+}
+
+/// Load the syntaxes that code can be highlighted with. This takes a while,
+/// so it's done up front rather than when the first post is rendered.
+#[inline]
+pub fn load_syntaxes() {
+    Lazy::force(&SYNTAX_SET);
+}
+
+/// The theme that pages use unless a post picks another.
+#[must_use]
+#[inline]
+pub fn default_theme() -> &'static str {
+    CONFIGURED_THEME.get().map_or(DEFAULT_THEME, String::as_str)
+}
+
+/// Look up a bundled theme by its name or by the slug of its name, along
+/// with its name.
+#[must_use]
+#[inline]
+pub fn find_theme(name: &str) -> Option<(&'static str, &'static Theme)> {
+    let themes = &THEME_SET.themes;
+    themes
+        .get_key_value(name)
+        .or_else(|| {
+            themes
+                .iter()
+                .find(|(theme_name, _)| slug::slugify(theme_name) == name)
+        })
+        .map(|(name, theme)| (name.as_str(), theme))
+}
+
+/// The path that the stylesheet for a theme is served from.
+#[must_use]
+#[inline]
+pub fn stylesheet_path(theme: &str) -> String {
+    format!("/highlight/{}.css", slug::slugify(theme))
+}
+
+/// Serve the stylesheet for any of the bundled themes.
+#[must_use]
+#[inline]
+pub fn stylesheet(
+) -> impl Filter<Extract = (impl Reply,), Error = warp::Rejection> + Clone + Send + Sync + 'static {
+    warp::path!("highlight" / String)
+        .and(warp::get())
+        .and_then(|file: String| {
+            let theme = file
+                .strip_suffix(".css")
+                .and_then(find_theme)
+                .map(|(_, theme)| theme);
+            future::ready(
+                theme
+                    .map(|theme| css_for_theme_with_class_style(theme, CLASS_STYLE))
+                    .ok_or_else(warp::reject::not_found),
+            )
+        })
+        .map(|css| with_header(css, "Content-Type", "text/css; charset=utf-8"))
+        .with(warp::reply::with::header("Cache-Control", "max-age=86400"))
+}
+
+/// Highlights code blocks with classes, for comrak.
+pub struct ClassedHighlighter;
+
+impl SyntaxHighlighterAdapter for ClassedHighlighter {
+    #[inline]
+    fn highlight(&self, lang: Option<&str>, code: &str) -> String {
+        let syntax_set = &*SYNTAX_SET;
+        let syntax = lang
+            .and_then(|lang| syntax_set.find_syntax_by_token(lang))
+            .unwrap_or_else(|| syntax_set.find_syntax_plain_text());
+
+        let mut generator =
+            ClassedHTMLGenerator::new_with_class_style(syntax, syntax_set, CLASS_STYLE);
+        for line in LinesWithEndings::from(code) {
+            generator.parse_html_for_line_which_includes_newline(line);
+        }
+        generator.finalize()
+    }
+
+    #[inline]
+    fn build_pre_tag(&self, attributes: &HashMap<String, String>) -> String {
+        let mut attributes = attributes.clone();
+        let class = attributes.entry("class".into()).or_default();
+        if !class.is_empty() {
+            class.push(' ');
+        }
+        class.push_str(CODE_CLASS);
+        opening_tag("pre", &attributes)
+    }
+
+    #[inline]
+    fn build_code_tag(&self, attributes: &HashMap<String, String>) -> String {
+        opening_tag("code", attributes)
+    }
+}
+
+#[inline]
+fn opening_tag(tag: &str, attributes: &HashMap<String, String>) -> String {
+    // sort the attributes, so that the same code always renders the same
+    let mut attributes: Vec<_> = attributes.iter().collect();
+    attributes.sort();
+
+    let mut html = format!("<{}", tag);
+    for (name, value) in attributes {
+        html.push_str(&format!(" {}=\"{}\"", name, tera::escape_html(value)));
+    }
+    html.push('>');
+    html
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{find_theme, stylesheet, stylesheet_path, ClassedHighlighter};
+    use comrak::adapters::SyntaxHighlighterAdapter;
+    use std::collections::HashMap;
+
+    #[test]
+    fn highlights_with_classes() {
+        let html = ClassedHighlighter.highlight(Some("rust"), "fn main() {}\n");
+        assert!(html.contains("class=\"hl-"));
+        assert!(!html.contains("style="));
+
+        let attributes = [("class".to_string(), "language-rust".to_string())]
+            .into_iter()
+            .collect::<HashMap<_, _>>();
+        assert_eq!(
+            ClassedHighlighter.build_pre_tag(&HashMap::new()),
+            "<pre class=\"hl-code\">"
+        );
+        assert_eq!(
+            ClassedHighlighter.build_code_tag(&attributes),
+            "<code class=\"language-rust\">"
+        );
+    }
+
+    #[test]
+    fn finds_themes() {
+        let (name, _) = find_theme("solarized-dark").unwrap();
+        assert_eq!(name, "Solarized (dark)");
+        assert_eq!(stylesheet_path(name), "/highlight/solarized-dark.css");
+        assert!(find_theme("base16-ocean.dark").is_some());
+        assert!(find_theme("nonexistent").is_none());
+    }
+
+    #[tokio::test]
+    async fn serves_stylesheets() {
+        let filter = stylesheet();
+
+        let value = warp::test::request()
+            .path("/highlight/base16-ocean-dark.css")
+            .reply(&filter)
+            .await;
+        assert_eq!(value.status(), 200);
+        assert_eq!(value.headers()["Content-Type"], "text/css; charset=utf-8");
+        let css = String::from_utf8(value.body().to_vec()).unwrap();
+        assert!(css.contains(".hl-code"));
+
+        for path in ["/highlight/nonexistent.css", "/highlight/base16-ocean-dark"] {
+            let value = warp::test::request().path(path).reply(&filter).await;
+            assert_eq!(value.status(), 404);
+        }
+    }
+}
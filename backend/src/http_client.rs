@@ -1,13 +1,22 @@
 // GNU AGPL v3 License
 
 use once_cell::sync::Lazy;
-use reqwest::Client;
+use reqwest::{Client, ClientBuilder};
 
 pub static CLIENT: Lazy<Client> = Lazy::new(|| {
+    client_builder()
+        .build()
+        .expect("Failed to build `reqwest` client")
+});
+
+/// Start building a client with the same settings as `CLIENT`, for requests
+/// that need their own.
+#[inline]
+pub fn client_builder() -> ClientBuilder {
     let builder = Client::builder();
 
     #[cfg(debug_assertions)]
     let builder = builder.danger_accept_invalid_certs(true);
 
-    builder.build().expect("Failed to build `reqwest` client")
-});
+    builder
+}
@@ -0,0 +1,644 @@
+// GNU AGPL v3 License
+
+//! Checking the markdown of posts for mistakes that don't stop them from
+//! rendering, like links to posts that don't exist and images without alt
+//! text.
+//!
+//! Links to other sites are only checked when asked for, since it means
+//! sending a request to each of them. Those requests only go to public
+//! addresses, so that posts can't be used to reach the server's own network.
+
+use crate::{
+    http_client, markdown,
+    models::{blogpost_path, blogpost_url},
+    placeholder, wikilink, Config, Database, DatabaseError, Urls,
+};
+use comrak::{
+    nodes::{AstNode, NodeValue},
+    parse_document, Arena,
+};
+use futures_util::{future, stream, StreamExt};
+use once_cell::sync::OnceCell;
+use reqwest::{header::LOCATION, redirect::Policy, Client, Response, StatusCode, Url};
+use serde::Serialize;
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    net::{IpAddr, Ipv4Addr, SocketAddr, ToSocketAddrs},
+    time::Duration,
+};
+
+/// How long another site has to answer before its link counts as broken.
+const EXTERNAL_TIMEOUT: Duration = Duration::from_secs(10);
+/// How many other sites are asked about their links at once.
+const EXTERNAL_CONCURRENCY: usize = 8;
+/// How many redirects a link to another site can go through before it
+/// counts as broken.
+const MAX_REDIRECTS: usize = 5;
+
+/// The URLs that the site and its uploads are served from, to tell links
+/// to them apart from links to other sites.
+static URLS: OnceCell<Urls> = OnceCell::new();
+
+#[inline]
+pub fn initialize_lint(cfg: &Config) {
+    let _ = URLS.set(cfg.urls.clone());
+}
+
+/// Addresses that links can be followed to even though they aren't public,
+/// for sites that tests serve themselves.
+#[cfg(test)]
+static TEST_ADDRESSES: once_cell::sync::Lazy<parking_lot::Mutex<BTreeSet<SocketAddr>>> =
+    once_cell::sync::Lazy::new(Default::default);
+
+#[cfg(test)]
+pub fn allow_test_address(addr: SocketAddr) {
+    TEST_ADDRESSES.lock().insert(addr);
+}
+
+#[cfg(test)]
+#[inline]
+fn is_test_address(addr: SocketAddr) -> bool {
+    TEST_ADDRESSES.lock().contains(&addr)
+}
+
+#[cfg(not(test))]
+#[inline]
+fn is_test_address(_: SocketAddr) -> bool {
+    false
+}
+
+#[cfg(test)]
+pub fn initialize_lint_test() {
+    let _ = URLS.set(Urls {
+        static_url: "https://test.static".into(),
+        api_url: "https://test.api/api".into(),
+        auth_url: "https://test.auth".into(),
+        web_url: "https://test.web".into(),
+    });
+}
+
+/// Something that looks wrong in a post.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct LintWarning {
+    pub kind: LintKind,
+    /// The line of the body that the problem is on, counting from one.
+    pub line: usize,
+    pub message: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LintKind {
+    /// A link to a post that doesn't exist, or isn't published.
+    BrokenLink,
+    /// A link to an upload that was never made.
+    MissingMedia,
+    MissingAltText,
+    /// A heading that's more than one level below the one before it. The
+    /// post's title counts as the first heading.
+    SkippedHeadingLevel,
+    /// A code block that runs to the end of the post.
+    UnclosedFence,
+    /// A link to another site that couldn't be followed.
+    BrokenExternalLink,
+}
+
+/// Check a post's markdown for problems, looking up the posts and uploads
+/// that it links to. Links to other sites are only followed if `external`
+/// is set.
+#[inline]
+pub async fn check(
+    database: &impl Database,
+    input: &str,
+    external: bool,
+) -> Result<Vec<LintWarning>, DatabaseError> {
+    let Scan {
+        mut warnings,
+        posts,
+        media,
+        external: external_links,
+    } = scan(input);
+
+    let found = database.get_link_targets(targets(&posts)).await?;
+    let found: BTreeSet<_> = found.into_iter().map(|target| target.slug).collect();
+    warnings.extend(
+        posts
+            .into_iter()
+            .filter(|post| !found.contains(&post.target))
+            .map(|post| {
+                let message = format!("There is no post at {}", blogpost_path(&post.target));
+                post.warning(LintKind::BrokenLink, message)
+            }),
+    );
+
+    let found = database.get_upload_paths(targets(&media)).await?;
+    warnings.extend(
+        media
+            .into_iter()
+            .filter(|file| !found.contains(&file.target))
+            .map(|file| {
+                let message = format!("Nothing has been uploaded to {}", &file.target);
+                file.warning(LintKind::MissingMedia, message)
+            }),
+    );
+
+    if external {
+        warnings.extend(check_external(external_links).await);
+    }
+
+    warnings.sort_by_key(|warning| warning.line);
+    Ok(warnings)
+}
+
+/// Something that a post links to, and where.
+struct Reference {
+    line: usize,
+    target: String,
+}
+
+impl Reference {
+    #[inline]
+    fn warning(self, kind: LintKind, message: String) -> LintWarning {
+        LintWarning {
+            kind,
+            line: self.line,
+            message,
+        }
+    }
+}
+
+/// Each target that is referred to, once.
+#[inline]
+fn targets(references: &[Reference]) -> Vec<String> {
+    references
+        .iter()
+        .map(|reference| reference.target.clone())
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .collect()
+}
+
+/// The problems that can be found in some markdown without looking
+/// anything up, along with everything that it links to.
+#[derive(Default)]
+struct Scan {
+    warnings: Vec<LintWarning>,
+    /// The URLs of the posts that are linked to.
+    posts: Vec<Reference>,
+    /// The paths of the uploads that are linked to.
+    media: Vec<Reference>,
+    external: Vec<Reference>,
+}
+
+impl Scan {
+    #[inline]
+    fn warn(&mut self, kind: LintKind, line: usize, message: String) {
+        self.warnings.push(LintWarning {
+            kind,
+            line,
+            message,
+        });
+    }
+
+    /// Sort a link by where it leads.
+    #[inline]
+    fn link(&mut self, line: usize, url: &[u8]) {
+        let url = String::from_utf8_lossy(url);
+        // the fragment and query don't change which page a link leads to
+        let url = url.split(&['#', '?'][..]).next().unwrap_or_default();
+        let urls = URLS.get();
+
+        let local = urls
+            .and_then(|urls| url.strip_prefix(urls.web_url.trim_end_matches('/')))
+            .or_else(|| url.starts_with('/').then(|| url));
+        let upload = urls
+            .and_then(|urls| url.strip_prefix(urls.static_url.trim_end_matches('/')))
+            .and_then(|path| path.strip_prefix('/'))
+            .filter(|path| path.starts_with("files/"));
+
+        let (references, target) = match (local, upload) {
+            (Some(path), _) => match blogpost_url(path) {
+                Some(slug) if !slug.is_empty() && !slug.contains('/') => (&mut self.posts, slug),
+                _ => return,
+            },
+            (None, Some(path)) => (&mut self.media, path),
+            (None, None) if url.starts_with("http://") || url.starts_with("https://") => {
+                (&mut self.external, url)
+            }
+            (None, None) => return,
+        };
+        references.push(Reference {
+            line,
+            target: target.to_string(),
+        });
+    }
+}
+
+/// Look through some markdown for problems, and for the things that it
+/// links to.
+#[inline]
+fn scan(input: &str) -> Scan {
+    let body = markdown::strip_front_matter(input);
+    // lines are counted from the top of the post, front matter and all
+    let offset = input[..input.len() - body.len()].matches('\n').count();
+    let mut scan = Scan::default();
+
+    if let Some(line) = placeholder::unclosed_fence(body) {
+        scan.warn(
+            LintKind::UnclosedFence,
+            offset + line + 1,
+            "This code block is never closed".into(),
+        );
+    }
+
+    let (text, wiki_links) = wikilink::extract(body);
+    let arena = Arena::new();
+    let root = parse_document(&arena, &text, &markdown::COMRAK_OPTIONS);
+    let mut level = 1;
+    for node in root.descendants() {
+        let line = offset + line_of(node);
+        match node.data.borrow().value {
+            NodeValue::Heading(ref heading) => {
+                if heading.level > level + 1 {
+                    scan.warn(
+                        LintKind::SkippedHeadingLevel,
+                        line,
+                        format!(
+                            "This heading is level {}, but the one before it is level {}",
+                            heading.level, level
+                        ),
+                    );
+                }
+                level = heading.level;
+            }
+            NodeValue::Image(ref image) => {
+                if !has_text(node) {
+                    scan.warn(
+                        LintKind::MissingAltText,
+                        line,
+                        format!(
+                            "The image {} has no alt text",
+                            String::from_utf8_lossy(&image.url)
+                        ),
+                    );
+                }
+                scan.link(line, &image.url);
+            }
+            NodeValue::Link(ref link) => scan.link(line, &link.url),
+            NodeValue::Text(ref literal) => {
+                let literal = String::from_utf8_lossy(literal);
+                for link in wikilink::placeholders(&literal, &wiki_links) {
+                    scan.posts.push(Reference {
+                        line,
+                        target: link.slug.clone(),
+                    });
+                }
+            }
+            _ => {}
+        }
+    }
+
+    scan
+}
+
+/// The line that a node starts on. Inline nodes don't keep track of their
+/// lines, so they're put on the line that their block starts on.
+#[inline]
+fn line_of<'a>(node: &'a AstNode<'a>) -> usize {
+    node.ancestors()
+        .map(|node| node.data.borrow().start_line)
+        .find(|&line| line > 0)
+        .map_or(1, |line| line as usize)
+}
+
+/// Tell whether there's any text in a node, like the alt text of an image.
+#[inline]
+fn has_text<'a>(node: &'a AstNode<'a>) -> bool {
+    node.descendants()
+        .any(|node| match node.data.borrow().value {
+            NodeValue::Text(ref literal) => !literal.iter().all(u8::is_ascii_whitespace),
+            NodeValue::Code(_) => true,
+            _ => false,
+        })
+}
+
+/// Follow each link to another site, warning about the ones that don't
+/// lead anywhere. Each URL is only asked for once, however many times it's
+/// linked to.
+#[inline]
+async fn check_external(links: Vec<Reference>) -> Vec<LintWarning> {
+    let mut first_lines = BTreeMap::new();
+    for link in links {
+        first_lines.entry(link.target).or_insert(link.line);
+    }
+
+    stream::iter(first_lines)
+        .map(|(url, line)| async move {
+            follow(&url).await.err().map(|message| LintWarning {
+                kind: LintKind::BrokenExternalLink,
+                line,
+                message,
+            })
+        })
+        .buffer_unordered(EXTERNAL_CONCURRENCY)
+        .filter_map(future::ready)
+        .collect()
+        .await
+}
+
+/// Make sure that a link to another site leads somewhere. Redirects are
+/// followed one at a time, so that each place they lead is checked before
+/// anything is sent there.
+#[inline]
+async fn follow(link: &str) -> Result<(), String> {
+    let mut url: Url = link
+        .parse()
+        .map_err(|e| format!("{} isn't a valid URL: {}", link, e))?;
+
+    for _ in 0..=MAX_REDIRECTS {
+        let client = client_for(&url)
+            .await
+            .map_err(|e| format!("Unable to reach {}: {}", link, e))?;
+        let response = match request(&client, &url).await {
+            Ok(response) => response,
+            Err(e) => return Err(format!("Unable to reach {}: {}", link, e)),
+        };
+
+        let status = response.status();
+        if status.is_redirection() {
+            url = response
+                .headers()
+                .get(LOCATION)
+                .and_then(|location| location.to_str().ok())
+                .and_then(|location| url.join(location).ok())
+                .ok_or_else(|| format!("{} redirects without saying where to", link))?;
+        } else if status.is_client_error() || status.is_server_error() {
+            return Err(format!("{} answered with {}", link, status));
+        } else {
+            return Ok(());
+        }
+    }
+
+    Err(format!("{} redirects too many times", link))
+}
+
+/// Ask for a page with a HEAD request, or with a GET request for sites that
+/// don't answer those.
+#[inline]
+async fn request(client: &Client, url: &Url) -> reqwest::Result<Response> {
+    let response = client.head(url.clone()).send().await?;
+    if response.status() == StatusCode::METHOD_NOT_ALLOWED {
+        client.get(url.clone()).send().await
+    } else {
+        Ok(response)
+    }
+}
+
+/// A client that only connects to the public address that a URL's host
+/// resolves to, and doesn't follow redirects, so that the host can't lead
+/// it anywhere else.
+#[inline]
+async fn client_for(url: &Url) -> Result<Client, String> {
+    if !matches!(url.scheme(), "http" | "https") {
+        return Err("only http and https links can be followed".into());
+    }
+    let port = url.port_or_known_default().ok_or("the URL has no port")?;
+    let builder = http_client::client_builder()
+        .redirect(Policy::none())
+        .timeout(EXTERNAL_TIMEOUT);
+
+    let host = url.host_str().ok_or("the URL has no host")?;
+
+    // IPv6 addresses are written in brackets
+    let ip = host.trim_start_matches('[').trim_end_matches(']');
+    if let Ok(ip) = ip.parse::<IpAddr>() {
+        let addr = SocketAddr::new(ip, port);
+        if !is_allowed(addr) {
+            return Err(format!("{} isn't a public address", ip));
+        }
+        return builder.build().map_err(|e| e.to_string());
+    }
+
+    let domain = host.to_owned();
+    let addrs = tokio::task::spawn_blocking(move || {
+        (domain.as_str(), port)
+            .to_socket_addrs()
+            .map(Iterator::collect::<Vec<_>>)
+    })
+    .await
+    .expect("Blocking DNS task panicked")
+    .map_err(|e| e.to_string())?;
+
+    // the connection could go to any of them, so they all have to be public,
+    // and it's pinned to one so that the host can't answer differently later
+    if let Some(addr) = addrs.iter().find(|addr| !is_allowed(**addr)) {
+        return Err(format!("{} isn't a public address", addr.ip()));
+    }
+    let addr = *addrs.first().ok_or("the host has no addresses")?;
+    builder
+        .resolve(host, addr)
+        .build()
+        .map_err(|e| e.to_string())
+}
+
+/// Tell whether links can be followed to an address.
+#[inline]
+fn is_allowed(addr: SocketAddr) -> bool {
+    is_public(addr.ip()) || is_test_address(addr)
+}
+
+/// Tell whether an address is out on the internet, rather than on the
+/// server itself or a network that it's a part of.
+#[inline]
+fn is_public(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => is_public_v4(ip),
+        IpAddr::V6(ip) => {
+            let first = ip.segments()[0];
+            if ip.is_loopback() || ip.is_unspecified() || ip.is_multicast() {
+                false
+            } else if let Some(ip) = ip.to_ipv4() {
+                // IPv4 addresses written as IPv6 ones
+                is_public_v4(ip)
+            } else {
+                // unique local and link-local addresses
+                first & 0xfe00 != 0xfc00 && first & 0xffc0 != 0xfe80
+            }
+        }
+    }
+}
+
+#[inline]
+fn is_public_v4(ip: Ipv4Addr) -> bool {
+    let [a, b, ..] = ip.octets();
+    // the shared address space that carriers put their customers behind
+    let shared = a == 100 && b & 0xc0 == 64;
+    !(ip.is_loopback()
+        || ip.is_private()
+        || ip.is_link_local()
+        || ip.is_unspecified()
+        || ip.is_broadcast()
+        || ip.is_multicast()
+        || ip.is_documentation()
+        || shared
+        || a == 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{allow_test_address, check, initialize_lint_test, is_public, LintKind};
+    use crate::{mock_database::MockDatabase, models::NewUpload, Database};
+    use std::net::{IpAddr, SocketAddr};
+    use warp::{
+        http::{StatusCode, Uri},
+        Filter,
+    };
+
+    /// The kind and line of each warning about some markdown.
+    #[inline]
+    async fn lint(input: &str, external: bool) -> Vec<(LintKind, usize)> {
+        initialize_lint_test();
+        let database = MockDatabase::with_test_data();
+        database
+            .insert_upload(NewUpload {
+                path: "files/images/2022/sun.png".into(),
+                content_type: "image/png".into(),
+                size: 1024,
+                uploader_id: 1,
+                pending_until: None,
+            })
+            .await
+            .unwrap();
+
+        check(&database, input, external)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|warning| (warning.kind, warning.line))
+            .collect()
+    }
+
+    /// Serve a page that exists, one that doesn't and redirects to each of
+    /// them and to the cloud metadata service, to link to. The site's
+    /// address is the only private one that links can be followed to.
+    #[inline]
+    fn stub_site() -> SocketAddr {
+        let ok = warp::path!("ok").map(|| "Hello!");
+        let gone = warp::path!("gone").map(|| warp::reply::with_status("", StatusCode::GONE));
+        let moved = warp::path!("moved").map(|| warp::redirect::found(Uri::from_static("/ok")));
+        let metadata = warp::path!("metadata").map(|| {
+            warp::redirect::found(Uri::from_static("http://169.254.169.254/latest/meta-data/"))
+        });
+        let routes = ok.or(gone).or(moved).or(metadata);
+        let (addr, server) = warp::serve(routes).bind_ephemeral(([127, 0, 0, 1], 0));
+        tokio::spawn(server);
+        allow_test_address(addr);
+        addr
+    }
+
+    #[tokio::test]
+    async fn clean_posts() {
+        let input = "\
+## Chasing suns
+
+![The sun](https://test.static/files/images/2022/sun.png)
+
+See [[chasing-suns]] and [this post](/blog/how-to-make-a-website#intro).
+
+### Further reading
+
+```rust
+fn main() {}
+```
+";
+        assert!(lint(input, false).await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn finds_problems() {
+        let input = "\
++++
+title = \"Chasing suns\"
++++
+#### Too deep
+
+![](https://test.static/files/images/2022/moon.png)
+
+See [[catching-suns]] and [that post](https://test.web/blog/nonexistent).
+
+```
+fn main() {}
+";
+        assert_eq!(
+            lint(input, false).await,
+            [
+                (LintKind::SkippedHeadingLevel, 4),
+                (LintKind::MissingAltText, 6),
+                (LintKind::MissingMedia, 6),
+                (LintKind::BrokenLink, 8),
+                (LintKind::BrokenLink, 8),
+                (LintKind::UnclosedFence, 10),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn checks_external_links() {
+        let addr = stub_site();
+        let input = format!(
+            "[Fine](http://{0}/ok)\n\n[Gone](http://{0}/gone)\n\n[Gone again](http://{0}/gone)",
+            addr
+        );
+
+        // other sites are left alone unless they're asked about
+        assert!(lint(&input, false).await.is_empty());
+        assert_eq!(
+            lint(&input, true).await,
+            [(LintKind::BrokenExternalLink, 3)]
+        );
+    }
+
+    #[tokio::test]
+    async fn only_follows_links_to_public_addresses() {
+        let addr = stub_site();
+        let input = format!(
+            "[Moved](http://{0}/moved)\n\n\
+            [Metadata](http://{0}/metadata)\n\n\
+            [Other port](http://127.0.0.1:{1}/ok)\n\n\
+            [Localhost](http://localhost:{1}/ok)\n\n\
+            [Router](http://192.168.0.1/)\n\n\
+            [Metadata again](http://[::ffff:169.254.169.254]/)",
+            addr,
+            addr.port().wrapping_add(1),
+        );
+        assert_eq!(
+            lint(&input, true).await,
+            [
+                (LintKind::BrokenExternalLink, 3),
+                (LintKind::BrokenExternalLink, 5),
+                (LintKind::BrokenExternalLink, 7),
+                (LintKind::BrokenExternalLink, 9),
+                (LintKind::BrokenExternalLink, 11),
+            ]
+        );
+
+        for ip in ["93.184.216.34", "2606:2800:220:1:248:1893:25c8:1946"] {
+            assert!(is_public(ip.parse::<IpAddr>().unwrap()), "{}", ip);
+        }
+        for ip in [
+            "127.0.0.1",
+            "10.1.2.3",
+            "172.16.0.1",
+            "100.64.0.1",
+            "169.254.169.254",
+            "0.0.0.0",
+            "::1",
+            "::",
+            "fd00::1",
+            "fe80::1",
+            "::ffff:10.0.0.1",
+        ] {
+            assert!(!is_public(ip.parse::<IpAddr>().unwrap()), "{}", ip);
+        }
+    }
+}
@@ -9,6 +9,7 @@ extern crate diesel;
 mod config;
 mod http_client;
 mod query;
+mod redirects;
 mod routes;
 mod serve;
 
@@ -21,11 +22,19 @@ pub mod csrf_integration;
 pub mod database;
 pub mod error_page;
 pub mod frontpages;
+pub mod highlight;
+pub mod lint;
 pub mod markdown;
+pub mod math;
 pub mod models;
 pub mod pagerender;
+pub mod placeholder;
+pub mod sanitize;
 pub mod schema;
+pub mod shortcode;
+pub mod slug;
 pub mod templates;
+pub mod wikilink;
 
 #[cfg(test)]
 pub mod mock_database;
@@ -34,6 +43,7 @@ pub use config::*;
 pub use http_client::CLIENT;
 pub use query::*;
 
+use chrono::{Local, NaiveDateTime, TimeZone, Utc};
 use std::{env, ffi::OsString, io, process};
 
 fn main() {
@@ -66,6 +76,8 @@ async fn entry() {
 
     api::initialize_api(&cfg).await;
     markdown::initialize_markdown();
+    highlight::initialize_highlighting(&cfg);
+    lint::initialize_lint(&cfg);
     csrf_integration::initialize_csrf(&cfg);
     auth::initialize_auth(&cfg);
 
@@ -75,10 +87,17 @@ async fn entry() {
         process::exit(1)
     }
 
+    // posts from before links and summaries were stored need theirs stored
+    // before anyone saves over them, or they're listed
+    let db = database::SqlDatabase::default();
+    wikilink::backfill_links(&db).await;
+    models::backfill_summaries(&db).await;
+
     // load the routes to use
     let routes = routes::routes(&cfg);
 
     let task = tokio::spawn(auth::clear_auth_task());
+    let purge_task = tokio::spawn(api::purge_trash_task(cfg.trash_retention_days));
 
     // serve them
     if let Err(e) = serve::serve(routes, &cfg).await {
@@ -87,6 +106,7 @@ async fn entry() {
     }
 
     task.await.expect("Auth clearing task failed");
+    purge_task.await.expect("Trash purging task failed");
 }
 
 #[derive(Debug, thiserror::Error)]
@@ -105,6 +125,21 @@ pub enum PageRenderError {
 
 impl warp::reject::Reject for PageRenderError {}
 
+/// Format a timestamp from the database for HTTP headers like
+/// `Last-Modified`. Timestamps are stored in the server's local time, so
+/// they're converted to GMT first.
+#[must_use]
+#[inline]
+pub fn http_date(time: NaiveDateTime) -> String {
+    // local times that were skipped over by daylight saving time can't be
+    // stored anyway
+    let time = Local
+        .from_local_datetime(&time)
+        .earliest()
+        .map_or(time, |time| time.with_timezone(&Utc).naive_utc());
+    time.format("%a, %d %b %Y %H:%M:%S GMT").to_string()
+}
+
 #[derive(serde::Serialize)]
 pub struct Title<'a> {
     title: &'a str,
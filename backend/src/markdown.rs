@@ -1,34 +1,523 @@
 // GNU AGPL v3 License
 
+use crate::{
+    highlight::{self, ClassedHighlighter},
+    math,
+    sanitize::{self, Policy, ID_PREFIX},
+    shortcode,
+    wikilink::{self, ResolvedLinks},
+};
+use chrono::{DateTime, Local, NaiveDate, NaiveDateTime};
 use comrak::{
-    markdown_to_html_with_plugins, plugins::syntect::SyntectAdapter, ComrakExtensionOptions,
-    ComrakOptions, ComrakParseOptions, ComrakPlugins, ComrakRenderOptions, ComrakRenderPlugins,
+    format_html_with_plugins,
+    nodes::{AstNode, NodeCode, NodeHtmlBlock, NodeValue},
+    parse_document, Anchorizer, Arena, ComrakExtensionOptions, ComrakOptions, ComrakParseOptions,
+    ComrakPlugins, ComrakRenderOptions, ComrakRenderPlugins,
 };
-use once_cell::sync::OnceCell;
+use serde::Deserialize;
+use serde_json::Value;
+
+/// Render markdown into HTML, leaving out the front matter if there is
+/// any.
+///
+/// Raw HTML in the markdown is kept, as far as the policy allows. Math
+/// between `$` or `$$` is rendered into MathML, and shortcodes are expanded.
+/// Wiki links lead to wherever their slug would be, without checking that
+/// there's a post there.
+#[inline]
+pub fn markdown(input: &str, policy: Policy) -> String {
+    render(input, policy, None).html
+}
+
+/// A paragraph with only this in it is replaced with the table of contents.
+pub const TOC_MARKER: &str = "[[toc]]";
+
+/// Markdown that has been rendered into HTML, along with its headings.
+#[derive(Debug)]
+pub struct Rendered {
+    pub html: String,
+    pub toc: Vec<TocEntry>,
+}
+
+/// A heading in the table of contents.
+#[derive(Debug, Clone, PartialEq, serde::Serialize)]
+pub struct TocEntry {
+    /// 1 for `#`, up through 6 for `######`.
+    pub level: u32,
+    pub title: String,
+    /// The ID that the heading has once the HTML is sanitized.
+    pub anchor: String,
+}
+
+/// Render markdown into HTML like `markdown` does, and also collect the
+/// headings in it. Wiki links are rendered with the targets that they were
+/// resolved to, if they have been.
+#[inline]
+pub fn render(input: &str, policy: Policy, links: Option<&ResolvedLinks>) -> Rendered {
+    let (html, mut toc) = render_unsanitized(strip_front_matter(input), links, 0);
+    let html = sanitize::sanitize(&html, policy);
+
+    // the sanitizer does the same to the IDs in the HTML
+    if policy == Policy::Strict {
+        toc.iter_mut()
+            .for_each(|entry| entry.anchor.insert_str(0, ID_PREFIX));
+    }
+
+    Rendered { html, toc }
+}
+
+/// Render markdown into HTML, before it's sanitized. `depth` is how many
+/// shortcodes the markdown is inside of.
+#[inline]
+fn render_unsanitized(
+    input: &str,
+    links: Option<&ResolvedLinks>,
+    depth: usize,
+) -> (String, Vec<TocEntry>) {
+    let (input, shortcodes) = shortcode::extract(input);
+    let (input, wiki_links) = wikilink::extract(&input);
+    let (input, maths) = math::extract(&input);
+    let arena = Arena::new();
+    let root = parse_document(&arena, &input, &COMRAK_OPTIONS);
+    let mut toc = table_of_contents(root);
+    for entry in &mut toc {
+        entry.title = math::restore(&entry.title, &maths, |math| math.delimited().into());
+        entry.title = shortcode::restore(&entry.title, &shortcodes, |_| String::new());
+        entry.title = wikilink::restore_text(&entry.title, &wiki_links, links);
+    }
+
+    // put the table of contents wherever the author asked for it
+    let markers: Vec<_> = root.children().filter(|node| is_toc_marker(node)).collect();
+    if !markers.is_empty() {
+        let toc_html = toc_to_html(&toc).into_bytes();
+        for marker in markers {
+            for child in marker.children().collect::<Vec<_>>() {
+                child.detach();
+            }
+            marker.data.borrow_mut().value = NodeValue::HtmlBlock(NodeHtmlBlock {
+                block_type: 0,
+                literal: toc_html.clone(),
+            });
+        }
+    }
+
+    let mut html = vec![];
+    format_html_with_plugins(root, &COMRAK_OPTIONS, &mut html, &comrak_plugins())
+        .expect("Writing HTML to a Vec failed");
+    let html = math::restore(&String::from_utf8_lossy(&html), &maths, |math| {
+        math.to_html().into()
+    });
+    let html = wikilink::restore(&html, &wiki_links, links);
+    let html = shortcode::restore(&html, &shortcodes, |shortcode| match &shortcode.inner {
+        Some(_) if depth >= shortcode::MAX_DEPTH => shortcode.too_deep(),
+        inner => shortcode.to_html(
+            inner
+                .as_deref()
+                .map(|inner| render_unsanitized(inner, links, depth + 1).0),
+        ),
+    });
+
+    (html, toc)
+}
+
+/// How many words are read in a minute, for working out how long a post
+/// takes to read.
+const WORDS_PER_MINUTE: usize = 200;
+
+/// The longest that an excerpt can be, in characters.
+const EXCERPT_LEN: usize = 300;
+
+/// The plain text of a post, boiled down for listings.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Summary {
+    /// The first paragraph, cut short at a word if it's too long. Empty if
+    /// the post doesn't have any paragraphs.
+    pub excerpt: String,
+    pub word_count: i32,
+    /// Rounded up, so that every post takes at least a minute.
+    pub reading_minutes: i32,
+}
+
+/// Summarize a post from the text that its markdown renders into, rather
+/// than from its source, so that the excerpt doesn't have any markdown in
+/// it.
+#[must_use]
+#[inline]
+pub fn summarize(input: &str) -> Summary {
+    let (first_paragraph, text) = plain_text(strip_front_matter(input), 0);
+    let words = text.split_whitespace().count();
+
+    Summary {
+        excerpt: first_paragraph.map_or_else(String::new, |paragraph| excerpt(&paragraph)),
+        word_count: i32::try_from(words).unwrap_or(i32::MAX),
+        reading_minutes: i32::try_from((words + WORDS_PER_MINUTE - 1) / WORDS_PER_MINUTE)
+            .unwrap_or(i32::MAX)
+            .max(1),
+    }
+}
+
+/// The text in some markdown, along with the text of its first paragraph.
+/// Math is kept as its source, and shortcodes as the text inside of them,
+/// as long as they aren't nested too deeply. `depth` is how many shortcodes
+/// the markdown is inside of.
+#[inline]
+fn plain_text(input: &str, depth: usize) -> (Option<String>, String) {
+    let (input, shortcodes) = shortcode::extract(input);
+    let (input, wiki_links) = wikilink::extract(&input);
+    let (input, maths) = math::extract(&input);
+    let arena = Arena::new();
+    let root = parse_document(&arena, &input, &COMRAK_OPTIONS);
+
+    let restore = |text: &[u8]| {
+        let text = math::restore(&String::from_utf8_lossy(text), &maths, |math| {
+            math.source.as_str().into()
+        });
+        let text = wikilink::restore_text(&text, &wiki_links, None);
+        shortcode::restore(&text, &shortcodes, |shortcode| {
+            shortcode
+                .inner
+                .as_deref()
+                .filter(|_| depth < shortcode::MAX_DEPTH)
+                .map(|inner| plain_text(inner, depth + 1).1)
+                .unwrap_or_default()
+        })
+    };
+
+    let mut first_paragraph = None;
+    let mut output = String::new();
+    for node in root.descendants() {
+        if is_toc_marker(node) {
+            continue;
+        }
+
+        let (text, paragraph) = match node.data.borrow().value {
+            NodeValue::Paragraph => {
+                let mut text = vec![];
+                collect_text(node, &mut text);
+                (text, true)
+            }
+            NodeValue::Heading(_) | NodeValue::TableCell => {
+                let mut text = vec![];
+                collect_text(node, &mut text);
+                (text, false)
+            }
+            NodeValue::CodeBlock(ref block) => (block.literal.clone(), false),
+            _ => continue,
+        };
 
+        let text = restore(&text);
+        if paragraph && first_paragraph.is_none() && !text.trim().is_empty() {
+            first_paragraph = Some(text.clone());
+        }
+        output.push_str(&text);
+        output.push('\n');
+    }
+
+    (first_paragraph, output)
+}
+
+/// Cut some text down to `EXCERPT_LEN` characters, at the end of a word.
+#[inline]
+fn excerpt(text: &str) -> String {
+    let text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+    let end = match text.char_indices().nth(EXCERPT_LEN) {
+        Some((end, _)) => end,
+        None => return text,
+    };
+
+    // char_indices only gives boundaries, so this can't split a character
+    let cut = &text[..end];
+    let cut = match cut.rfind(' ') {
+        Some(space) => &cut[..space],
+        None => cut,
+    };
+    format!(
+        "{}…",
+        cut.trim_end_matches(|c: char| c.is_ascii_punctuation())
+    )
+}
+
+/// Collect the headings in a document, with the same IDs that comrak gives
+/// them when it renders.
 #[inline]
-pub fn markdown(input: &str) -> String {
-    let plugins = comrak_plugins();
-    markdown_to_html_with_plugins(input, &COMRAK_OPTIONS, &plugins)
+fn table_of_contents<'a>(root: &'a AstNode<'a>) -> Vec<TocEntry> {
+    let mut anchorizer = Anchorizer::new();
+    root.descendants()
+        .filter_map(|node| match node.data.borrow().value {
+            NodeValue::Heading(ref heading) => Some((node, heading.level)),
+            _ => None,
+        })
+        .map(|(node, level)| {
+            let mut text = vec![];
+            collect_text(node, &mut text);
+            let title = String::from_utf8_lossy(&text).into_owned();
+            TocEntry {
+                level,
+                anchor: anchorizer.anchorize(title.clone()),
+                title,
+            }
+        })
+        .collect()
+}
+
+/// Gather up the text in a node, the way comrak does when it makes IDs for
+/// headings.
+#[inline]
+fn collect_text<'a>(node: &'a AstNode<'a>, output: &mut Vec<u8>) {
+    match node.data.borrow().value {
+        NodeValue::Text(ref literal) | NodeValue::Code(NodeCode { ref literal, .. }) => {
+            output.extend_from_slice(literal)
+        }
+        NodeValue::LineBreak | NodeValue::SoftBreak => output.push(b' '),
+        _ => {
+            for child in node.children() {
+                collect_text(child, output);
+            }
+        }
+    }
+}
+
+/// Tell whether a node is a paragraph with only the TOC marker in it.
+/// The brackets may be split into their own text nodes.
+#[inline]
+fn is_toc_marker<'a>(node: &'a AstNode<'a>) -> bool {
+    if !matches!(node.data.borrow().value, NodeValue::Paragraph) {
+        return false;
+    }
+
+    let mut text = vec![];
+    for child in node.children() {
+        match child.data.borrow().value {
+            NodeValue::Text(ref literal) => text.extend_from_slice(literal),
+            _ => return false,
+        }
+    }
+    String::from_utf8_lossy(&text).trim() == TOC_MARKER
+}
+
+/// The table of contents that goes in place of the marker.
+#[inline]
+fn toc_to_html(toc: &[TocEntry]) -> String {
+    let items: String = toc
+        .iter()
+        .map(|entry| {
+            format!(
+                "<li class=\"toc-level-{}\"><a href=\"#{}\">{}</a></li>",
+                entry.level,
+                tera::escape_html(&entry.anchor),
+                tera::escape_html(&entry.title)
+            )
+        })
+        .collect();
+    format!("<nav class=\"toc\"><ul>{}</ul></nav>\n", items)
+}
+
+/// Metadata about a post, given in a block of TOML between `+++` lines or
+/// YAML between `---` lines at the very top of its markdown.
+#[derive(Debug, Default, PartialEq)]
+pub struct FrontMatter {
+    pub title: Option<String>,
+    /// Joined with commas, the same way that `Blogpost` stores them.
+    pub tags: Option<String>,
+    pub summary: Option<String>,
+    pub cover_image: Option<String>,
+    pub canonical_url: Option<String>,
+    /// When the post was published.
+    pub date: Option<NaiveDateTime>,
+    /// The name of the theme that the post's code is highlighted with,
+    /// instead of the default one.
+    pub highlight_theme: Option<String>,
+}
+
+/// Read the front matter at the top of some markdown, if there is any.
+#[inline]
+pub fn front_matter(input: &str) -> Result<Option<FrontMatter>, FrontMatterError> {
+    let (format, block) = match split_front_matter(input) {
+        Some((format, block, _)) => (format, block),
+        None => return Ok(None),
+    };
+
+    let value = match format {
+        FrontMatterFormat::Toml => toml_to_json(toml::from_str(block)?),
+        FrontMatterFormat::Yaml if block.trim().is_empty() => Value::Null,
+        FrontMatterFormat::Yaml => serde_yaml::from_str(block)?,
+    };
+    let raw = match value {
+        // an empty block has nothing in it, rather than being invalid
+        Value::Null => RawFrontMatter::default(),
+        value => serde_json::from_value(value)?,
+    };
+
+    Ok(Some(FrontMatter {
+        title: raw.title,
+        tags: raw.tags.map(Tags::join),
+        summary: raw.summary,
+        cover_image: raw.cover_image,
+        canonical_url: raw.canonical_url,
+        date: raw.date.map(|date| parse_date(&date)).transpose()?,
+        highlight_theme: raw
+            .highlight_theme
+            .map(|theme| match highlight::find_theme(&theme) {
+                Some((name, _)) => Ok(name.to_string()),
+                None => Err(FrontMatterError::UnknownTheme(theme)),
+            })
+            .transpose()?,
+    }))
+}
+
+/// The markdown after the front matter, or all of it if there isn't any.
+#[must_use]
+#[inline]
+pub fn strip_front_matter(input: &str) -> &str {
+    match split_front_matter(input) {
+        Some((_, _, rest)) => rest,
+        None => input,
+    }
+}
+
+#[derive(Copy, Clone)]
+enum FrontMatterFormat {
+    Toml,
+    Yaml,
+}
+
+/// Split the front matter block off of the top of some markdown, if it
+/// starts with one that is closed off.
+#[inline]
+fn split_front_matter(input: &str) -> Option<(FrontMatterFormat, &str, &str)> {
+    let mut lines = input.split_inclusive('\n');
+    let delimiter = lines.next()?.trim_end();
+    let format = match delimiter {
+        "+++" => FrontMatterFormat::Toml,
+        "---" => FrontMatterFormat::Yaml,
+        _ => return None,
+    };
+
+    let start = input.find('\n')? + 1;
+    let mut end = start;
+    for line in lines {
+        if line.trim_end() == delimiter {
+            return Some((format, &input[start..end], &input[end + line.len()..]));
+        }
+        end += line.len();
+    }
+
+    None
+}
+
+/// TOML has its own date type, which is turned into a string so that it's
+/// read the same way as the dates in YAML are.
+#[inline]
+fn toml_to_json(value: toml::Value) -> Value {
+    match value {
+        toml::Value::String(s) => Value::String(s),
+        toml::Value::Integer(i) => Value::from(i),
+        toml::Value::Float(f) => Value::from(f),
+        toml::Value::Boolean(b) => Value::Bool(b),
+        toml::Value::Datetime(dt) => Value::String(dt.to_string()),
+        toml::Value::Array(items) => Value::Array(items.into_iter().map(toml_to_json).collect()),
+        toml::Value::Table(table) => Value::Object(
+            table
+                .into_iter()
+                .map(|(key, value)| (key, toml_to_json(value)))
+                .collect(),
+        ),
+    }
+}
+
+/// Read a date with an optional time and offset. Dates without an offset
+/// are taken to be in local time, the same as post creation dates.
+#[inline]
+fn parse_date(date: &str) -> Result<NaiveDateTime, FrontMatterError> {
+    if let Ok(date) = DateTime::parse_from_rfc3339(date) {
+        return Ok(date.with_timezone(&Local).naive_local());
+    }
+
+    [
+        "%Y-%m-%dT%H:%M:%S%.f",
+        "%Y-%m-%d %H:%M:%S%.f",
+        "%Y-%m-%dT%H:%M",
+        "%Y-%m-%d %H:%M",
+    ]
+    .iter()
+    .find_map(|format| NaiveDateTime::parse_from_str(date, format).ok())
+    .or_else(|| {
+        NaiveDate::parse_from_str(date, "%Y-%m-%d")
+            .ok()
+            .map(|date| date.and_hms(0, 0, 0))
+    })
+    .ok_or_else(|| FrontMatterError::InvalidDate(date.into()))
+}
+
+#[derive(Default, Deserialize)]
+struct RawFrontMatter {
+    title: Option<String>,
+    tags: Option<Tags>,
+    #[serde(alias = "description")]
+    summary: Option<String>,
+    #[serde(alias = "cover")]
+    cover_image: Option<String>,
+    #[serde(alias = "canonical")]
+    canonical_url: Option<String>,
+    #[serde(alias = "publish_date")]
+    date: Option<String>,
+    #[serde(alias = "code_theme")]
+    highlight_theme: Option<String>,
+}
+
+/// Tags can be given as a list or as a string of comma-separated tags.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Tags {
+    List(Vec<String>),
+    Joined(String),
+}
+
+impl Tags {
+    #[inline]
+    fn join(self) -> String {
+        let tags = match self {
+            Tags::List(tags) => tags,
+            Tags::Joined(tags) => tags.split(',').map(String::from).collect(),
+        };
+
+        tags.iter()
+            .map(|tag| tag.trim())
+            .filter(|tag| !tag.is_empty())
+            .collect::<Vec<_>>()
+            .join(",")
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum FrontMatterError {
+    #[error("{0}")]
+    Toml(#[from] toml::de::Error),
+    #[error("{0}")]
+    Yaml(#[from] serde_yaml::Error),
+    #[error("{0}")]
+    Fields(#[from] serde_json::Error),
+    #[error("Unable to read the date {0:?}")]
+    InvalidDate(String),
+    #[error("There is no highlighting theme named {0:?}")]
+    UnknownTheme(String),
 }
 
 #[inline]
 pub fn initialize_markdown() {
-    let _ = SYNTECT_ADAPTOR.set(SyntectAdapter::new("base16-ocean.dark"));
+    highlight::load_syntaxes();
 }
 
 #[inline]
 fn comrak_plugins() -> ComrakPlugins<'static> {
     ComrakPlugins {
         render: ComrakRenderPlugins {
-            codefence_syntax_highlighter: Some(SYNTECT_ADAPTOR.get().unwrap()),
+            codefence_syntax_highlighter: Some(&ClassedHighlighter),
         },
     }
 }
 
-static SYNTECT_ADAPTOR: OnceCell<SyntectAdapter> = OnceCell::new();
-
-const COMRAK_OPTIONS: ComrakOptions = ComrakOptions {
+pub(crate) const COMRAK_OPTIONS: ComrakOptions = ComrakOptions {
     extension: ComrakExtensionOptions {
         strikethrough: true,
         table: true,
@@ -37,7 +526,7 @@ const COMRAK_OPTIONS: ComrakOptions = ComrakOptions {
         footnotes: true,
         tagfilter: false,
         autolink: false,
-        header_ids: None,
+        header_ids: Some(String::new()),
         description_lists: false,
         front_matter_delimiter: None,
     },
@@ -56,11 +545,235 @@ const COMRAK_OPTIONS: ComrakOptions = ComrakOptions {
 
 #[cfg(test)]
 mod tests {
-    use super::{initialize_markdown, markdown};
+    use super::{
+        front_matter, initialize_markdown, markdown, render, summarize, FrontMatterError, TocEntry,
+        EXCERPT_LEN,
+    };
+    use crate::{sanitize::Policy, templates};
+    use chrono::NaiveDate;
 
     #[test]
     fn basic_markdown() {
         initialize_markdown();
-        assert!(markdown("**Hello, world!**").contains("<p><strong>Hello, world!</strong></p>"));
+        assert!(markdown("**Hello, world!**", Policy::Strict)
+            .contains("<p><strong>Hello, world!</strong></p>"));
+    }
+
+    #[test]
+    fn raw_html_is_sanitized() {
+        initialize_markdown();
+        let input = "Hi <script>alert(1)</script><b onclick=\"alert(1)\">there</b>";
+        for policy in [Policy::Trusted, Policy::Strict] {
+            let html = markdown(input, policy);
+            assert!(!html.contains("<script"));
+            assert!(!html.contains("onclick"));
+            assert!(html.contains("<b>there</b>"));
+        }
+    }
+
+    #[test]
+    fn math() {
+        initialize_markdown();
+        let html = markdown(
+            "## Where $x_1$ goes\n\nSo $a_1 * b_2 = c$, and\n\n$$\n\\frac{1}{2}\n$$\n\n$\\frac{1}{$",
+            Policy::Strict,
+        );
+        assert!(html.contains("<math display=\"inline\">"));
+        assert!(html.contains("<math display=\"block\">"));
+        assert!(html.contains("<mfrac>"));
+        assert!(!html.contains("<em>"));
+        assert!(html.contains("class=\"math-error\""));
+
+        let rendered = render("## Where $x_1$ goes", Policy::Trusted, None);
+        assert_eq!(rendered.toc[0].title, "Where $x_1$ goes");
+    }
+
+    #[test]
+    fn shortcodes() {
+        initialize_markdown();
+        templates::initialize_test_templates().unwrap();
+        let input = "{{< youtube dQw4w9WgXcQ >}}\n\n\
+            {{< callout warning >}}\n**Careful!**\n{{< /callout >}}\n\n\
+            {{< nonexistent >}}\n\n`{{< youtube x >}}`";
+
+        let html = markdown(input, Policy::Trusted);
+        assert!(html.contains("<iframe"));
+        assert!(html.contains("src=\"https://www.youtube-nocookie.com/embed/dQw4w9WgXcQ\""));
+        assert!(html.contains("class=\"callout callout-warning\""));
+        assert!(html.contains("<strong>Careful!</strong>"));
+        assert!(html.contains("<span class=\"shortcode-warning\">Unknown shortcode"));
+        assert!(html.contains("<code>{{&lt; youtube x &gt;}}</code>"));
+
+        // shortcodes are held to the author's policy like everything else
+        assert!(!markdown(input, Policy::Strict).contains("<iframe"));
+
+        // deeply nested shortcodes stop being rendered instead of overflowing
+        // the stack
+        let depth = 5000;
+        let input: String = (0..depth)
+            .map(|i| format!("{{{{< n{} >}}}}", i))
+            .chain(std::iter::once("deep".to_string()))
+            .chain((0..depth).rev().map(|i| format!("{{{{< /n{} >}}}}", i)))
+            .collect();
+        assert!(markdown(&input, Policy::Trusted).contains("is nested too deeply"));
+        assert_eq!(summarize(&input).word_count, 0);
+    }
+
+    #[test]
+    fn table_of_contents() {
+        initialize_markdown();
+        let input = "# Chasing Suns\n\n[[toc]]\n\n## Setup\n\n### The `sun`\n\n## Setup\n";
+
+        let rendered = render(input, Policy::Trusted, None);
+        let anchors: Vec<_> = rendered
+            .toc
+            .iter()
+            .map(|entry| (entry.level, entry.anchor.as_str()))
+            .collect();
+        assert_eq!(
+            anchors,
+            [
+                (1, "chasing-suns"),
+                (2, "setup"),
+                (3, "the-sun"),
+                (2, "setup-1")
+            ]
+        );
+        assert_eq!(rendered.toc[2].title, "The sun");
+        assert!(rendered.html.contains("id=\"setup-1\""));
+        assert!(rendered.html.contains("<nav class=\"toc\">"));
+        assert!(rendered.html.contains("<a href=\"#the-sun\">The sun</a>"));
+        assert!(!rendered.html.contains("[[toc]]"));
+
+        // anchors follow the IDs that the sanitizer prefixes
+        let rendered = render(input, Policy::Strict, None);
+        assert_eq!(
+            rendered.toc[1],
+            TocEntry {
+                level: 2,
+                title: "Setup".into(),
+                anchor: "user-content-setup".into(),
+            }
+        );
+        assert!(rendered.html.contains("id=\"user-content-setup\""));
+        assert!(rendered.html.contains("href=\"#user-content-setup\""));
+    }
+
+    #[test]
+    fn summaries() {
+        let summary = summarize(
+            "+++\ntitle = \"Suns\"\n+++\n# Chasing *Suns*\n\n\
+            ...and we spent so **much** time chasing `suns`, like $x^2$ and \
+            [[how-to-make-a-website|websites]].\n\n```\nlet x = 1;\n```\n",
+        );
+        assert_eq!(
+            summary.excerpt,
+            "…and we spent so much time chasing suns, like x^2 and websites."
+        );
+        assert_eq!(summary.word_count, 18);
+        assert_eq!(summary.reading_minutes, 1);
+
+        assert_eq!(summarize("# Only a heading").excerpt, "");
+        assert_eq!(summarize("").reading_minutes, 1);
+        let long = "word ".repeat(401);
+        assert_eq!(summarize(&long).reading_minutes, 3);
+    }
+
+    #[test]
+    fn excerpts_are_cut_at_words() {
+        // multi-byte characters right where the excerpt is cut used to
+        // panic
+        let text = "ü".repeat(EXCERPT_LEN * 2);
+        let excerpt = summarize(&text).excerpt;
+        assert_eq!(excerpt.chars().count(), EXCERPT_LEN + 1);
+        assert!(excerpt.ends_with('…'));
+
+        let text = "sun, ".repeat(EXCERPT_LEN);
+        let excerpt = summarize(&text).excerpt;
+        assert!(excerpt.ends_with("sun…"), "{}", excerpt);
+        assert!(excerpt.chars().count() <= EXCERPT_LEN + 1);
+    }
+
+    #[test]
+    fn toml_front_matter() {
+        initialize_markdown();
+        let input = "+++\ntitle = \"Chasing Suns\"\ntags = [\"story\", \" funny \"]\n\
+            date = 2022-01-16\ncover = \"/images/sun.png\"\n+++\n\n**Hello**";
+
+        let fm = front_matter(input).unwrap().unwrap();
+        assert_eq!(fm.title.as_deref(), Some("Chasing Suns"));
+        assert_eq!(fm.tags.as_deref(), Some("story,funny"));
+        assert_eq!(fm.cover_image.as_deref(), Some("/images/sun.png"));
+        assert_eq!(
+            fm.date,
+            Some(NaiveDate::from_ymd(2022, 1, 16).and_hms(0, 0, 0))
+        );
+
+        let html = markdown(input, Policy::Strict);
+        assert!(!html.contains("title"));
+        assert!(html.contains("<strong>Hello</strong>"));
+    }
+
+    #[test]
+    fn yaml_front_matter() {
+        let input = "---\r\ntitle: Chasing Suns\r\ntags: story, funny\r\n\
+            summary: We forgot what we were after\r\n\
+            canonical_url: https://example.com/suns\r\n\
+            date: 2022-01-16 13:37:00\r\n\
+            highlight_theme: solarized-dark\r\n---\r\nHello";
+
+        let fm = front_matter(input).unwrap().unwrap();
+        assert_eq!(fm.tags.as_deref(), Some("story,funny"));
+        assert_eq!(fm.summary.as_deref(), Some("We forgot what we were after"));
+        assert_eq!(
+            fm.canonical_url.as_deref(),
+            Some("https://example.com/suns")
+        );
+        assert_eq!(
+            fm.date,
+            Some(NaiveDate::from_ymd(2022, 1, 16).and_hms(13, 37, 0))
+        );
+        assert_eq!(fm.highlight_theme.as_deref(), Some("Solarized (dark)"));
+        assert_eq!(super::strip_front_matter(input), "Hello");
+    }
+
+    #[test]
+    fn no_front_matter() {
+        assert!(front_matter("Hello").unwrap().is_none());
+        // an unclosed block is just a horizontal rule
+        assert!(front_matter("---\ntitle: Hello").unwrap().is_none());
+        assert_eq!(
+            front_matter("---\n---\nHello").unwrap(),
+            Some(Default::default())
+        );
+    }
+
+    #[test]
+    fn invalid_front_matter() {
+        assert!(matches!(
+            front_matter("+++\ntitle = \n+++\n"),
+            Err(FrontMatterError::Toml(..))
+        ));
+        assert!(matches!(
+            front_matter("---\ntitle: [1, 2]\n---\n"),
+            Err(FrontMatterError::Fields(..))
+        ));
+        assert!(matches!(
+            front_matter("---\ndate: yesterday\n---\n"),
+            Err(FrontMatterError::InvalidDate(..))
+        ));
+        assert!(matches!(
+            front_matter("+++\nhighlight_theme = \"neon\"\n+++\n"),
+            Err(FrontMatterError::UnknownTheme(..))
+        ));
+    }
+
+    #[test]
+    fn code_is_highlighted_with_classes() {
+        initialize_markdown();
+        let html = markdown("```rust\nfn main() {}\n```\n", Policy::Strict);
+        assert!(html.contains("<pre class=\"hl-code\"><code class=\"language-rust\">"));
+        assert!(html.contains("<span class=\"hl-"));
+        assert!(!html.contains("style="));
     }
 }
@@ -0,0 +1,234 @@
+// GNU AGPL v3 License
+
+//! LaTeX math in markdown, written between `$` for inline math or `$$` for
+//! display math, which is rendered into MathML on the server.
+//!
+//! The math is taken out of the markdown before it's parsed, so that things
+//! like `_` and `\` inside of it aren't read as markdown. Placeholders are
+//! left behind in its place, and are swapped out for the MathML once the
+//! markdown is rendered.
+
+use crate::placeholder;
+use latex2mathml::{latex_to_mathml, DisplayStyle};
+use std::borrow::Cow;
+
+/// The kind of placeholder that math leaves behind.
+const PLACEHOLDER: char = 'm';
+
+/// A piece of math that was taken out of the markdown.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Math {
+    pub source: String,
+    /// Whether this is display math, between `$$`.
+    pub display: bool,
+}
+
+impl Math {
+    /// Render the math into MathML. If the LaTeX is invalid, this is an
+    /// error message with the original source in it instead.
+    #[must_use]
+    #[inline]
+    pub fn to_html(&self) -> String {
+        let style = if self.display {
+            DisplayStyle::Block
+        } else {
+            DisplayStyle::Inline
+        };
+
+        match latex_to_mathml(&self.source, style) {
+            Ok(mathml) => mathml,
+            Err(e) => format!(
+                "<span class=\"math-error\" title=\"{}\">{}</span>",
+                tera::escape_html(&e.to_string()),
+                tera::escape_html(&self.delimited())
+            ),
+        }
+    }
+
+    /// The math the way that the author wrote it.
+    #[must_use]
+    #[inline]
+    pub fn delimited(&self) -> String {
+        let delimiter = if self.display { "$$" } else { "$" };
+        format!("{}{}{}", delimiter, &self.source, delimiter)
+    }
+}
+
+/// Take the math out of some markdown, leaving placeholders in its place.
+///
+/// Math isn't looked for in code, and `\$` can be used for a dollar sign.
+/// For inline math, the opening `$` can't be followed by a space and the
+/// closing one can't follow one or be followed by a digit, so that prices
+/// like "$5 and $10" are left alone.
+#[must_use]
+#[inline]
+pub fn extract(input: &str) -> (String, Vec<Math>) {
+    let mut math = vec![];
+    let output = placeholder::outside_code(input, |text, output| scan(text, output, &mut math));
+    (output, math)
+}
+
+/// Replace each placeholder in some text with whatever the math it stands
+/// for turns into.
+#[must_use]
+#[inline]
+pub fn restore<'a>(
+    text: &str,
+    math: &'a [Math],
+    mut replacement: impl FnMut(&'a Math) -> Cow<'a, str>,
+) -> String {
+    placeholder::restore(text, PLACEHOLDER, |i| math.get(i).map(&mut replacement))
+}
+
+/// Look for math in markdown that isn't in a code block.
+#[inline]
+fn scan(text: &str, output: &mut String, math: &mut Vec<Math>) {
+    let mut i = 0;
+    while i < text.len() {
+        let rest = &text[i..];
+        let len = if rest.starts_with('\\') {
+            // keep escapes as they are, so that markdown handles `\$`
+            1 + rest[1..].chars().next().map_or(0, char::len_utf8)
+        } else if rest.starts_with('`') {
+            placeholder::code_span_len(rest)
+        } else if let Some((len, found)) = math_span(rest) {
+            placeholder::push(output, PLACEHOLDER, math.len());
+            math.push(found);
+            i += len;
+            continue;
+        } else if rest.starts_with("$$") {
+            2
+        } else {
+            rest.chars().next().map_or(1, char::len_utf8)
+        };
+
+        output.push_str(&rest[..len]);
+        i += len;
+    }
+}
+
+/// The length of the math at the start of some text, if it starts with
+/// any.
+#[inline]
+fn math_span(text: &str) -> Option<(usize, Math)> {
+    if let Some(body) = text.strip_prefix("$$") {
+        let end = body.find("$$")?;
+        let source = body[..end].trim();
+        (!source.is_empty()).then(|| {
+            (
+                end + 4,
+                Math {
+                    source: source.into(),
+                    display: true,
+                },
+            )
+        })
+    } else if let Some(body) = text.strip_prefix('$') {
+        if body.starts_with(char::is_whitespace) {
+            return None;
+        }
+
+        let line = &body[..body.find('\n').unwrap_or(body.len())];
+        let (end, _) = line.char_indices().find(|&(i, c)| {
+            c == '$'
+                && i > 0
+                && !line[..i].ends_with(|c: char| c.is_whitespace() || c == '\\')
+                && !line[i + 1..].starts_with(|c: char| c.is_ascii_digit())
+        })?;
+        Some((
+            end + 2,
+            Math {
+                source: line[..end].into(),
+                display: false,
+            },
+        ))
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{extract, restore, Math};
+
+    #[inline]
+    fn extract_sources(input: &str) -> Vec<(String, bool)> {
+        extract(input)
+            .1
+            .into_iter()
+            .map(|math| (math.source, math.display))
+            .collect()
+    }
+
+    #[test]
+    fn finds_math() {
+        assert_eq!(
+            extract_sources("Euler: $e^{i\\pi} + 1 = 0$, and\n\n$$\n\\sum_{n=1}^\\infty a_n\n$$\n"),
+            [
+                ("e^{i\\pi} + 1 = 0".to_string(), false),
+                ("\\sum_{n=1}^\\infty a_n".to_string(), true),
+            ]
+        );
+    }
+
+    #[test]
+    fn leaves_other_dollars_alone() {
+        for input in [
+            "It costs $5 and $10.",
+            "Not math: \\$x\\$",
+            "`$x$` is code",
+            "``a ` $x$ ``",
+            "```\n$x$\n```\n",
+            "~~~~\n```\n$x$\n~~~~\n",
+            "    $x$\n",
+            "Code:\n\n    $x$\n\n\tmore $y$\n",
+            "- item\n\n        $x$\n",
+            "$ x$ and $x $",
+        ] {
+            let (output, math) = extract(input);
+            assert!(math.is_empty(), "found math in {:?}", input);
+            assert_eq!(output, input);
+        }
+
+        assert_eq!(
+            extract_sources("```\ncode\n```\n$x$"),
+            [("x".into(), false)]
+        );
+        assert_eq!(
+            extract_sources("    code\n\n$x$\n    $y$"),
+            [("x".into(), false), ("y".into(), false)]
+        );
+        assert_eq!(extract_sources("- item\n\n    $x$"), [("x".into(), false)]);
+    }
+
+    #[test]
+    fn restores_placeholders() {
+        let (output, math) = extract("a $x$ b $$y$$ c");
+        assert_eq!(math.len(), 2);
+        assert!(!output.contains('$'));
+        assert_eq!(
+            restore(&output, &math, |math| math.delimited().into()),
+            "a $x$ b $$y$$ c"
+        );
+    }
+
+    #[test]
+    fn renders_mathml() {
+        let math = Math {
+            source: "x^2".into(),
+            display: true,
+        };
+        let html = math.to_html();
+        assert!(html.starts_with("<math"));
+        assert!(html.contains("display=\"block\""));
+        assert!(html.contains("<msup>"));
+
+        let invalid = Math {
+            source: "\\frac{1}{<b>".into(),
+            display: false,
+        };
+        let html = invalid.to_html();
+        assert!(html.starts_with("<span class=\"math-error\""));
+        assert!(html.contains("$\\frac{1}{&lt;b&gt;$"));
+    }
+}
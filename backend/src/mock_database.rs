@@ -1,16 +1,23 @@
 // GNU AGPL v3 License
 
 use crate::{
+    markdown::Summary,
     models::{
-        Blogpost, BlogpostChange, BlogpostFilter, NewBlogpost, NewUser, User, UserChange,
-        UserFilter,
+        blogpost_path, blogpost_url, Blogpost, BlogpostChange, BlogpostFilter, BlogpostSortField,
+        LinkTarget, MatchMode, NewBlogpost, NewSeries, NewUpload, NewUrlRedirect, NewUser, Series,
+        SeriesChange, SeriesFilter, Sort, SortDirection, SortField, Upload, UrlRedirect, User,
+        UserChange, UserDeletion, UserFilter, UserSortField,
     },
     Database, DatabaseError,
 };
 use chrono::prelude::*;
-use std::sync::{
-    atomic::{AtomicI32, Ordering::SeqCst},
-    Mutex,
+use futures_util::future::BoxFuture;
+use std::{
+    cmp::Ordering as CmpOrdering,
+    sync::{
+        atomic::{AtomicI32, Ordering::SeqCst},
+        Arc, Mutex,
+    },
 };
 
 macro_rules! apply_change {
@@ -25,20 +32,266 @@ macro_rules! apply_change {
     }
 }
 
+/// Tell whether a string field matches a filter value.
+#[inline]
+fn string_matches(mode: MatchMode, field: &str, value: &str) -> bool {
+    match mode {
+        MatchMode::Contains => field.contains(value),
+        MatchMode::Exact => field == value,
+    }
+}
+
+/// Tell whether a `Blogpost` satisfies each of the conditions in a filter.
+#[inline]
+fn blogpost_matches(filter: &BlogpostFilter, bp: &Blogpost) -> bool {
+    let mode = filter.match_mode;
+    let mut cond = filter.trashed == bp.deleted_at.is_some();
+    if let Some(title) = filter.title.as_deref() {
+        cond = cond && string_matches(mode, &bp.title, title);
+    }
+    if let Some(tags) = filter.tags.as_deref() {
+        cond = cond && string_matches(mode, &bp.tags, tags);
+    }
+    if let Some(url) = filter.url.as_deref() {
+        cond = cond && string_matches(mode, &bp.url, url);
+    }
+    if let Some(body) = filter.body.as_deref() {
+        cond = cond && string_matches(mode, &bp.body, body);
+    }
+    if let Some(ids) = &filter.author_id {
+        cond = cond && ids.0.contains(&bp.author_id);
+    }
+    if let Some(after) = filter.created_after {
+        cond = cond && bp.created_at >= after;
+    }
+    if let Some(before) = filter.created_before {
+        cond = cond && bp.created_at < before;
+    }
+    cond
+}
+
+/// Tell whether a `User` satisfies each of the conditions in a filter.
+#[inline]
+fn user_matches(filter: &UserFilter, user: &User) -> bool {
+    let mut cond = filter.include_deleted || user.deleted_at.is_none();
+    if let Some(ids) = &filter.id {
+        cond = cond && ids.0.contains(&user.id);
+    }
+    if let Some(name) = filter.name.as_deref() {
+        cond = cond
+            && user
+                .name
+                .as_deref()
+                .map_or(false, |n| string_matches(filter.match_mode, n, name));
+    }
+    cond
+}
+
+/// Compare two models by each of the fields in a `Sort`, in order.
+#[inline]
+fn compare_by<F: SortField, T>(
+    sort: &Sort<F>,
+    a: &T,
+    b: &T,
+    compare_field: impl Fn(F, &T, &T) -> CmpOrdering,
+) -> CmpOrdering {
+    sort.0
+        .iter()
+        .map(|&(field, direction)| match direction {
+            SortDirection::Ascending => compare_field(field, a, b),
+            SortDirection::Descending => compare_field(field, b, a),
+        })
+        .find(|ordering| *ordering != CmpOrdering::Equal)
+        .unwrap_or(CmpOrdering::Equal)
+}
+
+#[inline]
+fn compare_blogpost_field(field: BlogpostSortField, a: &Blogpost, b: &Blogpost) -> CmpOrdering {
+    match field {
+        BlogpostSortField::Id => a.id.cmp(&b.id),
+        BlogpostSortField::Title => a.title.cmp(&b.title),
+        BlogpostSortField::Url => a.url.cmp(&b.url),
+        BlogpostSortField::AuthorId => a.author_id.cmp(&b.author_id),
+        BlogpostSortField::CreatedAt => a.created_at.cmp(&b.created_at),
+    }
+}
+
+#[inline]
+fn compare_user_field(field: UserSortField, a: &User, b: &User) -> CmpOrdering {
+    match field {
+        UserSortField::Id => a.id.cmp(&b.id),
+        UserSortField::Name => a.name.cmp(&b.name),
+        UserSortField::Roles => a.roles.cmp(&b.roles),
+    }
+}
+
+/// A row of one of the mock tables.
+trait Row: Clone {
+    /// The ID of the row, or of the post that it belongs to for links.
+    fn key(&self) -> i32;
+    /// The rows of this table that a transaction has changed.
+    fn changes(journal: &mut Journal) -> &mut Changes<Self>;
+}
+
+macro_rules! impl_row {
+    ($($ty: ty => $table: ident),*) => {
+        $(
+            impl Row for $ty {
+                #[inline]
+                fn key(&self) -> i32 {
+                    self.id
+                }
+
+                #[inline]
+                fn changes(journal: &mut Journal) -> &mut Changes<Self> {
+                    &mut journal.$table
+                }
+            }
+        )*
+    };
+}
+
+impl_row!(
+    Blogpost => blogposts,
+    User => users,
+    Upload => uploads,
+    UrlRedirect => redirects,
+    Series => series
+);
+
+impl Row for (i32, String) {
+    #[inline]
+    fn key(&self) -> i32 {
+        self.0
+    }
+
+    #[inline]
+    fn changes(journal: &mut Journal) -> &mut Changes<Self> {
+        &mut journal.post_links
+    }
+}
+
+/// The rows of a table with each key, as they were before a transaction
+/// first changed them.
+struct Changes<T>(Vec<(i32, Vec<T>)>);
+
+impl<T> Default for Changes<T> {
+    #[inline]
+    fn default() -> Self {
+        Changes(Vec::new())
+    }
+}
+
+impl<T: Row> Changes<T> {
+    /// Remember the rows with a key, unless they've been changed already.
+    #[inline]
+    fn record(&mut self, rows: &[T], key: i32) {
+        if !self.0.iter().any(|(k, _)| *k == key) {
+            let before = rows
+                .iter()
+                .filter(|row| row.key() == key)
+                .cloned()
+                .collect();
+            self.0.push((key, before));
+        }
+    }
+
+    /// Put the rows that were changed back the way they were, leaving the
+    /// others alone.
+    #[inline]
+    fn undo(self, rows: &mut Vec<T>) {
+        for (key, before) in self.0.into_iter().rev() {
+            rows.retain(|row| row.key() != key);
+            rows.extend(before);
+        }
+    }
+
+    /// Add the changes of a transaction that was nested in this one and
+    /// succeeded.
+    #[inline]
+    fn merge(&mut self, inner: Self) {
+        for (key, before) in inner.0 {
+            if !self.0.iter().any(|(k, _)| *k == key) {
+                self.0.push((key, before));
+            }
+        }
+    }
+}
+
+/// Everything that a transaction has changed, so that only that is undone
+/// if it fails.
+#[derive(Default)]
+struct Journal {
+    blogposts: Changes<Blogpost>,
+    users: Changes<User>,
+    uploads: Changes<Upload>,
+    redirects: Changes<UrlRedirect>,
+    series: Changes<Series>,
+    post_links: Changes<(i32, String)>,
+}
+
+impl Journal {
+    #[inline]
+    fn undo(self, db: &MockDatabase) {
+        self.blogposts.undo(&mut db.blogposts.lock().unwrap());
+        self.users.undo(&mut db.users.lock().unwrap());
+        self.uploads.undo(&mut db.uploads.lock().unwrap());
+        self.redirects.undo(&mut db.redirects.lock().unwrap());
+        self.series.undo(&mut db.series.lock().unwrap());
+        self.post_links.undo(&mut db.post_links.lock().unwrap());
+    }
+
+    #[inline]
+    fn merge(&mut self, inner: Journal) {
+        self.blogposts.merge(inner.blogposts);
+        self.users.merge(inner.users);
+        self.uploads.merge(inner.uploads);
+        self.redirects.merge(inner.redirects);
+        self.series.merge(inner.series);
+        self.post_links.merge(inner.post_links);
+    }
+}
+
 /// Mock database used for basic testing.
+///
+/// The tables are shared with the handles of any transactions, which only
+/// differ in keeping track of what they change.
 pub struct MockDatabase {
-    last_id: AtomicI32,
-    blogposts: Mutex<Vec<Blogpost>>,
-    users: Mutex<Vec<User>>,
+    last_id: Arc<AtomicI32>,
+    blogposts: Arc<Mutex<Vec<Blogpost>>>,
+    users: Arc<Mutex<Vec<User>>>,
+    uploads: Arc<Mutex<Vec<Upload>>>,
+    redirects: Arc<Mutex<Vec<UrlRedirect>>>,
+    series: Arc<Mutex<Vec<Series>>>,
+    /// Pairs of the ID of a post and a URL that it links to.
+    post_links: Arc<Mutex<Vec<(i32, String)>>>,
+    /// What the transaction that this is a handle to has changed, if any.
+    journal: Option<Mutex<Journal>>,
 }
 
 impl MockDatabase {
     #[inline]
     pub fn new() -> Self {
         Self {
-            last_id: AtomicI32::new(1),
-            blogposts: Mutex::new(Vec::new()),
-            users: Mutex::new(Vec::new()),
+            last_id: Arc::new(AtomicI32::new(1)),
+            blogposts: Arc::default(),
+            users: Arc::default(),
+            uploads: Arc::default(),
+            redirects: Arc::default(),
+            series: Arc::default(),
+            post_links: Arc::default(),
+            journal: None,
+        }
+    }
+
+    /// Remember how the rows with the given keys were before the current
+    /// transaction changes them, if this is a handle to one.
+    #[inline]
+    fn touch<T: Row>(&self, rows: &[T], keys: impl IntoIterator<Item = i32>) {
+        if let Some(journal) = &self.journal {
+            let mut journal = journal.lock().unwrap();
+            let changes = T::changes(&mut journal);
+            keys.into_iter().for_each(|key| changes.record(rows, key));
         }
     }
 
@@ -47,12 +300,24 @@ impl MockDatabase {
         self.last_id.fetch_add(1, SeqCst)
     }
 
+    /// Get a blogpost that isn't in the trash.
     #[inline]
     fn get_blogpost_by(
         &self,
         mut f: impl FnMut(&Blogpost) -> bool,
     ) -> Result<Blogpost, DatabaseError> {
         self.blogposts
+            .lock()
+            .unwrap()
+            .iter()
+            .find(move |item| item.deleted_at.is_none() && f(item))
+            .cloned()
+            .ok_or(DatabaseError::NotFound)
+    }
+
+    #[inline]
+    fn get_series_by(&self, mut f: impl FnMut(&Series) -> bool) -> Result<Series, DatabaseError> {
+        self.series
             .lock()
             .unwrap()
             .iter()
@@ -78,17 +343,24 @@ impl MockDatabase {
         //  - John Notgull
         //  - Alan Smithee
         // and two blogposts
+        let now = Local::now().naive_local();
         let user1 = User {
             id: 1,
             uuid: "65a7e8c5-c235-49a9-ba00-6d9c049776f4".into(),
             name: Some("John Notgull".into()),
             roles: 0xFFFFFFFF,
+            version: 1,
+            updated_at: now,
+            deleted_at: None,
         };
         let user2 = User {
             id: 2,
             uuid: "995a066d-de0e-4378-92e6-407f7aa1dc19".into(),
             name: Some("Alan Smithee".into()),
             roles: 0,
+            version: 1,
+            updated_at: now,
+            deleted_at: None,
         };
 
         let blog1 = Blogpost {
@@ -99,7 +371,22 @@ impl MockDatabase {
             body: "...and we spent so much time chasing suns, we forgot what we were really after."
                 .into(),
             author_id: 1,
-            created_at: Local::now().naive_local(),
+            created_at: now,
+            version: 1,
+            updated_at: now,
+            deleted_at: None,
+            series_id: None,
+            series_position: None,
+            summary: Some(
+                "...and we spent so much time chasing suns, we forgot what we were really after."
+                    .into(),
+            ),
+            cover_image: None,
+            canonical_url: None,
+            word_count: 15,
+            reading_minutes: 1,
+            trusted_html: true,
+            summary_generated: true,
         };
         let blog2 = Blogpost {
             id: 2,
@@ -115,24 +402,80 @@ Hello, I am John Notgull. *What* **if** we made a website?
             "#
             .into(),
             author_id: 1,
-            created_at: Local::now().naive_local(),
+            created_at: now,
+            version: 1,
+            updated_at: now,
+            deleted_at: None,
+            series_id: None,
+            series_position: None,
+            summary: Some("Hello, I am John Notgull. What if we made a website?".into()),
+            cover_image: None,
+            canonical_url: None,
+            word_count: 19,
+            reading_minutes: 1,
+            trusted_html: true,
+            summary_generated: true,
         };
 
-        let mut this = Self::new();
-        this.users.get_mut().unwrap().extend([user1, user2]);
-        this.blogposts.get_mut().unwrap().extend([blog1, blog2]);
-        *this.last_id.get_mut() = 3;
+        let this = Self::new();
+        this.users.lock().unwrap().extend([user1, user2]);
+        this.blogposts.lock().unwrap().extend([blog1, blog2]);
+        this.last_id.store(3, SeqCst);
         this
     }
 }
 
 #[async_trait::async_trait]
 impl Database for MockDatabase {
+    #[inline]
+    async fn transaction<T, E, F>(&self, f: F) -> Result<T, E>
+    where
+        T: Send,
+        E: From<DatabaseError> + Send,
+        F: for<'a> FnOnce(&'a Self) -> BoxFuture<'a, Result<T, E>> + Send,
+    {
+        // remember the rows the transaction changes, and only put those back
+        // on failure, so that changes made at the same time by anyone else
+        // are kept; nested transactions hand their changes to the outer one
+        // if they succeed, the same way savepoints do
+        let tx = MockDatabase {
+            last_id: self.last_id.clone(),
+            blogposts: self.blogposts.clone(),
+            users: self.users.clone(),
+            uploads: self.uploads.clone(),
+            redirects: self.redirects.clone(),
+            series: self.series.clone(),
+            post_links: self.post_links.clone(),
+            journal: Some(Mutex::default()),
+        };
+
+        let result = f(&tx).await;
+        let journal = tx
+            .journal
+            .expect("Transaction has a journal")
+            .into_inner()
+            .unwrap();
+        match (&result, &self.journal) {
+            (Ok(_), Some(outer)) => outer.lock().unwrap().merge(journal),
+            (Ok(_), None) => {}
+            (Err(_), _) => journal.undo(self),
+        }
+
+        result
+    }
+
     #[inline]
     async fn get_blogpost_by_id(&self, sid: i32) -> Result<Blogpost, DatabaseError> {
         self.get_blogpost_by(|b| b.id == sid)
     }
 
+    #[inline]
+    async fn lock_blogpost(&self, sid: i32) -> Result<i32, DatabaseError> {
+        // there are no row locks to take, since transactions here only
+        // emulate rolling back
+        Ok(self.get_blogpost_by(|b| b.id == sid)?.version)
+    }
+
     #[inline]
     async fn get_blogpost_and_user_by_url(
         &self,
@@ -143,6 +486,21 @@ impl Database for MockDatabase {
         Ok((blogpost, user))
     }
 
+    #[inline]
+    async fn list_blogpost_urls(
+        &self,
+        prefix: String,
+    ) -> Result<Vec<(i32, String)>, DatabaseError> {
+        Ok(self
+            .blogposts
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|bp| bp.url.starts_with(&prefix))
+            .map(|bp| (bp.id, bp.url.clone()))
+            .collect())
+    }
+
     #[inline]
     async fn insert_blogpost(&self, bp: NewBlogpost) -> Result<i32, DatabaseError> {
         let NewBlogpost {
@@ -151,8 +509,19 @@ impl Database for MockDatabase {
             url,
             body,
             author_id,
+            series_id,
+            series_position,
+            summary,
+            cover_image,
+            canonical_url,
+            created_at,
+            word_count,
+            reading_minutes,
+            trusted_html,
+            summary_generated,
         } = bp;
         let id = self.next_id();
+        let now = Local::now().naive_local();
         let blogpost = Blogpost {
             id,
             title,
@@ -160,18 +529,43 @@ impl Database for MockDatabase {
             url,
             body,
             author_id,
-            created_at: Local::now().naive_local(),
+            created_at: created_at.unwrap_or(now),
+            version: 1,
+            updated_at: now,
+            deleted_at: None,
+            series_id,
+            series_position,
+            summary,
+            cover_image,
+            canonical_url,
+            word_count,
+            reading_minutes,
+            trusted_html,
+            summary_generated,
         };
-        self.blogposts.lock().unwrap().push(blogpost);
+        let mut blogposts = self.blogposts.lock().unwrap();
+        if blogposts.iter().any(|bp| bp.url == blogpost.url) {
+            return Err(DatabaseError::AlreadyExists);
+        }
+
+        self.touch(&blogposts, [id]);
+        blogposts.push(blogpost);
         Ok(id)
     }
 
     #[inline]
     async fn update_blogpost(&self, id: i32, bp: BlogpostChange) -> Result<(), DatabaseError> {
         let mut blogposts = self.blogposts.lock().unwrap();
+        if let Some(url) = bp.url.as_ref() {
+            if blogposts.iter().any(|bp| bp.id != id && bp.url == *url) {
+                return Err(DatabaseError::AlreadyExists);
+            }
+        }
+
+        self.touch(&blogposts, [id]);
         let blogpost = blogposts
             .iter_mut()
-            .find(|bp| bp.id == id)
+            .find(|bp| bp.id == id && bp.deleted_at.is_none())
             .ok_or(DatabaseError::NotFound)?;
         let BlogpostChange {
             title,
@@ -179,65 +573,298 @@ impl Database for MockDatabase {
             url,
             body,
             author_id,
+            series_id,
+            series_position,
+            summary,
+            cover_image,
+            canonical_url,
+            created_at,
+            word_count,
+            reading_minutes,
+            trusted_html,
+            summary_generated,
         } = bp;
-        apply_change!(blogpost: title, tags, url, body, author_id);
+
+        if let Some(new_url) = url.as_ref().filter(|new_url| **new_url != blogpost.url) {
+            let old_path = blogpost_path(&blogpost.url);
+            let new_path = blogpost_path(new_url);
+            let mut redirects = self.redirects.lock().unwrap();
+            let removed = redirects
+                .iter()
+                .filter(|r| r.path == old_path || (r.path == new_path && r.blogpost_id == Some(id)))
+                .map(|r| r.id)
+                .collect::<Vec<_>>();
+            let redirect_id = self.next_id();
+            self.touch(&redirects, removed.into_iter().chain([redirect_id]));
+            redirects.retain(|r| {
+                r.path != old_path && !(r.path == new_path && r.blogpost_id == Some(id))
+            });
+            redirects.push(UrlRedirect {
+                id: redirect_id,
+                path: old_path,
+                blogpost_id: Some(id),
+                target: None,
+                created_at: Local::now().naive_local(),
+            });
+        }
+
+        apply_change!(blogpost: title, tags, url, body, author_id, created_at);
+        apply_change!(blogpost: series_id, series_position, summary, cover_image, canonical_url);
+        apply_change!(blogpost: word_count, reading_minutes, trusted_html, summary_generated);
+        blogpost.version += 1;
+        blogpost.updated_at = Local::now().naive_local();
 
         Ok(())
     }
 
     #[inline]
     async fn list_blogposts(&self, filter: BlogpostFilter) -> Result<Vec<Blogpost>, DatabaseError> {
-        let BlogpostFilter {
-            title,
-            tags,
-            url,
-            body,
-            author_id,
-            skip,
-            count,
-        } = filter;
-
-        Ok(self
+        let mut posts: Vec<Blogpost> = self
             .blogposts
             .lock()
             .unwrap()
             .iter()
-            .filter(move |bp| {
-                let mut cond = true;
-                if let Some(title) = title.as_deref() {
-                    cond = cond && bp.title.contains(title);
-                }
-                if let Some(tags) = tags.as_deref() {
-                    cond = cond && bp.tags.contains(tags);
-                }
-                if let Some(url) = url.as_deref() {
-                    cond = cond && bp.url.contains(url);
-                }
-                if let Some(body) = body.as_deref() {
-                    cond = cond && bp.body.contains(body);
-                }
-                if let Some(author_id) = author_id {
-                    cond = cond && bp.author_id == author_id;
-                }
-                cond
+            .filter(|bp| blogpost_matches(&filter, bp))
+            .filter(|bp| match filter.cursor {
+                Some(cursor) => (bp.created_at, bp.id) < (cursor.created_at, cursor.id),
+                None => true,
             })
-            .skip(skip as usize)
-            .take(count as usize)
             .cloned()
+            .collect();
+
+        // newest first unless otherwise sorted, same as the SQL database
+        match &filter.sort {
+            Some(sort) => posts.sort_unstable_by(|a, b| {
+                compare_by(sort, a, b, compare_blogpost_field).then(b.id.cmp(&a.id))
+            }),
+            None => posts.sort_unstable_by(|a, b| (b.created_at, b.id).cmp(&(a.created_at, a.id))),
+        }
+
+        let skip = if filter.cursor.is_some() {
+            0
+        } else {
+            filter.skip as usize
+        };
+
+        Ok(posts
+            .into_iter()
+            .skip(skip)
+            .take(filter.count as usize)
             .collect())
     }
 
+    #[inline]
+    async fn count_blogposts(&self, filter: BlogpostFilter) -> Result<u64, DatabaseError> {
+        Ok(self
+            .blogposts
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|bp| blogpost_matches(&filter, bp))
+            .count() as u64)
+    }
+
+    #[inline]
+    async fn list_blogpost_dates(&self) -> Result<Vec<NaiveDateTime>, DatabaseError> {
+        let mut dates: Vec<NaiveDateTime> = self
+            .blogposts
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|bp| bp.deleted_at.is_none())
+            .map(|bp| bp.created_at)
+            .collect();
+        dates.sort_unstable_by(|a, b| b.cmp(a));
+        Ok(dates)
+    }
+
     #[inline]
     async fn delete_blogpost(&self, sid: i32) -> Result<(), DatabaseError> {
-        self.blogposts.lock().unwrap().retain(|bp| bp.id != sid);
+        let mut blogposts = self.blogposts.lock().unwrap();
+        self.touch(&blogposts, [sid]);
+        let blogpost = blogposts
+            .iter_mut()
+            .find(|bp| bp.id == sid && bp.deleted_at.is_none())
+            .ok_or(DatabaseError::NotFound)?;
+        blogpost.deleted_at = Some(Local::now().naive_local());
+        Ok(())
+    }
+
+    #[inline]
+    async fn restore_blogpost(&self, sid: i32) -> Result<(), DatabaseError> {
+        let mut blogposts = self.blogposts.lock().unwrap();
+        self.touch(&blogposts, [sid]);
+        let blogpost = blogposts
+            .iter_mut()
+            .find(|bp| bp.id == sid && bp.deleted_at.is_some())
+            .ok_or(DatabaseError::NotFound)?;
+        blogpost.deleted_at = None;
+        Ok(())
+    }
+
+    #[inline]
+    async fn purge_blogpost(&self, sid: i32) -> Result<(), DatabaseError> {
+        let mut blogposts = self.blogposts.lock().unwrap();
+        let index = blogposts
+            .iter()
+            .position(|bp| bp.id == sid && bp.deleted_at.is_some())
+            .ok_or(DatabaseError::NotFound)?;
+        self.touch(&blogposts, [sid]);
+        blogposts.remove(index);
+
+        let mut post_links = self.post_links.lock().unwrap();
+        self.touch(&post_links, [sid]);
+        post_links.retain(|(source, _)| *source != sid);
+        Ok(())
+    }
+
+    #[inline]
+    async fn purge_trash(&self, before: NaiveDateTime) -> Result<u64, DatabaseError> {
+        let mut blogposts = self.blogposts.lock().unwrap();
+        let purged = blogposts
+            .iter()
+            .filter(|bp| {
+                bp.deleted_at
+                    .map_or(false, |deleted_at| deleted_at < before)
+            })
+            .map(|bp| bp.id)
+            .collect::<Vec<_>>();
+        self.touch(&blogposts, purged.iter().copied());
+        blogposts.retain(|bp| !purged.contains(&bp.id));
+
+        let mut post_links = self.post_links.lock().unwrap();
+        self.touch(&post_links, purged.iter().copied());
+        post_links.retain(|(source, _)| !purged.contains(source));
+        Ok(purged.len() as u64)
+    }
+
+    #[inline]
+    async fn get_link_targets(&self, urls: Vec<String>) -> Result<Vec<LinkTarget>, DatabaseError> {
+        let redirects = self.redirects.lock().unwrap().clone();
+        Ok(urls
+            .into_iter()
+            .filter_map(|slug| {
+                // follow the URL that the post used to have, if it moved
+                let blogpost = self.get_blogpost_by(|b| b.url == slug).or_else(|_| {
+                    let old_path = blogpost_path(&slug);
+                    let sid = redirects
+                        .iter()
+                        .find(|r| r.path == old_path)
+                        .and_then(|r| r.blogpost_id)
+                        .ok_or(DatabaseError::NotFound)?;
+                    self.get_blogpost_by(|b| b.id == sid)
+                });
+
+                blogpost.ok().map(|blogpost| LinkTarget {
+                    slug,
+                    url: blogpost.url,
+                    title: blogpost.title,
+                })
+            })
+            .collect())
+    }
+
+    #[inline]
+    async fn set_post_links(&self, sid: i32, urls: Vec<String>) -> Result<(), DatabaseError> {
+        let mut post_links = self.post_links.lock().unwrap();
+        self.touch(&post_links, [sid]);
+        post_links.retain(|(source, _)| *source != sid);
+        for url in urls {
+            if !post_links.contains(&(sid, url.clone())) {
+                post_links.push((sid, url));
+            }
+        }
+        Ok(())
+    }
+
+    #[inline]
+    async fn list_unlinked_blogposts(&self) -> Result<Vec<(i32, String)>, DatabaseError> {
+        let blogposts = self.blogposts.lock().unwrap();
+        let post_links = self.post_links.lock().unwrap();
+        Ok(blogposts
+            .iter()
+            .filter(|bp| {
+                bp.body.contains("[[") && !post_links.iter().any(|(source, _)| *source == bp.id)
+            })
+            .map(|bp| (bp.id, bp.body.clone()))
+            .collect())
+    }
+
+    #[inline]
+    async fn list_unsummarized_blogposts(&self) -> Result<Vec<(i32, String)>, DatabaseError> {
+        Ok(self
+            .blogposts
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|bp| bp.word_count == 0 || (bp.summary.is_none() && bp.summary_generated))
+            .map(|bp| (bp.id, bp.body.clone()))
+            .collect())
+    }
+
+    #[inline]
+    async fn backfill_summary(&self, id: i32, summary: Summary) -> Result<(), DatabaseError> {
+        let mut blogposts = self.blogposts.lock().unwrap();
+        self.touch(&blogposts, [id]);
+        if let Some(bp) = blogposts.iter_mut().find(|bp| bp.id == id) {
+            bp.word_count = summary.word_count;
+            bp.reading_minutes = summary.reading_minutes;
+            if bp.summary.is_none() && bp.summary_generated {
+                bp.summary = (!summary.excerpt.is_empty()).then(|| summary.excerpt);
+            }
+        }
         Ok(())
     }
 
+    #[inline]
+    async fn list_backlinks(&self, sid: i32) -> Result<Vec<Blogpost>, DatabaseError> {
+        let current = self
+            .blogposts
+            .lock()
+            .unwrap()
+            .iter()
+            .find(|bp| bp.id == sid)
+            .map(|bp| bp.url.clone())
+            .ok_or(DatabaseError::NotFound)?;
+        let mut urls: Vec<String> = self
+            .redirects
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|r| r.blogpost_id == Some(sid))
+            .filter_map(|r| blogpost_url(&r.path).map(Into::into))
+            .collect();
+        urls.push(current);
+
+        let post_links = self.post_links.lock().unwrap().clone();
+        let mut posts: Vec<Blogpost> = self
+            .blogposts
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|bp| {
+                bp.id != sid
+                    && bp.deleted_at.is_none()
+                    && post_links
+                        .iter()
+                        .any(|(source, target)| *source == bp.id && urls.contains(target))
+            })
+            .cloned()
+            .collect();
+        posts.sort_by(|a, b| (b.created_at, b.id).cmp(&(a.created_at, a.id)));
+        Ok(posts)
+    }
+
     #[inline]
     async fn get_user_by_id(&self, sid: i32) -> Result<User, DatabaseError> {
         self.get_user_by(|user| user.id == sid)
     }
 
+    #[inline]
+    async fn lock_user(&self, sid: i32) -> Result<i32, DatabaseError> {
+        Ok(self.get_user_by(|user| user.id == sid)?.version)
+    }
+
     #[inline]
     async fn get_user_by_uuid(&self, suuid: String) -> Result<User, DatabaseError> {
         self.get_user_by(|user| user.uuid == suuid)
@@ -252,14 +879,20 @@ impl Database for MockDatabase {
             name,
             uuid,
             roles,
+            version: 1,
+            updated_at: Local::now().naive_local(),
+            deleted_at: None,
         };
-        self.users.lock().unwrap().push(user);
+        let mut users = self.users.lock().unwrap();
+        self.touch(&users, [id]);
+        users.push(user);
         Ok(id)
     }
 
     #[inline]
     async fn update_user(&self, id: i32, user: UserChange) -> Result<(), DatabaseError> {
         let mut users = self.users.lock().unwrap();
+        self.touch(&users, [id]);
         let UserChange { uuid, name, roles } = user;
         let user = users
             .iter_mut()
@@ -269,58 +902,393 @@ impl Database for MockDatabase {
         if let Some(name) = name {
             user.name = Some(name);
         }
+        user.version += 1;
+        user.updated_at = Local::now().naive_local();
 
         Ok(())
     }
 
     #[inline]
     async fn list_users(&self, filter: UserFilter) -> Result<Vec<User>, DatabaseError> {
-        let UserFilter { name } = filter;
-
-        Ok(self
+        let mut users: Vec<User> = self
             .users
             .lock()
             .unwrap()
             .iter()
-            .filter(move |user| {
-                let mut cond = true;
-                if let Some(name) = name.as_deref() {
-                    cond = cond && user.name.as_ref().unwrap().contains(&name);
-                }
-                cond
-            })
+            .filter(|user| user_matches(&filter, user))
             .cloned()
+            .collect();
+
+        match &filter.sort {
+            Some(sort) => users.sort_unstable_by(|a, b| {
+                compare_by(sort, a, b, compare_user_field).then(a.id.cmp(&b.id))
+            }),
+            None => users.sort_unstable_by(|a, b| (&a.name, a.id).cmp(&(&b.name, b.id))),
+        }
+
+        Ok(users
+            .into_iter()
+            .skip(filter.skip as usize)
+            .take(filter.count as usize)
             .collect())
     }
 
     #[inline]
-    async fn delete_user(&self, sid: i32) -> Result<(), DatabaseError> {
-        self.users.lock().unwrap().retain(|user| user.id != sid);
-        Ok(())
+    async fn count_users(&self, filter: UserFilter) -> Result<u64, DatabaseError> {
+        Ok(self
+            .users
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|user| user_matches(&filter, user))
+            .count() as u64)
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::MockDatabase;
-    use crate::{
-        models::{BlogpostChange, NewBlogpost, NewUser, UserChange},
-        Database,
-    };
+    #[inline]
+    async fn delete_user(&self, sid: i32, deletion: UserDeletion) -> Result<(), DatabaseError> {
+        let mut users = self.users.lock().unwrap();
+        let now = Local::now().naive_local();
+        let index = users
+            .iter()
+            .position(|u| u.id == sid)
+            .ok_or(DatabaseError::NotFound)?;
+        self.touch(&users, [sid]);
 
-    #[tokio::test]
-    async fn get_blogpost_by_id() {
-        let database = MockDatabase::with_test_data();
-        assert_eq!(
-            database.get_blogpost_by_id(1).await.unwrap().title,
-            "Chasing Suns"
-        );
+        match deletion {
+            UserDeletion::SoftDelete => users[index].deleted_at = Some(now),
+            UserDeletion::Anonymize => {
+                let user = &mut users[index];
+                user.uuid = format!("deleted:{}", sid);
+                user.name = None;
+                user.roles = 0;
+                user.deleted_at = Some(now);
+            }
+            UserDeletion::Reassign { to } => {
+                let mut blogposts = self.blogposts.lock().unwrap();
+                let authored = blogposts.iter().filter(|bp| bp.author_id == sid);
+                self.touch(&blogposts, authored.map(|bp| bp.id).collect::<Vec<_>>());
+                blogposts
+                    .iter_mut()
+                    .filter(|bp| bp.author_id == sid)
+                    .for_each(|bp| bp.author_id = to);
+
+                let mut uploads = self.uploads.lock().unwrap();
+                let uploaded = uploads.iter().filter(|upload| upload.uploader_id == sid);
+                self.touch(
+                    &uploads,
+                    uploaded.map(|upload| upload.id).collect::<Vec<_>>(),
+                );
+                uploads
+                    .iter_mut()
+                    .filter(|upload| upload.uploader_id == sid)
+                    .for_each(|upload| upload.uploader_id = to);
+                users.remove(index);
+            }
+        }
+
+        Ok(())
+    }
+
+    #[inline]
+    async fn insert_upload(&self, upload: NewUpload) -> Result<i32, DatabaseError> {
+        let NewUpload {
+            path,
+            content_type,
+            size,
+            uploader_id,
+            pending_until,
+        } = upload;
+        let id = self.next_id();
+        let upload = Upload {
+            id,
+            path,
+            content_type,
+            size,
+            uploader_id,
+            created_at: Local::now().naive_local(),
+            pending_until,
+        };
+        let mut uploads = self.uploads.lock().unwrap();
+        if uploads.iter().any(|existing| existing.path == upload.path) {
+            return Err(DatabaseError::AlreadyExists);
+        }
+
+        self.touch(&uploads, [id]);
+        uploads.push(upload);
+        Ok(id)
+    }
+
+    #[inline]
+    async fn get_upload_paths(&self, paths: Vec<String>) -> Result<Vec<String>, DatabaseError> {
+        let uploads = self.uploads.lock().unwrap();
+        Ok(paths
+            .into_iter()
+            .filter(|path| {
+                uploads
+                    .iter()
+                    .any(|upload| upload.path == *path && upload.pending_until.is_none())
+            })
+            .collect())
+    }
+
+    #[inline]
+    async fn get_upload_by_path(&self, path: String) -> Result<Upload, DatabaseError> {
+        self.uploads
+            .lock()
+            .unwrap()
+            .iter()
+            .find(|upload| upload.path == path)
+            .cloned()
+            .ok_or(DatabaseError::NotFound)
+    }
+
+    #[inline]
+    async fn complete_upload(&self, id: i32) -> Result<(), DatabaseError> {
+        let mut uploads = self.uploads.lock().unwrap();
+        self.touch(&uploads, [id]);
+        match uploads
+            .iter_mut()
+            .find(|upload| upload.id == id && upload.pending_until.is_some())
+        {
+            Some(upload) => {
+                upload.pending_until = None;
+                Ok(())
+            }
+            None => Err(DatabaseError::AlreadyExists),
+        }
+    }
+
+    #[inline]
+    async fn purge_pending_uploads(&self, before: NaiveDateTime) -> Result<u64, DatabaseError> {
+        let mut uploads = self.uploads.lock().unwrap();
+        let purged: Vec<i32> = uploads
+            .iter()
+            .filter(|upload| upload.pending_until.map_or(false, |until| until < before))
+            .map(|upload| upload.id)
+            .collect();
+        self.touch(&uploads, purged.iter().copied());
+        uploads.retain(|upload| !purged.contains(&upload.id));
+        Ok(purged.len() as u64)
+    }
+
+    #[inline]
+    async fn get_redirect_target(&self, path: String) -> Result<String, DatabaseError> {
+        let redirect = self
+            .redirects
+            .lock()
+            .unwrap()
+            .iter()
+            .find(|r| r.path == path)
+            .cloned()
+            .ok_or(DatabaseError::NotFound)?;
+
+        match (redirect.target, redirect.blogpost_id) {
+            (Some(target), _) => Ok(target),
+            (None, Some(sid)) => {
+                let blogpost = self.get_blogpost_by(|b| b.id == sid)?;
+                Ok(blogpost_path(&blogpost.url))
+            }
+            (None, None) => Err(DatabaseError::NotFound),
+        }
+    }
+
+    #[inline]
+    async fn list_redirects(&self) -> Result<Vec<UrlRedirect>, DatabaseError> {
+        let mut redirects = self.redirects.lock().unwrap().clone();
+        redirects.sort_by(|a, b| (b.created_at, b.id).cmp(&(a.created_at, a.id)));
+        Ok(redirects)
+    }
+
+    #[inline]
+    async fn insert_redirect(&self, redirect: NewUrlRedirect) -> Result<i32, DatabaseError> {
+        let NewUrlRedirect { path, target } = redirect;
+        let mut redirects = self.redirects.lock().unwrap();
+        if redirects.iter().any(|r| r.path == path) {
+            return Err(DatabaseError::AlreadyExists);
+        }
+
+        let id = self.next_id();
+        self.touch(&redirects, [id]);
+        redirects.push(UrlRedirect {
+            id,
+            path,
+            blogpost_id: None,
+            target: Some(target),
+            created_at: Local::now().naive_local(),
+        });
+        Ok(id)
+    }
+
+    #[inline]
+    async fn delete_redirect(&self, sid: i32) -> Result<(), DatabaseError> {
+        let mut redirects = self.redirects.lock().unwrap();
+        let index = redirects
+            .iter()
+            .position(|r| r.id == sid)
+            .ok_or(DatabaseError::NotFound)?;
+        self.touch(&redirects, [sid]);
+        redirects.remove(index);
+        Ok(())
+    }
+
+    #[inline]
+    async fn get_series_by_id(&self, sid: i32) -> Result<Series, DatabaseError> {
+        self.get_series_by(|s| s.id == sid)
+    }
+
+    #[inline]
+    async fn get_series_by_url(&self, surl: String) -> Result<Series, DatabaseError> {
+        self.get_series_by(|s| s.url == surl)
+    }
+
+    #[inline]
+    async fn insert_series(&self, new: NewSeries) -> Result<i32, DatabaseError> {
+        let NewSeries {
+            title,
+            url,
+            description,
+        } = new;
+        let mut series = self.series.lock().unwrap();
+        if series.iter().any(|s| s.url == url) {
+            return Err(DatabaseError::AlreadyExists);
+        }
+
+        let id = self.next_id();
+        self.touch(&series, [id]);
+        series.push(Series {
+            id,
+            title,
+            url,
+            description,
+            created_at: Local::now().naive_local(),
+        });
+        Ok(id)
+    }
+
+    #[inline]
+    async fn update_series(&self, id: i32, change: SeriesChange) -> Result<(), DatabaseError> {
+        let mut series = self.series.lock().unwrap();
+        let SeriesChange {
+            title,
+            url,
+            description,
+        } = change;
+        if url.as_ref().map_or(false, |url| {
+            series.iter().any(|s| s.id != id && s.url == *url)
+        }) {
+            return Err(DatabaseError::AlreadyExists);
+        }
+
+        self.touch(&series, [id]);
+        let found = series
+            .iter_mut()
+            .find(|s| s.id == id)
+            .ok_or(DatabaseError::NotFound)?;
+        apply_change!(found: title, url, description);
+        Ok(())
+    }
+
+    #[inline]
+    async fn list_series(&self, filter: SeriesFilter) -> Result<Vec<Series>, DatabaseError> {
+        let mut series = self.series.lock().unwrap().clone();
+        series.sort_by(|a, b| (b.created_at, b.id).cmp(&(a.created_at, a.id)));
+        Ok(series
+            .into_iter()
+            .skip(filter.skip as usize)
+            .take(filter.count as usize)
+            .collect())
+    }
+
+    #[inline]
+    async fn count_series(&self) -> Result<u64, DatabaseError> {
+        Ok(self.series.lock().unwrap().len() as u64)
+    }
+
+    #[inline]
+    async fn delete_series(&self, sid: i32) -> Result<(), DatabaseError> {
+        let mut series = self.series.lock().unwrap();
+        let index = series
+            .iter()
+            .position(|s| s.id == sid)
+            .ok_or(DatabaseError::NotFound)?;
+        self.touch(&series, [sid]);
+        series.remove(index);
+
+        let mut blogposts = self.blogposts.lock().unwrap();
+        let parts = blogposts.iter().filter(|bp| bp.series_id == Some(sid));
+        self.touch(&blogposts, parts.map(|bp| bp.id).collect::<Vec<_>>());
+        for blogpost in blogposts.iter_mut() {
+            if blogpost.series_id == Some(sid) {
+                blogpost.series_id = None;
+                blogpost.series_position = None;
+                blogpost.version += 1;
+            }
+        }
+        Ok(())
+    }
+
+    #[inline]
+    async fn list_series_blogposts(&self, sid: i32) -> Result<Vec<Blogpost>, DatabaseError> {
+        let mut posts: Vec<Blogpost> = self
+            .blogposts
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|bp| bp.series_id == Some(sid) && bp.deleted_at.is_none())
+            .cloned()
+            .collect();
+
+        // posts without a position go last, same as the SQL database
+        posts.sort_by_key(|bp| {
+            (
+                bp.series_position.is_none(),
+                bp.series_position,
+                bp.created_at,
+                bp.id,
+            )
+        });
+        Ok(posts)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::MockDatabase;
+    use crate::{
+        models::{
+            Blogpost, BlogpostChange, BlogpostCursor, BlogpostFilter, IdList, MatchMode,
+            NewBlogpost, NewSeries, NewUpload, NewUser, UserChange, UserDeletion, UserFilter,
+        },
+        Database, DatabaseError,
+    };
+    use chrono::{Duration, Local};
+    use std::sync::Arc;
+
+    #[tokio::test]
+    async fn get_blogpost_by_id() {
+        let database = MockDatabase::with_test_data();
+        assert_eq!(
+            database.get_blogpost_by_id(1).await.unwrap().title,
+            "Chasing Suns"
+        );
         assert_eq!(
             database.get_blogpost_by_id(2).await.unwrap().title,
             "How to make a website"
         );
     }
 
+    #[tokio::test]
+    async fn lock_blogpost() {
+        let database = MockDatabase::with_test_data();
+        assert_eq!(database.lock_blogpost(1).await.unwrap(), 1);
+        database
+            .update_blogpost(1, BlogpostChange::default())
+            .await
+            .unwrap();
+        assert_eq!(database.lock_blogpost(1).await.unwrap(), 2);
+        assert!(database.lock_blogpost(100).await.is_err());
+    }
+
     #[tokio::test]
     async fn get_blogpost_by_url() {
         let database = MockDatabase::with_test_data();
@@ -352,7 +1320,7 @@ mod tests {
             tags: "we,break,bones".into(),
             url: "breaking-bones".into(),
             body: "I broke some bones today".into(),
-            author_id: 1,
+            ..NewBlogpost::test()
         };
         let id = database.insert_blogpost(bp).await.unwrap();
         assert_eq!(
@@ -383,6 +1351,56 @@ mod tests {
         );
     }
 
+    #[tokio::test]
+    async fn count_blogposts() {
+        let database = MockDatabase::with_test_data();
+        let filter = BlogpostFilter {
+            tags: Some("funny".into()),
+            count: 1,
+            ..Default::default()
+        };
+        assert_eq!(database.count_blogposts(filter).await.unwrap(), 2);
+
+        let filter = BlogpostFilter {
+            tags: Some("tutorial".into()),
+            ..Default::default()
+        };
+        assert_eq!(database.count_blogposts(filter).await.unwrap(), 1);
+    }
+
+    #[tokio::test]
+    async fn list_blogposts_by_cursor() {
+        let database = MockDatabase::with_test_data();
+        let filter = BlogpostFilter {
+            count: 1,
+            ..Default::default()
+        };
+        let first = database.list_blogposts(filter).await.unwrap().remove(0);
+
+        // a post published between page loads shouldn't shift the next page
+        let bp = NewBlogpost {
+            title: "Breaking Bones".into(),
+            tags: "we,break,bones".into(),
+            url: "breaking-bones".into(),
+            body: "I broke some bones today".into(),
+            ..NewBlogpost::test()
+        };
+        database.insert_blogpost(bp).await.unwrap();
+
+        let filter = BlogpostFilter {
+            count: 1,
+            cursor: Some(BlogpostCursor {
+                created_at: first.created_at,
+                id: first.id,
+            }),
+            ..Default::default()
+        };
+        let second = database.list_blogposts(filter).await.unwrap();
+        assert_eq!(second.len(), 1);
+        assert_ne!(second[0].id, first.id);
+        assert_ne!(second[0].title, "Breaking Bones");
+    }
+
     #[tokio::test]
     async fn delete_blogpost() {
         let database = MockDatabase::with_test_data();
@@ -390,6 +1408,155 @@ mod tests {
         assert!(database.get_blogpost_by_id(1).await.is_err())
     }
 
+    #[tokio::test]
+    async fn trash_restore_and_purge() {
+        let database = MockDatabase::with_test_data();
+        database.delete_blogpost(1).await.unwrap();
+        database.delete_blogpost(2).await.unwrap();
+        assert_eq!(
+            database
+                .count_blogposts(BlogpostFilter::default())
+                .await
+                .unwrap(),
+            0
+        );
+
+        let trash = BlogpostFilter {
+            trashed: true,
+            ..Default::default()
+        };
+        assert_eq!(database.count_blogposts(trash.clone()).await.unwrap(), 2);
+
+        database.restore_blogpost(1).await.unwrap();
+        assert!(database.get_blogpost_by_id(1).await.is_ok());
+        assert!(database.purge_blogpost(1).await.is_err());
+
+        database.purge_blogpost(2).await.unwrap();
+        assert_eq!(database.count_blogposts(trash).await.unwrap(), 0);
+        assert!(database.restore_blogpost(2).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn purge_old_trash() {
+        let database = MockDatabase::with_test_data();
+        database.delete_blogpost(1).await.unwrap();
+        database.delete_blogpost(2).await.unwrap();
+
+        // only the first post has been in the trash for long enough
+        let now = Local::now().naive_local();
+        database.blogposts.lock().unwrap()[0].deleted_at = Some(now - Duration::days(31));
+        assert_eq!(
+            database.purge_trash(now - Duration::days(30)).await.unwrap(),
+            1
+        );
+        assert!(database.restore_blogpost(1).await.is_err());
+        assert!(database.restore_blogpost(2).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn url_change_leaves_redirect() {
+        let database = MockDatabase::with_test_data();
+        let rename = |to: &str| BlogpostChange {
+            url: Some(to.into()),
+            ..Default::default()
+        };
+
+        database.update_blogpost(1, rename("suns")).await.unwrap();
+        database
+            .update_blogpost(1, rename("sunsets"))
+            .await
+            .unwrap();
+        for old in ["/blog/chasing-suns", "/blog/suns"] {
+            assert_eq!(
+                database.get_redirect_target(old.into()).await.unwrap(),
+                "/blog/sunsets"
+            );
+        }
+
+        // moving back to an old URL drops the redirect for it
+        database.update_blogpost(1, rename("suns")).await.unwrap();
+        assert!(database
+            .get_redirect_target("/blog/suns".into())
+            .await
+            .is_err());
+        assert_eq!(database.list_redirects().await.unwrap().len(), 2);
+
+        // posts in the trash can't be redirected to
+        database.delete_blogpost(1).await.unwrap();
+        assert!(database
+            .get_redirect_target("/blog/chasing-suns".into())
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn manual_redirects() {
+        let database = MockDatabase::with_test_data();
+        let new = |path: &str| NewUrlRedirect {
+            path: path.into(),
+            target: "/blog/chasing-suns".into(),
+        };
+
+        let id = database
+            .insert_redirect(new("/old/feed.xml"))
+            .await
+            .unwrap();
+        assert!(matches!(
+            database.insert_redirect(new("/old/feed.xml")).await,
+            Err(DatabaseError::AlreadyExists)
+        ));
+        assert_eq!(
+            database
+                .get_redirect_target("/old/feed.xml".into())
+                .await
+                .unwrap(),
+            "/blog/chasing-suns"
+        );
+
+        database.delete_redirect(id).await.unwrap();
+        assert!(database.delete_redirect(id).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn series_reading_order() {
+        let database = MockDatabase::with_test_data();
+        let id = database
+            .insert_series(NewSeries {
+                title: "Chasing".into(),
+                url: "chasing".into(),
+                description: String::new(),
+            })
+            .await
+            .unwrap();
+        let place = |position: Option<i32>| BlogpostChange {
+            series_id: Some(Some(id)),
+            series_position: Some(position),
+            ..Default::default()
+        };
+
+        database.update_blogpost(1, place(None)).await.unwrap();
+        database.update_blogpost(2, place(Some(1))).await.unwrap();
+        let order = |posts: Vec<Blogpost>| posts.iter().map(|bp| bp.id).collect::<Vec<_>>();
+        assert_eq!(
+            order(database.list_series_blogposts(id).await.unwrap()),
+            [2, 1]
+        );
+
+        database.delete_blogpost(2).await.unwrap();
+        assert_eq!(
+            order(database.list_series_blogposts(id).await.unwrap()),
+            [1]
+        );
+
+        // the posts outlive their series
+        database.delete_series(id).await.unwrap();
+        assert!(database.get_series_by_id(id).await.is_err());
+        assert_eq!(
+            database.get_blogpost_by_id(1).await.unwrap().series_id,
+            None
+        );
+    }
+
     #[tokio::test]
     async fn get_user_by_id() {
         let database = MockDatabase::with_test_data();
@@ -462,10 +1629,218 @@ mod tests {
         );
     }
 
+    #[tokio::test]
+    async fn list_and_count_users() {
+        let database = MockDatabase::with_test_data();
+        let filter = UserFilter {
+            skip: 1,
+            ..Default::default()
+        };
+        let users = database.list_users(filter.clone()).await.unwrap();
+        assert_eq!(users.len(), 1);
+        assert_eq!(database.count_users(filter).await.unwrap(), 2);
+
+        let filter = UserFilter {
+            name: Some("Smithee".into()),
+            ..Default::default()
+        };
+        assert_eq!(database.count_users(filter).await.unwrap(), 1);
+    }
+
+    #[tokio::test]
+    async fn list_blogposts_sorted_and_matched() {
+        let database = MockDatabase::with_test_data();
+        let filter = BlogpostFilter {
+            sort: Some("-title".parse().unwrap()),
+            ..Default::default()
+        };
+        let titles: Vec<String> = database
+            .list_blogposts(filter)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|bp| bp.title)
+            .collect();
+        assert_eq!(titles, ["How to make a website", "Chasing Suns"]);
+
+        let filter = BlogpostFilter {
+            title: Some("Chasing".into()),
+            match_mode: MatchMode::Exact,
+            ..Default::default()
+        };
+        assert_eq!(database.count_blogposts(filter).await.unwrap(), 0);
+
+        let filter = BlogpostFilter {
+            author_id: Some(IdList(vec![2, 3])),
+            ..Default::default()
+        };
+        assert_eq!(database.count_blogposts(filter).await.unwrap(), 0);
+    }
+
+    #[tokio::test]
+    async fn list_users_sorted() {
+        let database = MockDatabase::with_test_data();
+        let filter = UserFilter {
+            sort: Some("-id".parse().unwrap()),
+            ..Default::default()
+        };
+        let ids: Vec<i32> = database
+            .list_users(filter)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|user| user.id)
+            .collect();
+        assert_eq!(ids, [2, 1]);
+
+        let filter = UserFilter {
+            id: Some(IdList(vec![1])),
+            ..Default::default()
+        };
+        assert_eq!(database.count_users(filter).await.unwrap(), 1);
+    }
+
     #[tokio::test]
     async fn delete_user() {
         let database = MockDatabase::with_test_data();
-        database.delete_user(1).await.unwrap();
-        assert!(database.get_user_by_id(1).await.is_err())
+        database
+            .delete_user(1, UserDeletion::Reassign { to: 2 })
+            .await
+            .unwrap();
+        assert!(database.get_user_by_id(1).await.is_err());
+        assert_eq!(database.get_blogpost_by_id(1).await.unwrap().author_id, 2);
+    }
+
+    #[tokio::test]
+    async fn soft_delete_user() {
+        let database = MockDatabase::with_test_data();
+        database
+            .delete_user(2, UserDeletion::SoftDelete)
+            .await
+            .unwrap();
+        assert!(database
+            .get_user_by_id(2)
+            .await
+            .unwrap()
+            .deleted_at
+            .is_some());
+        assert_eq!(
+            database.count_users(UserFilter::default()).await.unwrap(),
+            1
+        );
+
+        let filter = UserFilter {
+            include_deleted: true,
+            ..Default::default()
+        };
+        assert_eq!(database.count_users(filter).await.unwrap(), 2);
+
+        database
+            .delete_user(1, UserDeletion::Anonymize)
+            .await
+            .unwrap();
+        let user = database.get_user_by_id(1).await.unwrap();
+        assert_eq!(user.name, None);
+        assert_eq!(user.roles, 0);
+        assert_ne!(user.uuid, "65a7e8c5-c235-49a9-ba00-6d9c049776f4");
+        assert_eq!(database.get_blogpost_by_id(1).await.unwrap().author_id, 1);
+    }
+
+    #[tokio::test]
+    async fn transaction_rolls_back() {
+        let database = MockDatabase::with_test_data();
+        let result: Result<(), DatabaseError> = database
+            .transaction(|tx| {
+                Box::pin(async move {
+                    tx.delete_blogpost(1).await?;
+                    Err(DatabaseError::NotFound)
+                })
+            })
+            .await;
+        assert!(result.is_err());
+        assert!(database.get_blogpost_by_id(1).await.is_ok());
+
+        database
+            .transaction(|tx| Box::pin(async move { tx.delete_blogpost(1).await }))
+            .await
+            .unwrap();
+        assert!(database.get_blogpost_by_id(1).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn nested_transaction_rolls_back_alone() {
+        let database = MockDatabase::with_test_data();
+        database
+            .transaction(|tx| {
+                Box::pin(async move {
+                    tx.delete_blogpost(1).await?;
+                    let inner: Result<(), DatabaseError> = tx
+                        .transaction(|tx| {
+                            Box::pin(async move {
+                                tx.delete_blogpost(2).await?;
+                                Err(DatabaseError::NotFound)
+                            })
+                        })
+                        .await;
+                    assert!(inner.is_err());
+                    Ok::<_, DatabaseError>(())
+                })
+            })
+            .await
+            .unwrap();
+
+        assert!(database.get_blogpost_by_id(1).await.is_err());
+        assert!(database.get_blogpost_by_id(2).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn transaction_keeps_other_changes() {
+        let database = Arc::new(MockDatabase::with_test_data());
+        let other = database.clone();
+        let result: Result<(), DatabaseError> = database
+            .transaction(move |tx| {
+                Box::pin(async move {
+                    tx.delete_blogpost(1).await?;
+                    // someone else changes another post in the meantime
+                    let change = BlogpostChange {
+                        title: Some("Catching Suns".into()),
+                        ..Default::default()
+                    };
+                    other.update_blogpost(2, change).await?;
+                    Err(DatabaseError::NotFound)
+                })
+            })
+            .await;
+        assert!(result.is_err());
+        assert!(database.get_blogpost_by_id(1).await.is_ok());
+        assert_eq!(
+            database.get_blogpost_by_id(2).await.unwrap().title,
+            "Catching Suns"
+        );
+    }
+
+    #[tokio::test]
+    async fn insert_upload() {
+        let database = MockDatabase::with_test_data();
+        let upload = NewUpload {
+            path: "files/video/2022/intro.mp4".into(),
+            content_type: "video/mp4".into(),
+            size: 64 * 1024 * 1024,
+            uploader_id: 1,
+            pending_until: None,
+        };
+        let id = database.insert_upload(upload).await.unwrap();
+        let paths = database
+            .get_upload_paths(vec![
+                "files/video/2022/intro.mp4".into(),
+                "files/video/2022/outro.mp4".into(),
+            ])
+            .await
+            .unwrap();
+        assert_eq!(paths, ["files/video/2022/intro.mp4"]);
+
+        let uploads = database.uploads.lock().unwrap();
+        let upload = uploads.iter().find(|u| u.id == id).unwrap();
+        assert_eq!(upload.path, "files/video/2022/intro.mp4");
     }
 }
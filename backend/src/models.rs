@@ -1,24 +1,41 @@
 // GNU AGPL v3 License
 
 use super::{
-    auth::Permissions,
-    schema::{blogposts, users},
-    Database, DatabaseError,
+    auth::{self, Permissions},
+    lint::{self, LintWarning},
+    markdown::{self, FrontMatter, FrontMatterError, Summary},
+    sanitize::Policy,
+    schema::{blogposts, series, uploads, url_redirects, users},
+    slug::{self, SlugError},
+    wikilink, Database, DatabaseError,
 };
 use async_trait::async_trait;
 use chrono::NaiveDateTime;
-use serde::{Deserialize, Serialize};
+use data_encoding::BASE64URL_NOPAD;
+use schemars::{
+    gen::SchemaGenerator,
+    schema::{InstanceType, Metadata, Schema, SchemaObject},
+    JsonSchema,
+};
+use serde::{de, Deserialize, Deserializer, Serialize};
+use std::{convert::TryFrom, num::ParseIntError, str::FromStr};
 
-#[derive(Clone, Queryable, Identifiable, AsChangeset, Serialize)]
+#[derive(Clone, Queryable, Identifiable, AsChangeset, Serialize, JsonSchema)]
 #[table_name = "users"]
 pub struct User {
     pub id: i32,
     pub uuid: String,
     pub name: Option<String>,
     pub roles: i64,
+    /// Incremented every time the user is updated.
+    pub version: i32,
+    /// The last time the user was updated.
+    pub updated_at: NaiveDateTime,
+    /// When the user was deleted, if they were soft-deleted or anonymized.
+    pub deleted_at: Option<NaiveDateTime>,
 }
 
-#[derive(Insertable, Deserialize)]
+#[derive(Insertable, Deserialize, JsonSchema)]
 #[table_name = "users"]
 pub struct NewUser {
     pub uuid: String,
@@ -26,12 +43,39 @@ pub struct NewUser {
     pub roles: i64,
 }
 
-#[derive(Deserialize)]
+#[derive(Clone, Deserialize, JsonSchema)]
 pub struct UserFilter {
+    pub id: Option<IdList>,
     pub name: Option<String>,
+    /// Also list users that have been deleted.
+    #[serde(default)]
+    pub include_deleted: bool,
+
+    #[serde(default, rename = "match")]
+    pub match_mode: MatchMode,
+    pub sort: Option<Sort<UserSortField>>,
+    #[serde(default)]
+    pub skip: u64,
+    #[serde(default = "default_count")]
+    pub count: u64,
 }
 
-#[derive(Default, Deserialize, AsChangeset)]
+impl Default for UserFilter {
+    #[inline]
+    fn default() -> Self {
+        Self {
+            id: None,
+            name: None,
+            include_deleted: false,
+            match_mode: MatchMode::default(),
+            sort: None,
+            skip: 0,
+            count: default_count(),
+        }
+    }
+}
+
+#[derive(Default, Deserialize, AsChangeset, JsonSchema)]
 #[table_name = "users"]
 pub struct UserChange {
     pub uuid: Option<String>,
@@ -39,7 +83,90 @@ pub struct UserChange {
     pub roles: Option<i64>,
 }
 
-#[derive(Clone, Queryable, Identifiable, AsChangeset, Deserialize, Serialize)]
+/// How to delete a `User`, given that their posts and uploads still refer
+/// to them.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "UserDeletionParams")]
+pub enum UserDeletion {
+    /// Mark the user as deleted, leaving everything else in place.
+    SoftDelete,
+    /// Mark the user as deleted, and clear their name, UUID and roles so
+    /// they can't be identified. Since the UUID is gone, logging in again
+    /// with the same account starts over as a new user with no roles.
+    Anonymize,
+    /// Give the user's posts and uploads to another user, then delete them
+    /// for good.
+    Reassign { to: i32 },
+}
+
+impl Default for UserDeletion {
+    #[inline]
+    fn default() -> Self {
+        UserDeletion::SoftDelete
+    }
+}
+
+impl JsonSchema for UserDeletion {
+    #[inline]
+    fn schema_name() -> String {
+        "UserDeletion".into()
+    }
+
+    #[inline]
+    fn json_schema(gen: &mut SchemaGenerator) -> Schema {
+        UserDeletionParams::json_schema(gen)
+    }
+}
+
+/// `UserDeletion` as it's sent over the wire, e.g. `mode=reassign&reassign_to=1`.
+#[derive(Deserialize, JsonSchema)]
+pub struct UserDeletionParams {
+    #[serde(default)]
+    pub mode: UserDeletionMode,
+    /// Who gets the posts and uploads when reassigning them.
+    pub reassign_to: Option<i32>,
+}
+
+#[derive(Deserialize, Serialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum UserDeletionMode {
+    /// Mark the user as deleted.
+    SoftDelete,
+    /// Also clear their name, UUID and roles.
+    Anonymize,
+    /// Give their posts and uploads to the user `reassign_to`, then remove
+    /// them entirely.
+    Reassign,
+}
+
+impl Default for UserDeletionMode {
+    #[inline]
+    fn default() -> Self {
+        UserDeletionMode::SoftDelete
+    }
+}
+
+impl TryFrom<UserDeletionParams> for UserDeletion {
+    type Error = &'static str;
+
+    #[inline]
+    fn try_from(params: UserDeletionParams) -> Result<Self, &'static str> {
+        match (params.mode, params.reassign_to) {
+            (UserDeletionMode::SoftDelete, _) => Ok(UserDeletion::SoftDelete),
+            (UserDeletionMode::Anonymize, _) => Ok(UserDeletion::Anonymize),
+            (UserDeletionMode::Reassign, Some(to)) => Ok(UserDeletion::Reassign { to }),
+            (UserDeletionMode::Reassign, None) => {
+                Err("`reassign_to` is required when reassigning posts")
+            }
+        }
+    }
+}
+
+/// Delete options for models that can only be deleted one way.
+#[derive(Default, Deserialize, JsonSchema)]
+pub struct NoOptions {}
+
+#[derive(Clone, Queryable, Identifiable, AsChangeset, Deserialize, Serialize, JsonSchema)]
 #[table_name = "blogposts"]
 pub struct Blogpost {
     pub id: i32,
@@ -49,38 +176,496 @@ pub struct Blogpost {
     pub body: String,
     pub author_id: i32,
     pub created_at: NaiveDateTime,
+    /// Incremented every time the blogpost is updated.
+    pub version: i32,
+    /// The last time the blogpost was updated.
+    pub updated_at: NaiveDateTime,
+    /// When the blogpost was moved to the trash, if it's there.
+    pub deleted_at: Option<NaiveDateTime>,
+    /// The series that the blogpost is a part of, if any.
+    pub series_id: Option<i32>,
+    /// Where the blogpost goes in its series. Parts without a position go
+    /// last, in the order that they were written.
+    pub series_position: Option<i32>,
+    /// Plain text that's shown when the post is listed. Unless the author
+    /// gives one, it's an excerpt of the body.
+    pub summary: Option<String>,
+    pub cover_image: Option<String>,
+    /// Where the post was first published, if it was somewhere else.
+    pub canonical_url: Option<String>,
+    /// Words in the text that the body renders into.
+    pub word_count: i32,
+    pub reading_minutes: i32,
+    /// Whether the body was last saved by someone trusted to embed media,
+    /// which decides how much of its HTML is kept.
+    pub trusted_html: bool,
+    /// Whether the summary is an excerpt of the body rather than one that
+    /// the author gave, so that it follows the body when that changes.
+    pub summary_generated: bool,
 }
 
-#[derive(Insertable, Deserialize)]
+/// A new blogpost. Any fields that are left empty are taken from the
+/// front matter of the body, if it has any. That's TOML between `+++`
+/// lines or YAML between `---` lines, and can give the `title`, `tags`,
+/// `summary`, `cover_image`, `canonical_url` and publish `date`.
+#[derive(Clone, Insertable, Deserialize, JsonSchema)]
 #[table_name = "blogposts"]
 pub struct NewBlogpost {
+    #[serde(default)]
     pub title: String,
+    #[serde(default)]
     pub tags: String,
+    /// Generated from the title if left empty.
+    #[serde(default)]
     pub url: String,
     pub body: String,
     pub author_id: i32,
+    #[serde(default)]
+    pub series_id: Option<i32>,
+    #[serde(default)]
+    pub series_position: Option<i32>,
+    #[serde(default)]
+    pub summary: Option<String>,
+    #[serde(default)]
+    pub cover_image: Option<String>,
+    #[serde(default)]
+    pub canonical_url: Option<String>,
+    /// Only ever set from the publish date in the front matter.
+    #[serde(skip)]
+    pub created_at: Option<NaiveDateTime>,
+    /// Counted from the body when the post is saved.
+    #[serde(skip)]
+    pub word_count: i32,
+    #[serde(skip)]
+    pub reading_minutes: i32,
+    /// Set from the roles of whoever saves the body.
+    #[serde(skip)]
+    pub trusted_html: bool,
+    /// Set when the summary is filled in with an excerpt.
+    #[serde(skip)]
+    pub summary_generated: bool,
+}
+
+impl NewBlogpost {
+    /// Fill in the fields that were left empty from the front matter.
+    #[inline]
+    fn apply_front_matter(&mut self, fm: FrontMatter) {
+        if self.title.trim().is_empty() {
+            self.title = fm.title.unwrap_or_default();
+        }
+        if self.tags.trim().is_empty() {
+            self.tags = fm.tags.unwrap_or_default();
+        }
+        self.summary = self.summary.take().or(fm.summary);
+        self.cover_image = self.cover_image.take().or(fm.cover_image);
+        self.canonical_url = self.canonical_url.take().or(fm.canonical_url);
+        self.created_at = fm.date;
+    }
+
+    /// Fill in the summary with an excerpt if it was left empty, and count
+    /// the words in the body.
+    #[inline]
+    fn apply_summary(&mut self, summary: Summary) {
+        let given = self.summary.as_deref().map_or("", str::trim);
+        self.summary_generated = given.is_empty();
+        if self.summary_generated {
+            self.summary = (!summary.excerpt.is_empty()).then(|| summary.excerpt);
+        }
+        self.word_count = summary.word_count;
+        self.reading_minutes = summary.reading_minutes;
+    }
 }
 
-#[derive(Deserialize)]
+#[cfg(test)]
+impl Blogpost {
+    /// A blogpost for tests to fill in the parts they care about.
+    #[inline]
+    pub fn test() -> Self {
+        let now = chrono::Local::now().naive_local();
+        Self {
+            id: 1,
+            title: "Chasing Suns".into(),
+            tags: "story".into(),
+            url: "chasing-suns".into(),
+            body: String::new(),
+            author_id: 1,
+            created_at: now,
+            version: 1,
+            updated_at: now,
+            deleted_at: None,
+            series_id: None,
+            series_position: None,
+            summary: None,
+            cover_image: None,
+            canonical_url: None,
+            word_count: 0,
+            reading_minutes: 1,
+            trusted_html: false,
+            summary_generated: true,
+        }
+    }
+}
+
+#[cfg(test)]
+impl NewBlogpost {
+    /// A new blogpost for tests to fill in the parts they care about.
+    #[inline]
+    pub fn test() -> Self {
+        Self {
+            title: "Chasing Suns".into(),
+            tags: String::new(),
+            url: String::new(),
+            body: String::new(),
+            author_id: 1,
+            series_id: None,
+            series_position: None,
+            summary: None,
+            cover_image: None,
+            canonical_url: None,
+            created_at: None,
+            word_count: 0,
+            reading_minutes: 0,
+            trusted_html: false,
+            summary_generated: true,
+        }
+    }
+}
+
+#[derive(Clone, Deserialize, JsonSchema)]
 pub struct BlogpostFilter {
     pub title: Option<String>,
     pub tags: Option<String>,
     pub url: Option<String>,
     pub body: Option<String>,
-    pub author_id: Option<i32>,
+    pub author_id: Option<IdList>,
+    #[schemars(example = "example_date")]
+    pub created_after: Option<NaiveDateTime>,
+    #[schemars(example = "example_date")]
+    pub created_before: Option<NaiveDateTime>,
 
+    #[serde(default, rename = "match")]
+    pub match_mode: MatchMode,
+    /// Comma-separated fields to sort by, prefixed with `-` for descending
+    /// order. Can't be combined with a cursor, which only points into the
+    /// default newest-first ordering.
+    pub sort: Option<Sort<BlogpostSortField>>,
+    /// Start after this position instead of skipping a number of posts.
+    /// This is the `next_cursor` of the previous page.
+    pub cursor: Option<BlogpostCursor>,
+    /// List the posts in the trash instead of the published ones. This is
+    /// never taken from the query string.
+    #[serde(skip)]
+    pub trashed: bool,
     #[serde(default)]
     pub skip: u64,
     #[serde(default = "default_count")]
     pub count: u64,
 }
 
+impl Default for BlogpostFilter {
+    #[inline]
+    fn default() -> Self {
+        Self {
+            title: None,
+            tags: None,
+            url: None,
+            body: None,
+            author_id: None,
+            created_after: None,
+            created_before: None,
+            match_mode: MatchMode::default(),
+            sort: None,
+            cursor: None,
+            trashed: false,
+            skip: 0,
+            count: default_count(),
+        }
+    }
+}
+
 #[inline]
-const fn default_count() -> u64 {
+pub(crate) const fn default_count() -> u64 {
     25
 }
 
-#[derive(Default, Deserialize, AsChangeset)]
+/// Dates are ISO 8601 without a time zone.
+#[inline]
+fn example_date() -> &'static str {
+    "2022-01-08T15:42:10"
+}
+
+/// The schema for a type that's parsed from a string.
+#[inline]
+fn string_schema(description: &str, example: String) -> Schema {
+    SchemaObject {
+        instance_type: Some(InstanceType::String.into()),
+        metadata: Some(Box::new(Metadata {
+            description: Some(description.into()),
+            examples: vec![example.into()],
+            ..Default::default()
+        })),
+        ..Default::default()
+    }
+    .into()
+}
+
+/// How the string fields in a filter are compared against a model.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Deserialize, Serialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum MatchMode {
+    /// The field contains the value, ignoring case.
+    Contains,
+    /// The field is exactly the value.
+    Exact,
+}
+
+impl Default for MatchMode {
+    #[inline]
+    fn default() -> Self {
+        MatchMode::Contains
+    }
+}
+
+/// A comma-separated list of IDs, such as `1,2,3`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct IdList(pub Vec<i32>);
+
+impl<'de> Deserialize<'de> for IdList {
+    #[inline]
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let list = String::deserialize(deserializer)?;
+        list.split(',')
+            .map(|id| id.trim().parse())
+            .collect::<Result<Vec<i32>, _>>()
+            .map(IdList)
+            .map_err(de::Error::custom)
+    }
+}
+
+impl JsonSchema for IdList {
+    #[inline]
+    fn is_referenceable() -> bool {
+        false
+    }
+
+    #[inline]
+    fn schema_name() -> String {
+        "IdList".into()
+    }
+
+    #[inline]
+    fn json_schema(_: &mut SchemaGenerator) -> Schema {
+        string_schema("Comma-separated list of IDs", "1,2".into())
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum SortDirection {
+    Ascending,
+    Descending,
+}
+
+/// A field that a list of models can be sorted by.
+pub trait SortField: Copy + Sized {
+    /// The names of every field, as they're given in a sort string.
+    const NAMES: &'static [&'static str];
+
+    /// Get the field with the given name, if there is one.
+    fn from_name(name: &str) -> Option<Self>;
+}
+
+/// The fields to sort a list by, parsed from a string like `title,-id`.
+///
+/// Fields prefixed with a `-` are sorted in descending order.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Sort<F>(pub Vec<(F, SortDirection)>);
+
+impl<F: SortField> FromStr for Sort<F> {
+    type Err = UnknownSortField;
+
+    #[inline]
+    fn from_str(s: &str) -> Result<Self, UnknownSortField> {
+        s.split(',')
+            .filter(|field| !field.is_empty())
+            .map(|field| {
+                let (name, direction) = match field.strip_prefix('-') {
+                    Some(name) => (name, SortDirection::Descending),
+                    None => (field, SortDirection::Ascending),
+                };
+
+                F::from_name(name)
+                    .map(|field| (field, direction))
+                    .ok_or_else(|| UnknownSortField(name.to_string()))
+            })
+            .collect::<Result<Vec<_>, _>>()
+            .map(Sort)
+    }
+}
+
+impl<'de, F: SortField> Deserialize<'de> for Sort<F> {
+    #[inline]
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let sort = String::deserialize(deserializer)?;
+        sort.parse().map_err(de::Error::custom)
+    }
+}
+
+impl<F: SortField> JsonSchema for Sort<F> {
+    #[inline]
+    fn is_referenceable() -> bool {
+        false
+    }
+
+    #[inline]
+    fn schema_name() -> String {
+        "Sort".into()
+    }
+
+    #[inline]
+    fn json_schema(_: &mut SchemaGenerator) -> Schema {
+        let field = format!("-?({})", F::NAMES.join("|"));
+        let mut schema = string_schema(
+            "Comma-separated fields to sort by, prefixed with `-` for descending order",
+            format!("-{}", F::NAMES[0]),
+        )
+        .into_object();
+        schema.string().pattern = Some(format!("^{0}(,{0})*$", field));
+        schema.into()
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+#[error("Unknown sort field `{0}`")]
+pub struct UnknownSortField(pub String);
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum BlogpostSortField {
+    Id,
+    Title,
+    Url,
+    AuthorId,
+    CreatedAt,
+}
+
+impl SortField for BlogpostSortField {
+    const NAMES: &'static [&'static str] = &["created_at", "id", "title", "url", "author_id"];
+
+    #[inline]
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "id" => Some(Self::Id),
+            "title" => Some(Self::Title),
+            "url" => Some(Self::Url),
+            "author_id" => Some(Self::AuthorId),
+            "created_at" => Some(Self::CreatedAt),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum UserSortField {
+    Id,
+    Name,
+    Roles,
+}
+
+impl SortField for UserSortField {
+    const NAMES: &'static [&'static str] = &["id", "name", "roles"];
+
+    #[inline]
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "id" => Some(Self::Id),
+            "name" => Some(Self::Name),
+            "roles" => Some(Self::Roles),
+            _ => None,
+        }
+    }
+}
+
+/// A position in the list of blogposts, used for keyset pagination.
+///
+/// Blogposts are listed newest first, so the next page consists of the
+/// posts that come before this one.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "String")]
+pub struct BlogpostCursor {
+    pub created_at: NaiveDateTime,
+    pub id: i32,
+}
+
+impl BlogpostCursor {
+    /// Encode this cursor into an opaque token.
+    #[inline]
+    #[must_use]
+    pub fn encode(&self) -> String {
+        let raw = format!(
+            "{}.{}:{}",
+            self.created_at.timestamp(),
+            self.created_at.timestamp_subsec_nanos(),
+            self.id
+        );
+        BASE64URL_NOPAD.encode(raw.as_bytes())
+    }
+}
+
+impl TryFrom<String> for BlogpostCursor {
+    type Error = InvalidCursor;
+
+    #[inline]
+    fn try_from(token: String) -> Result<Self, InvalidCursor> {
+        let raw = BASE64URL_NOPAD
+            .decode(token.as_bytes())
+            .map_err(|_| InvalidCursor)?;
+        let raw = String::from_utf8(raw).map_err(|_| InvalidCursor)?;
+
+        let (time, id) = raw.split_once(':').ok_or(InvalidCursor)?;
+        let (secs, nanos) = time.split_once('.').ok_or(InvalidCursor)?;
+        let created_at = NaiveDateTime::from_timestamp_opt(secs.parse()?, nanos.parse()?)
+            .ok_or(InvalidCursor)?;
+
+        Ok(Self {
+            created_at,
+            id: id.parse()?,
+        })
+    }
+}
+
+impl JsonSchema for BlogpostCursor {
+    #[inline]
+    fn is_referenceable() -> bool {
+        false
+    }
+
+    #[inline]
+    fn schema_name() -> String {
+        "BlogpostCursor".into()
+    }
+
+    #[inline]
+    fn json_schema(_: &mut SchemaGenerator) -> Schema {
+        string_schema(
+            "The `next_cursor` of the previous page",
+            "MTY0MTYwMDAwMC4wOjE".into(),
+        )
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+#[error("Invalid pagination cursor")]
+pub struct InvalidCursor;
+
+impl From<ParseIntError> for InvalidCursor {
+    #[inline]
+    fn from(_: ParseIntError) -> InvalidCursor {
+        InvalidCursor
+    }
+}
+
+/// A change to a blogpost. If the new body has front matter, the fields
+/// that aren't changed are taken from it.
+#[derive(Default, Deserialize, AsChangeset, JsonSchema)]
 #[table_name = "blogposts"]
 pub struct BlogpostChange {
     pub title: Option<String>,
@@ -88,6 +673,267 @@ pub struct BlogpostChange {
     pub url: Option<String>,
     pub body: Option<String>,
     pub author_id: Option<i32>,
+    /// `null` takes the blogpost out of its series.
+    #[serde(default, deserialize_with = "nullable")]
+    #[schemars(with = "Option<i32>")]
+    pub series_id: Option<Option<i32>>,
+    #[serde(default, deserialize_with = "nullable")]
+    #[schemars(with = "Option<i32>")]
+    pub series_position: Option<Option<i32>>,
+    #[serde(default, deserialize_with = "nullable")]
+    #[schemars(with = "Option<String>")]
+    pub summary: Option<Option<String>>,
+    #[serde(default, deserialize_with = "nullable")]
+    #[schemars(with = "Option<String>")]
+    pub cover_image: Option<Option<String>>,
+    #[serde(default, deserialize_with = "nullable")]
+    #[schemars(with = "Option<String>")]
+    pub canonical_url: Option<Option<String>>,
+    /// Only ever set from the publish date in the front matter.
+    #[serde(skip)]
+    pub created_at: Option<NaiveDateTime>,
+    /// Counted from the new body when the post is saved.
+    #[serde(skip)]
+    pub word_count: Option<i32>,
+    #[serde(skip)]
+    pub reading_minutes: Option<i32>,
+    /// Set from the roles of whoever saves the new body.
+    #[serde(skip)]
+    pub trusted_html: Option<bool>,
+    /// Set whenever the summary is changed, by the author or with an
+    /// excerpt of the new body.
+    #[serde(skip)]
+    pub summary_generated: Option<bool>,
+}
+
+impl BlogpostChange {
+    /// Fill in the fields that weren't changed from the front matter of the
+    /// new body.
+    ///
+    /// The body is meant to describe the whole post, so the optional fields
+    /// that the front matter leaves out are cleared.
+    #[inline]
+    fn apply_front_matter(&mut self, fm: FrontMatter) {
+        self.title = self.title.take().or(fm.title);
+        self.tags = self.tags.take().or(fm.tags);
+        self.summary.get_or_insert(fm.summary);
+        self.cover_image.get_or_insert(fm.cover_image);
+        self.canonical_url.get_or_insert(fm.canonical_url);
+        self.created_at = fm.date;
+    }
+
+    /// Count the words in the new body, and replace the summary with an
+    /// excerpt of it unless the author gives one. `generated` is whether the
+    /// summary that the post has now is an excerpt, rather than one that the
+    /// author gave, so that it's kept up to date if the summary isn't
+    /// changed.
+    #[inline]
+    fn apply_summary(&mut self, summary: Summary, generated: bool) {
+        let keep = match &self.summary {
+            Some(Some(given)) => !given.trim().is_empty(),
+            Some(None) => false,
+            None => !generated,
+        };
+        if !keep {
+            self.summary = Some((!summary.excerpt.is_empty()).then(|| summary.excerpt));
+            self.summary_generated = Some(true);
+        } else if self.summary.is_some() {
+            self.summary_generated = Some(false);
+        }
+        self.word_count = Some(summary.word_count);
+        self.reading_minutes = Some(summary.reading_minutes);
+    }
+
+    /// Whether the summary is being cleared, so that an excerpt of the body
+    /// takes its place.
+    #[inline]
+    fn clears_summary(&self) -> bool {
+        matches!(
+            &self.summary,
+            Some(summary) if summary.as_deref().map_or(true, |given| given.trim().is_empty())
+        )
+    }
+
+    /// Keep track of a summary that the author gave without changing the
+    /// body.
+    #[inline]
+    fn apply_given_summary(&mut self) {
+        if self.summary.is_some() {
+            self.summary_generated = Some(false);
+        }
+    }
+}
+
+/// Tell a field that was set to `null` apart from one that was left out,
+/// which `#[serde(default)]` turns into `None`.
+#[inline]
+fn nullable<'de, T: Deserialize<'de>, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<Option<T>>, D::Error> {
+    Option::deserialize(deserializer).map(Some)
+}
+
+#[derive(Clone, Queryable, Identifiable, Serialize)]
+#[table_name = "uploads"]
+pub struct Upload {
+    pub id: i32,
+    pub path: String,
+    pub content_type: String,
+    pub size: i64,
+    pub uploader_id: i32,
+    pub created_at: NaiveDateTime,
+    /// For presigned uploads that haven't been completed, when they stop
+    /// being able to be.
+    pub pending_until: Option<NaiveDateTime>,
+}
+
+#[derive(Insertable)]
+#[table_name = "uploads"]
+pub struct NewUpload {
+    pub path: String,
+    pub content_type: String,
+    pub size: i64,
+    pub uploader_id: i32,
+    /// Set for presigned uploads, which keep their path to themselves until
+    /// then.
+    pub pending_until: Option<NaiveDateTime>,
+}
+
+/// An old path that should send visitors somewhere else.
+#[derive(Clone, Queryable, Identifiable, Serialize, JsonSchema)]
+#[table_name = "url_redirects"]
+pub struct UrlRedirect {
+    pub id: i32,
+    pub path: String,
+    /// The blogpost that used to live at the path, for redirects that are
+    /// recorded when a post's URL changes.
+    pub blogpost_id: Option<i32>,
+    /// Where the path leads, for redirects that were added by hand.
+    pub target: Option<String>,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Insertable, Deserialize, JsonSchema)]
+#[table_name = "url_redirects"]
+pub struct NewUrlRedirect {
+    pub path: String,
+    pub target: String,
+}
+
+/// A published blogpost that a wiki link leads to.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct LinkTarget {
+    /// The URL that the link was written with, which may be one that the
+    /// post used to have.
+    pub slug: String,
+    pub url: String,
+    pub title: String,
+}
+
+/// A set of blogposts that are meant to be read in order.
+#[derive(Clone, Queryable, Identifiable, Serialize, JsonSchema)]
+#[table_name = "series"]
+pub struct Series {
+    pub id: i32,
+    pub title: String,
+    pub url: String,
+    /// Markdown shown at the top of the series' index page.
+    pub description: String,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Insertable, Deserialize, JsonSchema)]
+#[table_name = "series"]
+pub struct NewSeries {
+    pub title: String,
+    /// Generated from the title if left empty.
+    #[serde(default)]
+    pub url: String,
+    #[serde(default)]
+    pub description: String,
+}
+
+#[derive(Default, Deserialize, AsChangeset, JsonSchema)]
+#[table_name = "series"]
+pub struct SeriesChange {
+    pub title: Option<String>,
+    pub url: Option<String>,
+    pub description: Option<String>,
+}
+
+#[derive(Clone, Deserialize, JsonSchema)]
+pub struct SeriesFilter {
+    #[serde(default)]
+    pub skip: u64,
+    #[serde(default = "default_count")]
+    pub count: u64,
+}
+
+impl Default for SeriesFilter {
+    #[inline]
+    fn default() -> Self {
+        Self {
+            skip: 0,
+            count: default_count(),
+        }
+    }
+}
+
+/// The path that a blogpost with the given URL slug is served from.
+#[must_use]
+#[inline]
+pub fn blogpost_path(url: &str) -> String {
+    format!("/blog/{}", url)
+}
+
+/// The URL slug of the blogpost that would be served from a path.
+#[must_use]
+#[inline]
+pub fn blogpost_url(path: &str) -> Option<&str> {
+    path.strip_prefix("/blog/")
+}
+
+/// Why an instance can't be saved as it was given, as opposed to the
+/// database failing to save it.
+#[derive(Debug, thiserror::Error)]
+pub enum ValidationError {
+    #[error("Unable to reassign posts to user {0}")]
+    InvalidReassignment(i32),
+    #[error("{0}")]
+    InvalidSlug(#[from] SlugError),
+    #[error("The URL is already taken, but {suggestion} is free")]
+    SlugTaken { suggestion: String },
+    #[error("Unable to find a free URL starting with {0}")]
+    NoFreeSlug(String),
+    #[error("There is no series with the ID {0}")]
+    InvalidSeries(i32),
+    #[error("Unable to read the front matter: {0}")]
+    InvalidFrontMatter(#[from] FrontMatterError),
+    #[error("The blogpost has no title")]
+    MissingTitle,
+}
+
+/// The ways that creating, updating or deleting an instance can fail.
+#[derive(Debug, thiserror::Error)]
+pub enum SaveError {
+    #[error("{0}")]
+    Database(#[from] DatabaseError),
+    #[error("{0}")]
+    Invalid(#[from] ValidationError),
+}
+
+impl From<SlugError> for SaveError {
+    #[inline]
+    fn from(e: SlugError) -> Self {
+        Self::Invalid(e.into())
+    }
+}
+
+impl From<FrontMatterError> for SaveError {
+    #[inline]
+    fn from(e: FrontMatterError) -> Self {
+        Self::Invalid(e.into())
+    }
 }
 
 #[async_trait]
@@ -98,9 +944,15 @@ pub trait Model: Sized {
     const UPDATE_PERMS: Permissions;
     const DELETE_PERMS: Permissions;
 
+    /// The query parameters that `ListFilter` is read from, which are kept
+    /// in the links to other pages of a list. Pagination parameters are
+    /// always kept.
+    const FILTER_KEYS: &'static [&'static str] = &[];
+
     type ListFilter;
     type NewInstance;
     type UpdateInstance;
+    type DeleteOptions;
 
     /// Get a single instance by its ID.
     async fn get(db: &(impl Database + Send + Sync), id: i32) -> Result<Self, DatabaseError>;
@@ -109,32 +961,101 @@ pub trait Model: Sized {
         db: &(impl Database + Send + Sync),
         filter: Self::ListFilter,
     ) -> Result<Vec<Self>, DatabaseError>;
+    /// Count the instances that match a filter, ignoring pagination.
+    async fn count(
+        db: &(impl Database + Send + Sync),
+        filter: Self::ListFilter,
+    ) -> Result<u64, DatabaseError>;
     /// Create a new instance.
     async fn create(
         db: &(impl Database + Send + Sync),
         new: Self::NewInstance,
-    ) -> Result<i32, DatabaseError>;
+    ) -> Result<i32, SaveError>;
     /// Update this instance with new properties.
     async fn update(
         db: &(impl Database + Send + Sync),
         id: i32,
         patch: Self::UpdateInstance,
-    ) -> Result<(), DatabaseError>;
+    ) -> Result<(), SaveError>;
     /// Delete this instance by its ID.
-    async fn delete(db: &(impl Database + Send + Sync), id: i32) -> Result<(), DatabaseError>;
+    async fn delete(
+        db: &(impl Database + Send + Sync),
+        id: i32,
+        options: Self::DeleteOptions,
+    ) -> Result<(), SaveError>;
+
+    /// Make sure that a list of instances can be sorted by each of the
+    /// fields in `sort`.
+    #[inline]
+    fn check_sort(_sort: &str) -> Result<(), UnknownSortField> {
+        Ok(())
+    }
+
+    /// Note the roles of whoever is creating an instance, before it's
+    /// saved.
+    #[inline]
+    fn created_by(_new: &mut Self::NewInstance, _roles: Permissions) {}
+
+    /// Note the roles of whoever is updating an instance, before it's
+    /// saved.
+    #[inline]
+    fn updated_by(_patch: &mut Self::UpdateInstance, _roles: Permissions) {}
+
+    /// A cursor pointing just after this instance, if the model supports
+    /// keyset pagination. Cursors only point into the default ordering.
+    #[inline]
+    fn cursor(&self) -> Option<String> {
+        None
+    }
+
+    /// The version of this instance, which changes every time it is
+    /// updated. Used as the entity tag in conditional requests.
+    #[inline]
+    fn version(&self) -> Option<i32> {
+        None
+    }
+
+    /// Get the version of an instance by its ID, keeping it from being
+    /// changed by anyone else until the current transaction ends.
+    #[inline]
+    async fn lock(
+        db: &(impl Database + Send + Sync),
+        id: i32,
+    ) -> Result<Option<i32>, DatabaseError> {
+        Ok(Self::get(db, id).await?.version())
+    }
+
+    /// Problems with an instance that didn't stop it from being saved, but
+    /// that whoever saved it should hear about.
+    #[inline]
+    async fn warnings(
+        _db: &(impl Database + Send + Sync),
+        _id: i32,
+    ) -> Result<Vec<LintWarning>, DatabaseError> {
+        Ok(vec![])
+    }
+
+    /// The last time this instance was updated, if it's tracked.
+    #[inline]
+    fn last_modified(&self) -> Option<NaiveDateTime> {
+        None
+    }
 }
 
 #[async_trait]
 impl Model for User {
-    const LIST_PERMS: Permissions = Permissions(0b10);
-    const GET_PERMS: Permissions = Permissions(0b10);
-    const CREATE_PERMS: Permissions = Permissions(0b10);
-    const UPDATE_PERMS: Permissions = Permissions(0b10);
-    const DELETE_PERMS: Permissions = Permissions(0b10);
+    const LIST_PERMS: Permissions = Permissions::ADMIN;
+    const GET_PERMS: Permissions = Permissions::ADMIN;
+    const CREATE_PERMS: Permissions = Permissions::ADMIN;
+    const UPDATE_PERMS: Permissions = Permissions::ADMIN;
+    const DELETE_PERMS: Permissions = Permissions::ADMIN;
+    const FILTER_KEYS: &'static [&'static str] =
+        &["id", "name", "include_deleted", "match", "sort"];
 
     type ListFilter = UserFilter;
     type NewInstance = NewUser;
     type UpdateInstance = UserChange;
+    type DeleteOptions = UserDeletion;
 
     #[inline]
     async fn get(db: &(impl Database + Send + Sync), id: i32) -> Result<Self, DatabaseError> {
@@ -149,12 +1070,20 @@ impl Model for User {
         db.list_users(filter).await
     }
 
+    #[inline]
+    async fn count(
+        db: &(impl Database + Send + Sync),
+        filter: Self::ListFilter,
+    ) -> Result<u64, DatabaseError> {
+        db.count_users(filter).await
+    }
+
     #[inline]
     async fn create(
         db: &(impl Database + Send + Sync),
         new: Self::NewInstance,
-    ) -> Result<i32, DatabaseError> {
-        db.insert_user(new).await
+    ) -> Result<i32, SaveError> {
+        Ok(db.insert_user(new).await?)
     }
 
     #[inline]
@@ -162,13 +1091,55 @@ impl Model for User {
         db: &(impl Database + Send + Sync),
         id: i32,
         patch: Self::UpdateInstance,
-    ) -> Result<(), DatabaseError> {
-        db.update_user(id, patch).await
+    ) -> Result<(), SaveError> {
+        Ok(db.update_user(id, patch).await?)
+    }
+
+    #[inline]
+    async fn delete(
+        db: &(impl Database + Send + Sync),
+        id: i32,
+        deletion: Self::DeleteOptions,
+    ) -> Result<(), SaveError> {
+        // the new author has to be someone else who is still around, and
+        // is kept that way until the posts are theirs
+        if let UserDeletion::Reassign { to } = deletion {
+            let target = match db.lock_user(to).await {
+                Ok(_) => Some(db.get_user_by_id(to).await?),
+                Err(DatabaseError::NotFound) => None,
+                Err(e) => return Err(e.into()),
+            };
+            if to == id || !target.map_or(false, |user| user.deleted_at.is_none()) {
+                return Err(ValidationError::InvalidReassignment(to).into());
+            }
+        }
+
+        db.delete_user(id, deletion).await?;
+        auth::end_user_sessions(id);
+        Ok(())
     }
 
     #[inline]
-    async fn delete(db: &(impl Database + Send + Sync), id: i32) -> Result<(), DatabaseError> {
-        db.delete_user(id).await
+    fn check_sort(sort: &str) -> Result<(), UnknownSortField> {
+        sort.parse::<Sort<UserSortField>>().map(drop)
+    }
+
+    #[inline]
+    async fn lock(
+        db: &(impl Database + Send + Sync),
+        id: i32,
+    ) -> Result<Option<i32>, DatabaseError> {
+        db.lock_user(id).await.map(Some)
+    }
+
+    #[inline]
+    fn version(&self) -> Option<i32> {
+        Some(self.version)
+    }
+
+    #[inline]
+    fn last_modified(&self) -> Option<NaiveDateTime> {
+        Some(self.updated_at)
     }
 }
 
@@ -176,13 +1147,25 @@ impl Model for User {
 impl Model for Blogpost {
     const LIST_PERMS: Permissions = Permissions(0b0);
     const GET_PERMS: Permissions = Permissions(0b0);
-    const CREATE_PERMS: Permissions = Permissions(0b1);
-    const UPDATE_PERMS: Permissions = Permissions(0b1);
-    const DELETE_PERMS: Permissions = Permissions(0b1);
+    const CREATE_PERMS: Permissions = Permissions::AUTHOR;
+    const UPDATE_PERMS: Permissions = Permissions::AUTHOR;
+    const DELETE_PERMS: Permissions = Permissions::AUTHOR;
+    const FILTER_KEYS: &'static [&'static str] = &[
+        "title",
+        "tags",
+        "url",
+        "body",
+        "author_id",
+        "created_after",
+        "created_before",
+        "match",
+        "sort",
+    ];
 
     type ListFilter = BlogpostFilter;
     type NewInstance = NewBlogpost;
     type UpdateInstance = BlogpostChange;
+    type DeleteOptions = NoOptions;
 
     #[inline]
     async fn get(db: &(impl Database + Send + Sync), id: i32) -> Result<Self, DatabaseError> {
@@ -197,25 +1180,618 @@ impl Model for Blogpost {
         db.list_blogposts(filter).await
     }
 
+    #[inline]
+    async fn count(
+        db: &(impl Database + Send + Sync),
+        filter: Self::ListFilter,
+    ) -> Result<u64, DatabaseError> {
+        db.count_blogposts(filter).await
+    }
+
     #[inline]
     async fn create(
         db: &(impl Database + Send + Sync),
-        new: Self::NewInstance,
-    ) -> Result<i32, DatabaseError> {
-        db.insert_blogpost(new).await
+        mut new: Self::NewInstance,
+    ) -> Result<i32, SaveError> {
+        if let Some(fm) = markdown::front_matter(&new.body)? {
+            new.apply_front_matter(fm);
+        }
+        if new.title.trim().is_empty() {
+            return Err(ValidationError::MissingTitle.into());
+        }
+        new.apply_summary(markdown::summarize(&new.body));
+
+        // generated slugs quietly move out of the way of existing ones, but
+        // the author is told if the one they chose is taken
+        let generated = new.url.trim().is_empty();
+        new.url = if generated {
+            slug::free_slug(db, &slug::slugify(&new.title), None).await?
+        } else {
+            slug::normalize(&new.url)?
+        };
+        check_series(db, new.series_id).await?;
+
+        // the UNIQUE constraint has the final say, since another post can
+        // take the slug between picking it and saving this one
+        let links = wikilink::slugs(&new.body);
+        match insert_blogpost(db, new.clone(), links.clone()).await {
+            Err(DatabaseError::AlreadyExists) if generated => {
+                new.url = slug::free_slug(db, &slug::slugify(&new.title), None).await?;
+                Ok(insert_blogpost(db, new, links).await?)
+            }
+            Err(DatabaseError::AlreadyExists) => Err(slug::taken(db, &new.url, None).await),
+            result => Ok(result?),
+        }
     }
 
     #[inline]
     async fn update(
         db: &(impl Database + Send + Sync),
         id: i32,
-        patch: Self::UpdateInstance,
-    ) -> Result<(), DatabaseError> {
-        db.update_blogpost(id, patch).await
+        mut patch: Self::UpdateInstance,
+    ) -> Result<(), SaveError> {
+        if let Some(body) = patch.body.clone() {
+            if let Some(fm) = markdown::front_matter(&body)? {
+                patch.apply_front_matter(fm);
+            }
+
+            let generated = match db.get_blogpost_by_id(id).await {
+                Ok(current) => current.summary_generated,
+                Err(DatabaseError::NotFound) => false,
+                Err(e) => return Err(e.into()),
+            };
+            patch.apply_summary(markdown::summarize(&body), generated);
+        } else if patch.clears_summary() {
+            let current = db.get_blogpost_by_id(id).await?;
+            patch.apply_summary(markdown::summarize(&current.body), true);
+        } else {
+            patch.apply_given_summary();
+        }
+        if patch
+            .title
+            .as_deref()
+            .map_or(false, |title| title.trim().is_empty())
+        {
+            return Err(ValidationError::MissingTitle.into());
+        }
+        if let Some(url) = patch.url {
+            patch.url = Some(slug::normalize(&url)?);
+        }
+        check_series(db, patch.series_id.flatten()).await?;
+
+        let url = patch.url.clone();
+        let links = patch.body.as_deref().map(wikilink::slugs);
+        let result = db
+            .transaction(move |tx| {
+                Box::pin(async move {
+                    tx.update_blogpost(id, patch).await?;
+                    if let Some(links) = links {
+                        tx.set_post_links(id, links).await?;
+                    }
+                    Ok::<_, DatabaseError>(())
+                })
+            })
+            .await;
+
+        match (result, url) {
+            (Err(DatabaseError::AlreadyExists), Some(url)) => {
+                Err(slug::taken(db, &url, Some(id)).await)
+            }
+            (result, _) => Ok(result?),
+        }
+    }
+
+    #[inline]
+    async fn delete(
+        db: &(impl Database + Send + Sync),
+        id: i32,
+        _options: Self::DeleteOptions,
+    ) -> Result<(), SaveError> {
+        Ok(db.delete_blogpost(id).await?)
+    }
+
+    /// Lint the post, without following links to other sites.
+    #[inline]
+    async fn warnings(
+        db: &(impl Database + Send + Sync),
+        id: i32,
+    ) -> Result<Vec<LintWarning>, DatabaseError> {
+        let blogpost = db.get_blogpost_by_id(id).await?;
+        lint::check(db, &blogpost.body, false).await
+    }
+
+    #[inline]
+    fn check_sort(sort: &str) -> Result<(), UnknownSortField> {
+        sort.parse::<Sort<BlogpostSortField>>().map(drop)
+    }
+
+    // the body is sanitized according to whoever wrote it, not whoever it's
+    // credited to, since any author can credit a post to anyone
+    #[inline]
+    fn created_by(new: &mut NewBlogpost, roles: Permissions) {
+        new.trusted_html = Policy::for_author(roles) == Policy::Trusted;
     }
 
     #[inline]
-    async fn delete(db: &(impl Database + Send + Sync), id: i32) -> Result<(), DatabaseError> {
-        db.delete_blogpost(id).await
+    fn updated_by(patch: &mut BlogpostChange, roles: Permissions) {
+        if patch.body.is_some() {
+            patch.trusted_html = Some(Policy::for_author(roles) == Policy::Trusted);
+        }
+    }
+
+    #[inline]
+    async fn lock(
+        db: &(impl Database + Send + Sync),
+        id: i32,
+    ) -> Result<Option<i32>, DatabaseError> {
+        db.lock_blogpost(id).await.map(Some)
+    }
+
+    #[inline]
+    fn cursor(&self) -> Option<String> {
+        let cursor = BlogpostCursor {
+            created_at: self.created_at,
+            id: self.id,
+        };
+        Some(cursor.encode())
+    }
+
+    #[inline]
+    fn version(&self) -> Option<i32> {
+        Some(self.version)
+    }
+
+    #[inline]
+    fn last_modified(&self) -> Option<NaiveDateTime> {
+        Some(self.updated_at)
+    }
+}
+
+#[async_trait]
+impl Model for Series {
+    const LIST_PERMS: Permissions = Permissions(0b0);
+    const GET_PERMS: Permissions = Permissions(0b0);
+    const CREATE_PERMS: Permissions = Permissions::AUTHOR;
+    const UPDATE_PERMS: Permissions = Permissions::AUTHOR;
+    const DELETE_PERMS: Permissions = Permissions::AUTHOR;
+
+    type ListFilter = SeriesFilter;
+    type NewInstance = NewSeries;
+    type UpdateInstance = SeriesChange;
+    type DeleteOptions = NoOptions;
+
+    #[inline]
+    async fn get(db: &(impl Database + Send + Sync), id: i32) -> Result<Self, DatabaseError> {
+        db.get_series_by_id(id).await
+    }
+
+    #[inline]
+    async fn list(
+        db: &(impl Database + Send + Sync),
+        filter: Self::ListFilter,
+    ) -> Result<Vec<Self>, DatabaseError> {
+        db.list_series(filter).await
+    }
+
+    #[inline]
+    async fn count(
+        db: &(impl Database + Send + Sync),
+        _filter: Self::ListFilter,
+    ) -> Result<u64, DatabaseError> {
+        db.count_series().await
+    }
+
+    #[inline]
+    async fn create(
+        db: &(impl Database + Send + Sync),
+        mut new: Self::NewInstance,
+    ) -> Result<i32, SaveError> {
+        // series URLs live under their own path, so they only have to be
+        // unique among themselves, which the database makes sure of
+        new.url = if new.url.trim().is_empty() {
+            slug::slugify(&new.title)
+        } else {
+            slug::normalize(&new.url)?
+        };
+        Ok(db.insert_series(new).await?)
+    }
+
+    #[inline]
+    async fn update(
+        db: &(impl Database + Send + Sync),
+        id: i32,
+        mut patch: Self::UpdateInstance,
+    ) -> Result<(), SaveError> {
+        if let Some(url) = patch.url {
+            patch.url = Some(slug::normalize(&url)?);
+        }
+        Ok(db.update_series(id, patch).await?)
+    }
+
+    #[inline]
+    async fn delete(
+        db: &(impl Database + Send + Sync),
+        id: i32,
+        _options: Self::DeleteOptions,
+    ) -> Result<(), SaveError> {
+        Ok(db.delete_series(id).await?)
+    }
+}
+
+/// Insert a blogpost along with the links in its body.
+#[inline]
+async fn insert_blogpost(
+    db: &(impl Database + Send + Sync),
+    new: NewBlogpost,
+    links: Vec<String>,
+) -> Result<i32, DatabaseError> {
+    db.transaction(move |tx| {
+        Box::pin(async move {
+            let id = tx.insert_blogpost(new).await?;
+            tx.set_post_links(id, links).await?;
+            Ok::<_, DatabaseError>(id)
+        })
+    })
+    .await
+}
+
+/// Store the summaries and word counts of the posts that were saved before
+/// they were stored, so that they can be listed without rendering them.
+#[inline]
+pub async fn backfill_summaries(database: &impl Database) {
+    let posts = match database.list_unsummarized_blogposts().await {
+        Ok(posts) => posts,
+        Err(e) => {
+            tracing::error!("Unable to find the blogposts without summaries: {}", e);
+            return;
+        }
+    };
+
+    let mut stored = 0;
+    for (id, body) in posts {
+        match database
+            .backfill_summary(id, markdown::summarize(&body))
+            .await
+        {
+            Ok(()) => stored += 1,
+            Err(e) => tracing::error!("Unable to store the summary of blogpost {}: {}", id, e),
+        }
+    }
+
+    if stored > 0 {
+        tracing::info!("Stored the summaries of {} blogposts", stored);
+    }
+}
+
+/// Make sure that the series a blogpost is being put into exists.
+#[inline]
+async fn check_series(
+    db: &(impl Database + Send + Sync),
+    series_id: Option<i32>,
+) -> Result<(), SaveError> {
+    match series_id {
+        Some(series_id) => match db.get_series_by_id(series_id).await {
+            Err(DatabaseError::NotFound) => Err(ValidationError::InvalidSeries(series_id).into()),
+            result => Ok(result.map(drop)?),
+        },
+        None => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        Blogpost, BlogpostChange, BlogpostCursor, BlogpostSortField, Model, NewBlogpost, NewSeries,
+        SaveError, Series, Sort, SortDirection, User, UserDeletion, ValidationError,
+    };
+    use crate::{mock_database::MockDatabase, slug::SlugError, Database};
+    use chrono::NaiveDate;
+    use std::convert::TryFrom;
+
+    #[test]
+    fn cursor_round_trip() {
+        let cursor = BlogpostCursor {
+            created_at: NaiveDate::from_ymd(2022, 1, 9).and_hms_micro(13, 37, 0, 123_456),
+            id: 42,
+        };
+        let token = cursor.encode();
+        assert_eq!(BlogpostCursor::try_from(token).unwrap(), cursor);
+    }
+
+    #[test]
+    fn cursor_rejects_garbage() {
+        assert!(BlogpostCursor::try_from("not a cursor".to_string()).is_err());
+        assert!(BlogpostCursor::try_from("MTIzNDU".to_string()).is_err());
+    }
+
+    #[test]
+    fn parse_sort() {
+        let sort: Sort<BlogpostSortField> = "title,-created_at".parse().unwrap();
+        assert_eq!(
+            sort.0,
+            vec![
+                (BlogpostSortField::Title, SortDirection::Ascending),
+                (BlogpostSortField::CreatedAt, SortDirection::Descending),
+            ]
+        );
+
+        let err = "title,-body".parse::<Sort<BlogpostSortField>>().unwrap_err();
+        assert_eq!(err.0, "body");
+    }
+
+    #[test]
+    fn parse_user_deletion() {
+        let deletion: UserDeletion = serde_urlencoded::from_str("").unwrap();
+        assert_eq!(deletion, UserDeletion::SoftDelete);
+
+        let deletion: UserDeletion =
+            serde_urlencoded::from_str("mode=reassign&reassign_to=2").unwrap();
+        assert_eq!(deletion, UserDeletion::Reassign { to: 2 });
+
+        let deletion: UserDeletion = serde_json::from_str(r#"{"mode":"anonymize"}"#).unwrap();
+        assert_eq!(deletion, UserDeletion::Anonymize);
+
+        assert!(serde_json::from_str::<UserDeletion>(r#"{"mode":"reassign"}"#).is_err());
+    }
+
+    #[tokio::test]
+    async fn user_reassignment() {
+        let db = MockDatabase::with_test_data();
+        db.delete_user(2, UserDeletion::SoftDelete).await.unwrap();
+
+        // not to themselves, someone who doesn't exist or someone deleted
+        for to in [1, 2, 404] {
+            assert!(matches!(
+                User::delete(&db, 1, UserDeletion::Reassign { to }).await,
+                Err(SaveError::Invalid(ValidationError::InvalidReassignment(invalid)))
+                    if invalid == to
+            ));
+        }
+        assert!(db.get_user_by_id(1).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn blogpost_slugs() {
+        let db = MockDatabase::with_test_data();
+        let new = |url: &str| NewBlogpost {
+            url: url.into(),
+            ..NewBlogpost::test()
+        };
+
+        // generated from the title, out of the way of the existing post
+        let id = Blogpost::create(&db, new("")).await.unwrap();
+        assert_eq!(
+            db.get_blogpost_by_id(id).await.unwrap().url,
+            "chasing-suns-2"
+        );
+
+        let id = Blogpost::create(&db, new("  Chasing_Moons "))
+            .await
+            .unwrap();
+        assert_eq!(
+            db.get_blogpost_by_id(id).await.unwrap().url,
+            "chasing-moons"
+        );
+
+        assert!(matches!(
+            Blogpost::create(&db, new("chasing-suns")).await,
+            Err(SaveError::Invalid(ValidationError::SlugTaken { suggestion })) if suggestion == "chasing-suns-3"
+        ));
+        assert!(matches!(
+            Blogpost::create(&db, new("create")).await,
+            Err(SaveError::Invalid(ValidationError::InvalidSlug(
+                SlugError::Reserved
+            )))
+        ));
+
+        // a post can keep its own URL
+        let change = |url: &str| BlogpostChange {
+            url: Some(url.into()),
+            ..Default::default()
+        };
+        assert!(Blogpost::update(&db, 1, change("Chasing-Suns"))
+            .await
+            .is_ok());
+        assert!(Blogpost::update(&db, 1, change("chasing-moons"))
+            .await
+            .is_err());
+    }
+    #[tokio::test]
+    async fn blogpost_series() {
+        let db = MockDatabase::with_test_data();
+        let id = Series::create(
+            &db,
+            NewSeries {
+                title: "Chasing, Part One".into(),
+                url: String::new(),
+                description: String::new(),
+            },
+        )
+        .await
+        .unwrap();
+        assert_eq!(Series::get(&db, id).await.unwrap().url, "chasing-part-one");
+
+        let join = |series_id: i32| BlogpostChange {
+            series_id: Some(Some(series_id)),
+            ..Default::default()
+        };
+        assert!(Blogpost::update(&db, 1, join(id)).await.is_ok());
+        assert!(matches!(
+            Blogpost::update(&db, 1, join(id + 1)).await,
+            Err(SaveError::Invalid(ValidationError::InvalidSeries(_)))
+        ));
+
+        // `null` takes the post out of the series, leaving it out doesn't
+        let change: BlogpostChange = serde_json::from_str(r#"{"title":"Suns"}"#).unwrap();
+        assert_eq!(change.series_id, None);
+        let change: BlogpostChange = serde_json::from_str(r#"{"series_id":null}"#).unwrap();
+        assert_eq!(change.series_id, Some(None));
+        Blogpost::update(&db, 1, change).await.unwrap();
+        assert_eq!(Blogpost::get(&db, 1).await.unwrap().series_id, None);
+    }
+    #[tokio::test]
+    async fn blogpost_front_matter() {
+        let db = MockDatabase::with_test_data();
+        let body = "+++\ntitle = \"Breaking Bones\"\ntags = [\"we\", \"break\"]\n\
+            summary = \"Ouch\"\ndate = 2021-12-25\n+++\nI broke some bones today";
+        let id = Blogpost::create(
+            &db,
+            serde_json::from_value(serde_json::json!({ "body": body, "author_id": 1 })).unwrap(),
+        )
+        .await
+        .unwrap();
+
+        let post = Blogpost::get(&db, id).await.unwrap();
+        assert_eq!(post.title, "Breaking Bones");
+        assert_eq!(post.tags, "we,break");
+        assert_eq!(post.url, "breaking-bones");
+        assert_eq!(post.summary.as_deref(), Some("Ouch"));
+        assert_eq!(
+            post.created_at,
+            NaiveDate::from_ymd(2021, 12, 25).and_hms(0, 0, 0)
+        );
+
+        // fields given alongside the body win, and the rest follow the body
+        let change = BlogpostChange {
+            title: Some("Mending Bones".into()),
+            body: Some("---\ntitle: Breaking Bones\ntags: we,mend\n---\nAll better".into()),
+            ..Default::default()
+        };
+        Blogpost::update(&db, id, change).await.unwrap();
+        let post = Blogpost::get(&db, id).await.unwrap();
+        assert_eq!(post.title, "Mending Bones");
+        assert_eq!(post.tags, "we,mend");
+        // the summary is left out of the front matter, so it's an excerpt
+        assert_eq!(post.summary.as_deref(), Some("All better"));
+
+        let change = BlogpostChange {
+            body: Some("---\ndate: someday\n---\n".into()),
+            ..Default::default()
+        };
+        assert!(matches!(
+            Blogpost::update(&db, id, change).await,
+            Err(SaveError::Invalid(ValidationError::InvalidFrontMatter(..)))
+        ));
+
+        // there has to be a title somewhere
+        let new = serde_json::json!({ "body": "---\ntags: we\n---\nHello", "author_id": 1 });
+        assert!(matches!(
+            Blogpost::create(&db, serde_json::from_value(new).unwrap()).await,
+            Err(SaveError::Invalid(ValidationError::MissingTitle))
+        ));
+        let change = BlogpostChange {
+            title: Some(" ".into()),
+            ..Default::default()
+        };
+        assert!(matches!(
+            Blogpost::update(&db, id, change).await,
+            Err(SaveError::Invalid(ValidationError::MissingTitle))
+        ));
+    }
+
+    #[tokio::test]
+    async fn blogpost_summaries() {
+        let db = MockDatabase::with_test_data();
+        let body = "# Breaking Bones\n\nI *broke* some `bones` today.\n\nIt hurt.";
+        let new = serde_json::json!({ "title": "Breaking Bones", "body": body, "author_id": 1 });
+        let id = Blogpost::create(&db, serde_json::from_value(new).unwrap())
+            .await
+            .unwrap();
+
+        let post = Blogpost::get(&db, id).await.unwrap();
+        assert_eq!(post.summary.as_deref(), Some("I broke some bones today."));
+        assert_eq!((post.word_count, post.reading_minutes), (9, 1));
+
+        // excerpts follow the body
+        let change = BlogpostChange {
+            body: Some("All better now.".into()),
+            ..Default::default()
+        };
+        Blogpost::update(&db, id, change).await.unwrap();
+        let post = Blogpost::get(&db, id).await.unwrap();
+        assert_eq!(post.summary.as_deref(), Some("All better now."));
+        assert_eq!(post.word_count, 3);
+
+        // but summaries that the author gave don't
+        let change = BlogpostChange {
+            summary: Some(Some("Ouch".into())),
+            ..Default::default()
+        };
+        Blogpost::update(&db, id, change).await.unwrap();
+        let change = BlogpostChange {
+            body: Some(format!("{} ", "word ".repeat(250))),
+            ..Default::default()
+        };
+        Blogpost::update(&db, id, change).await.unwrap();
+        let post = Blogpost::get(&db, id).await.unwrap();
+        assert_eq!(post.summary.as_deref(), Some("Ouch"));
+        assert_eq!((post.word_count, post.reading_minutes), (250, 2));
+
+        // even when they happen to match the excerpt
+        let change = BlogpostChange {
+            body: Some("Good as new.".into()),
+            summary: Some(Some("Good as new.".into())),
+            ..Default::default()
+        };
+        Blogpost::update(&db, id, change).await.unwrap();
+        let change = BlogpostChange {
+            body: Some("Broken again.".into()),
+            ..Default::default()
+        };
+        Blogpost::update(&db, id, change).await.unwrap();
+        let post = Blogpost::get(&db, id).await.unwrap();
+        assert_eq!(post.summary.as_deref(), Some("Good as new."));
+        assert!(!post.summary_generated);
+
+        // clearing the summary goes back to excerpts, right away
+        let change = BlogpostChange {
+            summary: Some(None),
+            ..Default::default()
+        };
+        Blogpost::update(&db, id, change).await.unwrap();
+        let post = Blogpost::get(&db, id).await.unwrap();
+        assert_eq!(post.summary.as_deref(), Some("Broken again."));
+        assert!(post.summary_generated);
+        let change = BlogpostChange {
+            body: Some("Healed up.".into()),
+            ..Default::default()
+        };
+        Blogpost::update(&db, id, change).await.unwrap();
+        let post = Blogpost::get(&db, id).await.unwrap();
+        assert_eq!(post.summary.as_deref(), Some("Healed up."));
+        assert!(post.summary_generated);
+    }
+
+    #[tokio::test]
+    async fn backfills_summaries() {
+        // posts from before summaries and word counts were stored
+        let db = MockDatabase::with_test_data();
+        let change = BlogpostChange {
+            summary: Some(None),
+            summary_generated: Some(true),
+            word_count: Some(0),
+            ..Default::default()
+        };
+        db.update_blogpost(2, change).await.unwrap();
+        let change = BlogpostChange {
+            summary: Some(Some("Sunsets".into())),
+            summary_generated: Some(false),
+            word_count: Some(0),
+            ..Default::default()
+        };
+        db.update_blogpost(1, change).await.unwrap();
+        assert_eq!(db.list_unsummarized_blogposts().await.unwrap().len(), 2);
+
+        super::backfill_summaries(&db).await;
+        let post = db.get_blogpost_by_id(2).await.unwrap();
+        assert_eq!(
+            post.summary.as_deref(),
+            Some("Hello, I am John Notgull. What if we made a website?")
+        );
+        assert_eq!((post.word_count, post.reading_minutes), (19, 1));
+        let post = db.get_blogpost_by_id(1).await.unwrap();
+        assert_eq!(post.summary.as_deref(), Some("Sunsets"));
+        assert_eq!(post.word_count, 15);
+        assert!(db.list_unsummarized_blogposts().await.unwrap().is_empty());
     }
 }
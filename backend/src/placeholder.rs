@@ -0,0 +1,218 @@
+// GNU AGPL v3 License
+
+//! Taking pieces out of markdown before it's parsed, and putting them back
+//! once it's rendered, for things that markdown shouldn't touch.
+//!
+//! Each piece leaves a placeholder behind, which wraps a letter for what
+//! kind of piece it is and the index of the piece. The placeholders are
+//! made of private use characters, so they won't clash with anything that
+//! the author wrote.
+
+use std::borrow::Cow;
+
+const START: char = '\u{E000}';
+const END: char = '\u{E001}';
+
+/// How far past the content around it that a line has to be indented to be
+/// code.
+const CODE_INDENT: usize = 4;
+
+/// Add a placeholder for a piece to some text.
+#[inline]
+pub fn push(output: &mut String, kind: char, index: usize) {
+    output.push(START);
+    output.push(kind);
+    output.push_str(&index.to_string());
+    output.push(END);
+}
+
+/// Replace each placeholder of one kind in some text with whatever the
+/// piece that it stands for turns into. Placeholders for pieces that
+/// don't exist are left alone.
+#[must_use]
+#[inline]
+pub fn restore<'a>(
+    text: &str,
+    kind: char,
+    mut replacement: impl FnMut(usize) -> Option<Cow<'a, str>>,
+) -> String {
+    let mut output = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find(START) {
+        output.push_str(&rest[..start]);
+        let placeholder = &rest[start..];
+        let len = placeholder
+            .find(END)
+            .map_or(START.len_utf8(), |end| end + END.len_utf8());
+
+        let index = placeholder[START.len_utf8()..len]
+            .strip_prefix(kind)
+            .and_then(|index| index.trim_end_matches(END).parse().ok());
+        match index.and_then(&mut replacement) {
+            Some(replaced) => output.push_str(&replaced),
+            None => output.push_str(&placeholder[..len]),
+        }
+        rest = &placeholder[len..];
+    }
+
+    output.push_str(rest);
+    output
+}
+
+/// Run `scan` over the parts of some markdown that aren't in fenced or
+/// indented code blocks, which are copied over as they are.
+///
+/// `scan` still needs to skip over inline code, with `code_span_len`.
+#[inline]
+pub fn outside_code(input: &str, mut scan: impl FnMut(&str, &mut String)) -> String {
+    let mut output = String::with_capacity(input.len());
+    let mut text = String::new();
+    let mut fence: Option<&str> = None;
+    let mut indented = false;
+    let mut after_blank = true;
+    // how far the content of the list item that we're in is indented, since
+    // code in a list item is indented past it
+    let mut list_indent = 0;
+
+    for line in input.split_inclusive('\n') {
+        if let Some(open) = fence {
+            output.push_str(line);
+            if fence_marker(line).map_or(false, |marker| closes_fence(open, marker, line)) {
+                fence = None;
+            }
+            continue;
+        }
+
+        // indented code can't interrupt a paragraph, but once it starts, it
+        // goes on until a line that isn't indented as far
+        let blank = line.trim().is_empty();
+        let indent = indentation(line);
+        let code_indent = list_indent + CODE_INDENT;
+        if (indented && (blank || indent >= code_indent))
+            || (!blank && after_blank && indent >= code_indent)
+        {
+            if !indented {
+                scan(&text, &mut output);
+                text.clear();
+                indented = true;
+            }
+            output.push_str(line);
+            after_blank = blank;
+            continue;
+        }
+        indented = false;
+
+        match fence_marker(line) {
+            Some(marker) => {
+                scan(&text, &mut output);
+                text.clear();
+                output.push_str(line);
+                fence = Some(marker);
+            }
+            None => text.push_str(line),
+        }
+
+        if !blank {
+            match list_item_indent(line) {
+                Some(content) => list_indent = content,
+                None if after_blank && indent < list_indent => list_indent = 0,
+                None => {}
+            }
+        }
+        after_blank = blank;
+    }
+
+    scan(&text, &mut output);
+    output
+}
+
+/// The line that a fenced code block which is never closed starts on,
+/// counting from zero.
+#[must_use]
+#[inline]
+pub fn unclosed_fence(input: &str) -> Option<usize> {
+    let mut fence: Option<(usize, &str)> = None;
+    for (number, line) in input.lines().enumerate() {
+        let marker = fence_marker(line);
+        fence = match (fence, marker) {
+            (Some((_, open)), Some(marker)) if closes_fence(open, marker, line) => None,
+            (None, Some(marker)) => Some((number, marker)),
+            (fence, _) => fence,
+        };
+    }
+    fence.map(|(number, _)| number)
+}
+
+/// The length of the code span at the start of some text, or of the run of
+/// backticks if it isn't closed.
+#[inline]
+pub fn code_span_len(text: &str) -> usize {
+    let run = backtick_run(text);
+    let mut i = run;
+    while let Some(start) = text[i..].find('`') {
+        let start = i + start;
+        let len = backtick_run(&text[start..]);
+        if len == run {
+            return start + len;
+        }
+        i = start + len;
+    }
+
+    run
+}
+
+#[inline]
+fn backtick_run(text: &str) -> usize {
+    text.len() - text.trim_start_matches('`').len()
+}
+
+/// How many columns a line is indented by, with tabs going to the next
+/// multiple of four.
+#[inline]
+fn indentation(line: &str) -> usize {
+    line.chars()
+        .take_while(|c| matches!(c, ' ' | '\t'))
+        .fold(0, |column, c| match c {
+            '\t' => column + 4 - column % 4,
+            _ => column + 1,
+        })
+}
+
+/// How far the content of a list item is indented, if a line starts one.
+#[inline]
+fn list_item_indent(line: &str) -> Option<usize> {
+    let rest = line.trim_start_matches(|c| matches!(c, ' ' | '\t'));
+    let digits = rest.len() - rest.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+    let marker = match rest[digits..].chars().next()? {
+        '-' | '*' | '+' if digits == 0 => 1,
+        '.' | ')' if (1..=9).contains(&digits) => digits + 1,
+        _ => return None,
+    };
+
+    // content that's indented too far is code, which starts one space in
+    let after = &rest[marker..];
+    let spaces = after.len() - after.trim_start_matches(' ').len();
+    let spaces = match spaces {
+        0 if !after.trim().is_empty() => return None,
+        1..=4 if !after.trim().is_empty() => spaces,
+        _ => 1,
+    };
+    Some(indentation(line) + marker + spaces)
+}
+
+/// The backticks or tildes that a line starts with, if it opens or closes a
+/// fenced code block.
+#[inline]
+fn fence_marker(line: &str) -> Option<&str> {
+    let line = line.trim_start();
+    let c = line.chars().next().filter(|c| matches!(c, '`' | '~'))?;
+    let len = line.len() - line.trim_start_matches(c).len();
+    (len >= 3).then(|| &line[..len])
+}
+
+/// Tell whether a line closes the code block that was opened with a
+/// marker.
+#[inline]
+fn closes_fence(open: &str, marker: &str, line: &str) -> bool {
+    marker.starts_with(open) && line.trim().len() == marker.len()
+}
@@ -1,43 +1,153 @@
 // GNU AGPL v3 License
 
-use crate::models::{
-    Blogpost, BlogpostChange, BlogpostFilter, NewBlogpost, NewUser, User, UserChange, UserFilter,
+use crate::{
+    markdown::Summary,
+    models::{
+        Blogpost, BlogpostChange, BlogpostFilter, LinkTarget, NewBlogpost, NewSeries, NewUpload,
+        NewUrlRedirect, NewUser, Series, SeriesChange, SeriesFilter, UrlRedirect, User, UserChange,
+        UserDeletion, UserFilter,
+    },
 };
-use diesel::result::Error as DieselError;
+use chrono::NaiveDateTime;
+use diesel::result::{DatabaseErrorKind, Error as DieselError};
+use futures_util::future::BoxFuture;
 use std::{convert::Infallible, sync::Arc};
 use warp::Filter;
 
 #[async_trait::async_trait]
 pub trait Database {
+    /// Run a series of operations inside of a single transaction.
+    ///
+    /// If the closure returns an error, none of the changes it made are
+    /// kept. Transactions started inside of the closure are nested: if the
+    /// inner closure fails, only its own changes are undone, and the outer
+    /// closure is free to handle the error and carry on.
+    ///
+    /// Operations on the transaction's database handle should be awaited
+    /// one at a time rather than run concurrently.
+    async fn transaction<T, E, F>(&self, f: F) -> Result<T, E>
+    where
+        T: Send,
+        E: From<DatabaseError> + Send,
+        F: for<'a> FnOnce(&'a Self) -> BoxFuture<'a, Result<T, E>> + Send;
+
     /// Fetch a `Blogpost` by its ID.
     async fn get_blogpost_by_id(&self, id: i32) -> Result<Blogpost, DatabaseError>;
+    /// Fetch the version of a `Blogpost` by its ID, and keep it from being
+    /// changed by anyone else until the current transaction ends.
+    async fn lock_blogpost(&self, id: i32) -> Result<i32, DatabaseError>;
     /// Fetch a `Blogpost` and `User` by its URL.
     async fn get_blogpost_and_user_by_url(
         &self,
         url: String,
     ) -> Result<(Blogpost, User), DatabaseError>;
+    /// List the IDs and URLs of every `Blogpost` whose URL starts with a
+    /// prefix, including posts in the trash.
+    async fn list_blogpost_urls(&self, prefix: String)
+        -> Result<Vec<(i32, String)>, DatabaseError>;
     /// Insert a new `Blogpost` into the database.
     async fn insert_blogpost(&self, bp: NewBlogpost) -> Result<i32, DatabaseError>;
-    /// Update a `Blogpost` with potential new information.
+    /// Update a `Blogpost` with potential new information. If its URL
+    /// changes, the old one is recorded as a redirect to the post.
     async fn update_blogpost(&self, id: i32, bp: BlogpostChange) -> Result<(), DatabaseError>;
     /// List all of the `Blogpost`s in the database, using some parameters.
     /// as filters.
     async fn list_blogposts(&self, filter: BlogpostFilter) -> Result<Vec<Blogpost>, DatabaseError>;
-    /// Delete a `Blogpost` by its ID.
+    /// Count the `Blogpost`s that match a filter, ignoring pagination.
+    async fn count_blogposts(&self, filter: BlogpostFilter) -> Result<u64, DatabaseError>;
+    /// List the creation dates of every published `Blogpost`, newest first.
+    async fn list_blogpost_dates(&self) -> Result<Vec<NaiveDateTime>, DatabaseError>;
+    /// Move a `Blogpost` to the trash by its ID, hiding it from every other
+    /// query except for listing the trash.
     async fn delete_blogpost(&self, id: i32) -> Result<(), DatabaseError>;
+    /// Take a `Blogpost` back out of the trash.
+    async fn restore_blogpost(&self, id: i32) -> Result<(), DatabaseError>;
+    /// Permanently delete a `Blogpost` that is in the trash.
+    async fn purge_blogpost(&self, id: i32) -> Result<(), DatabaseError>;
+    /// Permanently delete every `Blogpost` that was moved to the trash
+    /// before a point in time, returning how many there were.
+    async fn purge_trash(&self, before: NaiveDateTime) -> Result<u64, DatabaseError>;
+    /// Find the published `Blogpost`s that wiki links to the given URLs lead
+    /// to, following the URLs that posts used to have. URLs that don't lead
+    /// anywhere are left out.
+    async fn get_link_targets(&self, urls: Vec<String>) -> Result<Vec<LinkTarget>, DatabaseError>;
+    /// Replace the URLs that a `Blogpost` links to.
+    async fn set_post_links(&self, id: i32, urls: Vec<String>) -> Result<(), DatabaseError>;
+    /// List the IDs and bodies of the `Blogpost`s that look like they have
+    /// wiki links in them but don't have any links stored, including the
+    /// ones in the trash.
+    async fn list_unlinked_blogposts(&self) -> Result<Vec<(i32, String)>, DatabaseError>;
+    /// List the IDs and bodies of the `Blogpost`s that were saved before
+    /// their summaries and word counts were stored, including the ones in
+    /// the trash.
+    async fn list_unsummarized_blogposts(&self) -> Result<Vec<(i32, String)>, DatabaseError>;
+    /// Store the summary and word count of a `Blogpost` that was saved
+    /// before they were, without counting it as a change to the post. A
+    /// summary that the author gave is kept.
+    async fn backfill_summary(&self, id: i32, summary: Summary) -> Result<(), DatabaseError>;
+    /// List the published `Blogpost`s that link to a `Blogpost`, by either
+    /// its URL or one it used to have, newest first.
+    async fn list_backlinks(&self, id: i32) -> Result<Vec<Blogpost>, DatabaseError>;
 
     /// Fetch a `User` by its ID.
     async fn get_user_by_id(&self, id: i32) -> Result<User, DatabaseError>;
+    /// Fetch the version of a `User` by its ID, and keep it from being
+    /// changed by anyone else until the current transaction ends.
+    async fn lock_user(&self, id: i32) -> Result<i32, DatabaseError>;
     /// Fetch a `User` by its UUID.
     async fn get_user_by_uuid(&self, uuid: String) -> Result<User, DatabaseError>;
     /// Insert a new `User` into the database.
     async fn insert_user(&self, user: NewUser) -> Result<i32, DatabaseError>;
     /// Update a `User` with potential new information.
     async fn update_user(&self, id: i32, user: UserChange) -> Result<(), DatabaseError>;
-    /// List all available `User`s.
+    /// List all available `User`s, leaving out deleted ones unless the
+    /// filter asks for them.
     async fn list_users(&self, filter: UserFilter) -> Result<Vec<User>, DatabaseError>;
-    /// Delete a `User` by its ID.
-    async fn delete_user(&self, id: i32) -> Result<(), DatabaseError>;
+    /// Count the `User`s that match a filter, ignoring pagination.
+    async fn count_users(&self, filter: UserFilter) -> Result<u64, DatabaseError>;
+    /// Delete a `User` by its ID, in the given way.
+    async fn delete_user(&self, id: i32, deletion: UserDeletion) -> Result<(), DatabaseError>;
+
+    /// Record a file that has been uploaded to object storage.
+    async fn insert_upload(&self, upload: NewUpload) -> Result<i32, DatabaseError>;
+    /// Find which of the given paths have had files uploaded to them, not
+    /// counting presigned uploads that haven't been completed.
+    async fn get_upload_paths(&self, paths: Vec<String>) -> Result<Vec<String>, DatabaseError>;
+    /// Fetch an `Upload` by its path, whether or not it has been completed.
+    async fn get_upload_by_path(&self, path: String) -> Result<Upload, DatabaseError>;
+    /// Mark a presigned `Upload` as completed. Fails with `AlreadyExists` if
+    /// it already was.
+    async fn complete_upload(&self, id: i32) -> Result<(), DatabaseError>;
+    /// Delete the presigned `Upload`s that weren't completed before a point
+    /// in time, returning how many there were.
+    async fn purge_pending_uploads(&self, before: NaiveDateTime) -> Result<u64, DatabaseError>;
+
+    /// Find where a path that has moved should now lead.
+    async fn get_redirect_target(&self, path: String) -> Result<String, DatabaseError>;
+    /// List every redirect, newest first.
+    async fn list_redirects(&self) -> Result<Vec<UrlRedirect>, DatabaseError>;
+    /// Add a redirect from one path to another by hand.
+    async fn insert_redirect(&self, redirect: NewUrlRedirect) -> Result<i32, DatabaseError>;
+    /// Delete a redirect by its ID.
+    async fn delete_redirect(&self, id: i32) -> Result<(), DatabaseError>;
+
+    /// Fetch a `Series` by its ID.
+    async fn get_series_by_id(&self, id: i32) -> Result<Series, DatabaseError>;
+    /// Fetch a `Series` by its URL.
+    async fn get_series_by_url(&self, url: String) -> Result<Series, DatabaseError>;
+    /// Insert a new `Series` into the database.
+    async fn insert_series(&self, series: NewSeries) -> Result<i32, DatabaseError>;
+    /// Update a `Series` with potential new information.
+    async fn update_series(&self, id: i32, series: SeriesChange) -> Result<(), DatabaseError>;
+    /// List every `Series`, newest first.
+    async fn list_series(&self, filter: SeriesFilter) -> Result<Vec<Series>, DatabaseError>;
+    /// Count every `Series`.
+    async fn count_series(&self) -> Result<u64, DatabaseError>;
+    /// Delete a `Series` by its ID. Its blogposts are kept, but are no
+    /// longer a part of any series.
+    async fn delete_series(&self, id: i32) -> Result<(), DatabaseError>;
+    /// List the published `Blogpost`s in a `Series`, in reading order.
+    async fn list_series_blogposts(&self, series_id: i32) -> Result<Vec<Blogpost>, DatabaseError>;
 }
 
 #[derive(Debug, thiserror::Error)]
@@ -48,6 +158,8 @@ pub enum DatabaseError {
     Diesel(#[source] DieselError),
     #[error("{0}")]
     Pool(#[from] diesel::r2d2::PoolError),
+    #[error("An item with the same unique value already exists")]
+    AlreadyExists,
 }
 
 impl From<DieselError> for DatabaseError {
@@ -55,6 +167,9 @@ impl From<DieselError> for DatabaseError {
     fn from(de: DieselError) -> DatabaseError {
         match de {
             DieselError::NotFound => DatabaseError::NotFound,
+            DieselError::DatabaseError(DatabaseErrorKind::UniqueViolation, _) => {
+                DatabaseError::AlreadyExists
+            }
             de => DatabaseError::Diesel(de),
         }
     }
@@ -70,7 +185,7 @@ pub fn with_database(
             let test_db = Arc::new(test_db);
             warp::any().map(move || test_db.clone())
         } else {
-            warp::any().map(|| Arc::new(crate::database::SqlDatabase))
+            warp::any().map(|| Arc::new(crate::database::SqlDatabase::default()))
         }
     }
 }
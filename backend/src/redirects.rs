@@ -0,0 +1,75 @@
+// GNU AGPL v3 License
+
+use crate::{Database, DatabaseError, PageRenderError};
+use std::sync::Arc;
+use warp::{
+    http::Uri,
+    path::FullPath,
+    reject::{custom as reject, not_found},
+    reply::Response,
+    Filter, Rejection, Reply,
+};
+
+/// Send requests for paths that have moved to wherever they lead now.
+///
+/// This goes after every other route, so that pages that actually exist
+/// always win over redirects.
+#[inline]
+pub fn redirects(
+) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone + Send + Sync + 'static {
+    warp::get()
+        .and(warp::path::full())
+        .and(crate::with_database())
+        .and_then(|path: FullPath, database: Arc<_>| async move {
+            match find_redirect(&*database, path.as_str().to_string()).await {
+                Ok(response) => Ok(response),
+                Err(DatabaseError::NotFound) => Err(not_found()),
+                Err(e) => Err(reject(PageRenderError::from(e))),
+            }
+        })
+}
+
+/// Look up where a path has moved to, and build a permanent redirect
+/// there.
+#[inline]
+pub async fn find_redirect(
+    database: &impl Database,
+    path: String,
+) -> Result<Response, DatabaseError> {
+    let target = database.get_redirect_target(path).await?;
+
+    // targets are checked when they're added, so this shouldn't fail
+    let uri = target.parse::<Uri>().map_err(|e| {
+        tracing::error!("Redirect target {} is not a valid URI: {}", &target, e);
+        DatabaseError::NotFound
+    })?;
+    Ok(warp::redirect::permanent(uri).into_response())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{mock_database::MockDatabase, models::NewUrlRedirect};
+    use warp::http::{header::LOCATION, StatusCode};
+
+    #[tokio::test]
+    async fn redirects_permanently() {
+        let database = MockDatabase::with_test_data();
+        database
+            .insert_redirect(NewUrlRedirect {
+                path: "/rss".into(),
+                target: "/blog".into(),
+            })
+            .await
+            .unwrap();
+
+        let response = find_redirect(&database, "/rss".into()).await.unwrap();
+        assert_eq!(response.status(), StatusCode::MOVED_PERMANENTLY);
+        assert_eq!(response.headers()[LOCATION], "/blog");
+
+        assert!(matches!(
+            find_redirect(&database, "/atom".into()).await,
+            Err(DatabaseError::NotFound)
+        ));
+    }
+}
@@ -1,6 +1,6 @@
 // GNU AGPL v3 License
 
-use crate::{admin, api, auth, blog, error_page, frontpages, Config};
+use crate::{admin, api, auth, blog, error_page, frontpages, highlight, redirects, Config};
 use dashmap::mapref::one::Ref;
 use futures_util::future::{err, ok, ready};
 use std::convert::Infallible;
@@ -45,8 +45,12 @@ pub fn routes(
         .boxed()
         .or(favicon_route(cfg))
         .boxed()
+        .or(highlight::stylesheet())
+        .boxed()
         .or(frontpages::root_and_front(cfg))
         .boxed()
+        .or(redirects::redirects())
+        .boxed()
         .recover(|rej: Rejection| {
             tracing::debug!("Rejection encountered: {:?}", &rej);
             ready(error_page::process_error(rej).map_err(|err| {
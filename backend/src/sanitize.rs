@@ -0,0 +1,356 @@
+// GNU AGPL v3 License
+
+//! Cleaning up the HTML that markdown is rendered into, since it may contain
+//! raw HTML that was written by the author.
+
+use crate::auth::Permissions;
+use ammonia::Builder;
+use once_cell::sync::Lazy;
+use std::borrow::Cow;
+use warp::http::Uri;
+
+/// How much of the author's HTML to keep.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Policy {
+    /// Keep embedded media, including iframes from the hosts in
+    /// `EMBED_HOSTS`.
+    Trusted,
+    /// Only keep formatting. IDs are prefixed so that they can't clash with
+    /// the ones the rest of the page uses.
+    Strict,
+}
+
+impl Policy {
+    /// The policy for content written by someone with the given roles.
+    /// Only administrators are trusted.
+    #[must_use]
+    #[inline]
+    pub fn for_author(roles: Permissions) -> Policy {
+        if Permissions::ADMIN.applies_to(roles) {
+            Policy::Trusted
+        } else {
+            Policy::Strict
+        }
+    }
+}
+
+/// Hosts that trusted authors may embed iframes from.
+pub const EMBED_HOSTS: &[&str] = &[
+    "www.youtube.com",
+    "www.youtube-nocookie.com",
+    "player.vimeo.com",
+    "open.spotify.com",
+    "codepen.io",
+];
+
+/// The MathML elements that math is rendered into.
+const MATHML_TAGS: &[&str] = &[
+    "math",
+    "semantics",
+    "annotation",
+    "mrow",
+    "mi",
+    "mn",
+    "mo",
+    "ms",
+    "mtext",
+    "mspace",
+    "msub",
+    "msup",
+    "msubsup",
+    "munder",
+    "mover",
+    "munderover",
+    "mfrac",
+    "msqrt",
+    "mroot",
+    "mstyle",
+    "mpadded",
+    "mphantom",
+    "menclose",
+    "mtable",
+    "mtr",
+    "mtd",
+];
+
+/// The prefix given to IDs under the strict policy.
+pub const ID_PREFIX: &str = "user-content-";
+
+/// CSS properties that can be set through a `style` attribute, which only
+/// change how text looks.
+const STYLE_PROPERTIES: &[&str] = &[
+    "color",
+    "background-color",
+    "font-weight",
+    "font-style",
+    "text-decoration",
+];
+
+/// Remove everything from some HTML that the policy doesn't allow.
+#[must_use]
+#[inline]
+pub fn sanitize(html: &str, policy: Policy) -> String {
+    let builder = match policy {
+        Policy::Trusted => &*TRUSTED,
+        Policy::Strict => &*STRICT,
+    };
+
+    builder.clean(html).to_string()
+}
+
+static TRUSTED: Lazy<Builder<'static>> = Lazy::new(|| {
+    let mut builder = base_builder();
+    builder
+        .add_tags(&["iframe", "video", "audio", "source"])
+        .add_tag_attributes(
+            "iframe",
+            &[
+                "src",
+                "width",
+                "height",
+                "title",
+                "allow",
+                "allowfullscreen",
+                "frameborder",
+                "loading",
+            ],
+        )
+        .add_tag_attributes(
+            "video",
+            &[
+                "src", "controls", "width", "height", "poster", "loop", "muted",
+            ],
+        )
+        .add_tag_attributes("audio", &["src", "controls", "loop"])
+        .add_tag_attributes("source", &["src", "type"])
+        .attribute_filter(|element, attribute, value| {
+            filter_attribute(element, attribute, value, Policy::Trusted)
+        });
+    builder
+});
+
+static STRICT: Lazy<Builder<'static>> = Lazy::new(|| {
+    let mut builder = base_builder();
+    builder
+        .link_rel(Some("noopener noreferrer nofollow ugc"))
+        .attribute_filter(|element, attribute, value| {
+            filter_attribute(element, attribute, value, Policy::Strict)
+        });
+    builder
+});
+
+/// The parts of the policy that everyone gets, which keep everything that
+/// markdown can render into.
+#[inline]
+fn base_builder() -> Builder<'static> {
+    let mut builder = Builder::default();
+    builder
+        .add_tags(&["input", "section"])
+        .add_tags(MATHML_TAGS)
+        .add_tag_attributes("input", &["type", "checked", "disabled"])
+        .add_tag_attributes("math", &["display"])
+        .add_tag_attributes("mi", &["mathvariant"])
+        .add_tag_attributes(
+            "mo",
+            &["stretchy", "fence", "separator", "lspace", "rspace"],
+        )
+        .add_tag_attributes("mspace", &["width"])
+        .add_tag_attributes("mfrac", &["linethickness"])
+        .add_tag_attributes("mstyle", &["displaystyle", "scriptlevel", "mathvariant"])
+        .add_tag_attributes("menclose", &["notation"])
+        .add_tag_attributes("mtable", &["columnalign"])
+        .add_generic_attributes(&["id", "class", "style"]);
+    builder
+}
+
+#[inline]
+fn filter_attribute<'u>(
+    element: &str,
+    attribute: &str,
+    value: &'u str,
+    policy: Policy,
+) -> Option<Cow<'u, str>> {
+    match (element, attribute) {
+        (_, "style") => filter_style(value).map(Cow::Owned),
+        ("input", "type") => (value == "checkbox").then(|| value.into()),
+        ("iframe", "src") => is_embeddable(value).then(|| value.into()),
+        (_, "id") if policy == Policy::Strict => Some(format!("{}{}", ID_PREFIX, value).into()),
+        ("a", "href") if policy == Policy::Strict && value.starts_with('#') => {
+            Some(format!("#{}{}", ID_PREFIX, &value[1..]).into())
+        }
+        _ => Some(value.into()),
+    }
+}
+
+/// Keep only the declarations in a `style` attribute that set one of
+/// `STYLE_PROPERTIES` to a plain value.
+#[inline]
+fn filter_style(style: &str) -> Option<String> {
+    let style: String = style
+        .split(';')
+        .filter_map(|declaration| {
+            let (property, value) = declaration.split_once(':')?;
+            let (property, value) = (property.trim().to_ascii_lowercase(), value.trim());
+            let plain = !value.is_empty()
+                && value
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || matches!(c, '#' | '-' | '.' | ' '));
+
+            (STYLE_PROPERTIES.contains(&property.as_str()) && plain)
+                .then(|| format!("{}:{};", property, value))
+        })
+        .collect();
+
+    (!style.is_empty()).then(|| style)
+}
+
+/// Tell whether an iframe can be embedded from a URL.
+#[inline]
+fn is_embeddable(src: &str) -> bool {
+    match src.parse::<Uri>() {
+        Ok(uri) => {
+            uri.scheme_str() == Some("https")
+                && uri.port().is_none()
+                && uri.host().map_or(false, |host| EMBED_HOSTS.contains(&host))
+        }
+        Err(_) => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{sanitize, Policy};
+    use crate::auth::Permissions;
+
+    /// Each of these should come out without anything that can run.
+    const XSS_VECTORS: &[&str] = &[
+        "<script>alert(1)</script>",
+        "<SCRIPT SRC=//evil.example/xss.js></SCRIPT>",
+        "<img src=x onerror=alert(1)>",
+        "<img src=\"javascript:alert(1)\">",
+        "<a href=\"javascript:alert(1)\">click</a>",
+        "<a href=\"JaVaScRiPt:alert(1)\">click</a>",
+        "<a href=\"&#106;avascript:alert(1)\">click</a>",
+        "<a href=\"data:text/html;base64,PHNjcmlwdD5hbGVydCgxKTwvc2NyaXB0Pg==\">click</a>",
+        "<svg onload=alert(1)>",
+        "<svg><script>alert(1)</script></svg>",
+        "<math><mtext><table><mglyph><style><img src=x onerror=alert(1)>",
+        "<body onload=alert(1)>",
+        "<iframe src=\"javascript:alert(1)\"></iframe>",
+        "<iframe srcdoc=\"<script>alert(1)</script>\"></iframe>",
+        "<object data=\"javascript:alert(1)\"></object>",
+        "<embed src=\"javascript:alert(1)\">",
+        "<form action=\"javascript:alert(1)\"><button>go</button></form>",
+        "<input onfocus=alert(1) autofocus>",
+        "<details open ontoggle=alert(1)>",
+        "<meta http-equiv=\"refresh\" content=\"0;url=javascript:alert(1)\">",
+        "<link rel=\"stylesheet\" href=\"javascript:alert(1)\">",
+        "<style>@import 'javascript:alert(1)';</style>",
+        "<div style=\"background-image:url(javascript:alert(1))\">x</div>",
+        "<span style=\"color:expression(alert(1))\">x</span>",
+        "<scr<script>ipt>alert(1)</scr</script>ipt>",
+        "<noscript><p title=\"</noscript><img src=x onerror=alert(1)>\"></noscript>",
+        "<a href=\"#\" onclick=\"alert(1)\">click</a>",
+        "<video><source onerror=\"alert(1)\"></video>",
+    ];
+
+    #[test]
+    fn xss_vectors() {
+        for policy in [Policy::Trusted, Policy::Strict] {
+            for vector in XSS_VECTORS {
+                let clean = without_escaped_tags(&sanitize(vector, policy).to_ascii_lowercase());
+                for bad in [
+                    "<script",
+                    "javascript:",
+                    "data:",
+                    "onerror",
+                    "onload",
+                    "onclick",
+                    "onfocus",
+                    "ontoggle",
+                    "<object",
+                    "<embed",
+                    "<form",
+                    "<meta",
+                    "<link",
+                    "<style",
+                    "url(",
+                    "expression(",
+                    "srcdoc",
+                ] {
+                    assert!(
+                        !clean.contains(bad),
+                        "{:?} left `{}` in {:?} under {:?}",
+                        vector,
+                        bad,
+                        clean,
+                        policy
+                    );
+                }
+            }
+        }
+    }
+
+    /// Tags that were escaped into text can't run, so leave them out when
+    /// looking for ones that can.
+    #[inline]
+    fn without_escaped_tags(html: &str) -> String {
+        let mut rest = html;
+        let mut result = String::new();
+        while let Some(start) = rest.find("&lt;") {
+            result.push_str(&rest[..start]);
+            rest = match rest[start..].find("&gt;") {
+                Some(end) => &rest[start + end + 4..],
+                None => "",
+            };
+        }
+        result.push_str(rest);
+        result
+    }
+
+    #[test]
+    fn keeps_formatting() {
+        let html = "<p><strong>bold</strong> <a href=\"https://example.com\">link</a></p>\
+            <pre style=\"background-color:#2b303b;\"><span style=\"color:#c0c5ce;\">code</span></pre>\
+            <ul><li><input type=\"checkbox\" checked=\"\" disabled=\"\" /> done</li></ul>";
+        for policy in [Policy::Trusted, Policy::Strict] {
+            let clean = sanitize(html, policy);
+            assert!(clean.contains("<strong>bold</strong>"));
+            assert!(clean.contains("href=\"https://example.com\""));
+            assert!(clean.contains("style=\"color:#c0c5ce;\""));
+            assert!(clean.contains("<input type=\"checkbox\""));
+        }
+    }
+
+    #[test]
+    fn embeds_depend_on_policy() {
+        let youtube = "<iframe src=\"https://www.youtube.com/embed/dQw4w9WgXcQ\" \
+            allowfullscreen></iframe>";
+        assert!(sanitize(youtube, Policy::Trusted).contains("<iframe"));
+        assert!(!sanitize(youtube, Policy::Strict).contains("<iframe"));
+
+        let elsewhere = "<iframe src=\"https://evil.example/embed\"></iframe>";
+        assert!(!sanitize(elsewhere, Policy::Trusted).contains("src="));
+        let plain_http = "<iframe src=\"http://www.youtube.com/embed/x\"></iframe>";
+        assert!(!sanitize(plain_http, Policy::Trusted).contains("src="));
+    }
+
+    #[test]
+    fn strict_ids_are_prefixed() {
+        let html = "<p><a href=\"#fn1\" id=\"fnref1\">1</a></p><div id=\"edit_blogpost\"></div>";
+        let clean = sanitize(html, Policy::Strict);
+        assert!(clean.contains("href=\"#user-content-fn1\""));
+        assert!(clean.contains("id=\"user-content-fnref1\""));
+        assert!(clean.contains("id=\"user-content-edit_blogpost\""));
+
+        let clean = sanitize(html, Policy::Trusted);
+        assert!(clean.contains("id=\"edit_blogpost\""));
+    }
+
+    #[test]
+    fn policy_for_author() {
+        assert_eq!(Policy::for_author(Permissions(0b11)), Policy::Trusted);
+        assert_eq!(Policy::for_author(Permissions::AUTHOR), Policy::Strict);
+        assert_eq!(Policy::for_author(Permissions(0)), Policy::Strict);
+    }
+}
@@ -7,6 +7,57 @@ table! {
         body -> Text,
         author_id -> Int4,
         created_at -> Timestamp,
+        version -> Int4,
+        updated_at -> Timestamp,
+        deleted_at -> Nullable<Timestamp>,
+        series_id -> Nullable<Int4>,
+        series_position -> Nullable<Int4>,
+        summary -> Nullable<Text>,
+        cover_image -> Nullable<Varchar>,
+        canonical_url -> Nullable<Varchar>,
+        word_count -> Int4,
+        reading_minutes -> Int4,
+        trusted_html -> Bool,
+        summary_generated -> Bool,
+    }
+}
+
+table! {
+    postlinks (source_id, target_url) {
+        source_id -> Int4,
+        target_url -> Varchar,
+    }
+}
+
+table! {
+    series (id) {
+        id -> Int4,
+        title -> Varchar,
+        url -> Varchar,
+        description -> Text,
+        created_at -> Timestamp,
+    }
+}
+
+table! {
+    uploads (id) {
+        id -> Int4,
+        path -> Varchar,
+        content_type -> Varchar,
+        size -> Int8,
+        uploader_id -> Int4,
+        created_at -> Timestamp,
+        pending_until -> Nullable<Timestamp>,
+    }
+}
+
+table! {
+    url_redirects (id) {
+        id -> Int4,
+        path -> Varchar,
+        blogpost_id -> Nullable<Int4>,
+        target -> Nullable<Varchar>,
+        created_at -> Timestamp,
     }
 }
 
@@ -16,9 +67,23 @@ table! {
         uuid -> Varchar,
         name -> Nullable<Varchar>,
         roles -> Int8,
+        version -> Int4,
+        updated_at -> Timestamp,
+        deleted_at -> Nullable<Timestamp>,
     }
 }
 
+joinable!(blogposts -> series (series_id));
 joinable!(blogposts -> users (author_id));
+joinable!(postlinks -> blogposts (source_id));
+joinable!(uploads -> users (uploader_id));
+joinable!(url_redirects -> blogposts (blogpost_id));
 
-allow_tables_to_appear_in_same_query!(blogposts, users,);
+allow_tables_to_appear_in_same_query!(
+    blogposts,
+    postlinks,
+    series,
+    uploads,
+    url_redirects,
+    users,
+);
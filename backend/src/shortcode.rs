@@ -0,0 +1,354 @@
+// GNU AGPL v3 License
+
+//! Shortcodes, which are written like `{{< youtube dQw4w9WgXcQ >}}` in
+//! markdown and are expanded with the Tera template that has their name in
+//! the `shortcodes` directory of the template path.
+//!
+//! A shortcode can also wrap some markdown, like
+//! `{{< callout warning >}}Careful!{{< /callout >}}`. The markdown is
+//! rendered and given to the template as `inner`.
+//!
+//! Templates get the positional arguments as `args`, and the ones given as
+//! `key="value"` as `params`. The HTML that they produce is sanitized along
+//! with the rest of the post, so they should escape their arguments.
+
+use crate::{placeholder, templates};
+use std::collections::{BTreeMap, HashMap};
+
+/// The kind of placeholder that shortcodes leave behind.
+const PLACEHOLDER: char = 's';
+
+/// How deeply shortcodes can be nested in each other's markdown. Any deeper
+/// than this and they're replaced with a warning.
+pub const MAX_DEPTH: usize = 16;
+
+const OPEN: &str = "{{<";
+const CLOSE: &str = ">}}";
+
+/// A shortcode that was taken out of the markdown.
+#[derive(Debug, Clone, Default, PartialEq, serde::Serialize)]
+pub struct Shortcode {
+    pub name: String,
+    pub args: Vec<String>,
+    pub params: BTreeMap<String, String>,
+    /// The markdown between the opening and closing tags, if there are
+    /// both.
+    #[serde(skip)]
+    pub inner: Option<String>,
+}
+
+impl Shortcode {
+    /// Expand the shortcode with its template, given the HTML that its
+    /// inner markdown was rendered into. If that can't be done, this is a
+    /// warning that will show up on the page instead.
+    #[must_use]
+    #[inline]
+    pub fn to_html(&self, inner: Option<String>) -> String {
+        #[derive(serde::Serialize)]
+        struct ShortcodeContext<'a> {
+            #[serde(flatten)]
+            shortcode: &'a Shortcode,
+            #[serde(skip_serializing_if = "Option::is_none")]
+            inner: Option<String>,
+        }
+
+        let context = ShortcodeContext {
+            shortcode: self,
+            inner,
+        };
+        match templates::shortcode(&self.name, context) {
+            Some(Ok(html)) => html,
+            Some(Err(e)) => {
+                tracing::warn!("Unable to render shortcode {}: {:?}", &self.name, e);
+                warning(&format!(
+                    "Unable to render the shortcode \"{}\"",
+                    &self.name
+                ))
+            }
+            None => {
+                tracing::warn!("Unknown shortcode {}", &self.name);
+                warning(&format!("Unknown shortcode \"{}\"", &self.name))
+            }
+        }
+    }
+
+    /// The warning that shows up instead of a shortcode that's nested too
+    /// deeply to be rendered.
+    #[must_use]
+    #[inline]
+    pub fn too_deep(&self) -> String {
+        warning(&format!(
+            "The shortcode \"{}\" is nested too deeply",
+            &self.name
+        ))
+    }
+}
+
+/// Take the shortcodes out of some markdown, leaving placeholders in their
+/// place. Shortcodes aren't looked for in code.
+#[must_use]
+#[inline]
+pub fn extract(input: &str) -> (String, Vec<Shortcode>) {
+    let mut shortcodes = vec![];
+    let output =
+        placeholder::outside_code(input, |text, output| scan(text, output, &mut shortcodes));
+    (output, shortcodes)
+}
+
+/// Replace each placeholder in some text with what `replacement` gives for
+/// the shortcode it stands for.
+#[must_use]
+#[inline]
+pub fn restore(
+    text: &str,
+    shortcodes: &[Shortcode],
+    mut replacement: impl FnMut(&Shortcode) -> String,
+) -> String {
+    placeholder::restore(text, PLACEHOLDER, |i| {
+        shortcodes
+            .get(i)
+            .map(|shortcode| replacement(shortcode).into())
+    })
+}
+
+#[inline]
+fn scan(text: &str, output: &mut String, shortcodes: &mut Vec<Shortcode>) {
+    let tags = Tags::new(text);
+    let mut i = 0;
+    while i < text.len() {
+        let rest = &text[i..];
+        let len = if rest.starts_with('`') {
+            placeholder::code_span_len(rest)
+        } else if let Some((len, shortcode)) = tags.shortcode_at(i) {
+            placeholder::push(output, PLACEHOLDER, shortcodes.len());
+            shortcodes.push(shortcode);
+            i += len;
+            continue;
+        } else {
+            rest.chars().next().map_or(1, char::len_utf8)
+        };
+
+        output.push_str(&rest[..len]);
+        i += len;
+    }
+}
+
+/// The tags in some text, found in one pass so that looking for where a
+/// shortcode is closed doesn't go over the rest of the text each time.
+struct Tags<'a> {
+    text: &'a str,
+    /// Where each tag ends, by where it starts.
+    ends: HashMap<usize, usize>,
+    /// Where each closing tag starts and ends, by the name it closes, in
+    /// order.
+    closing: HashMap<&'a str, Vec<(usize, usize)>>,
+}
+
+impl<'a> Tags<'a> {
+    #[inline]
+    fn new(text: &'a str) -> Self {
+        let mut tags = Tags {
+            text,
+            ends: HashMap::new(),
+            closing: HashMap::new(),
+        };
+
+        let mut next_close: Option<usize> = None;
+        for (start, _) in text.match_indices(OPEN) {
+            let body = start + OPEN.len();
+            if next_close.map_or(true, |close| close < body) {
+                next_close = text[body..].find(CLOSE).map(|close| body + close);
+            }
+            // nothing after this can be a tag either
+            let close = match next_close {
+                Some(close) => close,
+                None => break,
+            };
+
+            let end = close + CLOSE.len();
+            tags.ends.insert(start, end);
+            if let Some(name) = text[body..close].trim().strip_prefix('/') {
+                tags.closing
+                    .entry(name.trim())
+                    .or_default()
+                    .push((start, end));
+            }
+        }
+
+        tags
+    }
+
+    /// The shortcode that starts at some point in the text, along with how
+    /// long it is.
+    #[inline]
+    fn shortcode_at(&self, start: usize) -> Option<(usize, Shortcode)> {
+        let end = *self.ends.get(&start)?;
+        let mut shortcode = parse_tag(&self.text[start + OPEN.len()..end - CLOSE.len()])?;
+
+        // see if the shortcode wraps some markdown
+        if let Some(closing) = self.closing.get(shortcode.name.as_str()) {
+            let next = closing.partition_point(|&(close_start, _)| close_start < end);
+            if let Some(&(close_start, close_end)) = closing.get(next) {
+                shortcode.inner = Some(self.text[end..close_start].into());
+                return Some((close_end - start, shortcode));
+            }
+        }
+
+        Some((end - start, shortcode))
+    }
+}
+
+/// Read an opening tag, given what's between its braces.
+#[inline]
+fn parse_tag(body: &str) -> Option<Shortcode> {
+    let mut words = split_words(body)?.into_iter();
+
+    let name = words.next()?;
+    if !name
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_'))
+    {
+        return None;
+    }
+
+    let mut shortcode = Shortcode {
+        name,
+        ..Default::default()
+    };
+    for word in words {
+        match word.split_once('=') {
+            Some((key, value)) if !key.is_empty() && !key.contains('"') => {
+                shortcode.params.insert(key.into(), unquote(value).into());
+            }
+            _ => shortcode.args.push(unquote(&word).into()),
+        }
+    }
+
+    Some(shortcode)
+}
+
+/// Split the inside of a tag into words, keeping quoted strings together.
+/// Quotes are kept, so that they can be told apart from the `=` of a
+/// parameter.
+#[inline]
+fn split_words(text: &str) -> Option<Vec<String>> {
+    let mut words = vec![];
+    let mut word = String::new();
+    let mut quoted = false;
+    for c in text.chars() {
+        match c {
+            '"' => {
+                quoted = !quoted;
+                word.push(c);
+            }
+            c if c.is_whitespace() && !quoted => {
+                if !word.is_empty() {
+                    words.push(std::mem::take(&mut word));
+                }
+            }
+            c => word.push(c),
+        }
+    }
+
+    if quoted {
+        return None;
+    }
+    if !word.is_empty() {
+        words.push(word);
+    }
+    Some(words)
+}
+
+#[inline]
+fn unquote(word: &str) -> &str {
+    word.strip_prefix('"')
+        .and_then(|word| word.strip_suffix('"'))
+        .unwrap_or(word)
+}
+
+/// A warning about a shortcode, which shows up where it would have gone.
+#[inline]
+fn warning(message: &str) -> String {
+    format!(
+        "<span class=\"shortcode-warning\">{}</span>",
+        tera::escape_html(message)
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{extract, restore, Shortcode};
+
+    #[test]
+    fn parses_shortcodes() {
+        let (output, shortcodes) = extract(
+            "Watch this:\n\n{{< youtube dQw4w9WgXcQ >}}\n\n\
+            {{< figure src=\"/images/sun.png\" caption=\"The sun, up close\" >}}",
+        );
+        assert_eq!(
+            shortcodes,
+            [
+                Shortcode {
+                    name: "youtube".into(),
+                    args: vec!["dQw4w9WgXcQ".into()],
+                    ..Default::default()
+                },
+                Shortcode {
+                    name: "figure".into(),
+                    params: [
+                        ("src".to_string(), "/images/sun.png".to_string()),
+                        ("caption".to_string(), "The sun, up close".to_string()),
+                    ]
+                    .into_iter()
+                    .collect(),
+                    ..Default::default()
+                },
+            ]
+        );
+        assert_eq!(
+            restore(&output, &shortcodes, |shortcode| shortcode.name.clone()),
+            "Watch this:\n\nyoutube\n\nfigure"
+        );
+    }
+
+    #[test]
+    fn wraps_markdown() {
+        let (_, shortcodes) =
+            extract("{{< callout warning >}}\n**Careful!**\n{{< / callout >}} after");
+        assert_eq!(shortcodes.len(), 1);
+        assert_eq!(shortcodes[0].args, ["warning"]);
+        assert_eq!(shortcodes[0].inner.as_deref(), Some("\n**Careful!**\n"));
+
+        let (_, shortcodes) =
+            extract("{{< a >}}{{< b >}}{{< a >}} {{< callout >}}inside{{< /callout >}} {{< /b >}}");
+        let inner: Vec<_> = shortcodes
+            .iter()
+            .map(|shortcode| (shortcode.name.as_str(), shortcode.inner.as_deref()))
+            .collect();
+        assert_eq!(
+            inner,
+            [
+                ("a", None),
+                (
+                    "b",
+                    Some("{{< a >}} {{< callout >}}inside{{< /callout >}} ")
+                )
+            ]
+        );
+    }
+
+    #[test]
+    fn leaves_code_alone() {
+        for input in [
+            "`{{< youtube x >}}`",
+            "```\n{{< youtube x >}}\n```\n",
+            "{{< /callout >}}",
+            "{{< youtube \"unclosed >}}",
+            "{{< youtube x",
+        ] {
+            let (output, shortcodes) = extract(input);
+            assert!(shortcodes.is_empty(), "found a shortcode in {:?}", input);
+            assert_eq!(output, input);
+        }
+    }
+}
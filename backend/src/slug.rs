@@ -0,0 +1,263 @@
+// GNU AGPL v3 License
+
+//! Blogpost URL slugs, which are what go after `/blog/` in a post's path.
+
+use crate::{
+    models::{SaveError, ValidationError},
+    Database,
+};
+use std::collections::HashSet;
+
+/// Slugs that would be shadowed by the other routes under `/blog`.
+pub const RESERVED: &[&str] = &["create", "edit", "delete", "archive"];
+
+/// The longest a slug is allowed to be.
+pub const MAX_LEN: usize = 100;
+
+/// How many numbered slugs to try before giving up on a base.
+const MAX_CANDIDATES: u32 = 1000;
+
+/// Turn a title into a slug, dropping any characters that can't go in one.
+#[must_use]
+#[inline]
+pub fn slugify(title: &str) -> String {
+    let slug = collapse(title.chars().map(|c| {
+        if c.is_ascii_alphanumeric() {
+            c.to_ascii_lowercase()
+        } else {
+            '-'
+        }
+    }));
+
+    let slug = slug[..slug.len().min(MAX_LEN)].trim_end_matches('-');
+    if slug.is_empty() {
+        "post".into()
+    } else {
+        slug.into()
+    }
+}
+
+/// Tidy up a slug that was given by the author, and make sure that it can
+/// be used.
+///
+/// Unlike `slugify`, this doesn't drop characters that can't go in a slug,
+/// since the author probably wants to know about them.
+#[inline]
+pub fn normalize(slug: &str) -> Result<String, SlugError> {
+    let slug = collapse(slug.trim().chars().map(|c| {
+        if c.is_whitespace() || c == '_' {
+            '-'
+        } else {
+            c.to_ascii_lowercase()
+        }
+    }));
+
+    validate(&slug)?;
+    Ok(slug)
+}
+
+/// Make sure a slug only has lowercase letters, digits and hyphens, and
+/// doesn't collide with another route.
+#[inline]
+pub fn validate(slug: &str) -> Result<(), SlugError> {
+    if slug.is_empty() {
+        Err(SlugError::Empty)
+    } else if slug.len() > MAX_LEN {
+        Err(SlugError::TooLong)
+    } else if let Some(c) = slug
+        .chars()
+        .find(|c| !(c.is_ascii_lowercase() || c.is_ascii_digit() || *c == '-'))
+    {
+        Err(SlugError::InvalidCharacter(c))
+    } else if RESERVED.contains(&slug) {
+        Err(SlugError::Reserved)
+    } else {
+        Ok(())
+    }
+}
+
+/// The error for a slug that another blogpost, including the ones in the
+/// trash, is already using, with another slug to suggest instead.
+#[inline]
+pub async fn taken(
+    db: &(impl Database + Send + Sync),
+    slug: &str,
+    owner: Option<i32>,
+) -> SaveError {
+    match free_slug(db, slug, owner).await {
+        Ok(suggestion) => ValidationError::SlugTaken { suggestion }.into(),
+        Err(e) => e,
+    }
+}
+
+/// Find the first of `base`, `base-2`, `base-3`, etc. that is valid and that
+/// no other blogpost is using.
+///
+/// Another post could take the slug before this one is saved, so this is
+/// only a good guess; the database has the final say.
+#[inline]
+pub async fn free_slug(
+    db: &(impl Database + Send + Sync),
+    base: &str,
+    owner: Option<i32>,
+) -> Result<String, SaveError> {
+    // every candidate starts with the base, cut short enough to fit the
+    // longest suffix
+    let longest = suffix(MAX_CANDIDATES).len();
+    let prefix = base[..base.len().min(MAX_LEN - longest)].trim_end_matches('-');
+    let taken = db
+        .list_blogpost_urls(prefix.to_string())
+        .await?
+        .into_iter()
+        .filter(|(id, _)| Some(*id) != owner)
+        .map(|(_, url)| url)
+        .collect::<HashSet<_>>();
+
+    (1..=MAX_CANDIDATES)
+        .map(|number| candidate(base, number))
+        .find(|candidate| validate(candidate).is_ok() && !taken.contains(candidate))
+        .ok_or_else(|| ValidationError::NoFreeSlug(base.to_string()).into())
+}
+
+/// The slug to try for a base, with the given number after it.
+#[inline]
+fn candidate(base: &str, number: u32) -> String {
+    if number == 1 {
+        base.to_string()
+    } else {
+        let suffix = suffix(number);
+        let base = base[..base.len().min(MAX_LEN - suffix.len())].trim_end_matches('-');
+        format!("{}{}", base, suffix)
+    }
+}
+
+#[inline]
+fn suffix(number: u32) -> String {
+    format!("-{}", number)
+}
+
+/// Collapse each run of hyphens into one, and trim them off of the ends.
+#[inline]
+fn collapse(chars: impl Iterator<Item = char>) -> String {
+    let mut slug = String::new();
+    for c in chars {
+        if c != '-' || !(slug.is_empty() || slug.ends_with('-')) {
+            slug.push(c);
+        }
+    }
+
+    if slug.ends_with('-') {
+        slug.pop();
+    }
+    slug
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum SlugError {
+    #[error("The URL is empty")]
+    Empty,
+    #[error("The URL is longer than {} characters", MAX_LEN)]
+    TooLong,
+    #[error("The URL contains the character {0:?}")]
+    InvalidCharacter(char),
+    #[error("The URL is used by another page")]
+    Reserved,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        mock_database::MockDatabase,
+        models::{BlogpostChange, NewBlogpost},
+    };
+
+    #[test]
+    fn slugify_titles() {
+        assert_eq!(slugify("Chasing Suns"), "chasing-suns");
+        assert_eq!(slugify("  What's new in 2022?! "), "what-s-new-in-2022");
+        assert_eq!(slugify("Ünïcödé"), "n-c-d");
+        assert_eq!(slugify("!!!"), "post");
+        assert_eq!(slugify(&"a".repeat(200)).len(), MAX_LEN);
+    }
+
+    #[test]
+    fn normalize_slugs() {
+        assert_eq!(normalize(" My_First  Post ").unwrap(), "my-first-post");
+        assert_eq!(normalize("--a--b--").unwrap(), "a-b");
+        assert_eq!(normalize("   "), Err(SlugError::Empty));
+        assert_eq!(normalize("a/b"), Err(SlugError::InvalidCharacter('/')));
+        assert_eq!(normalize("café"), Err(SlugError::InvalidCharacter('é')));
+        assert_eq!(normalize("Edit"), Err(SlugError::Reserved));
+        assert_eq!(normalize(&"a".repeat(200)), Err(SlugError::TooLong));
+    }
+
+    #[tokio::test]
+    async fn suggest_free_slugs() {
+        let db = MockDatabase::with_test_data();
+        assert_eq!(
+            free_slug(&db, "chasing-suns", Some(1)).await.unwrap(),
+            "chasing-suns"
+        );
+        assert!(matches!(
+            taken(&db, "chasing-suns", None).await,
+            SaveError::Invalid(ValidationError::SlugTaken { suggestion }) if suggestion == "chasing-suns-2"
+        ));
+
+        // posts in the trash still hold onto their slugs
+        db.update_blogpost(
+            2,
+            BlogpostChange {
+                url: Some("chasing-suns-2".into()),
+                ..Default::default()
+            },
+        )
+        .await
+        .unwrap();
+        db.delete_blogpost(2).await.unwrap();
+        assert_eq!(
+            free_slug(&db, "chasing-suns", None).await.unwrap(),
+            "chasing-suns-3"
+        );
+        assert_eq!(free_slug(&db, "create", None).await.unwrap(), "create-2");
+
+        // long slugs are cut short to make room for the number
+        let long = "a".repeat(MAX_LEN);
+        let suggestion = free_slug(&db, &long, Some(1)).await.unwrap();
+        assert_eq!(suggestion, long);
+        db.update_blogpost(
+            1,
+            BlogpostChange {
+                url: Some(long.clone()),
+                ..Default::default()
+            },
+        )
+        .await
+        .unwrap();
+        let suggestion = free_slug(&db, &long, None).await.unwrap();
+        assert_eq!(suggestion.len(), MAX_LEN);
+        assert!(suggestion.ends_with("a-2"));
+    }
+
+    #[tokio::test]
+    async fn gives_up_on_taken_slugs() {
+        let db = MockDatabase::with_test_data();
+        for number in 2..=MAX_CANDIDATES {
+            db.insert_blogpost(NewBlogpost {
+                url: candidate("chasing-suns", number),
+                ..NewBlogpost::test()
+            })
+            .await
+            .unwrap();
+        }
+
+        assert!(matches!(
+            free_slug(&db, "chasing-suns", None).await,
+            Err(SaveError::Invalid(ValidationError::NoFreeSlug(base))) if base == "chasing-suns"
+        ));
+        assert_eq!(
+            free_slug(&db, "chasing-suns", Some(1)).await.unwrap(),
+            "chasing-suns"
+        );
+    }
+}
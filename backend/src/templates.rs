@@ -1,6 +1,6 @@
 // GNU AGPL v3 License
 
-use crate::{auth::Permissions, Config, Urls};
+use crate::{auth::Permissions, highlight, Config, Urls};
 use arc_swap::ArcSwap;
 use notify::Watcher;
 use once_cell::sync::OnceCell;
@@ -22,6 +22,11 @@ struct TemplateState {
     urls: Urls,
 }
 
+/// The directory in the template path that shortcode templates are kept
+/// in. They're registered as `shortcodes/{name}`, so that they don't clash
+/// with the page templates.
+pub const SHORTCODE_DIR: &str = "shortcodes";
+
 #[derive(Default)]
 pub struct TemplateOptions {
     pub csrf_tokens: Option<(String, String)>,
@@ -56,6 +61,12 @@ pub fn template<T: serde::Serialize>(
     context.insert("web_url", &templates.urls.web_url);
     context.insert("user_perms", &perms);
 
+    // pages can pick their own highlighting theme
+    if !context.contains_key("highlight_stylesheet") {
+        let stylesheet = highlight::stylesheet_path(highlight::default_theme());
+        context.insert("highlight_stylesheet", &stylesheet);
+    }
+
     // add csrf token
     if let Some((csrf_token, csrf_cookie)) = csrf_tokens {
         context.insert("csrf_token", &csrf_token);
@@ -71,6 +82,27 @@ pub fn template<T: serde::Serialize>(
     templates.render(name, &context)
 }
 
+/// Render the template for a shortcode, or return `None` if there isn't
+/// one with that name.
+#[inline]
+pub fn shortcode<T: serde::Serialize>(name: &str, data: T) -> Option<Result<String, Error>> {
+    let templates = TEMPLATES
+        .get()
+        .expect("`initialize_templates` not called before `shortcode`");
+    let name = format!("{}/{}", SHORTCODE_DIR, name);
+
+    let tera = templates.templates.load();
+    if !tera.get_template_names().any(|template| template == name) {
+        return None;
+    }
+
+    Some(Context::from_serialize(data).and_then(|mut context| {
+        context.insert("static_url", &templates.urls.static_url);
+        context.insert("web_url", &templates.urls.web_url);
+        tera.render(&name, &context)
+    }))
+}
+
 #[inline]
 pub async fn initialize_templates(cfg: &Config) -> Result<(), PopulateTemplateError> {
     TEMPLATES
@@ -115,6 +147,7 @@ fn find_templates(
     template_path: &Path,
 ) -> Result<Vec<(PathBuf, Option<String>)>, PopulateTemplateError> {
     let root = fs::canonicalize(template_path)?;
+    let shortcode_dir = root.join(SHORTCODE_DIR);
 
     WalkDir::new(&root)
         .into_iter()
@@ -135,6 +168,9 @@ fn find_templates(
 
                     // convert the path to the basename
                     let basename = match path.file_prefix().and_then(OsStr::to_str) {
+                        Some(basename) if path.parent() == Some(shortcode_dir.as_path()) => {
+                            format!("{}/{}", SHORTCODE_DIR, basename)
+                        }
                         Some(basename) => basename.to_string(),
                         None => return None,
                     };
@@ -201,7 +237,17 @@ pub fn initialize_test_templates() -> Result<(), Error> {
         ("very_basic", "Hello, {{ name }}!"),
         ("base", include_str!("../templates/base.html.jinja")),
         ("blogpost", include_str!("../templates/blogpost.html.jinja")),
+        ("bloglist", include_str!("../templates/bloglist.html.jinja")),
+        ("series", include_str!("../templates/series.html.jinja")),
         ("error", include_str!("../templates/error.html.jinja")),
+        (
+            "shortcodes/youtube",
+            include_str!("../templates/shortcodes/youtube.html.jinja"),
+        ),
+        (
+            "shortcodes/callout",
+            include_str!("../templates/shortcodes/callout.html.jinja"),
+        ),
     ];
 
     let mut tera = Tera::default();
@@ -251,5 +297,6 @@ mod test {
         )
         .unwrap();
         assert!(res.contains("<title>WorldWideWeb"));
+        assert!(res.contains("href=\"/highlight/base16-ocean-dark.css\""));
     }
 }
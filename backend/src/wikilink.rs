@@ -0,0 +1,326 @@
+// GNU AGPL v3 License
+
+//! Links between blogposts, written as `[[slug]]` or `[[slug|text]]` in
+//! markdown. They're looked up when the post is rendered, so they follow a
+//! post to wherever its URL has moved, and links to posts that don't exist
+//! are flagged.
+
+use crate::{
+    markdown::{self, TOC_MARKER},
+    models::{blogpost_path, LinkTarget},
+    placeholder, slug, Database, DatabaseError,
+};
+use std::collections::{BTreeMap, BTreeSet};
+
+/// The kind of placeholder that wiki links leave behind.
+const PLACEHOLDER: char = 'w';
+
+/// Where wiki links lead, by the slug that they were written with. Slugs
+/// that map to `None` don't lead to any published post.
+pub type ResolvedLinks = BTreeMap<String, Option<LinkTarget>>;
+
+/// A wiki link that was taken out of the markdown.
+#[derive(Debug, Clone, PartialEq)]
+pub struct WikiLink {
+    pub slug: String,
+    /// The text to show instead of the post's title.
+    pub text: Option<String>,
+}
+
+impl WikiLink {
+    /// Render the link into HTML. If the links haven't been resolved, it
+    /// leads to wherever the slug would be.
+    ///
+    /// Slugs are validated when they're read, so they don't need escaping.
+    #[must_use]
+    #[inline]
+    pub fn to_html(&self, resolved: Option<&ResolvedLinks>) -> String {
+        let text = tera::escape_html(&self.text(resolved));
+        match resolved.map(|resolved| self.target(resolved)) {
+            Some(Some(target)) => format!(
+                "<a class=\"wiki-link\" href=\"{}\">{}</a>",
+                blogpost_path(&target.url),
+                text
+            ),
+            Some(None) => format!(
+                "<span class=\"broken-link\" title=\"There is no post at {}\">{}</span>",
+                blogpost_path(&self.slug),
+                text
+            ),
+            None => format!(
+                "<a class=\"wiki-link\" href=\"{}\">{}</a>",
+                blogpost_path(&self.slug),
+                text
+            ),
+        }
+    }
+
+    /// The text that the link shows: the text it was written with, or else
+    /// the title of the post it leads to.
+    #[must_use]
+    #[inline]
+    pub fn text(&self, resolved: Option<&ResolvedLinks>) -> String {
+        let target = resolved.and_then(|resolved| self.target(resolved));
+        match (&self.text, target) {
+            (Some(text), _) => text.clone(),
+            (None, Some(target)) => target.title.clone(),
+            (None, None) => self.slug.clone(),
+        }
+    }
+
+    #[inline]
+    fn target<'a>(&self, resolved: &'a ResolvedLinks) -> Option<&'a LinkTarget> {
+        resolved.get(&self.slug).and_then(Option::as_ref)
+    }
+}
+
+/// Take the wiki links out of some markdown, leaving placeholders in their
+/// place. Wiki links aren't looked for in code.
+#[must_use]
+#[inline]
+pub fn extract(input: &str) -> (String, Vec<WikiLink>) {
+    let mut links = vec![];
+    let output = placeholder::outside_code(input, |text, output| scan(text, output, &mut links));
+    (output, links)
+}
+
+/// Replace each placeholder in some HTML with the link it stands for.
+#[must_use]
+#[inline]
+pub fn restore(html: &str, links: &[WikiLink], resolved: Option<&ResolvedLinks>) -> String {
+    placeholder::restore(html, PLACEHOLDER, |i| {
+        links.get(i).map(|link| link.to_html(resolved).into())
+    })
+}
+
+/// The links that left placeholders in some text, in order.
+#[must_use]
+#[inline]
+pub fn placeholders<'a>(text: &str, links: &'a [WikiLink]) -> Vec<&'a WikiLink> {
+    let mut found = vec![];
+    let _ = placeholder::restore(text, PLACEHOLDER, |i| {
+        found.extend(links.get(i));
+        None
+    });
+    found
+}
+
+/// Replace each placeholder in some text with the text of the link it
+/// stands for, for places where there can't be a link.
+#[must_use]
+#[inline]
+pub fn restore_text(text: &str, links: &[WikiLink], resolved: Option<&ResolvedLinks>) -> String {
+    placeholder::restore(text, PLACEHOLDER, |i| {
+        links.get(i).map(|link| link.text(resolved).into())
+    })
+}
+
+/// Every slug that a post's markdown links to.
+#[must_use]
+#[inline]
+pub fn slugs(input: &str) -> Vec<String> {
+    let (_, links) = extract(markdown::strip_front_matter(input));
+    links
+        .into_iter()
+        .map(|link| link.slug)
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .collect()
+}
+
+/// Look up where each of the wiki links in some markdown lead.
+#[inline]
+pub async fn resolve(
+    database: &impl Database,
+    input: &str,
+) -> Result<ResolvedLinks, DatabaseError> {
+    let slugs = slugs(input);
+    if slugs.is_empty() {
+        return Ok(ResolvedLinks::new());
+    }
+
+    let mut resolved: ResolvedLinks = database
+        .get_link_targets(slugs.clone())
+        .await?
+        .into_iter()
+        .map(|target| (target.slug.clone(), Some(target)))
+        .collect();
+    for slug in slugs {
+        resolved.entry(slug).or_insert(None);
+    }
+    Ok(resolved)
+}
+
+/// Store the links in the posts that were saved before links were stored,
+/// so that they show up as backlinks.
+#[inline]
+pub async fn backfill_links(database: &impl Database) {
+    let posts = match database.list_unlinked_blogposts().await {
+        Ok(posts) => posts,
+        Err(e) => {
+            tracing::error!("Unable to find the blogposts without stored links: {}", e);
+            return;
+        }
+    };
+
+    let mut stored = 0;
+    for (id, body) in posts {
+        let links = slugs(&body);
+        if links.is_empty() {
+            continue;
+        }
+        match database.set_post_links(id, links).await {
+            Ok(()) => stored += 1,
+            Err(e) => tracing::error!("Unable to store the links of blogpost {}: {}", id, e),
+        }
+    }
+
+    if stored > 0 {
+        tracing::info!("Stored the links of {} blogposts", stored);
+    }
+}
+
+#[inline]
+fn scan(text: &str, output: &mut String, links: &mut Vec<WikiLink>) {
+    let mut i = 0;
+    while i < text.len() {
+        let rest = &text[i..];
+        let len = if rest.starts_with('\\') {
+            1 + rest[1..].chars().next().map_or(0, char::len_utf8)
+        } else if rest.starts_with('`') {
+            placeholder::code_span_len(rest)
+        } else if let Some((len, link)) = link_at(rest) {
+            placeholder::push(output, PLACEHOLDER, links.len());
+            links.push(link);
+            i += len;
+            continue;
+        } else {
+            rest.chars().next().map_or(1, char::len_utf8)
+        };
+
+        output.push_str(&rest[..len]);
+        i += len;
+    }
+}
+
+/// The wiki link at the start of some text, along with how long it is.
+#[inline]
+fn link_at(text: &str) -> Option<(usize, WikiLink)> {
+    let body = text.strip_prefix("[[")?;
+    let end = body.find("]]")?;
+    let inside = &body[..end];
+    if inside.contains('\n') || text.starts_with(TOC_MARKER) {
+        return None;
+    }
+
+    let (slug, link_text) = match inside.split_once('|') {
+        Some((slug, link_text)) => (slug.trim(), Some(link_text.trim())),
+        None => (inside.trim(), None),
+    };
+    slug::validate(slug).ok()?;
+
+    Some((
+        end + 4,
+        WikiLink {
+            slug: slug.into(),
+            text: link_text
+                .filter(|link_text| !link_text.is_empty())
+                .map(Into::into),
+        },
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{backfill_links, extract, restore, slugs, ResolvedLinks, WikiLink};
+    use crate::{
+        mock_database::MockDatabase,
+        models::{BlogpostChange, LinkTarget},
+        Database,
+    };
+
+    #[test]
+    fn finds_links() {
+        let (_, links) = extract("See [[chasing-suns]] and [[ summer-sun | the sequel ]].");
+        assert_eq!(
+            links,
+            [
+                WikiLink {
+                    slug: "chasing-suns".into(),
+                    text: None,
+                },
+                WikiLink {
+                    slug: "summer-sun".into(),
+                    text: Some("the sequel".into()),
+                },
+            ]
+        );
+
+        for input in [
+            "[[toc]]",
+            "`[[chasing-suns]]`",
+            "```\n[[chasing-suns]]\n```\n",
+            "\\[[chasing-suns]]",
+            "[[Not A Slug]]",
+            "[[chasing-\nsuns]]",
+        ] {
+            let (output, links) = extract(input);
+            assert!(links.is_empty(), "found a link in {:?}", input);
+            assert_eq!(output, input);
+        }
+
+        assert_eq!(
+            slugs("+++\ntitle = \"[[a]]\"\n+++\n[[b]] [[c]] [[b|again]]"),
+            ["b", "c"]
+        );
+    }
+
+    #[test]
+    fn renders_links() {
+        let (output, links) = extract("[[old-suns]], [[nowhere|this]] and [[chasing-suns]]");
+        let resolved: ResolvedLinks = [
+            (
+                "old-suns".to_string(),
+                Some(LinkTarget {
+                    slug: "old-suns".into(),
+                    url: "chasing-suns".into(),
+                    title: "Chasing <Suns>".into(),
+                }),
+            ),
+            ("nowhere".to_string(), None),
+        ]
+        .into_iter()
+        .collect();
+
+        assert_eq!(
+            restore(&output, &links, Some(&resolved)),
+            "<a class=\"wiki-link\" href=\"/blog/chasing-suns\">Chasing &lt;Suns&gt;</a>, \
+            <span class=\"broken-link\" title=\"There is no post at /blog/nowhere\">this</span> \
+            and <span class=\"broken-link\" title=\"There is no post at /blog/chasing-suns\">chasing-suns</span>"
+        );
+        assert_eq!(
+            restore(&output, &links, None),
+            "<a class=\"wiki-link\" href=\"/blog/old-suns\">old-suns</a>, \
+            <a class=\"wiki-link\" href=\"/blog/nowhere\">this</a> \
+            and <a class=\"wiki-link\" href=\"/blog/chasing-suns\">chasing-suns</a>"
+        );
+    }
+
+    #[tokio::test]
+    async fn backfills_links() {
+        // posts saved straight to the database don't have their links stored
+        let database = MockDatabase::with_test_data();
+        let change = BlogpostChange {
+            body: Some("See [[how-to-make-a-website]].".into()),
+            ..Default::default()
+        };
+        database.update_blogpost(1, change).await.unwrap();
+        assert!(database.list_backlinks(2).await.unwrap().is_empty());
+
+        backfill_links(&database).await;
+        let backlinks = database.list_backlinks(2).await.unwrap();
+        assert_eq!(backlinks.len(), 1);
+        assert_eq!(backlinks[0].id, 1);
+        assert!(database.list_unlinked_blogposts().await.unwrap().is_empty());
+    }
+}
@@ -3,6 +3,7 @@
 import axios from "axios";
 
 import getConsts from "./consts";
+import { Blogpost, UrlRedirect } from "./models";
 
 // the global axios instance, with a config
 const api = axios.create({
@@ -13,6 +14,10 @@ const api = axios.create({
 interface PaginationParameters {
     skip: number,
     count: number,
+    cursor?: string,
+    // comma-separated fields, prefixed with "-" for descending order
+    sort?: string,
+    match?: "contains" | "exact",
 }
 
 // Combine pagination parameters with filtering options
@@ -39,13 +44,30 @@ function authDetails(): AuthDetails {
     };
 }
 
-// send a GET request to retrive a list of objects, with a 
+// a page of objects, along with the total number of objects
+export interface ListPage<T> {
+    items: T[],
+    total: number,
+    skip: number,
+    count: number,
+    next?: string,
+    prev?: string,
+    next_cursor?: string,
+}
+
+// send a GET request to retrive a page of objects, with a
 // partial filtering
-export function list<T>(name: string, params: ListParameters<T>): Promise<T[]> {
+export function listPage<T>(name: string, params: ListParameters<T>): Promise<ListPage<T>> {
     const realParams = Object.assign(params, authDetails());
     return api.get(`${name}`, { params: realParams }).then(res => res.data);
 };
 
+// send a GET request to retrive a list of objects, with a 
+// partial filtering
+export function list<T>(name: string, params: ListParameters<T>): Promise<T[]> {
+    return listPage(name, params).then(page => page.items);
+};
+
 // send a GET request to retrieve a specific object
 export function get<T>(name: string, id: number): Promise<T> {
     return api.get(`${name}/${id}`, { params: authDetails() }).then(res => res.data);
@@ -57,18 +79,112 @@ export function post<T>(name: string, params: PostParameters<T>): Promise<number
     return api.post(`${name}`, params).then(res => res.data.id);
 };
 
+// only apply a change if the object is still at the given version
+function ifMatch(version?: number): Record<string, string> {
+    return version === undefined ? {} : { "If-Match": `"${version}"` };
+}
+
 // send a PATCH request to update an object
-export function patch<T>(name: string, id: number, params: PatchParameters<T>): Promise<void> {
+export function patch<T>(name: string, id: number, params: PatchParameters<T>, version?: number): Promise<void> {
     const realParams = Object.assign(params, authDetails());
-    return api.patch(`${name}/${id}`, realParams).then(_ => {});
+    return api.patch(`${name}/${id}`, realParams, { headers: ifMatch(version) }).then(_ => {});
 };
 
-// send a DELETE request to delete an object
-export function doDelete(name: string, id: number): Promise<void> {
-    return api.delete(`${name}/${id}`, { data: authDetails() }).then(_ => {});
+// send a DELETE request to delete an object, with any options the model
+// takes for how to delete it
+export function doDelete(name: string, id: number, version?: number, options?: object): Promise<void> {
+    const data = Object.assign({}, options, authDetails());
+    return api.delete(`${name}/${id}`, { data, headers: ifMatch(version) }).then(_ => {});
+}
+
+export type BatchOperation<T> =
+    { op: "create", data: PostParameters<T> } |
+    { op: "update", id: number, data: PatchParameters<T> } |
+    { op: "delete", id: number, options?: object };
+
+export interface BatchResult {
+    status: number,
+    id?: number,
+    description?: string,
+}
+
+export interface BatchResponse {
+    committed: boolean,
+    results: BatchResult[],
+}
+
+// send several operations in one request; either all of them take effect,
+// or none of them do
+export function batch<T>(name: string, operations: BatchOperation<T>[]): Promise<BatchResponse> {
+    const params = Object.assign({ operations }, authDetails());
+    return api.post(`${name}/batch`, params).then(res => res.data);
+}
+
+// a page of blogposts that have been moved to the trash
+export interface TrashPage {
+    items: Blogpost[],
+    total: number,
+}
+
+// list the blogposts in the trash
+export function listTrash(skip: number, count: number): Promise<TrashPage> {
+    const params = Object.assign({ skip, count }, authDetails());
+    return api.get("trash", { params }).then(res => res.data);
+}
+
+// take a blogpost back out of the trash
+export function restoreFromTrash(id: number): Promise<void> {
+    return api.post(`trash/${id}/restore`, authDetails()).then(_ => {});
+}
+
+// delete a blogpost in the trash for good
+export function purgeFromTrash(id: number): Promise<void> {
+    return api.delete(`trash/${id}`, { data: authDetails() }).then(_ => {});
+}
+
+// list every redirect, newest first
+export function listRedirects(): Promise<UrlRedirect[]> {
+    return api.get("redirects", { params: authDetails() }).then(res => res.data);
+}
+
+// redirect a path on this site somewhere else
+export function createRedirect(path: string, target: string): Promise<number> {
+    const data = Object.assign({ path, target }, authDetails());
+    return api.post("redirects", data).then(res => res.data.id);
+}
+
+// stop redirecting a path
+export function deleteRedirect(id: number): Promise<void> {
+    return api.delete(`redirects/${id}`, { data: authDetails() }).then(_ => {});
 }
 
 // upload a form data using POST
 export function postFormData<T>(name: string, data: FormData): Promise<T> {
     return api.post(`${name}`, data).then(res => res.data);
-}
\ No newline at end of file
+}
+
+interface PresignedUpload {
+    url: string,
+    path: string,
+    expires_in: number,
+    max_size: number,
+}
+
+// upload a file directly to storage using a presigned URL, then register it
+export function presignedUpload(category: string, subcategory: string, filename: string, file: File): Promise<string> {
+    const presignParams = Object.assign({
+        category,
+        subcategory,
+        filename,
+        content_type: file.type || "application/octet-stream",
+        size: file.size,
+    }, authDetails());
+
+    return api.post("upload/presign", presignParams)
+        .then(res => res.data as PresignedUpload)
+        .then(presigned => axios.put(presigned.url, file, {
+            headers: { "Content-Type": presignParams.content_type },
+        }).then(_ => presigned.path))
+        .then(path => api.post("upload/complete", Object.assign({ path }, authDetails()))
+            .then(_ => path));
+}
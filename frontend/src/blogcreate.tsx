@@ -4,11 +4,14 @@ import { Component, h } from "preact";
 
 import BlogpostEditor, { UploadedBlogpost } from "./blogpost_editor";
 import { Empty } from "./util";
-import { post } from "./api";
+import { get, post } from "./api";
+import { Blogpost } from "./models";
 
 export function BlogpostCreate(props: Empty) {
     const uploader = (ub: UploadedBlogpost) => (
-        post("blogpost", ub).then((_) => ({}))
+        post("blogpost", ub)
+            .then((id) => get<Blogpost>("blogpost", id))
+            .then((bp) => bp.url)
     );
 
     return (
@@ -36,15 +36,12 @@ export class BlogpostEdit extends Component<Empty, BlogpostEditState> {
         });
     }
 
-    uploadBlogpost(bp: UploadedBlogpost): Promise<Empty> {
-        const consts = getConsts();
-        return patch<Blogpost>("blogpost", consts.user_id!, bp).then(() => {
-            const url = `${consts.web_url}/blog/${bp.url}`;
-            window.location.href = url;
-
-            // unreachable
-            return {};
-        });
+    uploadBlogpost(bp: UploadedBlogpost): Promise<string> {
+        // the server may have tidied up the URL, so fetch the one it used
+        const id = getConsts().user_id!;
+        return patch<Blogpost>("blogpost", id, bp)
+            .then(() => get<Blogpost>("blogpost", id))
+            .then((updated) => updated.url);
     }
 
     render() {
@@ -3,7 +3,7 @@
 import { Component, Fragment, h } from "preact";
 
 import ImageUploader from "./image_uploader";
-import { capitalize, Empty } from "./util";
+import { capitalize } from "./util";
 import { getConsts } from "./consts";
 
 export interface UploadedBlogpost {
@@ -19,7 +19,8 @@ interface BlogpostEditorProps {
     original_url: string,
     original_tags: string,
     original_body: string,
-    uploader: (ub: UploadedBlogpost) => Promise<Empty>,
+    // resolves to the URL the blogpost ended up at
+    uploader: (ub: UploadedBlogpost) => Promise<string>,
 };
 
 interface BlogpostEditorState {
@@ -49,13 +50,21 @@ export class BlogpostEditor extends Component<BlogpostEditorProps, BlogpostEdito
 
     doUpload() {
         this.setState({ uploading: true });
-        this.props.uploader(this.state.ub).then((_) => {
+        this.props.uploader(this.state.ub).then((url) => {
             const consts = getConsts();
-            window.location.href = `${consts.web_url}/blog/${this.state.ub.url}`;
+            window.location.href = `${consts.web_url}/blog/${url}`;
         }).catch((err) => {
+            // if the URL is taken, offer the one the server suggests
+            const data = err.response?.data;
+            if (data?.suggestion !== undefined) {
+                this.setState({
+                    ub: Object.assign(this.state.ub, { url: data.suggestion }),
+                });
+            }
+
             this.setState({
                 uploading: false,
-                error: err.error,
+                error: data?.description ?? `${err}`,
             });
         })
     }
@@ -84,7 +93,7 @@ export class BlogpostEditor extends Component<BlogpostEditorProps, BlogpostEdito
                 {errorElem}
                 <table>
                     <TextField name="title" value={title} setValue={setTitle} />
-                    <TextField name="url" value={url} setValue={setUrl} />
+                    <TextField name="url" value={url} setValue={setUrl} placeholder="Generated from the title" />
                     <TextField name="body" value={body} setValue={setBody} isBody={true} />
                     <TextField name="tags" value={tags} setValue={setTags} />
                     <tr><td><ImageUploader /></td></tr>
@@ -102,6 +111,7 @@ interface TextFieldProps {
     value: string,
     setValue: (s: string) => void,
     isBody?: boolean,
+    placeholder?: string,
 };
 
 function TextField(props: TextFieldProps) {
@@ -113,6 +123,7 @@ function TextField(props: TextFieldProps) {
             <input  type="text" 
                     id={props.name} 
                     value={props.value} 
+                    placeholder={props.placeholder} 
                     onChange={(ev) => props.setValue((ev!.target! as HTMLInputElement).value)} />
         );
     } else {
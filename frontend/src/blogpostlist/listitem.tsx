@@ -15,8 +15,9 @@ export function BlogpostListItem(props: BlogpostListItemProps) {
     return (
         <div className="blogpost-list-item">
             <h2><a href={fullUrl}>{blogpost.title}</a></h2>
+            <p className="blogpost-list-item-date">{blogpost.reading_minutes} min read</p>
             <div className="blogpost-list-item-body">
-                {blogpost.body}
+                <p>{blogpost.summary || ""}</p>
             </div>
         </div>
     );
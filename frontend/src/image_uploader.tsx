@@ -4,7 +4,7 @@ import { Component, h } from "preact";
 
 import getConsts from "./consts";
 import { Empty } from "./util";
-import { postFormData } from "./api";
+import { postFormData, presignedUpload } from "./api";
 
 interface ImageUploaderState {
     category: string,
@@ -18,6 +18,9 @@ interface ImageUploaderState {
 
 type ChangeEvent<T> = any;
 
+// files larger than this go directly to storage instead of through the server
+const MAX_PROXIED_LEN = 5 * 1024 * 1024;
+
 interface UrlContainer {
     url: string,
 }
@@ -59,6 +62,20 @@ export class ImageUploader extends Component<Empty, ImageUploaderState> {
             });            
 
             const consts = getConsts();
+            const addUrl = (url: string) => {
+                const urls: string[] = this.state.urls;
+                urls.push(`${consts.static_url}/${url}`);
+                this.setState({
+                    urls,
+                    uploading: false,
+                });
+            };
+
+            const file: File = data![0];
+            if (file.size > MAX_PROXIED_LEN) {
+                presignedUpload(category, subcategory, filename, file).then(addUrl);
+                return;
+            }
 
             const fdata = new FormData();
             fdata.append("category", category);
@@ -68,14 +85,7 @@ export class ImageUploader extends Component<Empty, ImageUploaderState> {
             fdata.append("csrf_token", consts.csrf_token!);
             fdata.append("csrf_cookie", consts.csrf_cookie!);
 
-            postFormData<UrlContainer>("image", fdata).then(u => {
-                const urls: string[] = this.state.urls;
-                urls.push(`${consts.static_url}/${u.url}`);
-                this.setState({
-                    urls,
-                    uploading: false,
-                });
-            });
+            postFormData<UrlContainer>("image", fdata).then(u => addUrl(u.url));
         }
     }
 
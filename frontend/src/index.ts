@@ -7,19 +7,21 @@ import BlogpostEdit from "./blogedit";
 import DeletePost from "./deletepost";
 import Frontpage from "./frontpage";
 import getConsts from "./consts";
-import ListBlogpost from "./listblogpost";
 import navlink from "./navlink";
+import Redirects from "./redirects";
+import Trash from "./trash";
 import UsernameForm from "./usernameform";
 import UserInfo from "./user_info";
 import UserList from "./user_list";
 
 // If we find an element with this ID, load this component into it
 const router: Record<string, any> = {
-    "blogpost-list": ListBlogpost,
     "blogpost-edit": BlogpostEdit,
     "blogpost-create": BlogpostCreate,
     "blogpost-delete": DeletePost,
     "frontpage": Frontpage,
+    "redirects": Redirects,
+    "trash": Trash,
     "username-form": UsernameForm,
     "user-info": UserInfo,
     "user-list": UserList,
@@ -8,7 +8,28 @@ export interface Blogpost {
     url: string,
     body: string,
     author_id: number,
-    created_at: Date
+    created_at: Date,
+    version: number,
+    updated_at: Date,
+    deleted_at: Date | null,
+    series_id: number | null,
+    series_position: number | null,
+    summary: string | null,
+    cover_image: string | null,
+    canonical_url: string | null,
+    word_count: number,
+    reading_minutes: number,
+    trusted_html: boolean,
+    summary_generated: boolean,
+};
+
+// analagous to the Series struct on the backend
+export interface Series {
+    id: number,
+    title: string,
+    url: string,
+    description: string,
+    created_at: Date,
 };
 
 // analagous to the User struct on the backend
@@ -17,4 +38,22 @@ export interface User {
     uuid: string,
     name: string | undefined,
     roles: number,
+    version: number,
+    updated_at: Date,
+    deleted_at: Date | null,
+};
+
+// the ways a user can be deleted, analagous to UserDeletion on the backend
+export type UserDeletion =
+    { mode: "soft_delete" } |
+    { mode: "anonymize" } |
+    { mode: "reassign", reassign_to: number };
+
+// analagous to the UrlRedirect struct on the backend
+export interface UrlRedirect {
+    id: number,
+    path: string,
+    blogpost_id: number | null,
+    target: string | null,
+    created_at: Date,
 };
\ No newline at end of file
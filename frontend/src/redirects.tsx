@@ -0,0 +1,99 @@
+// GNU AGPL v3 License
+
+import { Component, Fragment, h } from "preact";
+
+import Loading from "./loading";
+import { Empty, LoadingState } from "./util";
+import { createRedirect, deleteRedirect, listRedirects } from "./api";
+import { UrlRedirect } from "./models";
+
+interface RedirectsState {
+    redirects: UrlRedirect[],
+    loadstate: LoadingState,
+    error: string,
+    path: string,
+    target: string,
+};
+
+export class Redirects extends Component<Empty, RedirectsState> {
+    state = {
+        redirects: [],
+        loadstate: LoadingState.Unmounted,
+        error: "",
+        path: "",
+        target: "",
+    };
+
+    private loadRedirects() {
+        this.setState({
+            loadstate: LoadingState.Loading,
+        });
+
+        listRedirects().then((redirects) => {
+            this.setState({
+                redirects,
+                loadstate: LoadingState.Loaded,
+            });
+        });
+    }
+
+    private act(action: Promise<unknown>) {
+        action.then(() => {
+            this.setState({ error: "", path: "", target: "" });
+            this.loadRedirects();
+        }).catch((err) => {
+            this.setState({
+                error: err.response?.data?.description ?? `${err}`,
+            });
+        });
+    }
+
+    componentDidMount() {
+        this.loadRedirects();
+    }
+
+    render() {
+        if (this.state.loadstate == LoadingState.Loading) {
+            return <Loading />;
+        } else if (this.state.loadstate == LoadingState.Unmounted) {
+            return <></>;
+        }
+
+        const entries = this.state.redirects.map((redirect: UrlRedirect) => (
+            <tr key={redirect.id}>
+                <td>{redirect.path}</td>
+                <td>{redirect.target ?? `Blogpost #${redirect.blogpost_id}`}</td>
+                <td>{redirect.created_at}</td>
+                <td>
+                    <button onClick={() => this.act(deleteRedirect(redirect.id))}>Delete</button>
+                </td>
+            </tr>
+        ));
+
+        const { path, target } = this.state;
+
+        return (
+            <>
+                {this.state.error.length > 0 && <p>Error: {this.state.error}</p>}
+                <p>
+                    <input type="text" placeholder="/old/path" value={path} onChange={(ev) => this.setState({ path: (ev!.target! as HTMLInputElement).value })} />
+                    <input type="text" placeholder="/new/path" value={target} onChange={(ev) => this.setState({ target: (ev!.target! as HTMLInputElement).value })} />
+                    <button onClick={() => this.act(createRedirect(path, target))}>Add Redirect</button>
+                </p>
+                <table>
+                    <tbody>
+                        <tr>
+                            <th>From</th>
+                            <th>To</th>
+                            <th>Created</th>
+                            <th></th>
+                        </tr>
+                        {entries}
+                    </tbody>
+                </table>
+            </>
+        );
+    }
+};
+
+export default Redirects;
@@ -0,0 +1,126 @@
+// GNU AGPL v3 License
+
+import { Component, Fragment, h } from "preact";
+
+import Loading from "./loading";
+import Pagination from "./pagination";
+import { Empty, LoadingState } from "./util";
+import { listTrash, purgeFromTrash, restoreFromTrash } from "./api";
+import { Blogpost } from "./models";
+
+interface TrashState {
+    posts: Blogpost[],
+    loadstate: LoadingState,
+    error: string,
+    page_size: number,
+    page_index: number,
+};
+
+export class Trash extends Component<Empty, TrashState> {
+    state = {
+        posts: [],
+        loadstate: LoadingState.Unmounted,
+        error: "",
+        page_size: 25,
+        page_index: 0,
+    };
+
+    private loadPosts() {
+        this.setState({
+            loadstate: LoadingState.Loading,
+        });
+
+        const { page_size, page_index } = this.state;
+        listTrash(page_size * page_index, page_size).then((page) => {
+            this.setState({
+                posts: page.items,
+                loadstate: LoadingState.Loaded,
+            });
+        });
+    }
+
+    private act(action: (id: number) => Promise<void>, id: number) {
+        action(id).then(() => this.loadPosts()).catch((err) => {
+            this.setState({
+                error: err.response?.data?.description ?? `${err}`,
+            });
+        });
+    }
+
+    componentDidMount() {
+        this.loadPosts();
+    }
+
+    render() {
+        if (this.state.loadstate == LoadingState.Loading) {
+            return <Loading />;
+        } else if (this.state.loadstate == LoadingState.Unmounted) {
+            return <></>;
+        }
+
+        const postEntries = this.state.posts.map((post: Blogpost) => (
+            <TrashItem
+                post={post}
+                restore={() => this.act(restoreFromTrash, post.id)}
+                purge={() => this.act(purgeFromTrash, post.id)} />
+        ));
+
+        const setPage = (page: number) => {
+            this.setState({
+                page_index: page,
+            });
+
+            this.loadPosts();
+        };
+
+        return (
+            <>
+                {this.state.error.length > 0 && <p>Error: {this.state.error}</p>}
+                <table>
+                    <tbody>
+                        <tr>
+                            <th>Title</th>
+                            <th>URL</th>
+                            <th>Deleted</th>
+                            <th></th>
+                        </tr>
+                        {postEntries}
+                    </tbody>
+                </table>
+                <Pagination
+                  page={this.state.page_index}
+                  setPage={setPage} />
+            </>
+        );
+    }
+};
+
+export default Trash;
+
+interface TrashItemProps {
+    post: Blogpost,
+    restore: () => void,
+    purge: () => void,
+};
+
+function TrashItem(props: TrashItemProps) {
+    const { post, restore, purge } = props;
+
+    const confirmPurge = () => {
+        if (window.confirm(`Permanently delete "${post.title}"? This cannot be undone.`)) {
+            purge();
+        }
+    };
+
+    return (
+        <tr key={post.id}>
+            <td>{post.title}</td>
+            <td>{post.url}</td>
+            <td>{post.deleted_at}</td>
+            <td>
+                <button onClick={restore}>Restore</button>
+                <button onClick={confirmPurge}>Delete Forever</button>
+            </td>
+        </tr>
+    );
+}
@@ -4,9 +4,9 @@ import { Component, Fragment, h } from "preact";
 
 import getConsts from "./consts";
 import Loading from "./loading";
-import { get, patch } from "./api";
+import { doDelete, get, patch } from "./api";
 import { Empty, LoadingState } from "./util";
-import { User } from "./models";
+import { User, UserDeletion } from "./models";
 
 interface UserInfoState {
     loadstate: LoadingState,
@@ -50,6 +50,25 @@ export class UserInfo extends Component<Empty, UserInfoState> {
         }
     }
 
+    deleteUser(deletion: UserDeletion) {
+        if (!this.state.uploading) {
+            this.setState({
+                uploading: true,
+            });
+
+            const { cur_user_id } = getConsts();
+            const version = (this.state.user! as User).version;
+            doDelete("user", cur_user_id!, version, deletion).then(() => {
+                window.location.href = "/admin/users";
+            }).catch((err) => {
+                this.setState({
+                    uploading: false,
+                    error: err.response?.data?.description ?? `${err}`,
+                });
+            });
+        }
+    }
+
     render() {
         const { loadstate, user, error } = this.state;
         if (loadstate == LoadingState.Unmounted) {
@@ -77,12 +96,18 @@ export class UserInfo extends Component<Empty, UserInfoState> {
             };
 
             return (
-                <UserForm
-                    user={u}
-                    setName={setName}
-                    setUuid={setUuid}
-                    updateRoles={updateRoles} 
-                    doSubmit={() => this.patchUser()} />
+                <>
+                    {error.length > 0 && <p>Error: {error}</p>}
+                    <UserForm
+                        user={u}
+                        setName={setName}
+                        setUuid={setUuid}
+                        updateRoles={updateRoles} 
+                        doSubmit={() => this.patchUser()} />
+                    <DeleteUserForm
+                        user={u}
+                        doDelete={(deletion) => this.deleteUser(deletion)} />
+                </>
             );
         } else {
             return (
@@ -149,4 +174,66 @@ function PermCheckbox(props: PermCheckboxProps) {
             <label>{name}</label>
         </>
     );
-}
\ No newline at end of file
+}
+interface DeleteUserFormProps {
+    user: User,
+    doDelete: (deletion: UserDeletion) => void,
+};
+
+interface DeleteUserFormState {
+    mode: UserDeletion["mode"],
+    reassign_to: string,
+};
+
+class DeleteUserForm extends Component<DeleteUserFormProps, DeleteUserFormState> {
+    state: DeleteUserFormState = {
+        mode: "soft_delete",
+        reassign_to: "",
+    };
+
+    private submit() {
+        const { mode, reassign_to } = this.state;
+        if (mode == "reassign") {
+            this.props.doDelete({ mode, reassign_to: parseInt(reassign_to, 10) });
+        } else {
+            this.props.doDelete({ mode });
+        }
+    }
+
+    render() {
+        const { user } = this.props;
+        const { mode, reassign_to } = this.state;
+
+        if (user.deleted_at !== null && mode != "reassign") {
+            return (
+                <>
+                    <h3>Delete User</h3>
+                    <p>This user was deleted on {user.deleted_at}.</p>
+                    <button onClick={() => this.setState({ mode: "reassign" })}>Remove for good</button>
+                </>
+            );
+        }
+
+        const setMode = (mode: UserDeletion["mode"]) => this.setState({ mode });
+
+        return (
+            <>
+                <h3>Delete User</h3>
+                <select value={mode} onChange={(ev) => setMode((ev!.target! as HTMLSelectElement).value as UserDeletion["mode"])}>
+                    <option value="soft_delete">Mark as deleted, keeping their posts</option>
+                    <option value="anonymize">Anonymize, keeping their posts</option>
+                    <option value="reassign">Give their posts to another user</option>
+                </select>
+                <br />
+                {mode == "reassign" && (
+                    <>
+                        <label>New author ID:</label>
+                        <input type="number" value={reassign_to} onChange={(ev) => this.setState({ reassign_to: (ev!.target! as HTMLInputElement).value })} />
+                        <br />
+                    </>
+                )}
+                <button onClick={() => this.submit()}>Delete</button>
+            </>
+        );
+    }
+}